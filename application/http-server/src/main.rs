@@ -15,7 +15,8 @@ async fn main() {
         .route("/on_publish", post(on_publish))
         .route("/on_unpublish", post(on_unpublish))
         .route("/on_play", post(on_play))
-        .route("/on_stop", post(on_stop));
+        .route("/on_stop", post(on_stop))
+        .route("/on_record_done", post(on_record_done));
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3001));
     log::info!("http server listen on: {}", 3001);
@@ -45,3 +46,7 @@ async fn on_play(body: String) {
 async fn on_stop(body: String) {
     log::info!("on_stop body: {}", body);
 }
+
+async fn on_record_done(body: String) {
+    log::info!("on_record_done body: {}", body);
+}
@@ -1,11 +1,22 @@
 use {
     anyhow::Result,
     axum::{
+        extract::Query,
+        http::{HeaderMap, StatusCode},
         routing::{get, post},
         Json, Router,
     },
-    rtmp::{channels::define, channels::define::ChannelEventProducer},
+    record::define::{RecordEvent, RecordEventProducer},
+    rtmp::{
+        channels::define,
+        channels::define::ChannelEventProducer,
+        cluster::{
+            http_registry::REGISTRY_SECRET_HEADER, memory_registry::MemoryRegistry, StreamLocation,
+        },
+        relay::define::{RelayEvent, RelayEventProducer},
+    },
     serde::Deserialize,
+    std::net::SocketAddr,
     std::str::FromStr,
     std::sync::Arc,
     uuid::Uuid,
@@ -21,9 +32,40 @@ struct KickOffClient {
     id: String,
 }
 
+// the input to our `StartRelayPush` handler
+#[derive(Deserialize)]
+struct StartRelayPush {
+    app_name: String,
+    stream_name: String,
+    url: String,
+}
+
+// the input to our `StopRelayPush` handler
+#[derive(Deserialize)]
+struct StopRelayPush {
+    id: String,
+}
+
+// the input to our `StartRecord` and `StopRecord` handlers
+#[derive(Deserialize)]
+struct RecordStream {
+    app_name: String,
+    stream_name: String,
+}
+
+// the input to our `LookupStream` handler
+#[derive(Deserialize)]
+struct LookupStream {
+    app_name: String,
+    stream_name: String,
+}
+
 #[derive(Clone)]
 struct ApiService {
     channel_event_producer: ChannelEventProducer,
+    relay_event_producer: RelayEventProducer,
+    //the recording is not enabled if it is none
+    record_event_producer: Option<RecordEventProducer>,
 }
 
 impl ApiService {
@@ -31,7 +73,13 @@ impl ApiService {
         String::from(
             "Usage of xiu http api:
                 ./get_stream_status(get)  get audio and video stream statistic information.
-                ./kick_off_client(post) kick off client by publish/subscribe id.\n",
+                ./kick_off_client(post) kick off client by publish/subscribe id.
+                ./start_relay_push(post) push a local stream to a remote rtmp url.
+                ./stop_relay_push(post) stop a relay push by id.
+                ./get_relay_push_status(get) get the status of the relay pushes.
+                ./start_record(post) record a published stream to flv files.
+                ./stop_record(post) stop the recording of a stream.
+                ./get_record_status(get) get the streams which are being recorded.\n",
         )
     }
 
@@ -85,11 +133,132 @@ impl ApiService {
 
         Ok(String::from("ok"))
     }
+
+    async fn start_relay_push(&self, push: StartRelayPush) -> Result<String> {
+        let (responder, receiver) = oneshot::channel();
+        let relay_event = RelayEvent::StartPush {
+            app_name: push.app_name,
+            stream_name: push.stream_name,
+            url: push.url,
+            responder,
+        };
+
+        if let Err(err) = self.relay_event_producer.send(relay_event) {
+            log::error!("send api start_relay_push event error: {}", err);
+            return Ok(String::from("error"));
+        }
+
+        match receiver.await? {
+            Ok(id) => Ok(id.to_string()),
+            Err(err) => Ok(err.to_string()),
+        }
+    }
+
+    async fn stop_relay_push(&self, push: StopRelayPush) -> Result<String> {
+        let id = Uuid::from_str(&push.id)?;
+
+        let (responder, receiver) = oneshot::channel();
+        let relay_event = RelayEvent::StopPush { id, responder };
+
+        if let Err(err) = self.relay_event_producer.send(relay_event) {
+            log::error!("send api stop_relay_push event error: {}", err);
+            return Ok(String::from("error"));
+        }
+
+        if receiver.await? {
+            Ok(String::from("ok"))
+        } else {
+            Ok(String::from("no relay push found"))
+        }
+    }
+
+    async fn get_relay_push_status(&self) -> Result<String> {
+        let (responder, receiver) = oneshot::channel();
+        let relay_event = RelayEvent::PushStatus { responder };
+
+        if let Err(err) = self.relay_event_producer.send(relay_event) {
+            log::error!("send api get_relay_push_status event error: {}", err);
+            return Ok(String::from("error"));
+        }
+
+        let status = receiver.await?;
+        Ok(serde_json::to_string(&status)?)
+    }
+
+    fn send_record_event(&self, record_event: RecordEvent) -> Option<String> {
+        match &self.record_event_producer {
+            Some(producer) => {
+                if let Err(err) = producer.send(record_event) {
+                    log::error!("send api record event error: {}", err);
+                    return Some(String::from("error"));
+                }
+                None
+            }
+            None => Some(String::from("record is not enabled")),
+        }
+    }
+
+    async fn start_record(&self, record: RecordStream) -> Result<String> {
+        let (responder, receiver) = oneshot::channel();
+        let record_event = RecordEvent::Start {
+            app_name: record.app_name,
+            stream_name: record.stream_name,
+            responder,
+        };
+
+        if let Some(response) = self.send_record_event(record_event) {
+            return Ok(response);
+        }
+
+        match receiver.await? {
+            Ok(()) => Ok(String::from("ok")),
+            Err(err) => Ok(err.to_string()),
+        }
+    }
+
+    async fn stop_record(&self, record: RecordStream) -> Result<String> {
+        let (responder, receiver) = oneshot::channel();
+        let record_event = RecordEvent::Stop {
+            app_name: record.app_name,
+            stream_name: record.stream_name,
+            responder,
+        };
+
+        if let Some(response) = self.send_record_event(record_event) {
+            return Ok(response);
+        }
+
+        if receiver.await? {
+            Ok(String::from("ok"))
+        } else {
+            Ok(String::from("no recording found"))
+        }
+    }
+
+    async fn get_record_status(&self) -> Result<String> {
+        let (responder, receiver) = oneshot::channel();
+        let record_event = RecordEvent::Status { responder };
+
+        if let Some(response) = self.send_record_event(record_event) {
+            return Ok(response);
+        }
+
+        let status = receiver.await?;
+        Ok(serde_json::to_string(&status)?)
+    }
 }
 
-pub async fn run(producer: ChannelEventProducer, port: usize) {
+/*the routes of the http api, they are served by the api server or under /api of
+the unified http server*/
+pub fn create_router(
+    producer: ChannelEventProducer,
+    relay_producer: RelayEventProducer,
+    record_producer: Option<RecordEventProducer>,
+) -> Router {
     let api = Arc::new(ApiService {
         channel_event_producer: producer,
+        relay_event_producer: relay_producer,
+        record_event_producer: record_producer,
     });
 
     let api_root = api.clone();
@@ -111,10 +280,73 @@ pub async fn run(producer: ChannelEventProducer, port: usize) {
         }
     };
 
-    let app = Router::new()
+    let start_push = api.clone();
+    let start_relay_push = move |Json(push): Json<StartRelayPush>| async move {
+        match start_push.start_relay_push(push).await {
+            Ok(response) => response,
+            Err(_) => "error".to_owned(),
+        }
+    };
+
+    let stop_push = api.clone();
+    let stop_relay_push = move |Json(push): Json<StopRelayPush>| async move {
+        match stop_push.stop_relay_push(push).await {
+            Ok(response) => response,
+            Err(_) => "error".to_owned(),
+        }
+    };
+
+    let push_status = api.clone();
+    let relay_push_status = move || async move {
+        match push_status.get_relay_push_status().await {
+            Ok(response) => response,
+            Err(_) => "error".to_owned(),
+        }
+    };
+
+    let start_recording = api.clone();
+    let start_record = move |Json(record): Json<RecordStream>| async move {
+        match start_recording.start_record(record).await {
+            Ok(response) => response,
+            Err(_) => "error".to_owned(),
+        }
+    };
+
+    let stop_recording = api.clone();
+    let stop_record = move |Json(record): Json<RecordStream>| async move {
+        match stop_recording.stop_record(record).await {
+            Ok(response) => response,
+            Err(_) => "error".to_owned(),
+        }
+    };
+
+    let recording_status = api.clone();
+    let record_status = move || async move {
+        match recording_status.get_record_status().await {
+            Ok(response) => response,
+            Err(_) => "error".to_owned(),
+        }
+    };
+
+    Router::new()
         .route("/", get(root))
         .route("/get_stream_status", get(status))
-        .route("/kick_off_client", post(kick));
+        .route("/kick_off_client", post(kick))
+        .route("/start_relay_push", post(start_relay_push))
+        .route("/stop_relay_push", post(stop_relay_push))
+        .route("/get_relay_push_status", get(relay_push_status))
+        .route("/start_record", post(start_record))
+        .route("/stop_record", post(stop_record))
+        .route("/get_record_status", get(record_status))
+}
+
+pub async fn run(
+    producer: ChannelEventProducer,
+    relay_producer: RelayEventProducer,
+    record_producer: Option<RecordEventProducer>,
+    port: usize,
+) {
+    let app = create_router(producer, relay_producer, record_producer);
 
     log::info!("Http api server listening on http://:{}", port);
     axum::Server::bind(&([127, 0, 0, 1], port as u16).into())
@@ -122,3 +354,77 @@ pub async fn run(producer: ChannelEventProducer, port: usize) {
         .await
         .unwrap();
 }
+
+/*the requests of the registry are accepted if no secret is set or they carry it*/
+fn is_registry_authorized(secret: &Option<String>, headers: &HeaderMap) -> bool {
+    match secret {
+        Some(secret) => headers
+            .get(REGISTRY_SECRET_HEADER)
+            .is_some_and(|value| value.as_bytes() == secret.as_bytes()),
+        None => true,
+    }
+}
+
+/*The http stream registry server of the cluster mode, the other nodes register and
+look up streams through it. It listens on the loopback address by default, listen on
+a private address and set the secret to serve the other hosts.*/
+pub async fn run_registry_server(
+    registry: MemoryRegistry,
+    address: String,
+    port: usize,
+    secret: Option<String>,
+) {
+    let secret = Arc::new(secret);
+
+    let register_registry = registry.clone();
+    let register_secret = secret.clone();
+    let register = move |headers: HeaderMap, Json(location): Json<StreamLocation>| async move {
+        if !is_registry_authorized(&register_secret, &headers) {
+            return (StatusCode::UNAUTHORIZED, "unauthorized".to_owned());
+        }
+        register_registry.register_stream(location);
+        (StatusCode::OK, "ok".to_owned())
+    };
+
+    let unregister_registry = registry.clone();
+    let unregister_secret = secret.clone();
+    let unregister = move |headers: HeaderMap, Json(location): Json<StreamLocation>| async move {
+        if !is_registry_authorized(&unregister_secret, &headers) {
+            return (StatusCode::UNAUTHORIZED, "unauthorized".to_owned());
+        }
+        unregister_registry.unregister_stream(location);
+        (StatusCode::OK, "ok".to_owned())
+    };
+
+    let lookup = move |headers: HeaderMap, Query(stream): Query<LookupStream>| async move {
+        if !is_registry_authorized(&secret, &headers) {
+            return (StatusCode::UNAUTHORIZED, String::new());
+        }
+        let address = registry
+            .lookup_stream(&stream.app_name, &stream.stream_name)
+            .unwrap_or_default();
+        (StatusCode::OK, address)
+    };
+
+    let app = Router::new()
+        .route("/register", post(register))
+        .route("/unregister", post(unregister))
+        .route("/lookup", get(lookup));
+
+    let socket_address: SocketAddr = match format!("{address}:{port}").parse() {
+        Ok(socket_address) => socket_address,
+        Err(err) => {
+            log::error!("stream registry server address {} error: {}", address, err);
+            return;
+        }
+    };
+
+    log::info!(
+        "Stream registry server listening on http://{}",
+        socket_address
+    );
+    axum::Server::bind(&socket_address)
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+}
@@ -1,7 +1,9 @@
 pub mod errors;
 
 use errors::ConfigError;
+use rtmp::channels::define::PublishConflictPolicy;
 use serde_derive::Deserialize;
+use std::collections::HashMap;
 use std::fs;
 use std::vec::Vec;
 
@@ -10,8 +12,12 @@ pub struct Config {
     pub rtmp: Option<RtmpConfig>,
     pub httpflv: Option<HttpFlvConfig>,
     pub hls: Option<HlsConfig>,
+    pub dash: Option<DashConfig>,
     pub httpapi: Option<HttpApiConfig>,
+    pub http: Option<HttpConfig>,
     pub httpnotify: Option<HttpNotifierConfig>,
+    pub cluster: Option<ClusterConfig>,
+    pub record: Option<RecordConfig>,
     pub log: Option<LogConfig>,
 }
 
@@ -22,6 +28,8 @@ impl Config {
             rtmp_config = Some(RtmpConfig {
                 enabled: true,
                 gop_num: Some(1),
+                publish_conflict: None,
+                publish_conflict_apps: None,
                 port: rtmp_port,
                 pull: None,
                 push: None,
@@ -33,6 +41,8 @@ impl Config {
             httpflv_config = Some(HttpFlvConfig {
                 enabled: true,
                 port: httpflv_port,
+                ingest: None,
+                subscribe_timeout: None,
             });
         }
 
@@ -41,6 +51,16 @@ impl Config {
             hls_config = Some(HlsConfig {
                 enabled: true,
                 port: hls_port,
+                store: None,
+                options: HlsOptionsConfig::default(),
+                apps: None,
+                variant_separator: None,
+                variants: None,
+                session_timeout: None,
+                max_sessions_per_ip: None,
+                playlist_max_age: None,
+                segment_max_age: None,
+                cors_origins: None,
             });
         }
 
@@ -53,8 +73,12 @@ impl Config {
             rtmp: rtmp_config,
             httpflv: httpflv_config,
             hls: hls_config,
+            dash: None,
             httpapi: None,
+            http: None,
             httpnotify: None,
+            cluster: None,
+            record: None,
             log: log_config,
         }
     }
@@ -65,14 +89,26 @@ pub struct RtmpConfig {
     pub enabled: bool,
     pub port: usize,
     pub gop_num: Option<usize>,
+    /*what to do when a stream is published while it has a publisher:
+    reject(default), replace or queue*/
+    pub publish_conflict: Option<PublishConflictPolicy>,
+    //override the publish conflict policy of the apps
+    pub publish_conflict_apps: Option<HashMap<String, PublishConflictPolicy>>,
     pub pull: Option<RtmpPullConfig>,
     pub push: Option<Vec<RtmpPushConfig>>,
 }
 #[derive(Debug, Deserialize, Clone)]
 pub struct RtmpPullConfig {
     pub enabled: bool,
+    #[serde(default)]
     pub address: String,
+    #[serde(default)]
     pub port: u16,
+    /*ordered origin url templates, e.g. rtmp://origin.cn/{app}/{stream}?token=abc,
+    address and port are ignored if it is set*/
+    pub origins: Option<Vec<String>>,
+    /*seconds to keep a pulled stream after its last subscriber leaves*/
+    pub idle_timeout: Option<u64>,
 }
 #[derive(Debug, Deserialize, Clone)]
 pub struct RtmpPushConfig {
@@ -85,12 +121,151 @@ pub struct RtmpPushConfig {
 pub struct HttpFlvConfig {
     pub enabled: bool,
     pub port: usize,
+    //accept the flv streams published by POST or websocket(?publish), default is false
+    pub ingest: Option<bool>,
+    //how long(milliseconds) a player waits for a stream which is not found before
+    //the 404 response, default is 3000
+    pub subscribe_timeout: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct HlsConfig {
     pub enabled: bool,
     pub port: usize,
+    /*where the playlists and segments are kept: memory(default) or file*/
+    pub store: Option<HlsStoreType>,
+    #[serde(flatten)]
+    pub options: HlsOptionsConfig,
+    //override the hls options of the apps
+    pub apps: Option<HashMap<String, HlsOptionsConfig>>,
+    /*group the streams named {name}{separator}{suffix} into a variant set, e.g. "_"
+    groups show_1080 and show_720 into show*/
+    pub variant_separator: Option<String>,
+    pub variants: Option<Vec<HlsVariantConfig>>,
+    //a viewer stops(on_stop) after the seconds without requests, default is 30
+    pub session_timeout: Option<u64>,
+    /*the sessions with a client session id of a remote ip per stream, the further ids are
+    counted as the viewer of the ip, 0 identifies the viewers by the ip, default is 16*/
+    pub max_sessions_per_ip: Option<usize>,
+    //the Cache-Control max-age of the live playlists in seconds, default is 1
+    pub playlist_max_age: Option<u64>,
+    //the Cache-Control max-age of the segments and the VOD playlists in seconds, default is 3600
+    pub segment_max_age: Option<u64>,
+    //the origins allowed by CORS, ["*"] allows any origin, default is none
+    pub cors_origins: Option<Vec<String>>,
+}
+
+/*the streams of an app which are the renditions of a master playlist*/
+#[derive(Debug, Deserialize, Clone)]
+pub struct HlsVariantConfig {
+    pub app: String,
+    pub name: String,
+    pub streams: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct HlsOptionsConfig {
+    //the root folder of the file store, default is the current folder
+    pub root: Option<String>,
+    //segment duration in seconds, default is 5
+    pub duration: Option<i64>,
+    //how many segments are listed in the live playlist, default is 6
+    pub playlist_length: Option<usize>,
+    //seconds to keep a segment after it is removed from the playlist, default is 0
+    pub deletion_delay: Option<u64>,
+    //segment name template, {app}, {stream} and {seq} are replaced, default is {seq}.ts
+    pub segment_name: Option<String>,
+    //enable LL-HLS, default is false
+    pub low_latency: Option<bool>,
+    //the target duration of a LL-HLS partial segment in milliseconds, default is 200
+    pub part_duration: Option<i64>,
+    //the container of the segments: ts(default) or fmp4
+    pub container: Option<HlsContainerType>,
+    //the DVR window in seconds, the segments within it can be played back, default is 0(disabled)
+    pub dvr_window: Option<u64>,
+    //archive the segments with a VOD playlist when the stream ends instead of deleting them
+    pub archive: Option<bool>,
+    //the encryption of the segments: none(default), aes-128 or sample-aes
+    pub encryption: Option<HlsEncryptionType>,
+    //how many segments are encrypted by a key, default is 0(one key for a stream)
+    pub key_rotation: Option<u64>,
+    //the key uri template in the playlist, {app}, {stream} and {index} are replaced, default is {index}.key
+    pub key_uri: Option<String>,
+    //cut a segment at any frame when it reaches the max duration in seconds, default is 0(no limit)
+    pub max_duration: Option<i64>,
+    //a timestamp jump longer than the seconds begins a discontinuity, default is 15, 0 disables it
+    pub discontinuity_threshold: Option<i64>,
+    //remux a stream when its playlist is requested instead of when it is published, default is false
+    pub on_demand: Option<bool>,
+    //stop remuxing an on demand stream after the seconds without playlist requests, default is 30
+    pub idle_timeout: Option<u64>,
+    //the playlist tags of the ad break cue points: cue(default) or daterange
+    pub ad_markers: Option<HlsAdMarkersType>,
+    //carry the onCuePoint and onTextData messages as ID3 timed metadata in the ts, default is false
+    pub timed_metadata: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HlsContainerType {
+    Ts,
+    Fmp4,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HlsEncryptionType {
+    None,
+    #[serde(rename = "aes-128")]
+    Aes128,
+    SampleAes,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HlsAdMarkersType {
+    Cue,
+    DateRange,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HlsStoreType {
+    Memory,
+    File,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DashConfig {
+    pub enabled: bool,
+    pub port: usize,
+    //segment duration in seconds, default is 4
+    pub duration: Option<i64>,
+    //how many segments are listed in the mpd, default is 6
+    pub window_size: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RecordConfig {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub options: RecordOptionsConfig,
+    //override the record options of the apps
+    pub apps: Option<HashMap<String, RecordOptionsConfig>>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RecordOptionsConfig {
+    //record the streams when they are published, otherwise by the http api, default is true
+    pub auto_start: Option<bool>,
+    //the root folder of the files, default is the current folder
+    pub root: Option<String>,
+    //file name template, {app}, {stream} and {time} are replaced, default is {app}/{stream}_{time}.flv
+    pub file_name: Option<String>,
+    //roll to a new file at the key frame after the seconds, default is 0(no limit)
+    pub max_duration: Option<u64>,
+    //roll to a new file at the key frame after the bytes, default is 0(no limit)
+    pub max_size: Option<u64>,
 }
 
 pub enum LogLevel {
@@ -119,6 +294,27 @@ pub struct HttpApiConfig {
     pub port: usize,
 }
 
+/*the unified http listener of httpflv, hls and the http api, they do not listen on
+their own ports if it is enabled*/
+#[derive(Debug, Deserialize, Clone)]
+pub struct HttpConfig {
+    pub enabled: bool,
+    pub port: usize,
+    //serve the http api under /api instead of the httpapi port, default is false
+    pub api: Option<bool>,
+    //the PEM certificate chain and private key files, https is served if they are set
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    //accept the HTTP/2 connections(ALPN h2 or prior knowledge), default is true
+    pub http2: Option<bool>,
+    //keep the HTTP/1 connections alive between requests, default is true
+    pub keepalive: Option<bool>,
+    //the interval of the HTTP/2 keep-alive pings in seconds, default is 0(disabled)
+    pub http2_keepalive_interval: Option<u64>,
+    //close the HTTP/2 connection if a ping is not acknowledged in the seconds, default is 20
+    pub http2_keepalive_timeout: Option<u64>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct HttpNotifierConfig {
     pub enabled: bool,
@@ -126,6 +322,25 @@ pub struct HttpNotifierConfig {
     pub on_unpublish: Option<String>,
     pub on_play: Option<String>,
     pub on_stop: Option<String>,
+    //a flv recording file of a stream begins
+    pub on_record: Option<String>,
+    //the hls archive or a flv recording of a stream is done
+    pub on_record_done: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClusterConfig {
+    pub enabled: bool,
+    //the rtmp address(ip:port) other nodes pull the streams of this node from
+    pub node_address: String,
+    //the url of the http registry server, the in-process registry is used if not set
+    pub registry_url: Option<String>,
+    //serve the http registry for the other nodes on this port
+    pub registry_server_port: Option<usize>,
+    //the ip the registry server listens on, default is 127.0.0.1
+    pub registry_server_address: Option<String>,
+    //the shared secret of the registry server and clients, sent in the X-Registry-Secret header
+    pub registry_secret: Option<String>,
 }
 
 pub fn load(cfg_path: &String) -> Result<Config, ConfigError> {
@@ -0,0 +1,413 @@
+use {
+    anyhow::Result,
+    axum::{
+        body::{self, BoxBody},
+        Router,
+    },
+    hls::server::{self as hls_server, ServerContext as HlsServerContext},
+    httpflv::server::{self as httpflv_server, ServerContext as HttpFlvServerContext},
+    hyper::{server::conn::Http, service::service_fn, Body, Request, Response, StatusCode},
+    openssl::ssl::{select_next_proto, AlpnError, Ssl, SslAcceptor, SslFiletype, SslMethod},
+    std::{net::SocketAddr, pin::Pin, sync::Arc, time::Duration},
+    tokio::net::{TcpListener, TcpStream},
+    tokio_openssl::SslStream,
+    tower::ServiceExt,
+};
+
+type GenericError = Box<dyn std::error::Error + Send + Sync>;
+static NOTFOUND: &[u8] = b"Not Found";
+
+/*the listener options of the unified http server*/
+#[derive(Debug, Clone)]
+pub struct HttpServerOptions {
+    //the PEM certificate chain file and private key file, https is served if they are set
+    pub tls: Option<(String, String)>,
+    //accept the HTTP/2 connections negotiated by ALPN or with prior knowledge
+    pub http2: bool,
+    //keep the HTTP/1 connections alive between requests
+    pub keepalive: bool,
+    //the interval of the HTTP/2 keep-alive pings, None disables them
+    pub http2_keepalive_interval: Option<Duration>,
+    //close the HTTP/2 connection if a keep-alive ping is not acknowledged in time
+    pub http2_keepalive_timeout: Option<Duration>,
+}
+
+impl Default for HttpServerOptions {
+    fn default() -> Self {
+        Self {
+            tls: None,
+            http2: true,
+            keepalive: true,
+            http2_keepalive_interval: None,
+            http2_keepalive_timeout: None,
+        }
+    }
+}
+
+/*the protocols served by the unified http server, the ones which are None are not
+served*/
+#[derive(Clone, Default)]
+pub struct HttpRoutes {
+    //the httpflv streams: /{app}/{stream}.flv
+    pub httpflv: Option<HttpFlvServerContext>,
+    //the hls playlists, segments and keys: /{app}/{stream}/{stream}.m3u8 ...
+    pub hls: Option<HlsServerContext>,
+    //the http api, its routes are served under /api
+    pub api: Option<Router>,
+}
+
+async fn handle_request(
+    req: Request<Body>,
+    routes: HttpRoutes,
+    remote_addr: SocketAddr,
+) -> std::result::Result<Response<BoxBody>, GenericError> {
+    let path = req.uri().path();
+
+    if path == "/api" || path.starts_with("/api/") {
+        if let Some(api) = routes.api {
+            return Ok(api.oneshot(req).await?);
+        }
+    } else if path.ends_with(".flv") {
+        if let Some(context) = routes.httpflv {
+            let response = httpflv_server::handle_connection(req, context, remote_addr).await?;
+            return Ok(response.map(body::boxed));
+        }
+    } else if let Some(context) = routes.hls {
+        let response = hls_server::handle_connection(req, context, remote_addr).await?;
+        return Ok(response.map(body::boxed));
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(body::boxed(Body::from(NOTFOUND)))
+        .unwrap())
+}
+
+fn create_tls_acceptor(cert_file: &str, key_file: &str, http2: bool) -> Result<SslAcceptor> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+    builder.set_certificate_chain_file(cert_file)?;
+    builder.set_private_key_file(key_file, SslFiletype::PEM)?;
+    builder.check_private_key()?;
+
+    //the ALPN protocols in the wire format, h2 is preferred
+    let protocols: &'static [u8] = if http2 {
+        b"\x02h2\x08http/1.1"
+    } else {
+        b"\x08http/1.1"
+    };
+    builder.set_alpn_select_callback(move |_, client_protocols| {
+        select_next_proto(protocols, client_protocols).ok_or(AlpnError::NOACK)
+    });
+
+    Ok(builder.build())
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    remote_addr: SocketAddr,
+    http: Http,
+    tls_acceptor: Option<Arc<SslAcceptor>>,
+    routes: HttpRoutes,
+) -> Result<()> {
+    let service = service_fn(move |req| handle_request(req, routes.clone(), remote_addr));
+
+    match tls_acceptor {
+        Some(tls_acceptor) => {
+            let mut tls_stream = SslStream::new(Ssl::new(tls_acceptor.context())?, stream)?;
+            Pin::new(&mut tls_stream).accept().await?;
+            http.serve_connection(tls_stream, service)
+                .with_upgrades()
+                .await?;
+        }
+        None => {
+            http.serve_connection(stream, service)
+                .with_upgrades()
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/*Serve httpflv, hls and the http api on one port, the requests are routed by the
+path: /api/... to the http api, *.flv to httpflv(and websocket-flv) and the others
+to hls.*/
+pub async fn run(port: usize, options: HttpServerOptions, mut routes: HttpRoutes) -> Result<()> {
+    let tls_acceptor = match &options.tls {
+        Some((cert_file, key_file)) => Some(Arc::new(create_tls_acceptor(
+            cert_file,
+            key_file,
+            options.http2,
+        )?)),
+        None => None,
+    };
+
+    let mut http = Http::new();
+    http.http1_keep_alive(options.keepalive);
+    //the HTTP/2 connections are detected by their preface if it is not HTTP/1 only
+    http.http1_only(!options.http2);
+    http.http2_keep_alive_interval(options.http2_keepalive_interval);
+    if let Some(timeout) = options.http2_keepalive_timeout {
+        http.http2_keep_alive_timeout(timeout);
+    }
+
+    routes.api = routes.api.map(|api| Router::new().nest("/api", api));
+    let listener = TcpListener::bind(format!("0.0.0.0:{port}")).await?;
+    log::info!(
+        "Http server listening on {}://0.0.0.0:{}",
+        if tls_acceptor.is_some() {
+            "https"
+        } else {
+            "http"
+        },
+        port
+    );
+
+    loop {
+        let (stream, remote_addr) = listener.accept().await?;
+        let http = http.clone();
+        let tls_acceptor = tls_acceptor.clone();
+        let routes = routes.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) =
+                serve_connection(stream, remote_addr, http, tls_acceptor, routes).await
+            {
+                log::trace!("http connection of {} error: {}", remote_addr, err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{create_tls_acceptor, handle_request, serve_connection, HttpRoutes, NOTFOUND},
+        axum::{routing::get, Router},
+        hls::{
+            server::{HlsServerOptions, ServerContext as HlsServerContext},
+            store::{memory::MemoryHlsStore, HlsStore},
+        },
+        httpflv::server::{HttpFlvServerOptions, ServerContext as HttpFlvServerContext},
+        hyper::{body::Bytes, server::conn::Http, Body, Method, Request, StatusCode},
+        openssl::{
+            asn1::Asn1Time,
+            bn::BigNum,
+            ec::{EcGroup, EcKey},
+            hash::MessageDigest,
+            nid::Nid,
+            pkey::PKey,
+            ssl::{SslConnector, SslMethod, SslVerifyMode},
+            x509::{X509NameBuilder, X509},
+        },
+        std::{fs, path::PathBuf, pin::Pin, sync::Arc},
+        tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::{TcpListener, TcpStream},
+            sync::mpsc,
+        },
+        tokio_openssl::SslStream,
+        uuid::Uuid,
+    };
+
+    fn new_routes(api: bool, httpflv: bool) -> HttpRoutes {
+        let store = Arc::new(MemoryHlsStore::default());
+        for (app_name, stream_name, name) in [
+            ("live", "test", "test.m3u8"),
+            ("api", "ping", "ping.m3u8"),
+            ("live", "test", "test.flv"),
+        ]
+        .iter()
+        {
+            store
+                .write(app_name, stream_name, name, Bytes::from("#EXTM3U\n"))
+                .unwrap();
+        }
+
+        let (event_producer, _) = mpsc::unbounded_channel();
+        HttpRoutes {
+            httpflv: if httpflv {
+                Some(HttpFlvServerContext::new(
+                    event_producer,
+                    HttpFlvServerOptions::default(),
+                ))
+            } else {
+                None
+            },
+            hls: Some(HlsServerContext::new(
+                HlsServerOptions::default(),
+                store,
+                None,
+                None,
+                None,
+                None,
+            )),
+            //nested as the server does
+            api: if api {
+                Some(Router::new().nest(
+                    "/api",
+                    Router::new().route("/ping", get(|| async { "pong" })),
+                ))
+            } else {
+                None
+            },
+        }
+    }
+
+    async fn request(routes: HttpRoutes, method: Method, uri: &str) -> (StatusCode, Bytes) {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = handle_request(req, routes, "127.0.0.1:50000".parse().unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        (
+            status,
+            hyper::body::to_bytes(response.into_body()).await.unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_route_requests() {
+        let routes = new_routes(true, true);
+        //the http api
+        assert_eq!(
+            request(routes.clone(), Method::GET, "/api/ping").await,
+            (StatusCode::OK, Bytes::from("pong"))
+        );
+        //httpflv, the ingest is disabled
+        let (status, _) = request(routes.clone(), Method::POST, "/live/test.flv").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        //hls
+        assert_eq!(
+            request(routes.clone(), Method::GET, "/live/test/test.m3u8").await,
+            (StatusCode::OK, Bytes::from("#EXTM3U\n"))
+        );
+        //the missing api routes are not served by hls
+        let (status, body) = request(routes, Method::GET, "/api/ping/ping.m3u8").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_ne!(body, Bytes::from("#EXTM3U\n"));
+
+        //the disabled protocols are not served by hls either
+        let routes = new_routes(false, false);
+        assert_eq!(
+            request(routes.clone(), Method::GET, "/api/ping").await,
+            (StatusCode::NOT_FOUND, Bytes::from(NOTFOUND))
+        );
+        assert_eq!(
+            request(routes.clone(), Method::GET, "/api/ping/ping.m3u8").await,
+            (StatusCode::NOT_FOUND, Bytes::from(NOTFOUND))
+        );
+        assert_eq!(
+            request(routes.clone(), Method::GET, "/live/test/test.flv").await,
+            (StatusCode::NOT_FOUND, Bytes::from(NOTFOUND))
+        );
+        let (status, _) = request(routes, Method::GET, "/live/test/test.m3u8").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    /*write a self-signed certificate of localhost and its key to a temporary directory*/
+    fn write_certificate() -> (PathBuf, String, String) {
+        let key = PKey::from_ec_key(
+            EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap(),
+        )
+        .unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        let serial_number = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+        builder.set_serial_number(&serial_number).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let certificate = builder.build();
+
+        let dir = std::env::temp_dir().join(format!("xiu_tls_{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let cert_file = dir.join("cert.pem");
+        let key_file = dir.join("key.pem");
+        fs::write(&cert_file, certificate.to_pem().unwrap()).unwrap();
+        fs::write(&key_file, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+
+        let cert_file = cert_file.to_string_lossy().to_string();
+        let key_file = key_file.to_string_lossy().to_string();
+        (dir, cert_file, key_file)
+    }
+
+    /*connect to a https server offering h2 and http/1.1, returns the negotiated protocol
+    and the response of a HTTP/1.1 request if http/1.1 is negotiated*/
+    async fn connect_tls(http2: bool, cert_file: &str, key_file: &str) -> (Vec<u8>, String) {
+        let tls_acceptor = Arc::new(create_tls_acceptor(cert_file, key_file, http2).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, remote_addr) = listener.accept().await.unwrap();
+            let routes = new_routes(false, false);
+            let _ = serve_connection(stream, remote_addr, Http::new(), Some(tls_acceptor), routes)
+                .await;
+        });
+
+        let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+        connector.set_verify(SslVerifyMode::NONE);
+        connector.set_alpn_protos(b"\x02h2\x08http/1.1").unwrap();
+        let ssl = connector
+            .build()
+            .configure()
+            .unwrap()
+            .into_ssl("localhost")
+            .unwrap();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut tls_stream = SslStream::new(ssl, stream).unwrap();
+        Pin::new(&mut tls_stream).connect().await.unwrap();
+
+        let protocol = tls_stream
+            .ssl()
+            .selected_alpn_protocol()
+            .unwrap_or_default()
+            .to_vec();
+        let mut response = String::new();
+        if protocol == b"http/1.1" {
+            tls_stream
+                .write_all(b"GET /live/test/test.m3u8 HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            tls_stream.read_to_string(&mut response).await.unwrap();
+        }
+        (protocol, response)
+    }
+
+    #[tokio::test]
+    async fn test_tls_alpn() {
+        let (dir, cert_file, key_file) = write_certificate();
+
+        //h2 is preferred if HTTP/2 is enabled
+        let (protocol, _) = connect_tls(true, &cert_file, &key_file).await;
+        assert_eq!(protocol, b"h2");
+
+        //only http/1.1 is negotiated if HTTP/2 is disabled
+        let (protocol, response) = connect_tls(false, &cert_file, &key_file).await;
+        assert_eq!(protocol, b"http/1.1");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("#EXTM3U\n"));
+
+        //the certificate must match the key
+        let (other_dir, other_cert_file, _) = write_certificate();
+        assert!(create_tls_acceptor(&other_cert_file, &key_file, true).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(other_dir).unwrap();
+    }
+}
@@ -2,4 +2,5 @@ extern crate rtmp;
 extern crate serde_derive;
 pub mod api;
 pub mod config;
+pub mod http_server;
 pub mod service;
@@ -1,31 +1,69 @@
 use {
     super::api,
-    super::config::Config,
+    super::config::{
+        Config, HlsAdMarkersType, HlsContainerType, HlsEncryptionType, HlsOptionsConfig,
+        HlsStoreType, RecordOptionsConfig,
+    },
+    super::http_server::{self, HttpRoutes, HttpServerOptions},
     //https://rustcc.cn/article?id=6dcbf032-0483-4980-8bfe-c64a7dfb33c7
     anyhow::Result,
+    dash::{
+        define::DashOptions, rtmp_event_processor::RtmpEventProcessor as DashEventProcessor,
+        server as dash_server,
+    },
     hls::rtmp_event_processor::RtmpEventProcessor,
-    hls::server as hls_server,
-    httpflv::server as httpflv_server,
+    hls::server::{self as hls_server, HlsServerOptions, ServerContext as HlsServerContext},
+    hls::{
+        define::{HlsAdMarkers, HlsContainer, HlsEncryption, HlsOptions},
+        session::{HlsSessionManager, DEFAULT_SESSION_TIMEOUT},
+        store::{filesystem::FileHlsStore, memory::MemoryHlsStore, HlsStoreRef},
+        variant::{MasterPlaylistGenerator, VariantSet, VariantSets},
+    },
+    httpflv::server::{
+        self as httpflv_server, HttpFlvServerOptions, ServerContext as HttpFlvServerContext,
+    },
+    record::{
+        define::{RecordEventProducer, RecordOptions},
+        rtmp_event_processor::RtmpEventProcessor as RecordEventProcessor,
+    },
     rtmp::{
         channels::ChannelsManager,
+        cluster::{http_registry::HttpRegistry, memory_registry::MemoryRegistry, StreamRegistry},
         notify::Notifier,
-        relay::{pull_client::PullClient, push_client::PushClient},
+        relay::{
+            define::RelayEventProducer, pull_client::PullClient, push_client::PushClient,
+            push_manager::PushManager,
+        },
         rtmp::RtmpServer,
     },
+    std::{sync::Arc, time::Duration},
     tokio,
 };
 
 pub struct Service {
     cfg: Config,
+    //the protocols served by the unified http server
+    http_routes: HttpRoutes,
 }
 
 impl Service {
     pub fn new(cfg: Config) -> Self {
-        Service { cfg }
+        Service {
+            cfg,
+            http_routes: HttpRoutes::default(),
+        }
     }
 
-    pub async fn run(&mut self) -> Result<()> {
-        let notifier = if let Some(httpnotifier) = &self.cfg.httpnotify {
+    fn is_http_enabled(&self) -> bool {
+        matches!(&self.cfg.http, Some(http_cfg) if http_cfg.enabled)
+    }
+
+    fn is_play_authorized(&self) -> bool {
+        matches!(&self.cfg.httpnotify, Some(httpnotifier) if httpnotifier.enabled && httpnotifier.on_play.is_some())
+    }
+
+    fn create_notifier(&self) -> Option<Notifier> {
+        if let Some(httpnotifier) = &self.cfg.httpnotify {
             if !httpnotifier.enabled {
                 None
             } else {
@@ -34,18 +72,39 @@ impl Service {
                     httpnotifier.on_unpublish.clone(),
                     httpnotifier.on_play.clone(),
                     httpnotifier.on_stop.clone(),
+                    httpnotifier.on_record.clone(),
+                    httpnotifier.on_record_done.clone(),
                 ))
             }
         } else {
             None
-        };
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let notifier = self.create_notifier();
 
         let mut channel = ChannelsManager::new(notifier);
 
+        /*the relay pushes started from the static config and the http api*/
+        let mut push_manager = PushManager::new(channel.get_channel_event_producer());
+        let relay_producer = push_manager.get_relay_event_producer();
+        tokio::spawn(async move {
+            push_manager.run().await;
+            log::info!("relay push manager end...");
+        });
+
+        let stream_registry = self.start_cluster(&mut channel).await?;
+
         self.start_httpflv(&mut channel).await?;
         self.start_hls(&mut channel).await?;
-        self.start_rtmp(&mut channel).await?;
-        self.start_http_api_server(&mut channel).await?;
+        self.start_dash(&mut channel).await?;
+        let record_producer = self.start_record(&mut channel).await?;
+        self.start_rtmp(&mut channel, relay_producer.clone(), stream_registry)
+            .await?;
+        self.start_http_api_server(&mut channel, relay_producer, record_producer)
+            .await?;
+        self.start_http_server().await?;
 
         tokio::spawn(async move {
             channel.run().await;
@@ -54,9 +113,25 @@ impl Service {
         Ok(())
     }
 
-    async fn start_http_api_server(&mut self, channel: &mut ChannelsManager) -> Result<()> {
+    async fn start_http_api_server(
+        &mut self,
+        channel: &mut ChannelsManager,
+        relay_producer: RelayEventProducer,
+        record_producer: Option<RecordEventProducer>,
+    ) -> Result<()> {
         let producer = channel.get_channel_event_producer();
 
+        if let Some(http_cfg) = self.cfg.http.as_ref().filter(|http_cfg| http_cfg.enabled) {
+            if http_cfg.api.unwrap_or(false) {
+                self.http_routes.api = Some(api::create_router(
+                    producer,
+                    relay_producer,
+                    record_producer,
+                ));
+                return Ok(());
+            }
+        }
+
         let http_api_port = if let Some(httpapi) = &self.cfg.httpapi {
             httpapi.port
         } else {
@@ -64,12 +139,61 @@ impl Service {
         };
 
         tokio::spawn(async move {
-            api::run(producer, http_api_port).await;
+            api::run(producer, relay_producer, record_producer, http_api_port).await;
+        });
+        Ok(())
+    }
+
+    async fn start_http_server(&mut self) -> Result<()> {
+        let http_cfg = match self.cfg.http.as_ref().filter(|http_cfg| http_cfg.enabled) {
+            Some(http_cfg) => http_cfg,
+            None => return Ok(()),
+        };
+
+        let mut options = HttpServerOptions::default();
+        match (&http_cfg.tls_cert, &http_cfg.tls_key) {
+            (Some(cert_file), Some(key_file)) => {
+                options.tls = Some((cert_file.clone(), key_file.clone()));
+            }
+            (None, None) => {}
+            _ => {
+                log::error!("http server tls needs both tls_cert and tls_key");
+            }
+        }
+        if let Some(http2) = http_cfg.http2 {
+            options.http2 = http2;
+        }
+        if let Some(keepalive) = http_cfg.keepalive {
+            options.keepalive = keepalive;
+        }
+        if let Some(interval) = http_cfg
+            .http2_keepalive_interval
+            .filter(|interval| *interval > 0)
+        {
+            options.http2_keepalive_interval = Some(Duration::from_secs(interval));
+        }
+        if let Some(timeout) = http_cfg.http2_keepalive_timeout {
+            options.http2_keepalive_timeout = Some(Duration::from_secs(timeout));
+        }
+
+        let port = http_cfg.port;
+        let routes = std::mem::take(&mut self.http_routes);
+
+        tokio::spawn(async move {
+            if let Err(err) = http_server::run(port, options, routes).await {
+                log::error!("http server error: {}\n", err);
+            }
         });
+
         Ok(())
     }
 
-    async fn start_rtmp(&mut self, channel: &mut ChannelsManager) -> Result<()> {
+    async fn start_rtmp(
+        &mut self,
+        channel: &mut ChannelsManager,
+        relay_producer: RelayEventProducer,
+        stream_registry: Option<Arc<dyn StreamRegistry>>,
+    ) -> Result<()> {
         let rtmp_cfg = &self.cfg.rtmp;
 
         if let Some(rtmp_cfg_value) = rtmp_cfg {
@@ -84,6 +208,15 @@ impl Service {
             };
 
             channel.set_rtmp_gop_num(gop_num);
+
+            if let Some(policy) = rtmp_cfg_value.publish_conflict {
+                channel.set_publish_conflict_policy(policy);
+            }
+            if let Some(app_policies) = &rtmp_cfg_value.publish_conflict_apps {
+                for (app_name, policy) in app_policies {
+                    channel.set_app_publish_conflict_policy(app_name.clone(), *policy);
+                }
+            }
             let producer = channel.get_channel_event_producer();
 
             /*static push */
@@ -102,7 +235,7 @@ impl Service {
                     let mut push_client = PushClient::new(
                         address,
                         channel.get_client_event_consumer(),
-                        producer.clone(),
+                        relay_producer.clone(),
                     );
                     tokio::spawn(async move {
                         if let Err(err) = push_client.run().await {
@@ -113,29 +246,57 @@ impl Service {
                     channel.set_rtmp_push_enabled(true);
                 }
             }
-            /*static pull*/
-            if let Some(pull_cfg_value) = &rtmp_cfg_value.pull {
-                if pull_cfg_value.enabled {
-                    let address = format!(
+            /*static pull, and pull from the origin nodes located in cluster mode*/
+            let pull_cfg = rtmp_cfg_value.pull.as_ref().filter(|cfg| cfg.enabled);
+            if pull_cfg.is_some() || stream_registry.is_some() {
+                let address = if let Some(pull_cfg_value) = pull_cfg {
+                    format!(
                         "{ip}:{port}",
                         ip = pull_cfg_value.address,
                         port = pull_cfg_value.port
-                    );
-                    log::info!("start rtmp pull client from address: {}", address);
-                    let mut pull_client = PullClient::new(
-                        address,
-                        channel.get_client_event_consumer(),
-                        producer.clone(),
-                    );
-
-                    tokio::spawn(async move {
-                        if let Err(err) = pull_client.run().await {
-                            log::error!("pull client error {}\n", err);
+                    )
+                } else {
+                    String::new()
+                };
+                let mut pull_client = PullClient::new(
+                    address.clone(),
+                    channel.get_client_event_consumer(),
+                    producer.clone(),
+                );
+
+                if let Some(pull_cfg_value) = pull_cfg {
+                    match &pull_cfg_value.origins {
+                        Some(origins) if !origins.is_empty() => {
+                            log::info!("start rtmp pull client from origins: {:?}", origins);
+                            pull_client.set_origins(origins.clone());
                         }
-                    });
+                        _ => {
+                            if pull_cfg_value.address.is_empty() {
+                                log::error!("rtmp pull client has no address or origins");
+                            }
+                            log::info!("start rtmp pull client from address: {}", address);
+                        }
+                    }
+                    if let Some(idle_timeout) = pull_cfg_value.idle_timeout {
+                        pull_client.set_idle_timeout(Duration::from_secs(idle_timeout));
+                    }
+                } else {
+                    //only pull from the origin nodes located in the stream registry
+                    pull_client.set_origins(Vec::new());
+                }
 
-                    channel.set_rtmp_pull_enabled(true);
+                if let (Some(registry), Some(cluster_cfg)) = (stream_registry, &self.cfg.cluster) {
+                    log::info!("start rtmp pull client in cluster mode");
+                    pull_client.set_stream_registry(registry, cluster_cfg.node_address.clone());
                 }
+
+                tokio::spawn(async move {
+                    if let Err(err) = pull_client.run().await {
+                        log::error!("pull client error {}\n", err);
+                    }
+                });
+
+                channel.set_rtmp_pull_enabled(true);
             }
 
             let listen_port = rtmp_cfg_value.port;
@@ -152,6 +313,58 @@ impl Service {
         Ok(())
     }
 
+    /*In cluster mode the streams published to this node are registered into the
+    stream registry, which is an http registry server or an in-process one.*/
+    async fn start_cluster(
+        &mut self,
+        channel: &mut ChannelsManager,
+    ) -> Result<Option<Arc<dyn StreamRegistry>>> {
+        let cluster_cfg_value = match &self.cfg.cluster {
+            Some(cluster_cfg_value) if cluster_cfg_value.enabled => cluster_cfg_value,
+            _ => return Ok(None),
+        };
+
+        let memory_registry = MemoryRegistry::new();
+        let registry_secret = cluster_cfg_value.registry_secret.clone();
+        if let Some(port) = cluster_cfg_value.registry_server_port {
+            let server_registry = memory_registry.clone();
+            let address = cluster_cfg_value
+                .registry_server_address
+                .clone()
+                .unwrap_or_else(|| String::from("127.0.0.1"));
+            if registry_secret.is_none() && address != "127.0.0.1" && address != "::1" {
+                log::warn!(
+                    "the stream registry server listens on {} without a registry_secret, any host can register streams",
+                    address
+                );
+            }
+            let server_secret = registry_secret.clone();
+            tokio::spawn(async move {
+                api::run_registry_server(server_registry, address, port, server_secret).await;
+            });
+        }
+
+        let stream_registry: Arc<dyn StreamRegistry> =
+            if let Some(registry_url) = &cluster_cfg_value.registry_url {
+                log::info!("cluster mode with the stream registry: {}", registry_url);
+                let mut http_registry = HttpRegistry::new(registry_url.clone());
+                if let Some(secret) = registry_secret {
+                    http_registry.set_secret(secret);
+                }
+                Arc::new(http_registry)
+            } else {
+                log::info!("cluster mode with the in-process stream registry");
+                Arc::new(memory_registry)
+            };
+
+        channel.set_stream_registry(
+            stream_registry.clone(),
+            cluster_cfg_value.node_address.clone(),
+        );
+
+        Ok(Some(stream_registry))
+    }
+
     async fn start_httpflv(&mut self, channel: &mut ChannelsManager) -> Result<()> {
         let httpflv_cfg = &self.cfg.httpflv;
 
@@ -160,10 +373,21 @@ impl Service {
                 return Ok(());
             }
             let port = httpflv_cfg_value.port;
-            let event_producer = channel.get_channel_event_producer();
+            let mut options = HttpFlvServerOptions {
+                ingest: httpflv_cfg_value.ingest.unwrap_or(false),
+                ..Default::default()
+            };
+            if let Some(subscribe_timeout) = httpflv_cfg_value.subscribe_timeout {
+                options.subscribe_timeout = Duration::from_millis(subscribe_timeout);
+            }
+            let context = HttpFlvServerContext::new(channel.get_channel_event_producer(), options);
+            if self.is_http_enabled() {
+                self.http_routes.httpflv = Some(context);
+                return Ok(());
+            }
 
             tokio::spawn(async move {
-                if let Err(err) = httpflv_server::run(event_producer, port).await {
+                if let Err(err) = httpflv_server::run(port, context).await {
                     log::error!("httpflv server error: {}\n", err);
                 }
             });
@@ -180,10 +404,100 @@ impl Service {
                 return Ok(());
             }
 
+            let mut options = HlsOptions::default();
+            override_hls_options(&mut options, &hls_cfg_value.options);
+            let mut app_options_list = Vec::new();
+            for (app_name, app_cfg) in hls_cfg_value.apps.iter().flatten() {
+                let mut app_options = options.clone();
+                override_hls_options(&mut app_options, app_cfg);
+                app_options_list.push((app_name.clone(), app_options));
+            }
+
+            let store: HlsStoreRef = match hls_cfg_value.store {
+                Some(HlsStoreType::File) => {
+                    let root = hls_cfg_value
+                        .options
+                        .root
+                        .clone()
+                        .unwrap_or_else(|| String::from("."));
+                    let mut file_store = FileHlsStore::new(root);
+                    for (app_name, app_cfg) in hls_cfg_value.apps.iter().flatten() {
+                        if let Some(app_root) = &app_cfg.root {
+                            file_store.set_app_root(app_name.clone(), app_root.clone());
+                        }
+                    }
+                    Arc::new(file_store)
+                }
+                Some(HlsStoreType::Memory) | None => {
+                    let capacity = app_options_list
+                        .iter()
+                        .map(|(_, app_options)| app_options)
+                        .chain(std::iter::once(&options))
+                        .map(memory_store_capacity)
+                        .fold(1024, usize::max);
+                    Arc::new(MemoryHlsStore::new(capacity))
+                }
+            };
+
             let event_producer = channel.get_channel_event_producer();
             let cient_event_consumer = channel.get_client_event_consumer();
             let mut rtmp_event_processor =
-                RtmpEventProcessor::new(cient_event_consumer, event_producer);
+                RtmpEventProcessor::new(cient_event_consumer, event_producer, store.clone());
+
+            //the playlist requests start the on demand streams
+            let demand_event_sender = if app_options_list
+                .iter()
+                .map(|(_, app_options)| app_options)
+                .chain(std::iter::once(&options))
+                .any(|options| options.on_demand)
+            {
+                Some(rtmp_event_processor.get_demand_event_sender())
+            } else {
+                None
+            };
+            //the keys are served to anyone without the on_play hook
+            if app_options_list
+                .iter()
+                .map(|(_, app_options)| app_options)
+                .chain(std::iter::once(&options))
+                .any(|options| options.encryption != HlsEncryption::None)
+                && !self.is_play_authorized()
+            {
+                log::warn!(
+                    "!!! hls encryption is enabled but httpnotify.on_play is not configured, \
+                     the keys are served to any player without authorization !!!"
+                );
+            }
+            for (app_name, app_options) in app_options_list {
+                rtmp_event_processor.set_app_options(app_name, app_options);
+            }
+
+            let mut variant_sets = VariantSets::default();
+            if let Some(separator) = &hls_cfg_value.variant_separator {
+                variant_sets.set_separator(separator.clone());
+            }
+            for variant_cfg in hls_cfg_value.variants.iter().flatten() {
+                variant_sets.add_set(VariantSet {
+                    app_name: variant_cfg.app.clone(),
+                    name: variant_cfg.name.clone(),
+                    stream_names: variant_cfg.streams.clone(),
+                });
+            }
+            let master_playlist = if variant_sets.is_empty() {
+                None
+            } else {
+                let variant_sets = Arc::new(variant_sets);
+                rtmp_event_processor.set_variant_sets(variant_sets.clone());
+                Some(MasterPlaylistGenerator::new(
+                    variant_sets,
+                    channel.get_channel_event_producer(),
+                ))
+            };
+            //send the on_record_done notifications of the archives
+            if let Some(notifier) = self.create_notifier() {
+                rtmp_event_processor.set_notifier(notifier);
+            }
+            rtmp_event_processor.set_options(options);
 
             tokio::spawn(async move {
                 if let Err(err) = rtmp_event_processor.run().await {
@@ -191,16 +505,231 @@ impl Service {
                 }
             });
 
+            let session_timeout = hls_cfg_value
+                .session_timeout
+                .unwrap_or(DEFAULT_SESSION_TIMEOUT);
+            let mut session_manager = HlsSessionManager::new(
+                channel.get_channel_event_producer(),
+                Duration::from_secs(session_timeout),
+            );
+            if let Some(max_sessions_per_ip) = hls_cfg_value.max_sessions_per_ip {
+                session_manager.set_max_sessions_per_ip(max_sessions_per_ip);
+            }
+            let session_manager = Arc::new(session_manager);
+            tokio::spawn(session_manager.clone().run());
+
             let port = hls_cfg_value.port;
+            let mut server_options = HlsServerOptions::default();
+            if let Some(playlist_max_age) = hls_cfg_value.playlist_max_age {
+                server_options.playlist_max_age = playlist_max_age;
+            }
+            if let Some(segment_max_age) = hls_cfg_value.segment_max_age {
+                server_options.segment_max_age = segment_max_age;
+            }
+            if let Some(cors_origins) = &hls_cfg_value.cors_origins {
+                server_options.cors_origins = cors_origins.clone();
+            }
+            //authorize the key requests by the on_play hook
+            let notifier = self.create_notifier();
+            let context = HlsServerContext::new(
+                server_options,
+                store,
+                notifier,
+                master_playlist,
+                demand_event_sender,
+                Some(session_manager),
+            );
+
+            if self.is_http_enabled() {
+                self.http_routes.hls = Some(context);
+            } else {
+                tokio::spawn(async move {
+                    if let Err(err) = hls_server::run(port, context).await {
+                        log::error!("hls server error: {}\n", err);
+                    }
+                });
+            }
+            channel.set_hls_enabled(true);
+        }
+
+        Ok(())
+    }
+
+    async fn start_dash(&mut self, channel: &mut ChannelsManager) -> Result<()> {
+        let dash_cfg = &self.cfg.dash;
+
+        if let Some(dash_cfg_value) = dash_cfg {
+            if !dash_cfg_value.enabled {
+                return Ok(());
+            }
+
+            let store: HlsStoreRef = Arc::new(MemoryHlsStore::default());
+
+            let mut options = DashOptions::default();
+            if let Some(duration) = dash_cfg_value.duration {
+                options.duration = duration;
+            }
+            if let Some(window_size) = dash_cfg_value.window_size {
+                options.window_size = window_size;
+            }
+
+            let event_producer = channel.get_channel_event_producer();
+            let client_event_consumer = channel.get_client_event_consumer();
+            let mut dash_event_processor =
+                DashEventProcessor::new(client_event_consumer, event_producer, store.clone());
+            dash_event_processor.set_options(options);
 
             tokio::spawn(async move {
-                if let Err(err) = hls_server::run(port).await {
-                    log::error!("hls server error: {}\n", err);
+                if let Err(err) = dash_event_processor.run().await {
+                    log::error!("dash event processor error: {}\n", err);
                 }
             });
-            channel.set_hls_enabled(true);
+
+            let port = dash_cfg_value.port;
+
+            tokio::spawn(async move {
+                if let Err(err) = dash_server::run(port, store).await {
+                    log::error!("dash server error: {}\n", err);
+                }
+            });
+            channel.set_dash_enabled(true);
         }
 
         Ok(())
     }
+
+    /*record the published streams to flv files, the returned producer sends the
+    start/stop events of the http api*/
+    async fn start_record(
+        &mut self,
+        channel: &mut ChannelsManager,
+    ) -> Result<Option<RecordEventProducer>> {
+        let record_cfg_value = match &self.cfg.record {
+            Some(record_cfg_value) if record_cfg_value.enabled => record_cfg_value,
+            _ => return Ok(None),
+        };
+
+        let mut options = RecordOptions::default();
+        override_record_options(&mut options, &record_cfg_value.options);
+
+        let mut rtmp_event_processor = RecordEventProcessor::new(
+            channel.get_client_event_consumer(),
+            channel.get_channel_event_producer(),
+        );
+        for (app_name, app_cfg) in record_cfg_value.apps.iter().flatten() {
+            let mut app_options = options.clone();
+            override_record_options(&mut app_options, app_cfg);
+            rtmp_event_processor.set_app_options(app_name.clone(), app_options);
+        }
+        rtmp_event_processor.set_options(options);
+        //send the on_record_done notifications of the files
+        if let Some(notifier) = self.create_notifier() {
+            rtmp_event_processor.set_notifier(notifier);
+        }
+        let record_producer = rtmp_event_processor.get_record_event_producer();
+
+        tokio::spawn(async move {
+            if let Err(err) = rtmp_event_processor.run().await {
+                log::error!("record event processor error: {}\n", err);
+            }
+        });
+        channel.set_record_enabled(true);
+
+        Ok(Some(record_producer))
+    }
+}
+
+fn override_record_options(options: &mut RecordOptions, cfg: &RecordOptionsConfig) {
+    if let Some(auto_start) = cfg.auto_start {
+        options.auto_start = auto_start;
+    }
+    if let Some(root) = &cfg.root {
+        options.root = root.clone();
+    }
+    if let Some(file_name) = &cfg.file_name {
+        options.file_name = file_name.clone();
+    }
+    if let Some(max_duration) = cfg.max_duration {
+        options.max_duration = max_duration;
+    }
+    if let Some(max_size) = cfg.max_size {
+        options.max_size = max_size;
+    }
+}
+
+fn override_hls_options(options: &mut HlsOptions, cfg: &HlsOptionsConfig) {
+    if let Some(duration) = cfg.duration {
+        options.duration = duration;
+    }
+    if let Some(playlist_length) = cfg.playlist_length {
+        options.live_ts_count = playlist_length;
+    }
+    if let Some(deletion_delay) = cfg.deletion_delay {
+        options.deletion_delay = deletion_delay;
+    }
+    if let Some(segment_name) = &cfg.segment_name {
+        options.segment_name = segment_name.clone();
+    }
+    if let Some(low_latency) = cfg.low_latency {
+        options.low_latency = low_latency;
+    }
+    if let Some(part_duration) = cfg.part_duration {
+        options.part_duration = part_duration;
+    }
+    if let Some(container) = cfg.container {
+        options.container = match container {
+            HlsContainerType::Ts => HlsContainer::Ts,
+            HlsContainerType::Fmp4 => HlsContainer::Fmp4,
+        };
+    }
+    if let Some(dvr_window) = cfg.dvr_window {
+        options.dvr_window = dvr_window;
+    }
+    if let Some(archive) = cfg.archive {
+        options.archive = archive;
+    }
+    if let Some(encryption) = cfg.encryption {
+        options.encryption = match encryption {
+            HlsEncryptionType::None => HlsEncryption::None,
+            HlsEncryptionType::Aes128 => HlsEncryption::Aes128,
+            HlsEncryptionType::SampleAes => HlsEncryption::SampleAes,
+        };
+    }
+    if let Some(key_rotation) = cfg.key_rotation {
+        options.key_rotation = key_rotation;
+    }
+    if let Some(key_uri) = &cfg.key_uri {
+        options.key_uri = key_uri.clone();
+    }
+    if let Some(max_duration) = cfg.max_duration {
+        options.max_duration = max_duration;
+    }
+    if let Some(discontinuity_threshold) = cfg.discontinuity_threshold {
+        options.discontinuity_threshold = discontinuity_threshold;
+    }
+    if let Some(on_demand) = cfg.on_demand {
+        options.on_demand = on_demand;
+    }
+    if let Some(idle_timeout) = cfg.idle_timeout {
+        options.idle_timeout = idle_timeout;
+    }
+    if let Some(ad_markers) = cfg.ad_markers {
+        options.ad_markers = match ad_markers {
+            HlsAdMarkersType::Cue => HlsAdMarkers::Cue,
+            HlsAdMarkersType::DateRange => HlsAdMarkers::DateRange,
+        };
+    }
+    if let Some(timed_metadata) = cfg.timed_metadata {
+        options.timed_metadata = timed_metadata;
+    }
+}
+
+/*the segments count the memory store keeps for a stream, it must cover the DVR window*/
+fn memory_store_capacity(options: &HlsOptions) -> usize {
+    let mut capacity = (options.dvr_window as i64 / options.duration.max(1)) as usize;
+    if options.low_latency {
+        //the partial segments of a segment
+        capacity *= (options.duration * 1000 / options.part_duration.max(1)) as usize + 1;
+    }
+    capacity * 2
 }
@@ -280,6 +280,12 @@ impl FlvDemuxer {
                     data: body,
                 }));
             }
+            tag_type::SCRIPT_DATA_AMF => {
+                return Ok(Some(FlvData::MetaData {
+                    timestamp: dts,
+                    data: body,
+                }));
+            }
 
             _ => {}
         }
@@ -34,6 +34,18 @@ impl FlvMuxer {
         Ok(())
     }
 
+    //the header of a stream which may only have the audio or the video tags
+    pub fn write_flv_header_with_flags(
+        &mut self,
+        has_audio: bool,
+        has_video: bool,
+    ) -> Result<(), MuxerError> {
+        let mut header = FLV_HEADER;
+        header[4] = (has_audio as u8) << 2 | has_video as u8;
+        self.writer.write(&header)?;
+        Ok(())
+    }
+
     pub fn write_flv_tag_header(
         &mut self,
         tag_type: u8,
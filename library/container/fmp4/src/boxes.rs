@@ -0,0 +1,424 @@
+use {
+    super::{
+        define::{AudioCodec, TrackInfo, VideoCodec, OPUS_SAMPLE_RATE},
+        errors::Fmp4Error,
+    },
+    byteorder::BigEndian,
+    bytes::BytesMut,
+    bytesio::bytes_writer::BytesWriter,
+};
+
+/*
+ The boxes of the init segment and the media segments, reference: ISO/IEC 14496-12.
+
+ init segment:  ftyp | moov(mvhd, trak(tkhd, mdia(mdhd, hdlr, minf(xmhd, dinf, stbl))), mvex(trex))
+ media segment: moof(mfhd, traf(tfhd, tfdt, trun)) | mdat
+*/
+
+const UNITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+pub fn write_box(box_type: &[u8; 4], payload: &[u8]) -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write_u32::<BigEndian>(8 + payload.len() as u32)?;
+    writer.write(box_type)?;
+    writer.write(payload)?;
+    Ok(writer.extract_current_bytes())
+}
+
+pub fn write_full_box(
+    box_type: &[u8; 4],
+    version: u8,
+    flags: u32,
+    payload: &[u8],
+) -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write_u8(version)?;
+    writer.write_u24::<BigEndian>(flags)?;
+    writer.write(payload)?;
+    write_box(box_type, &writer.extract_current_bytes())
+}
+
+fn write_matrix(writer: &mut BytesWriter) -> Result<(), Fmp4Error> {
+    for value in UNITY_MATRIX {
+        writer.write_u32::<BigEndian>(value)?;
+    }
+    Ok(())
+}
+
+pub fn ftyp() -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+    //major brand and minor version
+    writer.write(b"iso6")?;
+    writer.write_u32::<BigEndian>(0)?;
+    //compatible brands
+    for brand in [b"iso6", b"cmfc", b"mp41"] {
+        writer.write(brand)?;
+    }
+    write_box(b"ftyp", &writer.extract_current_bytes())
+}
+
+fn mvhd(next_track_id: u32) -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+    //creation time, modification time, timescale and duration
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(1000)?;
+    writer.write_u32::<BigEndian>(0)?;
+    //rate 1.0 and volume 1.0
+    writer.write_u32::<BigEndian>(0x0001_0000)?;
+    writer.write_u16::<BigEndian>(0x0100)?;
+    writer.write(&[0; 10])?;
+    write_matrix(&mut writer)?;
+    //pre defined
+    writer.write(&[0; 24])?;
+    writer.write_u32::<BigEndian>(next_track_id)?;
+    write_full_box(b"mvhd", 0, 0, &writer.extract_current_bytes())
+}
+
+fn tkhd(track_id: u32, track: &TrackInfo) -> Result<BytesMut, Fmp4Error> {
+    let (volume, width, height) = match track {
+        TrackInfo::Video { width, height, .. } => (0, *width, *height),
+        TrackInfo::Audio { .. } => (0x0100, 0, 0),
+    };
+
+    let mut writer = BytesWriter::new();
+    //creation time, modification time, track id, reserved and duration
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(track_id)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    //reserved, layer and alternate group
+    writer.write(&[0; 12])?;
+    writer.write_u16::<BigEndian>(volume)?;
+    writer.write_u16::<BigEndian>(0)?;
+    write_matrix(&mut writer)?;
+    writer.write_u32::<BigEndian>(width << 16)?;
+    writer.write_u32::<BigEndian>(height << 16)?;
+    //track enabled and in movie
+    write_full_box(b"tkhd", 0, 0x03, &writer.extract_current_bytes())
+}
+
+fn mdhd(timescale: u32) -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(timescale)?;
+    writer.write_u32::<BigEndian>(0)?;
+    //language: und
+    writer.write_u16::<BigEndian>(0x55c4)?;
+    writer.write_u16::<BigEndian>(0)?;
+    write_full_box(b"mdhd", 0, 0, &writer.extract_current_bytes())
+}
+
+fn hdlr(track: &TrackInfo) -> Result<BytesMut, Fmp4Error> {
+    let (handler_type, name): (&[u8; 4], &[u8]) = match track {
+        TrackInfo::Video { .. } => (b"vide", b"VideoHandler\0"),
+        TrackInfo::Audio { .. } => (b"soun", b"SoundHandler\0"),
+    };
+
+    let mut writer = BytesWriter::new();
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write(handler_type)?;
+    writer.write(&[0; 12])?;
+    writer.write(name)?;
+    write_full_box(b"hdlr", 0, 0, &writer.extract_current_bytes())
+}
+
+fn dinf() -> Result<BytesMut, Fmp4Error> {
+    //the media data is in the same file
+    let url = write_full_box(b"url ", 0, 0x01, &[])?;
+
+    let mut writer = BytesWriter::new();
+    writer.write_u32::<BigEndian>(1)?;
+    writer.write(&url[..])?;
+    let dref = write_full_box(b"dref", 0, 0, &writer.extract_current_bytes())?;
+
+    write_box(b"dinf", &dref[..])
+}
+
+fn visual_sample_entry(
+    box_type: &[u8; 4],
+    width: u32,
+    height: u32,
+    config_box: &[u8],
+) -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+    //reserved and data reference index
+    writer.write(&[0; 6])?;
+    writer.write_u16::<BigEndian>(1)?;
+    //pre defined and reserved
+    writer.write(&[0; 16])?;
+    writer.write_u16::<BigEndian>(width as u16)?;
+    writer.write_u16::<BigEndian>(height as u16)?;
+    //72 dpi
+    writer.write_u32::<BigEndian>(0x0048_0000)?;
+    writer.write_u32::<BigEndian>(0x0048_0000)?;
+    writer.write_u32::<BigEndian>(0)?;
+    //frame count
+    writer.write_u16::<BigEndian>(1)?;
+    //compressor name
+    writer.write(&[0; 32])?;
+    //depth and pre defined
+    writer.write_u16::<BigEndian>(0x0018)?;
+    writer.write_u16::<BigEndian>(0xffff)?;
+    writer.write(config_box)?;
+    write_box(box_type, &writer.extract_current_bytes())
+}
+
+fn audio_sample_entry(
+    box_type: &[u8; 4],
+    sample_rate: u32,
+    channels: u16,
+    config_box: &[u8],
+) -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write(&[0; 6])?;
+    writer.write_u16::<BigEndian>(1)?;
+    writer.write(&[0; 8])?;
+    writer.write_u16::<BigEndian>(channels)?;
+    //sample size
+    writer.write_u16::<BigEndian>(16)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(sample_rate << 16)?;
+    writer.write(config_box)?;
+    write_box(box_type, &writer.extract_current_bytes())
+}
+
+/*the size of a descriptor in the expandable format of ISO/IEC 14496-1*/
+fn write_descriptor(writer: &mut BytesWriter, tag: u8, payload: &[u8]) -> Result<(), Fmp4Error> {
+    writer.write_u8(tag)?;
+    let size = payload.len() as u32;
+    for shift in [21, 14, 7] {
+        writer.write_u8(((size >> shift) & 0x7f) as u8 | 0x80)?;
+    }
+    writer.write_u8((size & 0x7f) as u8)?;
+    writer.write(payload)?;
+    Ok(())
+}
+
+fn esds(track_id: u32, audio_specific_config: &[u8]) -> Result<BytesMut, Fmp4Error> {
+    let mut decoder_config = BytesWriter::new();
+    //object type indication: Audio ISO/IEC 14496-3
+    decoder_config.write_u8(0x40)?;
+    //stream type: audio stream
+    decoder_config.write_u8(0x15)?;
+    //buffer size, max bitrate and average bitrate
+    decoder_config.write_u24::<BigEndian>(0)?;
+    decoder_config.write_u32::<BigEndian>(0)?;
+    decoder_config.write_u32::<BigEndian>(0)?;
+    write_descriptor(&mut decoder_config, 0x05, audio_specific_config)?;
+
+    let mut es = BytesWriter::new();
+    es.write_u16::<BigEndian>(track_id as u16)?;
+    es.write_u8(0)?;
+    write_descriptor(&mut es, 0x04, &decoder_config.extract_current_bytes())?;
+    //SL config descriptor
+    write_descriptor(&mut es, 0x06, &[0x02])?;
+
+    let mut writer = BytesWriter::new();
+    write_descriptor(&mut writer, 0x03, &es.extract_current_bytes())?;
+    write_full_box(b"esds", 0, 0, &writer.extract_current_bytes())
+}
+
+/*Opus in ISO BMFF, the pre skip is read from the OpusHead packet if it exists*/
+fn dops(channels: u16, opus_head: &[u8]) -> Result<BytesMut, Fmp4Error> {
+    let pre_skip = if opus_head.len() >= 12 && opus_head.starts_with(b"OpusHead") {
+        u16::from_le_bytes([opus_head[10], opus_head[11]])
+    } else {
+        0
+    };
+
+    let mut writer = BytesWriter::new();
+    writer.write_u8(0)?;
+    writer.write_u8(channels as u8)?;
+    writer.write_u16::<BigEndian>(pre_skip)?;
+    writer.write_u32::<BigEndian>(OPUS_SAMPLE_RATE)?;
+    //output gain and channel mapping family
+    writer.write_u16::<BigEndian>(0)?;
+    writer.write_u8(0)?;
+    write_box(b"dOps", &writer.extract_current_bytes())
+}
+
+fn stsd(track_id: u32, track: &TrackInfo) -> Result<BytesMut, Fmp4Error> {
+    let sample_entry = match track {
+        TrackInfo::Video {
+            codec,
+            width,
+            height,
+            decoder_config,
+        } => {
+            let (entry_type, config_type) = match codec {
+                VideoCodec::H264 => (b"avc1", b"avcC"),
+                VideoCodec::H265 => (b"hvc1", b"hvcC"),
+            };
+            let config_box = write_box(config_type, &decoder_config[..])?;
+            visual_sample_entry(entry_type, *width, *height, &config_box[..])?
+        }
+        TrackInfo::Audio {
+            codec: AudioCodec::Aac,
+            sample_rate,
+            channels,
+            decoder_config,
+        } => {
+            let config_box = esds(track_id, &decoder_config[..])?;
+            audio_sample_entry(b"mp4a", *sample_rate, *channels, &config_box[..])?
+        }
+        TrackInfo::Audio {
+            codec: AudioCodec::Opus,
+            channels,
+            decoder_config,
+            ..
+        } => {
+            let config_box = dops(*channels, &decoder_config[..])?;
+            audio_sample_entry(b"Opus", OPUS_SAMPLE_RATE, *channels, &config_box[..])?
+        }
+    };
+
+    let mut writer = BytesWriter::new();
+    writer.write_u32::<BigEndian>(1)?;
+    writer.write(&sample_entry[..])?;
+    write_full_box(b"stsd", 0, 0, &writer.extract_current_bytes())
+}
+
+fn stbl(track_id: u32, track: &TrackInfo) -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write(&stsd(track_id, track)?[..])?;
+    //the samples are in the fragments, so the sample tables are empty
+    writer.write(&write_full_box(b"stts", 0, 0, &[0; 4])?[..])?;
+    writer.write(&write_full_box(b"stsc", 0, 0, &[0; 4])?[..])?;
+    writer.write(&write_full_box(b"stsz", 0, 0, &[0; 8])?[..])?;
+    writer.write(&write_full_box(b"stco", 0, 0, &[0; 4])?[..])?;
+    write_box(b"stbl", &writer.extract_current_bytes())
+}
+
+fn minf(track_id: u32, track: &TrackInfo) -> Result<BytesMut, Fmp4Error> {
+    let media_header = match track {
+        TrackInfo::Video { .. } => write_full_box(b"vmhd", 0, 0x01, &[0; 8])?,
+        TrackInfo::Audio { .. } => write_full_box(b"smhd", 0, 0, &[0; 4])?,
+    };
+
+    let mut writer = BytesWriter::new();
+    writer.write(&media_header[..])?;
+    writer.write(&dinf()?[..])?;
+    writer.write(&stbl(track_id, track)?[..])?;
+    write_box(b"minf", &writer.extract_current_bytes())
+}
+
+fn trak(track_id: u32, track: &TrackInfo) -> Result<BytesMut, Fmp4Error> {
+    let mut mdia = BytesWriter::new();
+    mdia.write(&mdhd(track.timescale())?[..])?;
+    mdia.write(&hdlr(track)?[..])?;
+    mdia.write(&minf(track_id, track)?[..])?;
+
+    let mut writer = BytesWriter::new();
+    writer.write(&tkhd(track_id, track)?[..])?;
+    writer.write(&write_box(b"mdia", &mdia.extract_current_bytes())?[..])?;
+    write_box(b"trak", &writer.extract_current_bytes())
+}
+
+fn trex(track_id: u32) -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write_u32::<BigEndian>(track_id)?;
+    //default sample description index, duration, size and flags
+    writer.write_u32::<BigEndian>(1)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    writer.write_u32::<BigEndian>(0)?;
+    write_full_box(b"trex", 0, 0, &writer.extract_current_bytes())
+}
+
+/*the track ids start from 1 in the order of the tracks*/
+pub fn moov(tracks: &[TrackInfo]) -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+    writer.write(&mvhd(tracks.len() as u32 + 1)?[..])?;
+
+    let mut mvex = BytesWriter::new();
+    for (index, track) in tracks.iter().enumerate() {
+        let track_id = index as u32 + 1;
+        writer.write(&trak(track_id, track)?[..])?;
+        mvex.write(&trex(track_id)?[..])?;
+    }
+    writer.write(&write_box(b"mvex", &mvex.extract_current_bytes())?[..])?;
+
+    write_box(b"moov", &writer.extract_current_bytes())
+}
+
+/*a sample in the trun box, the times are in the track timescale*/
+pub struct TrunSample {
+    pub duration: u32,
+    pub size: u32,
+    pub flags: u32,
+    pub cts: i32,
+}
+
+/*the samples of a track in a fragment*/
+pub struct TrafInfo {
+    pub track_id: u32,
+    pub base_media_decode_time: u64,
+    pub samples: Vec<TrunSample>,
+}
+
+impl TrafInfo {
+    //traf(8) + tfhd(16) + tfdt(20) + trun(20 + 16 per sample)
+    fn size(&self) -> usize {
+        64 + self.samples.len() * 16
+    }
+}
+
+fn traf(traf_info: &TrafInfo, data_offset: u32) -> Result<BytesMut, Fmp4Error> {
+    let mut writer = BytesWriter::new();
+
+    //default-base-is-moof
+    let mut tfhd = BytesWriter::new();
+    tfhd.write_u32::<BigEndian>(traf_info.track_id)?;
+    writer.write(&write_full_box(b"tfhd", 0, 0x02_0000, &tfhd.extract_current_bytes())?[..])?;
+
+    let mut tfdt = BytesWriter::new();
+    tfdt.write_u32::<BigEndian>((traf_info.base_media_decode_time >> 32) as u32)?;
+    tfdt.write_u32::<BigEndian>(traf_info.base_media_decode_time as u32)?;
+    writer.write(&write_full_box(b"tfdt", 1, 0, &tfdt.extract_current_bytes())?[..])?;
+
+    let mut trun = BytesWriter::new();
+    trun.write_u32::<BigEndian>(traf_info.samples.len() as u32)?;
+    trun.write_u32::<BigEndian>(data_offset)?;
+    for sample in &traf_info.samples {
+        trun.write_u32::<BigEndian>(sample.duration)?;
+        trun.write_u32::<BigEndian>(sample.size)?;
+        trun.write_u32::<BigEndian>(sample.flags)?;
+        trun.write_u32::<BigEndian>(sample.cts as u32)?;
+    }
+    //data offset, sample duration, size, flags and composition time offset are present,
+    //version 1 for the signed composition time offsets
+    writer.write(&write_full_box(b"trun", 1, 0x0f01, &trun.extract_current_bytes())?[..])?;
+
+    write_box(b"traf", &writer.extract_current_bytes())
+}
+
+/*the data of the trafs are written into the mdat in the same order*/
+pub fn moof(
+    sequence_number: u32,
+    trafs: &[TrafInfo],
+    data_sizes: &[usize],
+) -> Result<BytesMut, Fmp4Error> {
+    //moof(8) + mfhd(16)
+    let moof_size = 24 + trafs.iter().map(|traf| traf.size()).sum::<usize>();
+
+    let mut writer = BytesWriter::new();
+    let mut mfhd = BytesWriter::new();
+    mfhd.write_u32::<BigEndian>(sequence_number)?;
+    writer.write(&write_full_box(b"mfhd", 0, 0, &mfhd.extract_current_bytes())?[..])?;
+
+    //the data offset is relative to the beginning of the moof, mdat header is 8 bytes
+    let mut data_offset = moof_size + 8;
+    for (traf_info, data_size) in trafs.iter().zip(data_sizes) {
+        writer.write(&traf(traf_info, data_offset as u32)?[..])?;
+        data_offset += data_size;
+    }
+
+    write_box(b"moof", &writer.extract_current_bytes())
+}
+
+pub fn mdat(data: &[u8]) -> Result<BytesMut, Fmp4Error> {
+    write_box(b"mdat", data)
+}
@@ -0,0 +1,198 @@
+use bytes::BytesMut;
+
+pub const VIDEO_TIMESCALE: u32 = 90000;
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+//the samples count of an AAC frame
+pub const AAC_FRAME_SAMPLES: u32 = 1024;
+//the samples count of a 20ms Opus frame in 48KHz
+pub const OPUS_FRAME_SAMPLES: u32 = 960;
+
+pub mod sample_flags {
+    //sample_depends_on = 2, the sample does not depend on others
+    pub const SYNC_SAMPLE: u32 = 0x0200_0000;
+    //sample_depends_on = 1 and sample_is_non_sync_sample = 1
+    pub const NON_SYNC_SAMPLE: u32 = 0x0101_0000;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    H264,
+    H265,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioCodec {
+    Aac,
+    Opus,
+}
+
+#[derive(Debug, Clone)]
+pub enum TrackInfo {
+    Video {
+        codec: VideoCodec,
+        width: u32,
+        height: u32,
+        /*AVCDecoderConfigurationRecord for H264 and HEVCDecoderConfigurationRecord for H265,
+        they are the payloads of the flv video sequence headers*/
+        decoder_config: BytesMut,
+    },
+    Audio {
+        codec: AudioCodec,
+        sample_rate: u32,
+        channels: u16,
+        /*AudioSpecificConfig for AAC, the OpusHead packet(optional) for Opus*/
+        decoder_config: BytesMut,
+    },
+}
+
+impl TrackInfo {
+    pub fn timescale(&self) -> u32 {
+        match self {
+            TrackInfo::Video { .. } => VIDEO_TIMESCALE,
+            TrackInfo::Audio {
+                codec: AudioCodec::Opus,
+                ..
+            } => OPUS_SAMPLE_RATE,
+            TrackInfo::Audio { sample_rate, .. } => *sample_rate,
+        }
+    }
+
+    /*the duration of an audio frame in the track timescale*/
+    pub fn frame_duration(&self) -> Option<u32> {
+        match self {
+            TrackInfo::Video { .. } => None,
+            TrackInfo::Audio {
+                codec: AudioCodec::Aac,
+                ..
+            } => Some(AAC_FRAME_SAMPLES),
+            TrackInfo::Audio {
+                codec: AudioCodec::Opus,
+                ..
+            } => Some(OPUS_FRAME_SAMPLES),
+        }
+    }
+
+    /*the RFC 6381 codecs parameter used by the DASH manifests and the HLS master playlists,
+    e.g. avc1.64001f, hvc1.1.6.L93.B0 and mp4a.40.2*/
+    pub fn codec_string(&self) -> String {
+        match self {
+            TrackInfo::Video {
+                codec: VideoCodec::H264,
+                decoder_config,
+                ..
+            } => match decoder_config.get(1..4) {
+                //AVCProfileIndication, profile_compatibility and AVCLevelIndication
+                Some(profile) => format!(
+                    "avc1.{:02x}{:02x}{:02x}",
+                    profile[0], profile[1], profile[2]
+                ),
+                None => String::from("avc1"),
+            },
+            TrackInfo::Video {
+                codec: VideoCodec::H265,
+                decoder_config,
+                ..
+            } => match decoder_config.get(1..13) {
+                Some(profile) => hevc_codec_string(profile),
+                None => String::from("hvc1"),
+            },
+            TrackInfo::Audio {
+                codec: AudioCodec::Aac,
+                decoder_config,
+                ..
+            } => {
+                //the audio object type is the first 5 bits of the AudioSpecificConfig
+                let object_type = match decoder_config.first() {
+                    Some(first) if *first >> 3 > 0 => *first >> 3,
+                    _ => 2,
+                };
+                format!("mp4a.40.{object_type}")
+            }
+            TrackInfo::Audio {
+                codec: AudioCodec::Opus,
+                ..
+            } => String::from("opus"),
+        }
+    }
+}
+
+/*ISO/IEC 14496-15 Annex E, the profile is the 12 bytes after the configurationVersion
+of the HEVCDecoderConfigurationRecord*/
+fn hevc_codec_string(profile: &[u8]) -> String {
+    let profile_space = ["", "A", "B", "C"][(profile[0] >> 6) as usize];
+    let tier = if (profile[0] >> 5) & 0x01 > 0 {
+        'H'
+    } else {
+        'L'
+    };
+    let profile_idc = profile[0] & 0x1f;
+    //the compatibility flags are written in the reverse bit order
+    let compatibility_flags =
+        u32::from_be_bytes([profile[1], profile[2], profile[3], profile[4]]).reverse_bits();
+    let level_idc = profile[11];
+
+    let mut codec =
+        format!("hvc1.{profile_space}{profile_idc}.{compatibility_flags:x}.{tier}{level_idc}");
+    //the trailing zero bytes of the constraint flags are omitted
+    let constraint_flags = &profile[5..11];
+    let count = constraint_flags
+        .iter()
+        .rposition(|flag| *flag != 0)
+        .map_or(0, |pos| pos + 1);
+    for flag in &constraint_flags[..count] {
+        codec.push_str(&format!(".{flag:x}"));
+    }
+
+    codec
+}
+
+/*a video frame or an audio frame, the timestamps are in milliseconds*/
+#[derive(Debug, Clone)]
+pub struct Fmp4Sample {
+    pub dts: i64,
+    //pts - dts
+    pub cts: i64,
+    pub is_key: bool,
+    /*the length-prefixed NALUs for the video, the raw frame for the audio*/
+    pub data: BytesMut,
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{AudioCodec, TrackInfo, VideoCodec},
+        bytes::BytesMut,
+    };
+
+    #[test]
+    fn test_codec_string() {
+        let avc = TrackInfo::Video {
+            codec: VideoCodec::H264,
+            width: 1280,
+            height: 720,
+            decoder_config: BytesMut::from(&[0x01, 0x64, 0x00, 0x1f, 0xff][..]),
+        };
+        assert_eq!(avc.codec_string(), "avc1.64001f");
+
+        let hevc = TrackInfo::Video {
+            codec: VideoCodec::H265,
+            width: 1920,
+            height: 1080,
+            decoder_config: BytesMut::from(
+                &[
+                    0x01, 0x01, 0x60, 0x00, 0x00, 0x00, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x78,
+                    0xf0,
+                ][..],
+            ),
+        };
+        assert_eq!(hevc.codec_string(), "hvc1.1.6.L120.90");
+
+        let aac = TrackInfo::Audio {
+            codec: AudioCodec::Aac,
+            sample_rate: 44100,
+            channels: 2,
+            decoder_config: BytesMut::from(&[0x12, 0x10][..]),
+        };
+        assert_eq!(aac.codec_string(), "mp4a.40.2");
+    }
+}
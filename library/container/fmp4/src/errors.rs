@@ -0,0 +1,73 @@
+use {
+    bytesio::{
+        bits_errors::BitError,
+        bytes_errors::{BytesReadError, BytesWriteError},
+    },
+    failure::{Backtrace, Fail},
+    std::fmt,
+};
+
+#[derive(Debug, Fail)]
+pub enum Fmp4ErrorValue {
+    #[fail(display = "bytes read error\n")]
+    BytesReadError(BytesReadError),
+
+    #[fail(display = "bytes write error\n")]
+    BytesWriteError(BytesWriteError),
+
+    #[fail(display = "bits read error\n")]
+    BitError(BitError),
+
+    #[fail(display = "track not found\n")]
+    TrackNotFound,
+
+    #[fail(display = "no track is added\n")]
+    NoTrack,
+
+    #[fail(display = "sps not found\n")]
+    SpsNotFound,
+}
+#[derive(Debug)]
+pub struct Fmp4Error {
+    pub value: Fmp4ErrorValue,
+}
+
+impl From<BytesReadError> for Fmp4Error {
+    fn from(error: BytesReadError) -> Self {
+        Fmp4Error {
+            value: Fmp4ErrorValue::BytesReadError(error),
+        }
+    }
+}
+
+impl From<BytesWriteError> for Fmp4Error {
+    fn from(error: BytesWriteError) -> Self {
+        Fmp4Error {
+            value: Fmp4ErrorValue::BytesWriteError(error),
+        }
+    }
+}
+
+impl From<BitError> for Fmp4Error {
+    fn from(error: BitError) -> Self {
+        Fmp4Error {
+            value: Fmp4ErrorValue::BitError(error),
+        }
+    }
+}
+
+impl fmt::Display for Fmp4Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl Fail for Fmp4Error {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.value.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.value.backtrace()
+    }
+}
@@ -0,0 +1,150 @@
+use {
+    super::errors::{Fmp4Error, Fmp4ErrorValue},
+    byteorder::BigEndian,
+    bytes::BytesMut,
+    bytesio::{bits_reader::BitsReader, bytes_reader::BytesReader},
+};
+
+const HEVC_NAL_SPS: u8 = 33;
+
+//ue(v) Exp-Golomb code
+fn read_uev(bits_reader: &mut BitsReader) -> Result<u32, Fmp4Error> {
+    let mut leading_zero_bits: usize = 0;
+    while bits_reader.read_bit()? == 0 {
+        leading_zero_bits += 1;
+    }
+    let code_num = (1 << leading_zero_bits) - 1 + bits_reader.read_n_bits(leading_zero_bits)?;
+    Ok(code_num as u32)
+}
+
+fn skip_bits(bits_reader: &mut BitsReader, mut count: usize) -> Result<(), Fmp4Error> {
+    while count > 0 {
+        let n = count.min(32);
+        bits_reader.read_n_bits(n)?;
+        count -= n;
+    }
+    Ok(())
+}
+
+/*remove the emulation prevention bytes(00 00 03 -> 00 00)*/
+fn nal_to_rbsp(nal: &[u8]) -> BytesMut {
+    let mut rbsp = BytesMut::with_capacity(nal.len());
+    let mut zero_count = 0;
+    for byte in nal {
+        if zero_count >= 2 && *byte == 0x03 {
+            zero_count = 0;
+            continue;
+        }
+        zero_count = if *byte == 0 { zero_count + 1 } else { 0 };
+        rbsp.extend_from_slice(&[*byte]);
+    }
+    rbsp
+}
+
+/*get the SPS NAL unit from a HEVCDecoderConfigurationRecord(ISO/IEC 14496-15)*/
+fn get_sps(hvcc: &[u8]) -> Result<BytesMut, Fmp4Error> {
+    let mut bytes_reader = BytesReader::new(BytesMut::from(hvcc));
+    //the fixed fields before numOfArrays
+    bytes_reader.read_bytes(22)?;
+
+    let num_of_arrays = bytes_reader.read_u8()?;
+    for _ in 0..num_of_arrays {
+        let nal_unit_type = bytes_reader.read_u8()? & 0x3f;
+        let num_nalus = bytes_reader.read_u16::<BigEndian>()?;
+        for _ in 0..num_nalus {
+            let nal_unit_length = bytes_reader.read_u16::<BigEndian>()?;
+            let nal_unit = bytes_reader.read_bytes(nal_unit_length as usize)?;
+            if nal_unit_type == HEVC_NAL_SPS {
+                return Ok(nal_unit);
+            }
+        }
+    }
+
+    Err(Fmp4Error {
+        value: Fmp4ErrorValue::SpsNotFound,
+    })
+}
+
+/*parse the video resolution(width, height) from a HEVCDecoderConfigurationRecord,
+reference: ITU-T H.265 7.3.2.2 Sequence parameter set RBSP syntax*/
+pub fn get_resolution(hvcc: &[u8]) -> Result<(u32, u32), Fmp4Error> {
+    let sps = get_sps(hvcc)?;
+    //skip the 2 bytes NAL unit header
+    let rbsp = nal_to_rbsp(&sps[2.min(sps.len())..]);
+    let mut bits_reader = BitsReader::new(BytesReader::new(rbsp));
+
+    //sps_video_parameter_set_id
+    skip_bits(&mut bits_reader, 4)?;
+    let max_sub_layers_minus1 = bits_reader.read_n_bits(3)? as usize;
+    //sps_temporal_id_nesting_flag
+    skip_bits(&mut bits_reader, 1)?;
+
+    /*profile_tier_level: general profile(88 bits) and general level(8 bits)*/
+    skip_bits(&mut bits_reader, 96)?;
+    let mut sub_layer_flags = Vec::new();
+    for _ in 0..max_sub_layers_minus1 {
+        let profile_present = bits_reader.read_bit()?;
+        let level_present = bits_reader.read_bit()?;
+        sub_layer_flags.push((profile_present, level_present));
+    }
+    if max_sub_layers_minus1 > 0 {
+        skip_bits(&mut bits_reader, (8 - max_sub_layers_minus1) * 2)?;
+    }
+    for (profile_present, level_present) in sub_layer_flags {
+        if profile_present > 0 {
+            skip_bits(&mut bits_reader, 88)?;
+        }
+        if level_present > 0 {
+            skip_bits(&mut bits_reader, 8)?;
+        }
+    }
+
+    //sps_seq_parameter_set_id
+    read_uev(&mut bits_reader)?;
+    let chroma_format_idc = read_uev(&mut bits_reader)?;
+    if chroma_format_idc == 3 {
+        //separate_colour_plane_flag
+        skip_bits(&mut bits_reader, 1)?;
+    }
+    let mut width = read_uev(&mut bits_reader)?;
+    let mut height = read_uev(&mut bits_reader)?;
+
+    let conformance_window_flag = bits_reader.read_bit()?;
+    if conformance_window_flag > 0 {
+        let left = read_uev(&mut bits_reader)?;
+        let right = read_uev(&mut bits_reader)?;
+        let top = read_uev(&mut bits_reader)?;
+        let bottom = read_uev(&mut bits_reader)?;
+
+        let sub_width = if chroma_format_idc == 1 || chroma_format_idc == 2 {
+            2
+        } else {
+            1
+        };
+        let sub_height = if chroma_format_idc == 1 { 2 } else { 1 };
+        width = width.saturating_sub(sub_width * (left + right));
+        height = height.saturating_sub(sub_height * (top + bottom));
+    }
+
+    Ok((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_resolution;
+
+    #[test]
+    fn test_get_resolution() {
+        //the hvcC of a 1920x1080 stream encoded by x265
+        let hvcc: [u8; 23 + 3 + 2 + 42] = [
+            0x01, 0x01, 0x60, 0x00, 0x00, 0x00, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x78, 0xf0,
+            0x00, 0xfc, 0xfd, 0xf8, 0xf8, 0x00, 0x00, 0x0f, 0x01, //
+            0xa1, 0x00, 0x01, //
+            0x00, 0x2a, //
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x78, 0xa0, 0x03, 0xc0, 0x80, 0x10, 0xe5, 0x96, 0x66, 0x69, 0x24,
+            0xca, 0xe0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03, 0x01, 0xe0, 0x80,
+        ];
+        assert_eq!(get_resolution(&hvcc).unwrap(), (1920, 1080));
+    }
+}
@@ -0,0 +1,5 @@
+pub mod boxes;
+pub mod define;
+pub mod errors;
+pub mod hevc;
+pub mod muxer;
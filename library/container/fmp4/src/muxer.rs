@@ -0,0 +1,262 @@
+use {
+    super::{
+        boxes::{self, TrafInfo, TrunSample},
+        define::{sample_flags, Fmp4Sample, TrackInfo},
+        errors::{Fmp4Error, Fmp4ErrorValue},
+    },
+    bytes::BytesMut,
+};
+
+//used as the duration of the last video sample if it can not be calculated, 40ms
+const DEFAULT_VIDEO_SAMPLE_DURATION: u32 = 3600;
+
+fn to_timescale(timestamp: i64, timescale: u32) -> i64 {
+    timestamp * timescale as i64 / 1000
+}
+
+/*Mux the samples into the fragmented mp4 format, the init segment describes the tracks
+and each media segment(fragment) contains the samples written since the last one.*/
+pub struct Fmp4Muxer {
+    tracks: Vec<TrackInfo>,
+    samples: Vec<Vec<Fmp4Sample>>,
+    last_durations: Vec<u32>,
+    sequence_number: u32,
+}
+
+impl Default for Fmp4Muxer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fmp4Muxer {
+    pub fn new() -> Self {
+        Self {
+            tracks: Vec::new(),
+            samples: Vec::new(),
+            last_durations: Vec::new(),
+            sequence_number: 0,
+        }
+    }
+
+    /*return the track id*/
+    pub fn add_track(&mut self, track: TrackInfo) -> u32 {
+        let default_duration = match &track {
+            TrackInfo::Video { .. } => DEFAULT_VIDEO_SAMPLE_DURATION,
+            TrackInfo::Audio { .. } => track.frame_duration().unwrap_or_default(),
+        };
+
+        self.tracks.push(track);
+        self.samples.push(Vec::new());
+        self.last_durations.push(default_duration);
+        self.tracks.len() as u32
+    }
+
+    pub fn get_tracks(&self) -> &[TrackInfo] {
+        &self.tracks
+    }
+
+    pub fn write_init_segment(&self) -> Result<BytesMut, Fmp4Error> {
+        if self.tracks.is_empty() {
+            return Err(Fmp4Error {
+                value: Fmp4ErrorValue::NoTrack,
+            });
+        }
+
+        let mut data = boxes::ftyp()?;
+        data.extend_from_slice(&boxes::moov(&self.tracks)?[..]);
+        Ok(data)
+    }
+
+    pub fn write_sample(&mut self, track_id: u32, sample: Fmp4Sample) -> Result<(), Fmp4Error> {
+        match self.samples.get_mut((track_id as usize).wrapping_sub(1)) {
+            Some(samples) => {
+                samples.push(sample);
+                Ok(())
+            }
+            None => Err(Fmp4Error {
+                value: Fmp4ErrorValue::TrackNotFound,
+            }),
+        }
+    }
+
+    /*Write the cached samples into a fragment(moof+mdat), end_dts is the dts in milliseconds
+    where the fragment ends, it is used to calculate the duration of the last video sample.
+    An empty buffer is returned if there are no samples.*/
+    pub fn write_media_segment(&mut self, end_dts: i64) -> Result<BytesMut, Fmp4Error> {
+        let mut trafs = Vec::new();
+        let mut data_sizes = Vec::new();
+        let mut mdat_data = BytesMut::new();
+
+        for (index, track) in self.tracks.iter().enumerate() {
+            let samples = std::mem::take(&mut self.samples[index]);
+            if samples.is_empty() {
+                continue;
+            }
+
+            let timescale = track.timescale();
+            let mut trun_samples = Vec::new();
+            let mut data_size = 0;
+
+            for (sample_index, sample) in samples.iter().enumerate() {
+                let dts = to_timescale(sample.dts, timescale);
+                let next_dts = match samples.get(sample_index + 1) {
+                    Some(next_sample) => Some(to_timescale(next_sample.dts, timescale)),
+                    None if track.frame_duration().is_none() => {
+                        Some(to_timescale(end_dts, timescale))
+                    }
+                    None => None,
+                };
+
+                let duration = match next_dts {
+                    Some(next_dts) if next_dts > dts => (next_dts - dts) as u32,
+                    _ => track.frame_duration().unwrap_or(self.last_durations[index]),
+                };
+                self.last_durations[index] = duration;
+
+                let flags = if sample.is_key || track.frame_duration().is_some() {
+                    sample_flags::SYNC_SAMPLE
+                } else {
+                    sample_flags::NON_SYNC_SAMPLE
+                };
+
+                trun_samples.push(TrunSample {
+                    duration,
+                    size: sample.data.len() as u32,
+                    flags,
+                    cts: to_timescale(sample.cts, timescale) as i32,
+                });
+                data_size += sample.data.len();
+                mdat_data.extend_from_slice(&sample.data[..]);
+            }
+
+            trafs.push(TrafInfo {
+                track_id: index as u32 + 1,
+                base_media_decode_time: to_timescale(samples[0].dts, timescale).max(0) as u64,
+                samples: trun_samples,
+            });
+            data_sizes.push(data_size);
+        }
+
+        if trafs.is_empty() {
+            return Ok(BytesMut::new());
+        }
+
+        self.sequence_number += 1;
+        let mut data = boxes::moof(self.sequence_number, &trafs, &data_sizes)?;
+        data.extend_from_slice(&boxes::mdat(&mdat_data[..])?[..]);
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::Fmp4Muxer,
+        crate::define::{AudioCodec, Fmp4Sample, TrackInfo, VideoCodec},
+        bytes::BytesMut,
+    };
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    }
+
+    /*return the top level boxes as (type, offset, size)*/
+    fn parse_boxes(data: &[u8]) -> Vec<(String, usize, usize)> {
+        let mut boxes = Vec::new();
+        let mut offset = 0;
+        while offset + 8 <= data.len() {
+            let size = read_u32(data, offset) as usize;
+            let box_type = String::from_utf8_lossy(&data[offset + 4..offset + 8]).to_string();
+            boxes.push((box_type, offset, size));
+            offset += size;
+        }
+        assert_eq!(offset, data.len());
+        boxes
+    }
+
+    #[test]
+    fn test_fmp4_muxer() {
+        let mut muxer = Fmp4Muxer::new();
+        let video_track_id = muxer.add_track(TrackInfo::Video {
+            codec: VideoCodec::H264,
+            width: 1280,
+            height: 720,
+            decoder_config: BytesMut::from(&[0x01, 0x64, 0x00, 0x1f, 0xff][..]),
+        });
+        let audio_track_id = muxer.add_track(TrackInfo::Audio {
+            codec: AudioCodec::Aac,
+            sample_rate: 44100,
+            channels: 2,
+            decoder_config: BytesMut::from(&[0x12, 0x10][..]),
+        });
+
+        let init_segment = muxer.write_init_segment().unwrap();
+        let boxes = parse_boxes(&init_segment[..]);
+        assert_eq!(boxes[0].0, "ftyp");
+        assert_eq!(boxes[1].0, "moov");
+
+        for i in 0..3 {
+            muxer
+                .write_sample(
+                    video_track_id,
+                    Fmp4Sample {
+                        dts: i * 40,
+                        cts: 0,
+                        is_key: i == 0,
+                        data: BytesMut::from(&[0, 0, 0, 1, 0x65][..]),
+                    },
+                )
+                .unwrap();
+        }
+        muxer
+            .write_sample(
+                audio_track_id,
+                Fmp4Sample {
+                    dts: 0,
+                    cts: 0,
+                    is_key: true,
+                    data: BytesMut::from(&[0x21, 0x10][..]),
+                },
+            )
+            .unwrap();
+        assert!(muxer
+            .write_sample(
+                3,
+                Fmp4Sample {
+                    dts: 0,
+                    cts: 0,
+                    is_key: true,
+                    data: BytesMut::new(),
+                }
+            )
+            .is_err());
+
+        let media_segment = muxer.write_media_segment(120).unwrap();
+        let boxes = parse_boxes(&media_segment[..]);
+        assert_eq!(boxes[0].0, "moof");
+        assert_eq!(boxes[1].0, "mdat");
+        //3 video samples and 1 audio sample
+        assert_eq!(boxes[1].2, 8 + 3 * 5 + 2);
+
+        //the data offset of the video trun points to the beginning of the mdat payload
+        let moof = &media_segment[..boxes[0].2];
+        let trun_offset = moof
+            .windows(4)
+            .position(|window| window == b"trun")
+            .unwrap();
+        assert_eq!(read_u32(moof, trun_offset + 8), 3);
+        assert_eq!(read_u32(moof, trun_offset + 12) as usize, boxes[1].1 + 8);
+        //the duration of the last video sample is calculated from the end dts
+        assert_eq!(read_u32(moof, trun_offset + 16 + 2 * 16), 3600);
+
+        //no more samples
+        assert!(muxer.write_media_segment(160).unwrap().is_empty());
+    }
+}
@@ -15,6 +15,11 @@ pub mod epsi_stream_type {
     pub const PSI_STREAM_AAC: u8 = 0x0f;
     pub const PSI_STREAM_MPEG4_AAC: u8 = 0x1c;
     pub const PSI_STREAM_AUDIO_OPUS: u8 = 0x9c;
+    //the SAMPLE-AES encrypted streams of HLS
+    pub const PSI_STREAM_H264_SAMPLE_AES: u8 = 0xdb;
+    pub const PSI_STREAM_AAC_SAMPLE_AES: u8 = 0xcf;
+    //the ID3 timed metadata carried in PES
+    pub const PSI_STREAM_METADATA: u8 = 0x15;
 }
 
 pub mod epes_stream_id {
@@ -0,0 +1,87 @@
+use bytes::{BufMut, BytesMut};
+
+/*The ID3 timed metadata stream, see the Timed Metadata for HTTP Live Streaming,
+the ID3 tags are carried in the PES of the metadata stream and described by the
+metadata pointer descriptor(program info) and the metadata descriptor(ES info).*/
+
+const METADATA_POINTER_DESCRIPTOR: u8 = 0x25;
+const METADATA_DESCRIPTOR: u8 = 0x26;
+
+fn write_metadata_format(descriptor: &mut BytesMut) {
+    //metadata_application_format
+    descriptor.put_u16(0xffff);
+    descriptor.put_slice(b"ID3 ");
+    //metadata_format
+    descriptor.put_u8(0xff);
+    descriptor.put_slice(b"ID3 ");
+    //metadata_service_id
+    descriptor.put_u8(0);
+}
+
+/*the descriptor in the program info of the PMT*/
+pub fn metadata_pointer_descriptor(program_number: u16) -> BytesMut {
+    let mut descriptor = BytesMut::new();
+    descriptor.put_u8(METADATA_POINTER_DESCRIPTOR);
+    descriptor.put_u8(15);
+    write_metadata_format(&mut descriptor);
+    //metadata_locator_record_flag(1) + MPEG_carriage_flags(2) + reserved(5)
+    descriptor.put_u8(0x1f);
+    descriptor.put_u16(program_number);
+    descriptor
+}
+
+/*the descriptor in the ES info of the metadata stream*/
+pub fn metadata_descriptor() -> BytesMut {
+    let mut descriptor = BytesMut::new();
+    descriptor.put_u8(METADATA_DESCRIPTOR);
+    descriptor.put_u8(13);
+    write_metadata_format(&mut descriptor);
+    //decoder_config_flags(3) + DSM-CC_flag(1) + reserved(4)
+    descriptor.put_u8(0x0f);
+    descriptor
+}
+
+/*the 28 bits integer of ID3v2.4 whose bytes have no most significant bit*/
+fn put_syncsafe(data: &mut BytesMut, value: usize) {
+    data.put_u8(((value >> 21) & 0x7f) as u8);
+    data.put_u8(((value >> 14) & 0x7f) as u8);
+    data.put_u8(((value >> 7) & 0x7f) as u8);
+    data.put_u8((value & 0x7f) as u8);
+}
+
+/*an ID3v2.4 tag with a TXXX(user defined text information) frame*/
+pub fn txxx_tag(description: &str, value: &str) -> BytesMut {
+    let mut frame = BytesMut::new();
+    //UTF-8
+    frame.put_u8(0x03);
+    frame.put_slice(description.as_bytes());
+    frame.put_u8(0);
+    frame.put_slice(value.as_bytes());
+
+    let mut tag = BytesMut::new();
+    tag.put_slice(b"ID3");
+    //version 2.4.0 without flags
+    tag.put_slice(&[0x04, 0x00, 0x00]);
+    put_syncsafe(&mut tag, frame.len() + 10);
+
+    tag.put_slice(b"TXXX");
+    put_syncsafe(&mut tag, frame.len());
+    //frame flags
+    tag.put_u16(0);
+    tag.put(frame);
+    tag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::txxx_tag;
+
+    #[test]
+    fn test_txxx_tag() {
+        let tag = txxx_tag("onTextData", "hi");
+        assert_eq!(
+            &tag[..],
+            b"ID3\x04\x00\x00\x00\x00\x00\x18TXXX\x00\x00\x00\x0e\x00\x00\x03onTextData\x00hi"
+        );
+    }
+}
@@ -1,8 +1,11 @@
 pub mod crc32;
 pub mod define;
 pub mod errors;
+pub mod id3;
 pub mod ts;
 pub mod pat;
 pub mod pes;
 pub mod pmt;
+pub mod sample_aes;
+pub mod scte35;
 pub mod utils;
@@ -1,5 +1,5 @@
 use {
-    super::{define, errors::MpegTsError},
+    super::{define, errors::MpegTsError, utils},
     bytes::BytesMut,
     bytesio::bytes_writer::BytesWriter,
 };
@@ -158,8 +158,7 @@ impl PesMuxer {
             self.bytes_writer.write_u8(b18)?;
         }
 
-        if define::epsi_stream_type::PSI_STREAM_H264 == stream_data.codec_id && !h264_h265_with_aud
-        {
+        if utils::is_steam_type_video(stream_data.codec_id) && !h264_h265_with_aud {
             let header: [u8; 6] = [0x00, 0x00, 0x00, 0x01, 0x09, 0xF0];
             self.bytes_writer.write(&header)?;
         }
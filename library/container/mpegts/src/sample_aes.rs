@@ -0,0 +1,38 @@
+use bytes::{BufMut, BytesMut};
+
+/*The descriptors of the SAMPLE-AES encrypted streams, they are written in the
+ES info of the PMT, see the MPEG-2 Stream Encryption Format for HTTP Live Streaming.*/
+
+const PRIVATE_DATA_INDICATOR_DESCRIPTOR: u8 = 0x0f;
+const REGISTRATION_DESCRIPTOR: u8 = 0x05;
+
+/*the private data indicator descriptor of the H.264 stream*/
+pub fn h264_descriptor() -> BytesMut {
+    let mut descriptor = BytesMut::new();
+    descriptor.put_u8(PRIVATE_DATA_INDICATOR_DESCRIPTOR);
+    descriptor.put_u8(4);
+    descriptor.put_slice(b"zavc");
+    descriptor
+}
+
+/*the private data indicator descriptor and the registration descriptor which
+carries the audio setup information(the AudioSpecificConfig) of the AAC stream*/
+pub fn aac_descriptor(audio_specific_config: &[u8]) -> BytesMut {
+    let mut descriptor = BytesMut::new();
+    descriptor.put_u8(PRIVATE_DATA_INDICATOR_DESCRIPTOR);
+    descriptor.put_u8(4);
+    descriptor.put_slice(b"aacd");
+
+    descriptor.put_u8(REGISTRATION_DESCRIPTOR);
+    //format_identifier(4) + audio_type(4) + priming(2) + version(1) + setup_data_length(1)
+    descriptor.put_u8(12 + audio_specific_config.len() as u8);
+    descriptor.put_slice(b"apad");
+    descriptor.put_slice(b"zaac");
+    //priming
+    descriptor.put_u16(0);
+    //version
+    descriptor.put_u8(1);
+    descriptor.put_u8(audio_specific_config.len() as u8);
+    descriptor.put_slice(audio_specific_config);
+    descriptor
+}
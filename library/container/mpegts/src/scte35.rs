@@ -0,0 +1,81 @@
+use {
+    super::crc32,
+    bytes::{BufMut, BytesMut},
+};
+
+/*The SCTE-35 splice_info_section with an immediate splice_insert command, it is
+carried in the SCTE35-OUT and SCTE35-IN attributes of EXT-X-DATERANGE.*/
+
+const SPLICE_INFO_TABLE_ID: u8 = 0xfc;
+const SPLICE_INSERT: u8 = 0x05;
+
+/*out_of_network is true for the splice out to the ad break and false for the
+splice in back to the program, duration is the break duration in 90kHz*/
+pub fn splice_insert(event_id: u32, out_of_network: bool, duration: Option<u64>) -> BytesMut {
+    let mut command = BytesMut::new();
+    command.put_u32(event_id);
+    //splice_event_cancel_indicator(1) + reserved(7)
+    command.put_u8(0x7f);
+    //out_of_network_indicator(1) + program_splice_flag(1) + duration_flag(1) +
+    //splice_immediate_flag(1) + reserved(4)
+    let mut flags: u8 = 0x5f;
+    if out_of_network {
+        flags |= 0x80;
+    }
+    if duration.is_some() {
+        flags |= 0x20;
+    }
+    command.put_u8(flags);
+    if let Some(duration) = duration {
+        //auto_return(1) + reserved(6) + duration(33)
+        command.put_u8(0xfe | ((duration >> 32) & 0x01) as u8);
+        command.put_u32(duration as u32);
+    }
+    //unique_program_id
+    command.put_u16(0);
+    //avail_num
+    command.put_u8(0);
+    //avails_expected
+    command.put_u8(0);
+
+    let mut section = BytesMut::new();
+    //protocol_version
+    section.put_u8(0);
+    //encrypted_packet(1) + encryption_algorithm(6) + pts_adjustment(33)
+    section.put_u8(0);
+    section.put_u32(0);
+    //cw_index
+    section.put_u8(0);
+    //tier(12) + splice_command_length(12)
+    section.put_u8(0xff);
+    section.put_u16(0xf000 | (command.len() + 1) as u16);
+    section.put_u8(SPLICE_INSERT);
+    section.put(command);
+    //descriptor_loop_length
+    section.put_u16(0);
+
+    let mut data = BytesMut::new();
+    data.put_u8(SPLICE_INFO_TABLE_ID);
+    //section_syntax_indicator(1) + private_indicator(1) + sap_type(2) + section_length(12)
+    data.put_u16(0x3000 | (section.len() + 4) as u16);
+    data.put(section);
+
+    let crc32_value = crc32::gen_crc32(0xffffffff, data.clone());
+    data.put_u32_le(crc32_value);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::splice_insert;
+
+    #[test]
+    fn test_splice_insert() {
+        let data = splice_insert(1, true, Some(30 * 90000));
+        let hex: String = data.iter().map(|byte| format!("{byte:02X}")).collect();
+        assert_eq!(
+            hex,
+            "FC302000000000000000FFF01005000000017FFFFE002932E0000000000000C544D25E"
+        );
+    }
+}
@@ -405,6 +405,21 @@ impl TsMuxer {
         Ok(self.pid - 1)
     }
 
+    /*set the descriptors of a stream, they are written in the ES info of the PMT*/
+    pub fn set_stream_info(&mut self, pid: u16, esinfo: BytesMut) -> Result<(), MpegTsError> {
+        for pmt in &mut self.pat.pmt {
+            if let Some(stream) = pmt.streams.iter_mut().find(|stream| stream.pid == pid) {
+                stream.esinfo = esinfo;
+                pmt.version_number = (pmt.version_number + 1) % 32;
+                return Ok(());
+            }
+        }
+
+        Err(MpegTsError {
+            value: MpegTsErrorValue::StreamNotFound,
+        })
+    }
+
     pub fn add_program(&mut self, program_number: u16, info: BytesMut) -> Result<(), MpegTsError> {
         for cur_pmt in self.pat.pmt.iter() {
             if cur_pmt.program_number == program_number {
@@ -0,0 +1,20 @@
+pub const INIT_SEGMENT_NAME: &str = "init.mp4";
+pub const SEGMENT_EXTENSION: &str = "m4s";
+
+/*the options used to generate the mpd and segments of a stream*/
+#[derive(Debug, Clone)]
+pub struct DashOptions {
+    //the target duration of a segment in seconds
+    pub duration: i64,
+    //how many segments are listed in the mpd
+    pub window_size: usize,
+}
+
+impl Default for DashOptions {
+    fn default() -> Self {
+        Self {
+            duration: 4,
+            window_size: 6,
+        }
+    }
+}
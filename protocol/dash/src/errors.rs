@@ -0,0 +1,64 @@
+use {
+    failure::{Backtrace, Fail},
+    hls::errors::MediaError,
+    rtmp::session::errors::SessionError,
+    std::fmt,
+    tokio::sync::broadcast::error::RecvError,
+};
+
+#[derive(Debug)]
+pub struct DashError {
+    pub value: DashErrorValue,
+}
+
+#[derive(Debug, Fail)]
+pub enum DashErrorValue {
+    #[fail(display = "dash error")]
+    Error,
+    #[fail(display = "session error:{}\n", _0)]
+    SessionError(#[cause] SessionError),
+    #[fail(display = "media error:{}\n", _0)]
+    MediaError(#[cause] MediaError),
+    #[fail(display = "receive error:{}\n", _0)]
+    RecvError(#[cause] RecvError),
+}
+
+impl From<RecvError> for DashError {
+    fn from(error: RecvError) -> Self {
+        DashError {
+            value: DashErrorValue::RecvError(error),
+        }
+    }
+}
+
+impl From<MediaError> for DashError {
+    fn from(error: MediaError) -> Self {
+        DashError {
+            value: DashErrorValue::MediaError(error),
+        }
+    }
+}
+
+impl From<SessionError> for DashError {
+    fn from(error: SessionError) -> Self {
+        DashError {
+            value: DashErrorValue::SessionError(error),
+        }
+    }
+}
+
+impl fmt::Display for DashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl Fail for DashError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.value.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.value.backtrace()
+    }
+}
@@ -0,0 +1,86 @@
+use {
+    super::{define::DashOptions, mpd::Mpd},
+    hls::{errors::MediaError, flv2fmp4::Flv2Fmp4Muxer, store::HlsStoreRef},
+    xflv::define::FlvData,
+    xfmp4::define::TrackInfo,
+};
+
+/*Remux the flv tags into CMAF segments, the fmp4 muxing is shared with the hls
+fmp4 container. A segment begins with a video key frame, or any audio frame
+for the audio only streams.*/
+pub struct Flv2DashRemuxer {
+    fmp4_muxer: Flv2Fmp4Muxer,
+    mpd_handler: Mpd,
+
+    //the target duration of a segment in milliseconds
+    duration: i64,
+    has_video: bool,
+    //the dts of the first frame of the current segment
+    segment_start_dts: Option<i64>,
+}
+
+impl Flv2DashRemuxer {
+    pub fn new(
+        app_name: String,
+        stream_name: String,
+        store: HlsStoreRef,
+        options: &DashOptions,
+    ) -> Self {
+        let duration = options.duration * 1000;
+
+        Self {
+            fmp4_muxer: Flv2Fmp4Muxer::new(),
+            mpd_handler: Mpd::new(duration, options.window_size, app_name, stream_name, store),
+
+            duration,
+            has_video: false,
+            segment_start_dts: None,
+        }
+    }
+
+    pub fn process_flv_data(&mut self, data: FlvData) -> Result<(), MediaError> {
+        let frame = match self.fmp4_muxer.demux(data)? {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        if let Some(init_segment) = self.fmp4_muxer.take_init_segment()? {
+            let tracks = self.fmp4_muxer.get_tracks().to_vec();
+            self.has_video = tracks
+                .iter()
+                .any(|track| matches!(track, TrackInfo::Video { .. }));
+            self.mpd_handler.set_init_segment(init_segment, tracks)?;
+        }
+        //the frames before the decoder configurations can not be decoded
+        if !self.fmp4_muxer.is_init_generated() {
+            return Ok(());
+        }
+
+        let dts = frame.sample.dts;
+        let is_boundary = !self.has_video || (frame.is_video && frame.sample.is_key);
+
+        match self.segment_start_dts {
+            Some(start_dts) => {
+                if is_boundary && dts - start_dts >= self.duration {
+                    let data = self.fmp4_muxer.get_data(dts)?;
+                    self.mpd_handler
+                        .add_segment(start_dts, dts - start_dts, data)?;
+                    self.mpd_handler.refresh_mpd()?;
+                    self.segment_start_dts = Some(dts);
+                }
+            }
+            None => {
+                //the first segment must begin with a stream access point
+                if !is_boundary {
+                    return Ok(());
+                }
+                self.segment_start_dts = Some(dts);
+            }
+        }
+
+        self.fmp4_muxer.write_frame(frame)
+    }
+
+    pub fn clear_files(&mut self) -> Result<(), MediaError> {
+        self.mpd_handler.clear()
+    }
+}
@@ -0,0 +1,181 @@
+use {
+    super::{
+        define::DashOptions,
+        errors::{DashError, DashErrorValue},
+        flv2dash::Flv2DashRemuxer,
+    },
+    hls::store::HlsStoreRef,
+    rtmp::channels::define::{
+        ChannelData, ChannelDataConsumer, ChannelEvent, ChannelEventProducer,
+    },
+    rtmp::session::{
+        common::{NotifyInfo, SubscribeOptions, SubscriberInfo},
+        define::SubscribeType,
+        errors::{SessionError, SessionErrorValue},
+    },
+    std::time::Duration,
+    tokio::{
+        sync::{mpsc, oneshot},
+        time::sleep,
+    },
+    uuid::Uuid,
+    xflv::define::FlvData,
+};
+
+pub struct FlvDataReceiver {
+    app_name: String,
+    stream_name: String,
+
+    event_producer: ChannelEventProducer,
+    data_consumer: ChannelDataConsumer,
+    media_processor: Flv2DashRemuxer,
+    subscriber_id: Uuid,
+}
+
+impl FlvDataReceiver {
+    pub fn new(
+        app_name: String,
+        stream_name: String,
+        event_producer: ChannelEventProducer,
+        store: HlsStoreRef,
+        options: &DashOptions,
+    ) -> Self {
+        let (_, data_consumer) = mpsc::unbounded_channel();
+        let subscriber_id = Uuid::new_v4();
+
+        Self {
+            app_name: app_name.clone(),
+            stream_name: stream_name.clone(),
+
+            data_consumer,
+            event_producer,
+            media_processor: Flv2DashRemuxer::new(app_name, stream_name, store, options),
+            subscriber_id,
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<(), DashError> {
+        self.subscribe_from_rtmp_channels(self.app_name.clone(), self.stream_name.clone())
+            .await?;
+        self.receive_flv_data().await?;
+
+        Ok(())
+    }
+
+    pub async fn receive_flv_data(&mut self) -> Result<(), DashError> {
+        let mut retry_count = 0;
+
+        loop {
+            if let Some(data) = self.data_consumer.recv().await {
+                let flv_data: FlvData = match data {
+                    ChannelData::Audio { timestamp, data } => FlvData::Audio { timestamp, data },
+                    ChannelData::Video { timestamp, data } => FlvData::Video { timestamp, data },
+                    _ => continue,
+                };
+                retry_count = 0;
+                self.media_processor.process_flv_data(flv_data)?;
+            } else {
+                sleep(Duration::from_millis(100)).await;
+                retry_count += 1;
+            }
+            //When rtmp stream is interupted here we retry 10 times.
+            //maybe have a better way to judge the stream status.
+            //will do an optimization in the future.
+            //todo
+            if retry_count > 10 {
+                break;
+            }
+        }
+
+        self.media_processor.clear_files()?;
+        self.unsubscribe_from_rtmp_channels().await
+    }
+
+    pub fn flush_response_data(&mut self) -> Result<(), DashError> {
+        Ok(())
+    }
+
+    pub async fn subscribe_from_rtmp_channels(
+        &mut self,
+        app_name: String,
+        stream_name: String,
+    ) -> Result<(), DashError> {
+        let mut retry_count: u8 = 0;
+
+        loop {
+            let (sender, receiver) = oneshot::channel();
+            /*the sub info is only used to transfer from RTMP to DASH, but not for client player */
+            let sub_info = SubscriberInfo {
+                id: self.subscriber_id,
+                sub_type: SubscribeType::GenerateDash,
+                notify_info: NotifyInfo {
+                    request_url: String::from(""),
+                    remote_addr: String::from(""),
+                },
+                options: SubscribeOptions::default(),
+            };
+
+            let subscribe_event = ChannelEvent::Subscribe {
+                app_name: app_name.clone(),
+                stream_name: stream_name.clone(),
+                info: sub_info,
+                responder: sender,
+            };
+
+            let rv = self.event_producer.send(subscribe_event);
+            if rv.is_err() {
+                let session_error = SessionError {
+                    value: SessionErrorValue::SendChannelDataErr,
+                };
+                return Err(DashError {
+                    value: DashErrorValue::SessionError(session_error),
+                });
+            }
+
+            match receiver.await {
+                Ok(consumer) => {
+                    self.data_consumer = consumer;
+                    break;
+                }
+                Err(_) => {
+                    if retry_count > 10 {
+                        let session_error = SessionError {
+                            value: SessionErrorValue::SubscribeCountLimitReach,
+                        };
+                        return Err(DashError {
+                            value: DashErrorValue::SessionError(session_error),
+                        });
+                    }
+                }
+            }
+
+            sleep(Duration::from_millis(800)).await;
+            retry_count += 1;
+        }
+
+        Ok(())
+    }
+
+    pub async fn unsubscribe_from_rtmp_channels(&mut self) -> Result<(), DashError> {
+        let sub_info = SubscriberInfo {
+            id: self.subscriber_id,
+            sub_type: SubscribeType::GenerateDash,
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: String::from(""),
+            },
+            options: SubscribeOptions::default(),
+        };
+
+        let subscribe_event = ChannelEvent::UnSubscribe {
+            app_name: self.app_name.clone(),
+            stream_name: self.stream_name.clone(),
+            info: sub_info,
+        };
+        if let Err(err) = self.event_producer.send(subscribe_event) {
+            log::error!("unsubscribe_from_channels err {}\n", err);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+use {
+    super::define::{INIT_SEGMENT_NAME, SEGMENT_EXTENSION},
+    bytes::BytesMut,
+    chrono::{DateTime, Duration, SecondsFormat, Utc},
+    hls::{errors::MediaError, store::HlsStoreRef},
+    std::collections::VecDeque,
+    xfmp4::define::TrackInfo,
+};
+
+//the segment timeline is in milliseconds
+const TIMESCALE: i64 = 1000;
+
+struct Segment {
+    /*the $Number$ of the segment*/
+    number: u64,
+    /*the dts of the first frame in milliseconds*/
+    start: i64,
+    duration: i64,
+    size: usize,
+}
+
+/*Generate the dynamic MPD of a live stream, all the tracks are muxed into the same
+CMAF segments, so there is one AdaptationSet with one Representation.*/
+pub struct Mpd {
+    app_name: String,
+    stream_name: String,
+    mpd_name: String,
+    store: HlsStoreRef,
+
+    //the target duration of a segment in milliseconds
+    duration: i64,
+    window_size: usize,
+    segments: VecDeque<Segment>,
+    sequence_no: u64,
+    tracks: Vec<TrackInfo>,
+
+    /*the wall clock time of the presentation time 0, it is when the first segment begins,
+    and the presentation time 0 is the dts of the first segment*/
+    availability_start_time: Option<DateTime<Utc>>,
+    presentation_time_offset: i64,
+}
+
+impl Mpd {
+    pub fn new(
+        duration: i64,
+        window_size: usize,
+        app_name: String,
+        stream_name: String,
+        store: HlsStoreRef,
+    ) -> Self {
+        Self {
+            mpd_name: format!("{stream_name}.mpd"),
+            app_name,
+            stream_name,
+            store,
+
+            duration,
+            window_size: window_size.max(1),
+            segments: VecDeque::new(),
+            sequence_no: 0,
+            tracks: Vec::new(),
+
+            availability_start_time: None,
+            presentation_time_offset: 0,
+        }
+    }
+
+    pub fn set_init_segment(
+        &mut self,
+        data: BytesMut,
+        tracks: Vec<TrackInfo>,
+    ) -> Result<(), MediaError> {
+        self.tracks = tracks;
+        self.store.write(
+            &self.app_name,
+            &self.stream_name,
+            INIT_SEGMENT_NAME,
+            data.freeze(),
+        )
+    }
+
+    pub fn add_segment(
+        &mut self,
+        start: i64,
+        duration: i64,
+        data: BytesMut,
+    ) -> Result<(), MediaError> {
+        if self.availability_start_time.is_none() {
+            self.availability_start_time = Some(Utc::now() - Duration::milliseconds(duration));
+            self.presentation_time_offset = start;
+        }
+
+        if self.segments.len() >= self.window_size {
+            if let Some(segment) = self.segments.pop_front() {
+                self.store.delete(
+                    &self.app_name,
+                    &self.stream_name,
+                    &segment_name(segment.number),
+                )?;
+            }
+        }
+
+        let number = self.sequence_no;
+        self.sequence_no += 1;
+
+        let size = data.len();
+        self.store.write(
+            &self.app_name,
+            &self.stream_name,
+            &segment_name(number),
+            data.freeze(),
+        )?;
+        self.segments.push_back(Segment {
+            number,
+            start,
+            duration,
+            size,
+        });
+
+        Ok(())
+    }
+
+    pub fn refresh_mpd(&mut self) -> Result<(), MediaError> {
+        let mpd = self.generate_mpd(Utc::now());
+        self.store.write(
+            &self.app_name,
+            &self.stream_name,
+            &self.mpd_name,
+            mpd.into(),
+        )
+    }
+
+    pub fn clear(&mut self) -> Result<(), MediaError> {
+        for segment in self.segments.drain(..) {
+            self.store.delete(
+                &self.app_name,
+                &self.stream_name,
+                &segment_name(segment.number),
+            )?;
+        }
+        self.store
+            .delete(&self.app_name, &self.stream_name, INIT_SEGMENT_NAME)?;
+        self.store
+            .delete(&self.app_name, &self.stream_name, &self.mpd_name)
+    }
+
+    /*the peak bitrate of the segments in the window*/
+    fn bandwidth(&self) -> u64 {
+        self.segments
+            .iter()
+            .filter(|segment| segment.duration > 0)
+            .map(|segment| segment.size as u64 * 8 * 1000 / segment.duration as u64)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn generate_mpd(&self, publish_time: DateTime<Utc>) -> String {
+        let availability_start_time = self.availability_start_time.unwrap_or(publish_time);
+        let time_shift_buffer_depth: i64 =
+            self.segments.iter().map(|segment| segment.duration).sum();
+
+        let mut mpd = String::new();
+        mpd.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        mpd.push_str(&format!(
+            "<MPD xmlns=\"urn:mpeg:dash:schema:mpd:2011\" profiles=\"urn:mpeg:dash:profile:isoff-live:2011\" type=\"dynamic\" availabilityStartTime=\"{}\" publishTime=\"{}\" minimumUpdatePeriod=\"{}\" minBufferTime=\"{}\" timeShiftBufferDepth=\"{}\" suggestedPresentationDelay=\"{}\">\n",
+            format_date_time(availability_start_time),
+            format_date_time(publish_time),
+            format_duration(self.duration),
+            format_duration(self.duration),
+            format_duration(time_shift_buffer_depth),
+            format_duration(self.duration * 3),
+        ));
+        mpd.push_str("  <Period id=\"0\" start=\"PT0S\">\n");
+
+        let is_video = self
+            .tracks
+            .iter()
+            .any(|track| matches!(track, TrackInfo::Video { .. }));
+        let mime_type = if is_video { "video/mp4" } else { "audio/mp4" };
+        mpd.push_str(&format!(
+            "    <AdaptationSet id=\"0\" mimeType=\"{mime_type}\" segmentAlignment=\"true\" startWithSAP=\"1\">\n"
+        ));
+
+        let codecs: Vec<String> = self.tracks.iter().map(TrackInfo::codec_string).collect();
+        let mut representation = format!(
+            "      <Representation id=\"0\" codecs=\"{}\" bandwidth=\"{}\"",
+            codecs.join(","),
+            self.bandwidth()
+        );
+        for track in &self.tracks {
+            match track {
+                TrackInfo::Video { width, height, .. } => {
+                    representation.push_str(&format!(" width=\"{width}\" height=\"{height}\""));
+                }
+                TrackInfo::Audio { .. } => {
+                    representation
+                        .push_str(&format!(" audioSamplingRate=\"{}\"", track.timescale()));
+                }
+            }
+        }
+        mpd.push_str(&representation);
+        mpd.push_str(">\n");
+
+        let start_number = self
+            .segments
+            .front()
+            .map_or(self.sequence_no, |segment| segment.number);
+        mpd.push_str(&format!(
+            "        <SegmentTemplate timescale=\"{TIMESCALE}\" presentationTimeOffset=\"{}\" initialization=\"{INIT_SEGMENT_NAME}\" media=\"$Number$.{SEGMENT_EXTENSION}\" startNumber=\"{start_number}\">\n",
+            self.presentation_time_offset
+        ));
+        mpd.push_str("          <SegmentTimeline>\n");
+        for segment in &self.segments {
+            mpd.push_str(&format!(
+                "            <S t=\"{}\" d=\"{}\"/>\n",
+                segment.start, segment.duration
+            ));
+        }
+        mpd.push_str("          </SegmentTimeline>\n");
+        mpd.push_str("        </SegmentTemplate>\n");
+        mpd.push_str("      </Representation>\n");
+        mpd.push_str("    </AdaptationSet>\n");
+        mpd.push_str("  </Period>\n");
+        mpd.push_str("</MPD>\n");
+
+        mpd
+    }
+}
+
+fn segment_name(number: u64) -> String {
+    format!("{number}.{SEGMENT_EXTENSION}")
+}
+
+fn format_date_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/*xs:duration in seconds, e.g. PT4.000S*/
+fn format_duration(milliseconds: i64) -> String {
+    format!("PT{}.{:03}S", milliseconds / 1000, milliseconds % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::Mpd,
+        bytes::BytesMut,
+        chrono::Utc,
+        hls::store::{memory::MemoryHlsStore, HlsStore},
+        std::sync::Arc,
+        xfmp4::define::{AudioCodec, TrackInfo, VideoCodec},
+    };
+
+    #[test]
+    fn test_segment_timeline() {
+        let store = Arc::new(MemoryHlsStore::default());
+        let mut mpd = Mpd::new(
+            2000,
+            2,
+            String::from("live"),
+            String::from("test"),
+            store.clone(),
+        );
+
+        let tracks = vec![
+            TrackInfo::Video {
+                codec: VideoCodec::H264,
+                width: 1280,
+                height: 720,
+                decoder_config: BytesMut::from(&[0x01, 0x64, 0x00, 0x1f][..]),
+            },
+            TrackInfo::Audio {
+                codec: AudioCodec::Aac,
+                sample_rate: 44100,
+                channels: 2,
+                decoder_config: BytesMut::from(&[0x12, 0x10][..]),
+            },
+        ];
+        mpd.set_init_segment(BytesMut::from(&b"init"[..]), tracks)
+            .unwrap();
+        for i in 0..3 {
+            mpd.add_segment(1000 + i * 2000, 2000, BytesMut::from(&[0_u8; 500][..]))
+                .unwrap();
+        }
+
+        //the first segment is removed from the window
+        assert!(store.read("live", "test", "0.m4s").unwrap().is_none());
+        assert!(store.read("live", "test", "2.m4s").unwrap().is_some());
+
+        let content = mpd.generate_mpd(Utc::now());
+        assert!(content.contains("codecs=\"avc1.64001f,mp4a.40.2\" bandwidth=\"2000\""));
+        assert!(content.contains("presentationTimeOffset=\"1000\""));
+        assert!(content.contains("startNumber=\"1\""));
+        assert!(
+            content.contains("<S t=\"3000\" d=\"2000\"/>\n            <S t=\"5000\" d=\"2000\"/>")
+        );
+        assert!(content.contains("timeShiftBufferDepth=\"PT4.000S\""));
+    }
+}
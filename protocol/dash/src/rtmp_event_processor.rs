@@ -0,0 +1,60 @@
+use {
+    super::{define::DashOptions, errors::DashError, flv_data_receiver::FlvDataReceiver},
+    hls::store::HlsStoreRef,
+    rtmp::channels::define::{ChannelEventProducer, ClientEvent, ClientEventConsumer},
+};
+
+pub struct RtmpEventProcessor {
+    client_event_consumer: ClientEventConsumer,
+    event_producer: ChannelEventProducer,
+    store: HlsStoreRef,
+    options: DashOptions,
+}
+
+impl RtmpEventProcessor {
+    pub fn new(
+        consumer: ClientEventConsumer,
+        event_producer: ChannelEventProducer,
+        store: HlsStoreRef,
+    ) -> Self {
+        Self {
+            client_event_consumer: consumer,
+            event_producer,
+            store,
+            options: DashOptions::default(),
+        }
+    }
+
+    pub fn set_options(&mut self, options: DashOptions) {
+        self.options = options;
+    }
+
+    pub async fn run(&mut self) -> Result<(), DashError> {
+        loop {
+            let val = self.client_event_consumer.recv().await?;
+            match val {
+                ClientEvent::Publish {
+                    app_name,
+                    stream_name,
+                } => {
+                    let mut rtmp_subscriber = FlvDataReceiver::new(
+                        app_name,
+                        stream_name,
+                        self.event_producer.clone(),
+                        self.store.clone(),
+                        &self.options,
+                    );
+
+                    tokio::spawn(async move {
+                        if let Err(err) = rtmp_subscriber.run().await {
+                            log::error!("dash handler run error {err}");
+                        }
+                    });
+                }
+                _ => {
+                    log::trace!("other infos...");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use {
+    hls::store::HlsStoreRef,
+    hyper::{
+        header,
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server, StatusCode,
+    },
+};
+
+type GenericError = Box<dyn std::error::Error + Send + Sync>;
+type Result<T> = std::result::Result<T, GenericError>;
+static NOTFOUND: &[u8] = b"Not Found";
+
+fn content_type(file_name: &str) -> &'static str {
+    if file_name.ends_with(".mpd") {
+        "application/dash+xml"
+    } else if file_name.ends_with(".m4s") {
+        "video/iso.segment"
+    } else {
+        "video/mp4"
+    }
+}
+
+async fn handle_connection(req: Request<Body>, store: HlsStoreRef) -> Result<Response<Body>> {
+    let path = req.uri().path();
+
+    //http://127.0.0.1/app_name/stream_name/stream_name.mpd
+    //http://127.0.0.1/app_name/stream_name/init.mp4
+    //http://127.0.0.1/app_name/stream_name/segment_number.m4s
+    if [".mpd", ".m4s", ".mp4"]
+        .iter()
+        .any(|extension| path.ends_with(extension))
+    {
+        let rv: Vec<_> = path.split('/').collect();
+
+        if rv.len() == 4 && rv[1..].iter().all(|item| !item.is_empty()) {
+            let (app_name, stream_name, file_name) = (rv[1], rv[2], rv[3]);
+
+            match store.read(app_name, stream_name, file_name) {
+                Ok(Some(data)) => {
+                    return Ok(Response::builder()
+                        .header(header::CONTENT_TYPE, content_type(file_name))
+                        .body(Body::from(data))
+                        .unwrap())
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    log::error!("dash server read {} error: {}", path, err);
+                    return Ok(Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(Body::empty())
+                        .unwrap());
+                }
+            }
+        }
+    }
+
+    Ok(not_found())
+}
+
+/// HTTP status code 404
+fn not_found() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(NOTFOUND.into())
+        .unwrap()
+}
+
+pub async fn run(port: usize, store: HlsStoreRef) -> Result<()> {
+    let listen_address = format!("0.0.0.0:{port}");
+    let sock_addr = listen_address.parse().unwrap();
+
+    let new_service = make_service_fn(move |_| {
+        let store = store.clone();
+        async move {
+            Ok::<_, GenericError>(service_fn(move |req| handle_connection(req, store.clone())))
+        }
+    });
+
+    let server = Server::bind(&sock_addr).serve(new_service);
+    log::info!("Dash server listening on http://{}", sock_addr);
+    server.await?;
+
+    Ok(())
+}
@@ -1,6 +1,173 @@
-use xflv::demuxer::{FlvDemuxerAudioData, FlvDemuxerVideoData};
+use {
+    std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+    tokio::sync::{mpsc, oneshot},
+    xflv::demuxer::{FlvDemuxerAudioData, FlvDemuxerVideoData},
+};
 
 pub const HLS_DURATION: u8 = 10;
+pub const DEFAULT_SEGMENT_NAME: &str = "{seq}.ts";
+pub const DEFAULT_PART_DURATION: i64 = 200;
+pub const DEFAULT_KEY_URI: &str = "{index}.key";
+pub const DEFAULT_DISCONTINUITY_THRESHOLD: i64 = 15;
+pub const DEFAULT_IDLE_TIMEOUT: u64 = 30;
+
+/*the encryption method of the segments*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HlsEncryption {
+    None,
+    //the whole segment is encrypted by AES-128 CBC
+    Aes128,
+    //only the H.264 slices and the AAC frames are encrypted, it is for the ts container
+    SampleAes,
+}
+
+/*the container format of the segments*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HlsContainer {
+    Ts,
+    //fragmented mp4(CMAF), the segments are .m4s files with an init.mp4
+    Fmp4,
+}
+
+/*how the splice points of the ad breaks are signaled in the playlist*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HlsAdMarkers {
+    //EXT-X-CUE-OUT, EXT-X-CUE-OUT-CONT and EXT-X-CUE-IN
+    Cue,
+    //EXT-X-DATERANGE with the SCTE35-OUT and SCTE35-IN attributes
+    DateRange,
+}
+
+/*the options used to generate the hls playlist and segments of a stream*/
+#[derive(Debug, Clone)]
+pub struct HlsOptions {
+    //the target duration of a segment in seconds
+    pub duration: i64,
+    //how many segments are listed in the live playlist
+    pub live_ts_count: usize,
+    //how many seconds a segment is kept after it is removed from the playlist
+    pub deletion_delay: u64,
+    /*the segment name template, {app}, {stream} and {seq} are replaced,
+    it must contain {seq} and end with .ts, the extension is .m4s for fmp4*/
+    pub segment_name: String,
+    pub container: HlsContainer,
+    //enable LL-HLS
+    pub low_latency: bool,
+    //the target duration of a LL-HLS partial segment in milliseconds
+    pub part_duration: i64,
+    /*the DVR(time-shift) window in seconds, the segments within it are kept
+    and listed in the playlist, 0 disables the DVR*/
+    pub dvr_window: u64,
+    /*live-to-VOD, keep all the segments and archive them with a VOD playlist
+    when the stream ends instead of deleting them*/
+    pub archive: bool,
+    pub encryption: HlsEncryption,
+    //how many segments are encrypted by a key, 0 means one key for a stream
+    pub key_rotation: u64,
+    /*the key uri template in the playlist, {app}, {stream} and {index} are replaced,
+    the keys are served by the hls server as {index}.key*/
+    pub key_uri: String,
+    /*cut the segments at the multiples of the duration instead of the duration
+    after the last cut, so the renditions of a variant set are aligned*/
+    pub align_segments: bool,
+    /*the segment is cut at any frame when it reaches the max duration in seconds,
+    it bounds the segments of the streams with long GOPs, 0 means no limit*/
+    pub max_duration: i64,
+    /*a timestamp jump longer than the threshold in seconds begins a new segment
+    with EXT-X-DISCONTINUITY, 0 disables it*/
+    pub discontinuity_threshold: i64,
+    /*the stream is remuxed when its playlist is requested instead of when it is
+    published, and the remuxing stops after idle_timeout seconds without requests*/
+    pub on_demand: bool,
+    pub idle_timeout: u64,
+    pub ad_markers: HlsAdMarkers,
+    /*carry the onCuePoint and onTextData messages in an ID3 metadata stream of the
+    ts segments, the stream is not added to the PMT when it is disabled*/
+    pub timed_metadata: bool,
+}
+
+impl Default for HlsOptions {
+    fn default() -> Self {
+        Self {
+            duration: 5,
+            live_ts_count: 6,
+            deletion_delay: 0,
+            segment_name: String::from(DEFAULT_SEGMENT_NAME),
+            container: HlsContainer::Ts,
+            low_latency: false,
+            part_duration: DEFAULT_PART_DURATION,
+            dvr_window: 0,
+            archive: false,
+            encryption: HlsEncryption::None,
+            key_rotation: 0,
+            key_uri: String::from(DEFAULT_KEY_URI),
+            align_segments: false,
+            max_duration: 0,
+            discontinuity_threshold: DEFAULT_DISCONTINUITY_THRESHOLD,
+            on_demand: false,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            ad_markers: HlsAdMarkers::Cue,
+            timed_metadata: false,
+        }
+    }
+}
+
+/*the playlist request of a stream, it starts the remuxing of an on demand stream,
+the responder is told whether the stream is an on demand one*/
+#[derive(Debug)]
+pub struct HlsDemandEvent {
+    pub app_name: String,
+    pub stream_name: String,
+    pub responder: oneshot::Sender<bool>,
+}
+
+pub type HlsDemandEventSender = mpsc::UnboundedSender<HlsDemandEvent>;
+pub type HlsDemandEventReceiver = mpsc::UnboundedReceiver<HlsDemandEvent>;
+
+/*the playlist requests of an on demand stream which is being remuxed*/
+#[derive(Debug, Clone)]
+pub struct DemandActivity {
+    last_request_time: Arc<Mutex<Instant>>,
+    is_stopped: Arc<AtomicBool>,
+}
+
+impl Default for DemandActivity {
+    fn default() -> Self {
+        Self {
+            last_request_time: Arc::new(Mutex::new(Instant::now())),
+            is_stopped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl DemandActivity {
+    pub fn touch(&self) {
+        if let Ok(mut last_request_time) = self.last_request_time.lock() {
+            *last_request_time = Instant::now();
+        }
+    }
+
+    pub fn idle_time(&self) -> Duration {
+        match self.last_request_time.lock() {
+            Ok(last_request_time) => last_request_time.elapsed(),
+            Err(_) => Duration::ZERO,
+        }
+    }
+
+    pub fn stop(&self) {
+        self.is_stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.is_stopped.load(Ordering::SeqCst)
+    }
+}
 
 pub enum FlvDemuxerData {
     Video { data: FlvDemuxerVideoData },
@@ -0,0 +1,34 @@
+use {crate::errors::MediaError, rand::RngCore, std::sync::Arc};
+
+/*
+ Provide the AES-128 keys of the encrypted streams, the keys of a stream are indexed
+ from 0 and rotated every N segments. get_key is called once for each key, the key is
+ written to the hls store as {key_index}.key and only served to the authorized players.
+*/
+pub trait HlsKeyProvider: Send + Sync {
+    fn get_key(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        key_index: u64,
+    ) -> Result<[u8; 16], MediaError>;
+}
+
+pub type HlsKeyProviderRef = Arc<dyn HlsKeyProvider>;
+
+/*generate a random key for every key index*/
+#[derive(Default)]
+pub struct RandomKeyProvider {}
+
+impl HlsKeyProvider for RandomKeyProvider {
+    fn get_key(
+        &self,
+        _app_name: &str,
+        _stream_name: &str,
+        _key_index: u64,
+    ) -> Result<[u8; 16], MediaError> {
+        let mut key = [0_u8; 16];
+        rand::thread_rng().fill_bytes(&mut key);
+        Ok(key)
+    }
+}
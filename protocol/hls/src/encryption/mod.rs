@@ -0,0 +1,26 @@
+pub mod key;
+pub mod sample_aes;
+
+use {
+    aes::cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit},
+    bytes::BytesMut,
+};
+
+pub type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+
+pub const AES_BLOCK_SIZE: usize = 16;
+
+/*the key and the initialization vector of AES-128*/
+pub type AesKey = [u8; AES_BLOCK_SIZE];
+pub type AesIv = [u8; AES_BLOCK_SIZE];
+
+/*AES-128, the whole segment is encrypted by AES-128 CBC with the PKCS7 padding*/
+pub fn encrypt_segment(key: &AesKey, iv: &AesIv, data: &[u8]) -> BytesMut {
+    let encrypted = Aes128CbcEnc::new(key.into(), iv.into()).encrypt_padded_vec_mut::<Pkcs7>(data);
+    BytesMut::from(&encrypted[..])
+}
+
+/*the iv of a segment is its media sequence number when the IV attribute is absent*/
+pub fn sequence_iv(sequence_no: u64) -> AesIv {
+    (sequence_no as u128).to_be_bytes()
+}
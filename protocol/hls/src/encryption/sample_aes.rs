@@ -0,0 +1,235 @@
+use {
+    super::{Aes128CbcEnc, AesIv, AesKey, AES_BLOCK_SIZE},
+    aes::cipher::{generic_array::GenericArray, BlockEncryptMut, KeyIvInit},
+    bytes::{BufMut, BytesMut},
+};
+
+/*
+ SAMPLE-AES, only the samples are encrypted and the containers are kept, see the
+ MPEG-2 Stream Encryption Format for HTTP Live Streaming. The CBC chain is restarted
+ with the segment iv for every NAL unit and every AAC frame.
+*/
+
+//the NAL units which are not longer than it are not encrypted
+const NAL_MIN_ENCRYPTED_SIZE: usize = 48;
+//the nal_unit_type byte and the unencrypted leader of 31 bytes
+const NAL_CLEAR_LEADER_SIZE: usize = 32;
+//an encrypted block is followed by up to 144 unencrypted bytes
+const NAL_CLEAR_BLOCK_SIZE: usize = 144;
+const AAC_CLEAR_LEADER_SIZE: usize = 16;
+
+const H264_NAL_SLICE: u8 = 1;
+const H264_NAL_IDR: u8 = 5;
+
+fn encrypt_block(encryptor: &mut Aes128CbcEnc, block: &mut [u8]) {
+    encryptor.encrypt_block_mut(GenericArray::from_mut_slice(block));
+}
+
+/*the position of the start code and the NAL unit of every NAL unit in the annexb data*/
+fn split_annexb(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut nal_units = Vec::new();
+    let mut i = 0;
+
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            //the 4 bytes start code
+            let start_code_pos = if i > 0 && data[i - 1] == 0 { i - 1 } else { i };
+            nal_units.push((start_code_pos, i + 3));
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+
+    nal_units
+}
+
+fn remove_emulation_prevention(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zero_count = 0;
+
+    for &byte in nal {
+        if zero_count >= 2 && byte == 0x03 {
+            zero_count = 0;
+            continue;
+        }
+        zero_count = if byte == 0 { zero_count + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+
+    rbsp
+}
+
+fn add_emulation_prevention(rbsp: &[u8]) -> Vec<u8> {
+    let mut nal = Vec::with_capacity(rbsp.len() + rbsp.len() / 64);
+    let mut zero_count = 0;
+
+    for &byte in rbsp {
+        if zero_count >= 2 && byte <= 0x03 {
+            nal.push(0x03);
+            zero_count = 0;
+        }
+        zero_count = if byte == 0 { zero_count + 1 } else { 0 };
+        nal.push(byte);
+    }
+    //a NAL unit can not end with a zero byte
+    if zero_count > 0 {
+        nal.push(0x03);
+    }
+
+    nal
+}
+
+fn encrypt_nal(key: &AesKey, iv: &AesIv, nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = remove_emulation_prevention(nal);
+    let mut encryptor = Aes128CbcEnc::new(key.into(), iv.into());
+
+    let mut pos = NAL_CLEAR_LEADER_SIZE;
+    while rbsp.len() > pos + AES_BLOCK_SIZE {
+        encrypt_block(&mut encryptor, &mut rbsp[pos..pos + AES_BLOCK_SIZE]);
+        pos += AES_BLOCK_SIZE + NAL_CLEAR_BLOCK_SIZE;
+    }
+
+    add_emulation_prevention(&rbsp)
+}
+
+/*encrypt the slices of the H.264 annexb data*/
+pub fn encrypt_h264(key: &AesKey, iv: &AesIv, data: &[u8]) -> BytesMut {
+    let nal_units = split_annexb(data);
+    let mut encrypted = BytesMut::with_capacity(data.len() + data.len() / 64);
+
+    match nal_units.first() {
+        Some((start_code_pos, _)) => encrypted.put_slice(&data[..*start_code_pos]),
+        None => {
+            encrypted.put_slice(data);
+            return encrypted;
+        }
+    }
+
+    for (index, (start_code_pos, nal_pos)) in nal_units.iter().enumerate() {
+        let nal_end = nal_units
+            .get(index + 1)
+            .map_or(data.len(), |(next_start_code_pos, _)| *next_start_code_pos);
+        let nal = &data[*nal_pos..nal_end];
+
+        encrypted.put_slice(&data[*start_code_pos..*nal_pos]);
+        let nal_type = nal.first().map_or(0, |header| header & 0x1f);
+        if (nal_type == H264_NAL_SLICE || nal_type == H264_NAL_IDR)
+            && nal.len() > NAL_MIN_ENCRYPTED_SIZE
+        {
+            encrypted.put_slice(&encrypt_nal(key, iv, nal));
+        } else {
+            encrypted.put_slice(nal);
+        }
+    }
+
+    encrypted
+}
+
+/*encrypt the frames of the AAC ADTS data*/
+pub fn encrypt_aac(key: &AesKey, iv: &AesIv, data: &[u8]) -> BytesMut {
+    let mut encrypted = BytesMut::from(data);
+    let mut pos = 0;
+
+    while pos + 7 <= encrypted.len() {
+        let header = &encrypted[pos..pos + 7];
+        if header[0] != 0xff || header[1] & 0xf0 != 0xf0 {
+            break;
+        }
+        //the crc is present if protection_absent is 0
+        let header_size = if header[1] & 0x01 > 0 { 7 } else { 9 };
+        let frame_size = (((header[3] & 0x03) as usize) << 11)
+            | ((header[4] as usize) << 3)
+            | ((header[5] as usize) >> 5);
+        if frame_size < header_size || pos + frame_size > encrypted.len() {
+            break;
+        }
+
+        let mut encryptor = Aes128CbcEnc::new(key.into(), iv.into());
+        let mut block_pos = pos + header_size + AAC_CLEAR_LEADER_SIZE;
+        while block_pos + AES_BLOCK_SIZE <= pos + frame_size {
+            encrypt_block(
+                &mut encryptor,
+                &mut encrypted[block_pos..block_pos + AES_BLOCK_SIZE],
+            );
+            block_pos += AES_BLOCK_SIZE;
+        }
+
+        pos += frame_size;
+    }
+
+    encrypted
+}
+
+/*the AudioSpecificConfig of the ADTS header, it is the audio setup information
+of the SAMPLE-AES AAC stream*/
+pub fn audio_specific_config(data: &[u8]) -> Option<[u8; 2]> {
+    if data.len() < 7 || data[0] != 0xff || data[1] & 0xf0 != 0xf0 {
+        return None;
+    }
+
+    let object_type = (data[2] >> 6) + 1;
+    let sampling_frequency_index = (data[2] >> 2) & 0x0f;
+    let channel_configuration = ((data[2] & 0x01) << 2) | (data[3] >> 6);
+
+    Some([
+        (object_type << 3) | (sampling_frequency_index >> 1),
+        ((sampling_frequency_index & 0x01) << 7) | (channel_configuration << 3),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{audio_specific_config, encrypt_aac, encrypt_h264};
+
+    #[test]
+    fn test_encrypt_h264() {
+        let key = [0x11_u8; 16];
+        let iv = [0x22_u8; 16];
+
+        //a SPS which is not encrypted and an IDR slice of 200 bytes
+        let mut data = vec![0x00, 0x00, 0x00, 0x01, 0x67, 0x64, 0x00, 0x1f];
+        data.extend_from_slice(&[0x00, 0x00, 0x01, 0x65]);
+        data.extend((0..199).map(|i| (i % 7) as u8 + 1));
+
+        let encrypted = encrypt_h264(&key, &iv, &data);
+        //the start codes, the SPS and the clear leader are kept
+        assert_eq!(&encrypted[..8 + 3 + 32], &data[..8 + 3 + 32]);
+        assert_ne!(&encrypted[..], &data[..]);
+        //only the first block after the leader is encrypted, the following 144 bytes are clear
+        assert_eq!(&encrypted[encrypted.len() - 16..], &data[data.len() - 16..]);
+
+        //there is no start code emulation in the encrypted slice
+        let slice = &encrypted[8 + 3..];
+        assert!(!slice
+            .windows(3)
+            .any(|window| window[0] == 0 && window[1] == 0 && window[2] <= 0x02));
+    }
+
+    #[test]
+    fn test_encrypt_aac() {
+        let key = [0x11_u8; 16];
+        let iv = [0x22_u8; 16];
+
+        //AAC LC, 44100Hz, stereo, 7 bytes header and 60 bytes frame
+        let frame_size: usize = 67;
+        let mut data = vec![
+            0xff,
+            0xf1,
+            0x50,
+            0x80 | (frame_size >> 11) as u8,
+            (frame_size >> 3) as u8,
+            ((frame_size & 0x07) << 5) as u8 | 0x1f,
+            0xfc,
+        ];
+        data.extend((0..60).map(|i| i as u8));
+
+        let encrypted = encrypt_aac(&key, &iv, &data);
+        //header and the leader of 16 bytes are clear, 2 blocks are encrypted, 12 bytes are clear
+        assert_eq!(&encrypted[..23], &data[..23]);
+        assert_ne!(&encrypted[23..55], &data[23..55]);
+        assert_eq!(&encrypted[55..], &data[55..]);
+
+        assert_eq!(audio_specific_config(&data), Some([0x12, 0x10]));
+    }
+}
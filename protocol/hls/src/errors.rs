@@ -6,6 +6,7 @@ use {
     std::fmt,
     tokio::sync::broadcast::error::RecvError,
     xflv::errors::FlvDemuxerError,
+    xfmp4::errors::Fmp4Error,
     xmpegts::errors::MpegTsError,
 };
 
@@ -38,8 +39,14 @@ pub enum MediaErrorValue {
     FlvDemuxerError(#[cause] FlvDemuxerError),
     #[fail(display = "mpegts error:{}\n", _0)]
     MpegTsError(#[cause] MpegTsError),
+    #[fail(display = "fmp4 error:{}\n", _0)]
+    Fmp4Error(#[cause] Fmp4Error),
     #[fail(display = "write file error:{}\n", _0)]
     IOError(#[cause] std::io::Error),
+    #[fail(display = "key provider error:{}\n", _0)]
+    KeyProviderError(String),
+    #[fail(display = "invalid app or stream name: {}", _0)]
+    InvalidPathName(String),
 }
 
 impl From<SessionError> for MediaError {
@@ -66,6 +73,14 @@ impl From<MpegTsError> for MediaError {
     }
 }
 
+impl From<Fmp4Error> for MediaError {
+    fn from(error: Fmp4Error) -> Self {
+        MediaError {
+            value: MediaErrorValue::Fmp4Error(error),
+        }
+    }
+}
+
 impl From<Amf0WriteError> for MediaError {
     fn from(error: Amf0WriteError) -> Self {
         MediaError {
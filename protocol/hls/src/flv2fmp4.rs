@@ -0,0 +1,233 @@
+use {
+    super::errors::MediaError,
+    bytes::BytesMut,
+    xflv::{
+        define::{aac_packet_type, avc_packet_type, frame_type, AvcCodecId, FlvData, SoundFormat},
+        demuxer_tag::{AudioTagHeaderDemuxer, VideoTagHeaderDemuxer},
+        errors::FlvDemuxerError,
+        mpeg4_aac::Mpeg4AacProcessor,
+        mpeg4_avc::Mpeg4AvcProcessor,
+    },
+    xfmp4::{
+        define::{AudioCodec, Fmp4Sample, TrackInfo, VideoCodec},
+        hevc,
+        muxer::Fmp4Muxer,
+    },
+};
+
+/*a demuxed audio or video frame*/
+pub struct Fmp4Frame {
+    pub is_video: bool,
+    pub sample: Fmp4Sample,
+}
+
+/*Demux the flv tags into the fmp4 tracks and samples, the flv sequence headers
+are the decoder configurations of the tracks and the frames are kept as they are.
+The init segment is generated when the first frame comes, so the tracks whose
+sequence headers come later are ignored.*/
+pub struct Flv2Fmp4Muxer {
+    fmp4_muxer: Fmp4Muxer,
+    video_track: Option<TrackInfo>,
+    audio_track: Option<TrackInfo>,
+    video_track_id: Option<u32>,
+    audio_track_id: Option<u32>,
+    is_init_generated: bool,
+}
+
+impl Default for Flv2Fmp4Muxer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Flv2Fmp4Muxer {
+    pub fn new() -> Self {
+        Self {
+            fmp4_muxer: Fmp4Muxer::new(),
+            video_track: None,
+            audio_track: None,
+            video_track_id: None,
+            audio_track_id: None,
+            is_init_generated: false,
+        }
+    }
+
+    pub fn demux(&mut self, data: FlvData) -> Result<Option<Fmp4Frame>, MediaError> {
+        match data {
+            FlvData::Video { timestamp, data } => self.demux_video(timestamp, data),
+            FlvData::Audio { timestamp, data } => self.demux_audio(timestamp, data),
+            _ => Ok(None),
+        }
+    }
+
+    fn demux_video(
+        &mut self,
+        timestamp: u32,
+        data: BytesMut,
+    ) -> Result<Option<Fmp4Frame>, MediaError> {
+        let mut tag_demuxer = VideoTagHeaderDemuxer::new(data);
+        let header = tag_demuxer.parse_tag_header()?;
+        let payload = tag_demuxer.get_remaining_bytes();
+
+        let codec = if header.codec_id == AvcCodecId::H264 as u8 {
+            VideoCodec::H264
+        } else if header.codec_id == AvcCodecId::HEVC as u8 {
+            VideoCodec::H265
+        } else {
+            return Ok(None);
+        };
+
+        match header.avc_packet_type {
+            avc_packet_type::AVC_SEQHDR => {
+                let (width, height) = match codec {
+                    VideoCodec::H264 => {
+                        let mut avc_processor = Mpeg4AvcProcessor::new();
+                        avc_processor
+                            .extend_data(payload.clone())
+                            .decoder_configuration_record_load()
+                            .map_err(FlvDemuxerError::from)?;
+                        (
+                            avc_processor.mpeg4_avc.width,
+                            avc_processor.mpeg4_avc.height,
+                        )
+                    }
+                    VideoCodec::H265 => hevc::get_resolution(&payload[..]).unwrap_or_else(|err| {
+                        log::warn!("parse hevc resolution error: {}", err);
+                        (0, 0)
+                    }),
+                };
+
+                self.set_track(
+                    true,
+                    TrackInfo::Video {
+                        codec,
+                        width,
+                        height,
+                        decoder_config: payload,
+                    },
+                );
+                Ok(None)
+            }
+            avc_packet_type::AVC_NALU => {
+                //the composition time is a signed 24 bits integer
+                let mut cts = header.composition_time as i64;
+                if cts & 0x80_0000 > 0 {
+                    cts -= 0x100_0000;
+                }
+
+                Ok(Some(Fmp4Frame {
+                    is_video: true,
+                    sample: Fmp4Sample {
+                        dts: timestamp as i64,
+                        cts,
+                        is_key: header.frame_type == frame_type::KEY_FRAME,
+                        data: payload,
+                    },
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn demux_audio(
+        &mut self,
+        timestamp: u32,
+        data: BytesMut,
+    ) -> Result<Option<Fmp4Frame>, MediaError> {
+        let mut tag_demuxer = AudioTagHeaderDemuxer::new(data);
+        let header = tag_demuxer.parse_tag_header()?;
+        let payload = tag_demuxer.get_remaining_bytes();
+
+        if header.sound_format != SoundFormat::AAC as u8 {
+            return Ok(None);
+        }
+
+        match header.aac_packet_type {
+            aac_packet_type::AAC_SEQHDR => {
+                let mut aac_processor = Mpeg4AacProcessor::new();
+                aac_processor
+                    .extend_data(payload.clone())
+                    .audio_specific_config_load()
+                    .map_err(FlvDemuxerError::from)?;
+
+                self.set_track(
+                    false,
+                    TrackInfo::Audio {
+                        codec: AudioCodec::Aac,
+                        sample_rate: aac_processor.mpeg4_aac.sampling_frequency,
+                        channels: aac_processor.mpeg4_aac.channels as u16,
+                        decoder_config: payload,
+                    },
+                );
+                Ok(None)
+            }
+            aac_packet_type::AAC_RAW => Ok(Some(Fmp4Frame {
+                is_video: false,
+                sample: Fmp4Sample {
+                    dts: timestamp as i64,
+                    cts: 0,
+                    is_key: true,
+                    data: payload,
+                },
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    fn set_track(&mut self, is_video: bool, track: TrackInfo) {
+        if self.is_init_generated {
+            log::warn!("the fmp4 tracks can not be changed after the init segment is generated");
+            return;
+        }
+        if is_video {
+            self.video_track = Some(track);
+        } else {
+            self.audio_track = Some(track);
+        }
+    }
+
+    /*generate the init segment when it is called the first time after the decoder
+    configurations are received*/
+    pub fn take_init_segment(&mut self) -> Result<Option<BytesMut>, MediaError> {
+        if self.is_init_generated || (self.video_track.is_none() && self.audio_track.is_none()) {
+            return Ok(None);
+        }
+
+        if let Some(video_track) = self.video_track.take() {
+            self.video_track_id = Some(self.fmp4_muxer.add_track(video_track));
+        }
+        if let Some(audio_track) = self.audio_track.take() {
+            self.audio_track_id = Some(self.fmp4_muxer.add_track(audio_track));
+        }
+        self.is_init_generated = true;
+
+        Ok(Some(self.fmp4_muxer.write_init_segment()?))
+    }
+
+    pub fn is_init_generated(&self) -> bool {
+        self.is_init_generated
+    }
+
+    /*the tracks of the init segment*/
+    pub fn get_tracks(&self) -> &[TrackInfo] {
+        self.fmp4_muxer.get_tracks()
+    }
+
+    pub fn write_frame(&mut self, frame: Fmp4Frame) -> Result<(), MediaError> {
+        let track_id = if frame.is_video {
+            self.video_track_id
+        } else {
+            self.audio_track_id
+        };
+
+        if let Some(track_id) = track_id {
+            self.fmp4_muxer.write_sample(track_id, frame.sample)?;
+        }
+        Ok(())
+    }
+
+    /*get the fragment of the frames written since the last call*/
+    pub fn get_data(&mut self, end_dts: i64) -> Result<BytesMut, MediaError> {
+        Ok(self.fmp4_muxer.write_media_segment(end_dts)?)
+    }
+}
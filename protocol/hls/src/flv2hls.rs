@@ -1,12 +1,22 @@
 use {
-    super::{define::FlvDemuxerData, errors::MediaError, m3u8::M3u8},
+    super::{
+        define::{FlvDemuxerData, HlsContainer, HlsEncryption, HlsOptions},
+        encryption::{key::HlsKeyProviderRef, sample_aes},
+        errors::MediaError,
+        flv2fmp4::Flv2Fmp4Muxer,
+        m3u8::M3u8,
+        store::HlsStoreRef,
+        timed_metadata::{self, SpliceCue, TimedMetadata},
+    },
     bytes::BytesMut,
+    std::time::Duration,
     xflv::{
         define::{frame_type, FlvData},
         demuxer::{FlvAudioTagDemuxer, FlvVideoTagDemuxer},
     },
     xmpegts::{
         define::{epsi_stream_type, MPEG_FLAG_IDR_FRAME},
+        id3, sample_aes as ts_sample_aes,
         ts::TsMuxer,
     },
 };
@@ -16,57 +26,203 @@ pub struct Flv2HlsRemuxer {
     audio_demuxer: FlvAudioTagDemuxer,
 
     ts_muxer: TsMuxer,
+    //only used for the fmp4 container
+    fmp4_muxer: Option<Flv2Fmp4Muxer>,
 
     last_ts_dts: i64,
     last_ts_pts: i64,
 
     last_dts: i64,
     last_pts: i64,
+    //the first segment begins with the first frame
+    is_started: bool,
 
     duration: i64,
     need_new_segment: bool,
+    align_segments: bool,
+    /*the segment is cut at any frame when it reaches max_duration milliseconds,
+    0 means no limit*/
+    max_duration: i64,
+    /*a timestamp jump longer than discontinuity_threshold milliseconds between two frames
+    begins a new segment with EXT-X-DISCONTINUITY, 0 disables it*/
+    discontinuity_threshold: i64,
+    //the segment in progress begins after a timestamp jump
+    discontinuity: bool,
+    //the segments of the audio only streams are cut at any audio frame
+    has_video: bool,
 
     video_pid: u16,
     audio_pid: u16,
+    //the ID3 timed metadata stream, it is only added when the timed metadata is enabled
+    id3_pid: Option<u16>,
+
+    /*the splice point of an ad break begins a segment at the next boundary, the
+    ad break with a duration returns to the program automatically at auto_return_dts*/
+    splice_cue: Option<SpliceCue>,
+    auto_return_dts: Option<i64>,
+
+    /*LL-HLS, the segment is cut into partial segments of part_duration milliseconds,
+    and the segment data is the concatenation of its parts. A part is cut before the
+    next frame could take it past part_duration, part_frame_interval is the largest
+    frame interval of the part in progress*/
+    low_latency: bool,
+    part_duration: i64,
+    last_part_dts: i64,
+    part_frame_interval: i64,
+    part_independent: bool,
+    segment_data: BytesMut,
+
+    /*SAMPLE-AES, the H.264 slices and the AAC frames are encrypted before they
+    are muxed, the audio setup is written into the PMT with the first AAC frame*/
+    sample_aes: bool,
+    audio_setup_written: bool,
 
     m3u8_handler: M3u8,
 }
 
 impl Flv2HlsRemuxer {
-    pub fn new(duration: i64, app_name: String, stream_name: String) -> Self {
+    pub fn new(
+        app_name: String,
+        stream_name: String,
+        store: HlsStoreRef,
+        options: &HlsOptions,
+    ) -> Self {
+        let mut encryption = options.encryption;
+        if encryption == HlsEncryption::SampleAes && options.container == HlsContainer::Fmp4 {
+            log::warn!("SAMPLE-AES is only supported by the ts container, use AES-128 instead");
+            encryption = HlsEncryption::Aes128;
+        }
+        let sample_aes = encryption == HlsEncryption::SampleAes;
+
         let mut ts_muxer = TsMuxer::new();
-        let audio_pid = ts_muxer
-            .add_stream(epsi_stream_type::PSI_STREAM_AAC, BytesMut::new())
-            .unwrap();
-        let video_pid = ts_muxer
-            .add_stream(epsi_stream_type::PSI_STREAM_H264, BytesMut::new())
-            .unwrap();
+        if options.timed_metadata {
+            ts_muxer
+                .add_program(1, id3::metadata_pointer_descriptor(1))
+                .unwrap();
+        }
+        let (audio_pid, video_pid) = if sample_aes {
+            (
+                ts_muxer
+                    .add_stream(epsi_stream_type::PSI_STREAM_AAC_SAMPLE_AES, BytesMut::new())
+                    .unwrap(),
+                ts_muxer
+                    .add_stream(
+                        epsi_stream_type::PSI_STREAM_H264_SAMPLE_AES,
+                        ts_sample_aes::h264_descriptor(),
+                    )
+                    .unwrap(),
+            )
+        } else {
+            (
+                ts_muxer
+                    .add_stream(epsi_stream_type::PSI_STREAM_AAC, BytesMut::new())
+                    .unwrap(),
+                ts_muxer
+                    .add_stream(epsi_stream_type::PSI_STREAM_H264, BytesMut::new())
+                    .unwrap(),
+            )
+        };
+        let id3_pid = if options.timed_metadata {
+            Some(
+                ts_muxer
+                    .add_stream(
+                        epsi_stream_type::PSI_STREAM_METADATA,
+                        id3::metadata_descriptor(),
+                    )
+                    .unwrap(),
+            )
+        } else {
+            None
+        };
 
         let m3u8_name = format!("{stream_name}.m3u8");
+        let mut m3u8_handler = M3u8::new(
+            options.duration * 1000,
+            options.live_ts_count,
+            m3u8_name,
+            app_name,
+            stream_name,
+            store,
+        );
+        m3u8_handler.set_deletion_delay(Duration::from_secs(options.deletion_delay));
+        m3u8_handler.set_segment_name_template(options.segment_name.clone());
+        m3u8_handler.set_dvr_window(options.dvr_window as i64 * 1000);
+        m3u8_handler.set_archive(options.archive);
+        //the max duration can not be shorter than the target duration
+        let max_duration = if options.max_duration > 0 {
+            options.max_duration.max(options.duration) * 1000
+        } else {
+            0
+        };
+        m3u8_handler.set_max_duration(max_duration);
+        m3u8_handler.set_encryption(encryption, options.key_rotation, options.key_uri.clone());
+        m3u8_handler.set_ad_markers(options.ad_markers);
+        if options.low_latency {
+            m3u8_handler.set_low_latency(options.part_duration);
+        }
+        let fmp4_muxer = match options.container {
+            HlsContainer::Fmp4 => {
+                m3u8_handler.set_fmp4();
+                Some(Flv2Fmp4Muxer::new())
+            }
+            HlsContainer::Ts => None,
+        };
 
         Self {
             video_demuxer: FlvVideoTagDemuxer::new(),
             audio_demuxer: FlvAudioTagDemuxer::new(),
 
             ts_muxer,
+            fmp4_muxer,
 
             last_ts_dts: 0,
             last_ts_pts: 0,
 
             last_dts: 0,
             last_pts: 0,
+            is_started: false,
 
-            duration,
+            duration: options.duration,
             need_new_segment: false,
+            align_segments: options.align_segments,
+            max_duration,
+            discontinuity_threshold: options.discontinuity_threshold * 1000,
+            discontinuity: false,
+            has_video: false,
 
             video_pid,
             audio_pid,
+            id3_pid,
+
+            splice_cue: None,
+            auto_return_dts: None,
+
+            low_latency: options.low_latency,
+            part_duration: options.part_duration,
+            last_part_dts: 0,
+            part_frame_interval: 0,
+            part_independent: false,
+            segment_data: BytesMut::new(),
+
+            sample_aes,
+            audio_setup_written: false,
 
-            m3u8_handler: M3u8::new(duration, 6, m3u8_name, app_name, stream_name),
+            m3u8_handler,
         }
     }
 
+    pub fn set_key_provider(&mut self, key_provider: HlsKeyProviderRef) {
+        self.m3u8_handler.set_key_provider(key_provider);
+    }
+
     pub fn process_flv_data(&mut self, data: FlvData) -> Result<(), MediaError> {
+        if let FlvData::MetaData { timestamp, data } = data {
+            return self.process_metadata(timestamp as i64, data);
+        }
+        if self.fmp4_muxer.is_some() {
+            return self.process_fmp4_data(data);
+        }
+
         let flv_demux_data: FlvDemuxerData = match data {
             FlvData::Audio { timestamp, data } => {
                 let audio_data = self.audio_demuxer.demux(timestamp, data)?;
@@ -84,15 +240,108 @@ impl Flv2HlsRemuxer {
         Ok(())
     }
 
-    pub fn flush_remaining_data(&mut self) -> Result<(), MediaError> {
-        let data = self.ts_muxer.get_data();
-        let mut discontinuity: bool = false;
-        if self.last_dts > self.last_ts_dts + 15 * 1000 {
-            discontinuity = true;
+    /*The ad break cue points are signaled in the playlist from the next segment
+    boundary, and the ID3 tags are muxed into the metadata stream of the ts, they
+    are dropped by the fmp4 container or when the timed metadata is disabled.*/
+    fn process_metadata(&mut self, timestamp: i64, data: BytesMut) -> Result<(), MediaError> {
+        match timed_metadata::parse_data_message(data) {
+            Some(TimedMetadata::Splice(cue)) => {
+                self.auto_return_dts = match cue {
+                    SpliceCue::Out {
+                        duration: Some(duration),
+                        ..
+                    } => Some(timestamp + duration),
+                    _ => None,
+                };
+                self.splice_cue = Some(cue);
+            }
+            //the PCR is carried by the media streams which begin the ts
+            Some(TimedMetadata::Id3(tag)) if self.fmp4_muxer.is_none() && self.is_started => {
+                if let Some(id3_pid) = self.id3_pid {
+                    self.ts_muxer
+                        .write(id3_pid, timestamp * 90, timestamp * 90, 0, tag)?;
+                }
+            }
+            Some(TimedMetadata::Id3(_)) | None => {}
+        }
+
+        Ok(())
+    }
+
+    fn process_fmp4_data(&mut self, data: FlvData) -> Result<(), MediaError> {
+        let fmp4_muxer = match self.fmp4_muxer.as_mut() {
+            Some(fmp4_muxer) => fmp4_muxer,
+            None => return Ok(()),
+        };
+
+        let frame = match fmp4_muxer.demux(data)? {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        if let Some(init_segment) = fmp4_muxer.take_init_segment()? {
+            self.m3u8_handler.set_init_segment(init_segment)?;
+        }
+        //the frames before the decoder configurations can not be decoded
+        if !fmp4_muxer.is_init_generated() {
+            return Ok(());
+        }
+
+        let dts = frame.sample.dts;
+        let pts = dts + frame.sample.cts;
+        self.cut_segment(dts, pts, frame.is_video, frame.sample.is_key)?;
+
+        self.last_dts = dts;
+        self.last_pts = pts;
+
+        if let Some(fmp4_muxer) = self.fmp4_muxer.as_mut() {
+            fmp4_muxer.write_frame(frame)?;
+        }
+
+        Ok(())
+    }
+
+    /*get the muxed data since the last call, dts is where the data ends*/
+    fn get_muxed_data(&mut self, dts: i64) -> Result<BytesMut, MediaError> {
+        match self.fmp4_muxer.as_mut() {
+            Some(fmp4_muxer) => fmp4_muxer.get_data(dts),
+            None => Ok(self.ts_muxer.get_data()),
+        }
+    }
+
+    /*cut the muxed data since the last part into a partial segment*/
+    fn cut_part(&mut self, dts: i64) -> Result<(), MediaError> {
+        let data = self.get_muxed_data(dts)?;
+        if !data.is_empty() {
+            self.segment_data.extend_from_slice(&data[..]);
+            self.m3u8_handler
+                .add_part(dts - self.last_part_dts, self.part_independent, data)?;
+        }
+        self.last_part_dts = dts;
+
+        Ok(())
+    }
+
+    /*the part in progress ends at dts if the next frame, expected after the largest
+    frame interval of the part, would take it past the part target*/
+    fn is_part_full(&self, dts: i64) -> bool {
+        let elapsed = dts - self.last_part_dts;
+        elapsed > 0 && elapsed + self.part_frame_interval > self.part_duration
+    }
+
+    fn get_segment_data(&mut self, dts: i64) -> Result<BytesMut, MediaError> {
+        if self.low_latency {
+            self.cut_part(dts)?;
+            Ok(self.segment_data.split())
+        } else {
+            self.get_muxed_data(dts)
         }
+    }
+
+    pub fn flush_remaining_data(&mut self) -> Result<(), MediaError> {
+        let data = self.get_segment_data(self.last_dts)?;
         self.m3u8_handler.add_segment(
             self.last_dts - self.last_ts_dts,
-            discontinuity,
+            self.discontinuity,
             true,
             data,
         )?;
@@ -110,6 +359,7 @@ impl Flv2HlsRemuxer {
         let pid: u16;
         let pts: i64;
         let dts: i64;
+        let is_video: bool;
         let mut flags: u16 = 0;
         let mut payload: BytesMut = BytesMut::new();
 
@@ -122,13 +372,11 @@ impl Flv2HlsRemuxer {
                 pts = data.pts;
                 dts = data.dts;
                 pid = self.video_pid;
+                is_video = true;
                 payload.extend_from_slice(&data.data[..]);
 
                 if data.frame_type == frame_type::KEY_FRAME {
                     flags = MPEG_FLAG_IDR_FRAME;
-                    if dts - self.last_ts_dts >= self.duration * 1000 {
-                        self.need_new_segment = true;
-                    }
                 }
             }
             FlvDemuxerData::Audio { data } => {
@@ -139,37 +387,152 @@ impl Flv2HlsRemuxer {
                 pts = data.pts;
                 dts = data.dts;
                 pid = self.audio_pid;
+                is_video = false;
                 payload.extend_from_slice(&data.data[..]);
             }
             _ => return Ok(()),
         }
 
-        if self.need_new_segment {
-            let mut discontinuity: bool = false;
-            if dts > self.last_ts_dts + 15 * 1000 {
-                discontinuity = true;
+        self.cut_segment(dts, pts, is_video, flags & MPEG_FLAG_IDR_FRAME > 0)?;
+
+        self.last_dts = dts;
+        self.last_pts = pts;
+
+        if self.sample_aes {
+            payload = self.encrypt_sample(pid, payload)?;
+        }
+
+        self.ts_muxer
+            .write(pid, pts * 90, dts * 90, flags, payload)?;
+
+        Ok(())
+    }
+
+    /*SAMPLE-AES encrypts the payload with the key of the segment in progress*/
+    fn encrypt_sample(&mut self, pid: u16, payload: BytesMut) -> Result<BytesMut, MediaError> {
+        if pid == self.audio_pid && !self.audio_setup_written {
+            if let Some(audio_specific_config) = sample_aes::audio_specific_config(&payload[..]) {
+                self.ts_muxer
+                    .set_stream_info(pid, ts_sample_aes::aac_descriptor(&audio_specific_config))?;
+                self.audio_setup_written = true;
             }
-            let data = self.ts_muxer.get_data();
+        }
 
-            self.m3u8_handler
-                .add_segment(dts - self.last_ts_dts, discontinuity, false, data)?;
+        let (key, iv) = match self.m3u8_handler.get_segment_key()? {
+            Some(key_iv) => key_iv,
+            None => return Ok(payload),
+        };
+        if pid == self.video_pid {
+            Ok(sample_aes::encrypt_h264(&key, &iv, &payload[..]))
+        } else {
+            Ok(sample_aes::encrypt_aac(&key, &iv, &payload[..]))
+        }
+    }
+
+    /*Cut a new segment(or a partial segment for LL-HLS) before the frame is written.
+    A segment begins with a video key frame(any audio frame for the audio only streams)
+    after the target duration or at a splice point, and it is cut at any frame when it
+    reaches the max duration or the timestamp jumps.*/
+    fn cut_segment(
+        &mut self,
+        dts: i64,
+        pts: i64,
+        is_video: bool,
+        is_key_frame: bool,
+    ) -> Result<(), MediaError> {
+        if !self.is_started {
+            self.is_started = true;
+            self.last_ts_dts = dts;
+            self.last_ts_pts = pts;
+            self.last_part_dts = dts;
+            self.last_dts = dts;
+            self.last_pts = pts;
+        }
+        if is_video {
+            self.has_video = true;
+        }
+        self.part_frame_interval = std::cmp::max(self.part_frame_interval, dts - self.last_dts);
+        let is_boundary = if self.has_video {
+            is_video && is_key_frame
+        } else {
+            true
+        };
+
+        let duration = self.duration * 1000;
+        let elapsed = dts - self.last_ts_dts;
+        let is_duration_reached = if self.align_segments {
+            duration > 0 && dts / duration > self.last_ts_dts / duration
+        } else {
+            elapsed >= duration
+        };
+        let is_jumped = self.discontinuity_threshold > 0
+            && (dts - self.last_dts).abs() > self.discontinuity_threshold;
+        if self
+            .auto_return_dts
+            .is_some_and(|auto_return_dts| dts >= auto_return_dts)
+        {
+            self.auto_return_dts = None;
+            self.splice_cue = Some(SpliceCue::In);
+        }
+        let is_splice = is_boundary && self.splice_cue.is_some();
+        self.need_new_segment = is_jumped
+            || (is_boundary && is_duration_reached)
+            || (is_splice && elapsed > 0)
+            || (self.max_duration > 0 && elapsed >= self.max_duration);
+
+        if self.need_new_segment {
+            //the segment ends with the last frame before the timestamp jump
+            let end_dts = if is_jumped { self.last_dts } else { dts };
+            let data = self.get_segment_data(end_dts)?;
+
+            self.m3u8_handler.add_segment(
+                end_dts - self.last_ts_dts,
+                self.discontinuity,
+                false,
+                data,
+            )?;
+            self.discontinuity = is_jumped;
             self.m3u8_handler.refresh_playlist()?;
 
             self.ts_muxer.reset();
             self.last_ts_dts = dts;
             self.last_ts_pts = pts;
+            self.last_part_dts = dts;
+            self.part_frame_interval = 0;
             self.need_new_segment = false;
+            self.part_independent = is_boundary;
+        } else if self.low_latency && self.is_part_full(dts) {
+            self.cut_part(dts)?;
+            self.m3u8_handler.refresh_playlist()?;
+            self.part_frame_interval = 0;
+            self.part_independent = is_key_frame;
         }
 
-        self.last_dts = dts;
-        self.last_pts = pts;
-
-        self.ts_muxer
-            .write(pid, pts * 90, dts * 90, flags, payload)?;
+        //the segment in progress begins at the splice point
+        if is_splice {
+            if let Some(cue) = self.splice_cue.take() {
+                self.m3u8_handler.set_splice_cue(cue);
+            }
+        }
 
         Ok(())
     }
 
+    /*write the remaining data as the last segment and archive the segments with a
+    VOD playlist, returns the path of the archive and the duration in milliseconds*/
+    pub fn archive_files(&mut self) -> Result<(String, i64), MediaError> {
+        if self.last_dts > self.last_ts_dts {
+            let data = self.get_segment_data(self.last_dts)?;
+            self.m3u8_handler.add_segment(
+                self.last_dts - self.last_ts_dts,
+                self.discontinuity,
+                false,
+                data,
+            )?;
+        }
+        self.m3u8_handler.archive()
+    }
+
     pub fn clear_files(&mut self) -> Result<(), MediaError> {
         self.m3u8_handler.clear()
     }
@@ -1,20 +1,24 @@
 use {
     super::{
+        define::{DemandActivity, HlsOptions},
+        encryption::key::HlsKeyProviderRef,
         errors::{HlsError, HlsErrorValue},
         flv2hls::Flv2HlsRemuxer,
+        store::HlsStoreRef,
     },
     rtmp::channels::define::{
         ChannelData, ChannelDataConsumer, ChannelEvent, ChannelEventProducer,
     },
+    rtmp::notify::{Notifier, RecordInfo},
     rtmp::session::{
-        common::{NotifyInfo, SubscriberInfo},
+        common::{NotifyInfo, SubscribeOptions, SubscriberInfo},
         define::SubscribeType,
         errors::{SessionError, SessionErrorValue},
     },
-    std::time::Duration,
+    std::{sync::Arc, time::Duration},
     tokio::{
         sync::{mpsc, oneshot},
-        time::sleep,
+        time::{interval, sleep},
     },
     uuid::Uuid,
     xflv::define::FlvData,
@@ -22,6 +26,9 @@ use {
 
 ////https://www.jianshu.com/p/d6311f03b81f
 
+//how often an on demand stream is checked for idleness, it also stops when the publisher stalls
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
 pub struct FlvDataReceiver {
     app_name: String,
     stream_name: String,
@@ -30,6 +37,13 @@ pub struct FlvDataReceiver {
     data_consumer: ChannelDataConsumer,
     media_processor: Flv2HlsRemuxer,
     subscriber_id: Uuid,
+    archive: bool,
+    //used to send the on_record_done notification
+    notifier: Option<Arc<Notifier>>,
+    /*an on demand stream stops being remuxed when there is no playlist
+    request for idle_timeout*/
+    activity: Option<DemandActivity>,
+    idle_timeout: Duration,
 }
 
 impl FlvDataReceiver {
@@ -37,8 +51,8 @@ impl FlvDataReceiver {
         app_name: String,
         stream_name: String,
         event_producer: ChannelEventProducer,
-
-        duration: i64,
+        store: HlsStoreRef,
+        options: &HlsOptions,
     ) -> Self {
         let (_, data_consumer) = mpsc::unbounded_channel();
         let subscriber_id = Uuid::new_v4();
@@ -49,11 +63,27 @@ impl FlvDataReceiver {
 
             data_consumer,
             event_producer,
-            media_processor: Flv2HlsRemuxer::new(duration, app_name, stream_name),
+            media_processor: Flv2HlsRemuxer::new(app_name, stream_name, store, options),
             subscriber_id,
+            archive: options.archive,
+            notifier: None,
+            activity: None,
+            idle_timeout: Duration::from_secs(options.idle_timeout),
         }
     }
 
+    pub fn set_notifier(&mut self, notifier: Arc<Notifier>) {
+        self.notifier = Some(notifier);
+    }
+
+    pub fn set_demand_activity(&mut self, activity: DemandActivity) {
+        self.activity = Some(activity);
+    }
+
+    pub fn set_key_provider(&mut self, key_provider: HlsKeyProviderRef) {
+        self.media_processor.set_key_provider(key_provider);
+    }
+
     pub async fn run(&mut self) -> Result<(), HlsError> {
         self.subscribe_from_rtmp_channels(self.app_name.clone(), self.stream_name.clone())
             .await?;
@@ -64,19 +94,37 @@ impl FlvDataReceiver {
 
     pub async fn receive_flv_data(&mut self) -> Result<(), HlsError> {
         let mut retry_count = 0;
+        let mut idle_check = interval(IDLE_CHECK_INTERVAL);
 
         loop {
-            if let Some(data) = self.data_consumer.recv().await {
-                let flv_data: FlvData = match data {
-                    ChannelData::Audio { timestamp, data } => FlvData::Audio { timestamp, data },
-                    ChannelData::Video { timestamp, data } => FlvData::Video { timestamp, data },
-                    _ => continue,
-                };
-                retry_count = 0;
-                self.media_processor.process_flv_data(flv_data)?;
-            } else {
-                sleep(Duration::from_millis(100)).await;
-                retry_count += 1;
+            tokio::select! {
+                data = self.data_consumer.recv() => {
+                    if let Some(data) = data {
+                        let flv_data: FlvData = match data {
+                            ChannelData::Audio { timestamp, data } => FlvData::Audio { timestamp, data },
+                            ChannelData::Video { timestamp, data } => FlvData::Video { timestamp, data },
+                            //the cue points and the text data
+                            ChannelData::MetaData { timestamp, data } => {
+                                FlvData::MetaData { timestamp, data }
+                            }
+                        };
+                        retry_count = 0;
+                        self.media_processor.process_flv_data(flv_data)?;
+                    } else {
+                        sleep(Duration::from_millis(100)).await;
+                        retry_count += 1;
+                    }
+                }
+                _ = idle_check.tick(), if self.activity.is_some() => {
+                    if self.is_idle() {
+                        log::info!(
+                            "hls stream {}/{} is idle, stop remuxing",
+                            self.app_name,
+                            self.stream_name
+                        );
+                        break;
+                    }
+                }
             }
             //When rtmp stream is interupted here we retry 10 times.
             //maybe have a better way to judge the stream status.
@@ -87,10 +135,43 @@ impl FlvDataReceiver {
             }
         }
 
-        self.media_processor.clear_files()?;
+        if self.archive {
+            self.archive_files().await?;
+        } else {
+            self.media_processor.clear_files()?;
+        }
         self.unsubscribe_from_rtmp_channels().await
     }
 
+    fn is_idle(&self) -> bool {
+        match &self.activity {
+            Some(activity) => activity.idle_time() > self.idle_timeout,
+            None => false,
+        }
+    }
+
+    async fn archive_files(&mut self) -> Result<(), HlsError> {
+        let (path, duration) = self.media_processor.archive_files()?;
+        log::info!(
+            "hls archive of {}/{} is done: {}",
+            self.app_name,
+            self.stream_name,
+            path
+        );
+
+        if let Some(notifier) = &self.notifier {
+            let record_info = RecordInfo {
+                app_name: self.app_name.clone(),
+                stream_name: self.stream_name.clone(),
+                path,
+                duration: duration as f64 / 1000.0,
+            };
+            notifier.on_record_done_notify(&record_info).await;
+        }
+
+        Ok(())
+    }
+
     pub fn flush_response_data(&mut self) -> Result<(), HlsError> {
         Ok(())
     }
@@ -112,6 +193,7 @@ impl FlvDataReceiver {
                     request_url: String::from(""),
                     remote_addr: String::from(""),
                 },
+                options: SubscribeOptions::default(),
             };
 
             let subscribe_event = ChannelEvent::Subscribe {
@@ -163,6 +245,7 @@ impl FlvDataReceiver {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
             },
+            options: SubscribeOptions::default(),
         };
 
         let subscribe_event = ChannelEvent::UnSubscribe {
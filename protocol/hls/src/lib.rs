@@ -1,9 +1,15 @@
 pub mod define;
+pub mod encryption;
 pub mod errors;
+pub mod flv2fmp4;
 pub mod flv2hls;
 pub mod flv_data_receiver;
 pub mod m3u8;
 pub mod rtmp_event_processor;
 pub mod server;
+pub mod session;
+pub mod store;
 mod test_flv2hls;
+pub mod timed_metadata;
 pub mod ts;
+pub mod variant;
@@ -1,17 +1,75 @@
 use {
-    super::{errors::MediaError, ts::Ts},
-    bytes::BytesMut,
-    std::{collections::VecDeque, fs, fs::File, io::Write},
+    super::{
+        define::{HlsAdMarkers, HlsEncryption, DEFAULT_KEY_URI},
+        encryption::{
+            self,
+            key::{HlsKeyProviderRef, RandomKeyProvider},
+            AesIv, AesKey,
+        },
+        errors::MediaError,
+        store::HlsStoreRef,
+        timed_metadata::SpliceCue,
+        ts::Ts,
+    },
+    bytes::{Bytes, BytesMut},
+    chrono::{DateTime, Duration as ChronoDuration, SecondsFormat, Utc},
+    std::{
+        collections::{BTreeSet, VecDeque},
+        sync::Arc,
+        time::{Duration, Instant},
+    },
+    xmpegts::scte35,
 };
 
+/*the partial segment of LL-HLS*/
+pub struct Part {
+    duration: i64,
+    name: String,
+    /*the part begins with a key frame*/
+    independent: bool,
+}
+
+/*the ad break signaling of a segment*/
+enum SegmentCue {
+    //the ad break begins with the segment
+    Out {
+        id: u32,
+        duration: Option<i64>,
+    },
+    //the segment is in the ad break which has lasted for elapsed milliseconds
+    Cont {
+        elapsed: i64,
+        duration: Option<i64>,
+    },
+    //the ad break which lasted for duration milliseconds ends before the segment
+    In {
+        id: u32,
+        start_time: DateTime<Utc>,
+        duration: i64,
+    },
+}
+
+/*the ad break in progress*/
+struct AdBreak {
+    id: u32,
+    start_time: DateTime<Utc>,
+    elapsed: i64,
+    duration: Option<i64>,
+}
+
 pub struct Segment {
     /*ts duration*/
     duration: i64,
     discontinuity: bool,
     /*ts name*/
     name: String,
-    path: String,
     is_eof: bool,
+    parts: Vec<Part>,
+    /*the wall clock time of the first frame, it is the EXT-X-PROGRAM-DATE-TIME*/
+    program_date_time: DateTime<Utc>,
+    /*the index of the key which encrypts the segment*/
+    key_index: Option<u64>,
+    cue: Option<SegmentCue>,
 }
 
 impl Segment {
@@ -19,15 +77,66 @@ impl Segment {
         duration: i64,
         discontinuity: bool,
         name: String,
-        path: String,
         is_eof: bool,
+        program_date_time: DateTime<Utc>,
     ) -> Self {
         Self {
             duration,
             discontinuity,
             name,
-            path,
             is_eof,
+            parts: Vec::new(),
+            program_date_time,
+            key_index: None,
+            cue: None,
+        }
+    }
+}
+
+/*The position of the latest segment in a playlist, used by the blocking playlist reload
+of LL-HLS, msn is the media sequence number of the segment in progress and part_count
+is the count of its published partial segments.*/
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PlaylistPosition {
+    pub target_duration: u64,
+    pub msn: u64,
+    pub part_count: u64,
+    pub is_end: bool,
+}
+
+impl PlaylistPosition {
+    pub fn parse(content: &str) -> Self {
+        let mut position = PlaylistPosition::default();
+        let mut sequence_no: u64 = 0;
+        let mut segment_count: u64 = 0;
+
+        for line in content.lines() {
+            if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+                position.target_duration = value.trim().parse().unwrap_or_default();
+            } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+                sequence_no = value.trim().parse().unwrap_or_default();
+            } else if line.starts_with("#EXTINF:") {
+                segment_count += 1;
+                position.part_count = 0;
+            } else if line.starts_with("#EXT-X-PART:") {
+                position.part_count += 1;
+            } else if line.starts_with("#EXT-X-ENDLIST") {
+                position.is_end = true;
+            }
+        }
+        position.msn = sequence_no + segment_count;
+
+        position
+    }
+
+    /*if the playlist contains the segment msn, or its partial segment part*/
+    pub fn contains(&self, msn: u64, part: Option<u64>) -> bool {
+        if msn < self.msn {
+            return true;
+        }
+        match part {
+            Some(part) => msn == self.msn && part < self.part_count,
+            None => false,
         }
     }
 }
@@ -44,13 +153,50 @@ pub struct M3u8 {
     /*How many files should be listed in the index file during a continuous, ongoing session?
     The normal recommendation is 3, but the optimum number may be larger.*/
     live_ts_count: usize,
+    /*DVR, the segments within the window(milliseconds) are kept and listed in the
+    playlist with EXT-X-PROGRAM-DATE-TIME instead of the last live_ts_count ones*/
+    dvr_window: i64,
 
     segments: VecDeque<Segment>,
+    /*the segments removed from the playlist are deleted after the deletion delay,
+    the players who have loaded the old playlist may still request them*/
+    deletion_delay: Duration,
+    expired_segments: VecDeque<(Instant, String)>,
+    /*live-to-VOD, the segments removed from the playlist are kept for the VOD playlist
+    which is written when the stream ends*/
+    archive: bool,
+    archived_segments: VecDeque<Segment>,
     is_header_generated: bool,
 
+    /*LL-HLS, the max duration of the partial segments and the parts of the segment in progress*/
+    low_latency: bool,
+    part_duration: i64,
+    parts: Vec<Part>,
+
+    /*the init segment of fmp4, it is referenced by EXT-X-MAP*/
+    init_name: Option<String>,
+
+    /*the segments are encrypted by the keys rotated every key_rotation segments,
+    the iv of a segment is its media sequence number*/
+    encryption: HlsEncryption,
+    key_provider: HlsKeyProviderRef,
+    key_rotation: u64,
+    key_uri: String,
+    //the key of the segment in progress
+    current_key: Option<(u64, AesKey)>,
+
+    /*the splice point at the beginning of the segment in progress and the ad break
+    in progress, the splice event ids are generated if the cue points have none*/
+    ad_markers: HlsAdMarkers,
+    splice_cue: Option<SpliceCue>,
+    ad_break: Option<AdBreak>,
+    splice_event_id: u32,
+
     m3u8_header: String,
-    m3u8_folder: String,
     m3u8_name: String,
+    app_name: String,
+    stream_name: String,
+    store: HlsStoreRef,
 
     ts_handler: Ts,
 }
@@ -62,24 +208,348 @@ impl M3u8 {
         name: String,
         app_name: String,
         stream_name: String,
+        store: HlsStoreRef,
     ) -> Self {
-        let m3u8_folder = format!("./{app_name}/{stream_name}");
-        fs::create_dir_all(m3u8_folder.clone()).unwrap();
         Self {
             version: 3,
             sequence_no: 0,
             duration,
             is_live: true,
             live_ts_count,
+            dvr_window: 0,
             segments: VecDeque::new(),
+            deletion_delay: Duration::ZERO,
+            expired_segments: VecDeque::new(),
+            archive: false,
+            archived_segments: VecDeque::new(),
             is_header_generated: false,
-            m3u8_folder,
+            low_latency: false,
+            part_duration: 0,
+            parts: Vec::new(),
+            init_name: None,
+            encryption: HlsEncryption::None,
+            key_provider: Arc::new(RandomKeyProvider::default()),
+            key_rotation: 0,
+            key_uri: String::from(DEFAULT_KEY_URI),
+            current_key: None,
+            ad_markers: HlsAdMarkers::Cue,
+            splice_cue: None,
+            ad_break: None,
+            splice_event_id: 0,
             m3u8_header: String::new(),
             m3u8_name: name,
-            ts_handler: Ts::new(app_name, stream_name),
+            ts_handler: Ts::new(app_name.clone(), stream_name.clone(), store.clone()),
+            app_name,
+            stream_name,
+            store,
+        }
+    }
+
+    pub fn set_deletion_delay(&mut self, deletion_delay: Duration) {
+        self.deletion_delay = deletion_delay;
+    }
+
+    /*dvr_window is the time-shift window in milliseconds, 0 disables the DVR*/
+    pub fn set_dvr_window(&mut self, dvr_window: i64) {
+        self.dvr_window = dvr_window;
+    }
+
+    pub fn set_archive(&mut self, archive: bool) {
+        self.archive = archive;
+    }
+
+    pub fn set_segment_name_template(&mut self, name_template: String) {
+        self.ts_handler.set_name_template(name_template);
+    }
+
+    /*the segments are not longer than max_duration milliseconds, the target duration
+    covers it from the beginning so it is not changed while the stream is live*/
+    pub fn set_max_duration(&mut self, max_duration: i64) {
+        self.duration = std::cmp::max(max_duration, self.duration);
+    }
+
+    /*part_duration is the target duration of the partial segments in milliseconds*/
+    pub fn set_low_latency(&mut self, part_duration: i64) {
+        //EXT-X-PART-INF and EXT-X-SERVER-CONTROL
+        self.version = self.version.max(6);
+        self.low_latency = true;
+        self.part_duration = part_duration;
+    }
+
+    /*use the fragmented mp4 segments*/
+    pub fn set_fmp4(&mut self) {
+        //EXT-X-MAP for the fmp4 segments
+        self.version = self.version.max(7);
+        self.ts_handler.set_extension("m4s");
+    }
+
+    /*key_rotation is how many segments are encrypted by a key, 0 means one key for
+    the stream, key_uri is the uri template of the keys in the playlist*/
+    pub fn set_encryption(
+        &mut self,
+        encryption: HlsEncryption,
+        key_rotation: u64,
+        key_uri: String,
+    ) {
+        if encryption == HlsEncryption::SampleAes {
+            //METHOD=SAMPLE-AES
+            self.version = self.version.max(5);
+        }
+        self.encryption = encryption;
+        self.key_rotation = key_rotation;
+        self.key_uri = key_uri;
+    }
+
+    pub fn set_key_provider(&mut self, key_provider: HlsKeyProviderRef) {
+        self.key_provider = key_provider;
+    }
+
+    pub fn set_ad_markers(&mut self, ad_markers: HlsAdMarkers) {
+        self.ad_markers = ad_markers;
+    }
+
+    /*the segment in progress begins at the splice point*/
+    pub fn set_splice_cue(&mut self, cue: SpliceCue) {
+        self.splice_cue = Some(cue);
+    }
+
+    /*the ad break signaling of the segment to be added, a splice in without
+    an ad break in progress is ignored*/
+    fn take_segment_cue(
+        &mut self,
+        duration: i64,
+        program_date_time: DateTime<Utc>,
+    ) -> Option<SegmentCue> {
+        let cue = match (self.splice_cue.take(), self.ad_break.take()) {
+            (
+                Some(SpliceCue::Out {
+                    id,
+                    duration: break_duration,
+                }),
+                _,
+            ) => {
+                let id = id.unwrap_or_else(|| {
+                    self.splice_event_id += 1;
+                    self.splice_event_id
+                });
+                self.ad_break = Some(AdBreak {
+                    id,
+                    start_time: program_date_time,
+                    elapsed: 0,
+                    duration: break_duration,
+                });
+                Some(SegmentCue::Out {
+                    id,
+                    duration: break_duration,
+                })
+            }
+            (Some(SpliceCue::In), Some(ad_break)) => Some(SegmentCue::In {
+                id: ad_break.id,
+                start_time: ad_break.start_time,
+                duration: ad_break.elapsed,
+            }),
+            (None, Some(ad_break)) => {
+                let cue = SegmentCue::Cont {
+                    elapsed: ad_break.elapsed,
+                    duration: ad_break.duration,
+                };
+                self.ad_break = Some(ad_break);
+                Some(cue)
+            }
+            (Some(SpliceCue::In), None) | (None, None) => None,
+        };
+
+        if let Some(ad_break) = &mut self.ad_break {
+            ad_break.elapsed += duration;
+        }
+        cue
+    }
+
+    fn generate_cue_line(&self, segment: &Segment) -> String {
+        let cue = match &segment.cue {
+            Some(cue) => cue,
+            None => return String::new(),
+        };
+        let seconds = |milliseconds: i64| milliseconds as f64 / 1000.0;
+
+        match (self.ad_markers, cue) {
+            (HlsAdMarkers::Cue, SegmentCue::Out { duration, .. }) => match duration {
+                Some(duration) => format!("#EXT-X-CUE-OUT:DURATION={:.3}\n", seconds(*duration)),
+                None => String::from("#EXT-X-CUE-OUT\n"),
+            },
+            (HlsAdMarkers::Cue, SegmentCue::Cont { elapsed, duration }) => match duration {
+                Some(duration) => format!(
+                    "#EXT-X-CUE-OUT-CONT:ElapsedTime={:.3},Duration={:.3}\n",
+                    seconds(*elapsed),
+                    seconds(*duration)
+                ),
+                None => format!("#EXT-X-CUE-OUT-CONT:ElapsedTime={:.3}\n", seconds(*elapsed)),
+            },
+            (HlsAdMarkers::Cue, SegmentCue::In { .. }) => String::from("#EXT-X-CUE-IN\n"),
+            (HlsAdMarkers::DateRange, SegmentCue::Out { id, duration }) => {
+                let mut line = format!(
+                    "#EXT-X-DATERANGE:ID=\"splice-{id}\",START-DATE=\"{}\"",
+                    segment
+                        .program_date_time
+                        .to_rfc3339_opts(SecondsFormat::Millis, true)
+                );
+                if let Some(duration) = duration {
+                    line += &format!(",PLANNED-DURATION={:.3}", seconds(*duration));
+                }
+                let splice_info =
+                    scte35::splice_insert(*id, true, duration.map(|duration| duration as u64 * 90));
+                line += &format!(",SCTE35-OUT=0x{}\n", to_hex(&splice_info[..]));
+                line
+            }
+            (HlsAdMarkers::DateRange, SegmentCue::Cont { .. }) => String::new(),
+            (
+                HlsAdMarkers::DateRange,
+                SegmentCue::In {
+                    id,
+                    start_time,
+                    duration,
+                },
+            ) => {
+                let splice_info = scte35::splice_insert(*id, false, None);
+                format!(
+                    "#EXT-X-DATERANGE:ID=\"splice-{id}\",START-DATE=\"{}\",DURATION={:.3},SCTE35-IN=0x{}\n",
+                    start_time.to_rfc3339_opts(SecondsFormat::Millis, true),
+                    seconds(*duration),
+                    to_hex(&splice_info[..])
+                )
+            }
+        }
+    }
+
+    /*the media sequence number of the segment in progress*/
+    fn next_sequence_no(&self) -> u64 {
+        self.sequence_no + self.segments.len() as u64
+    }
+
+    fn get_key_index(&self, sequence_no: u64) -> u64 {
+        //0 means one key for the stream
+        sequence_no.checked_div(self.key_rotation).unwrap_or(0)
+    }
+
+    /*Get the key and the iv of the segment in progress, the new key is generated
+    by the key provider and written to the store when the key is rotated.*/
+    pub fn get_segment_key(&mut self) -> Result<Option<(AesKey, AesIv)>, MediaError> {
+        if self.encryption == HlsEncryption::None {
+            return Ok(None);
+        }
+
+        let sequence_no = self.next_sequence_no();
+        let key_index = self.get_key_index(sequence_no);
+        let key = match self.current_key {
+            Some((index, key)) if index == key_index => key,
+            _ => {
+                let key =
+                    self.key_provider
+                        .get_key(&self.app_name, &self.stream_name, key_index)?;
+                self.store.write(
+                    &self.app_name,
+                    &self.stream_name,
+                    &key_name(key_index),
+                    Bytes::copy_from_slice(&key),
+                )?;
+                self.current_key = Some((key_index, key));
+                key
+            }
+        };
+
+        Ok(Some((key, encryption::sequence_iv(sequence_no))))
+    }
+
+    /*AES-128 encrypts the whole segment(or partial segment) data*/
+    fn encrypt_data(&mut self, data: BytesMut) -> Result<BytesMut, MediaError> {
+        if self.encryption != HlsEncryption::Aes128 {
+            return Ok(data);
+        }
+        match self.get_segment_key()? {
+            Some((key, iv)) => Ok(encryption::encrypt_segment(&key, &iv, &data[..])),
+            None => Ok(data),
+        }
+    }
+
+    fn generate_key_line(&self, key_index: u64) -> String {
+        let method = match self.encryption {
+            HlsEncryption::SampleAes => "SAMPLE-AES",
+            _ => "AES-128",
+        };
+        let uri = self
+            .key_uri
+            .replace("{app}", &self.app_name)
+            .replace("{stream}", &self.stream_name)
+            .replace("{index}", &key_index.to_string());
+        format!("#EXT-X-KEY:METHOD={method},URI=\"{uri}\"\n")
+    }
+
+    pub fn set_init_segment(&mut self, data: BytesMut) -> Result<(), MediaError> {
+        let init_name = String::from("init.mp4");
+        self.store
+            .write(&self.app_name, &self.stream_name, &init_name, data.freeze())?;
+        self.init_name = Some(init_name);
+        Ok(())
+    }
+
+    pub fn add_part(
+        &mut self,
+        duration: i64,
+        independent: bool,
+        ts_data: BytesMut,
+    ) -> Result<(), MediaError> {
+        let ts_data = self.encrypt_data(ts_data)?;
+        let name = self.ts_handler.write_part(self.parts.len(), ts_data)?;
+        self.parts.push(Part {
+            duration,
+            name,
+            independent,
+        });
+
+        Ok(())
+    }
+
+    /*remove the first segment from the playlist, it is deleted after the deletion delay*/
+    fn expire_front_segment(&mut self) {
+        if let Some(segment) = self.segments.pop_front() {
+            let expire_time = Instant::now() + self.deletion_delay;
+            for part in &segment.parts {
+                self.expired_segments
+                    .push_back((expire_time, part.name.clone()));
+            }
+            self.sequence_no += 1;
+            if self.archive {
+                self.archived_segments.push_back(segment);
+                return;
+            }
+
+            //the key is deleted with the last segment it encrypts
+            if let Some(key_index) = segment.key_index {
+                let next_key_index = match self.segments.front() {
+                    Some(next_segment) => next_segment.key_index,
+                    None => Some(self.get_key_index(self.next_sequence_no())),
+                };
+                if next_key_index != Some(key_index) {
+                    self.expired_segments
+                        .push_back((expire_time, key_name(key_index)));
+                }
+            }
+            self.expired_segments.push_back((expire_time, segment.name));
         }
     }
 
+    fn delete_expired_segments(&mut self) -> Result<(), MediaError> {
+        while let Some((expire_time, _)) = self.expired_segments.front() {
+            if *expire_time > Instant::now() {
+                break;
+            }
+            if let Some((_, name)) = self.expired_segments.pop_front() {
+                self.ts_handler.delete(&name)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_segment(
         &mut self,
         duration: i64,
@@ -87,31 +557,136 @@ impl M3u8 {
         is_eof: bool,
         ts_data: BytesMut,
     ) -> Result<(), MediaError> {
-        let segment_count = self.segments.len();
+        let ts_data = self.encrypt_data(ts_data)?;
+        let key_index = match self.encryption {
+            HlsEncryption::None => None,
+            _ => Some(self.get_key_index(self.next_sequence_no())),
+        };
 
-        if self.is_live && segment_count >= self.live_ts_count {
-            let segment = self.segments.pop_front().unwrap();
-            self.ts_handler.delete(segment.path);
-            self.sequence_no += 1;
+        if self.is_live {
+            if self.dvr_window > 0 {
+                let mut window: i64 = self
+                    .segments
+                    .iter()
+                    .map(|segment| segment.duration)
+                    .sum::<i64>()
+                    + duration;
+                while window > self.dvr_window {
+                    match self.segments.front() {
+                        Some(segment) => window -= segment.duration,
+                        None => break,
+                    }
+                    self.expire_front_segment();
+                }
+            } else if self.segments.len() >= self.live_ts_count {
+                self.expire_front_segment();
+            }
         }
+        self.delete_expired_segments()?;
 
         self.duration = std::cmp::max(duration, self.duration);
 
-        let (ts_name, ts_path) = self.ts_handler.write(ts_data)?;
-        let segment = Segment::new(duration, discontinuity, ts_name, ts_path, is_eof);
+        //the segments are continuous in wall clock time unless there is a discontinuity
+        let program_date_time = match self.segments.back() {
+            Some(last) if !discontinuity => {
+                last.program_date_time + ChronoDuration::milliseconds(last.duration)
+            }
+            _ => Utc::now() - ChronoDuration::milliseconds(duration),
+        };
+
+        let ts_name = self.ts_handler.write(ts_data)?;
+        let mut segment = Segment::new(duration, discontinuity, ts_name, is_eof, program_date_time);
+        segment.parts = std::mem::take(&mut self.parts);
+        segment.key_index = key_index;
+        segment.cue = self.take_segment_cue(duration, program_date_time);
         self.segments.push_back(segment);
 
         Ok(())
     }
 
+    /*Finish the recording, all the segments are listed in a VOD playlist and moved to
+    an archive folder named by the stream name and the start time, returns the path of
+    the archive and the duration in milliseconds.*/
+    pub fn archive(&mut self) -> Result<(String, i64), MediaError> {
+        //the partial segments are not needed by the VOD
+        for (_, name) in &self.expired_segments {
+            self.ts_handler.delete(name)?;
+        }
+        self.expired_segments.clear();
+        for part in &self.parts {
+            self.ts_handler.delete(&part.name)?;
+        }
+        self.parts.clear();
+
+        self.sequence_no -= self.archived_segments.len() as u64;
+        let mut segments = std::mem::take(&mut self.archived_segments);
+        segments.append(&mut self.segments);
+        for segment in &mut segments {
+            for part in &segment.parts {
+                self.ts_handler.delete(&part.name)?;
+            }
+            segment.parts.clear();
+        }
+        self.segments = segments;
+
+        self.is_live = false;
+        self.low_latency = false;
+        self.refresh_playlist()?;
+
+        let start_time = self
+            .segments
+            .front()
+            .map_or_else(Utc::now, |segment| segment.program_date_time);
+        let archive_name = format!("{}_{}", self.stream_name, start_time.format("%Y%m%d%H%M%S"));
+        let path = self
+            .store
+            .archive(&self.app_name, &self.stream_name, &archive_name)?;
+        let duration = self.segments.iter().map(|segment| segment.duration).sum();
+
+        //the files are moved, there is nothing to clear
+        self.segments.clear();
+        self.init_name = None;
+        self.current_key = None;
+
+        Ok((path, duration))
+    }
+
     pub fn clear(&mut self) -> Result<(), MediaError> {
         //clear ts
-        for segment in &self.segments {
-            self.ts_handler.delete(segment.path.clone());
+        for segment in self.archived_segments.iter().chain(self.segments.iter()) {
+            self.ts_handler.delete(&segment.name)?;
+            for part in &segment.parts {
+                self.ts_handler.delete(&part.name)?;
+            }
+        }
+        for part in &self.parts {
+            self.ts_handler.delete(&part.name)?;
+        }
+        self.parts.clear();
+        if let Some(init_name) = &self.init_name {
+            self.store
+                .delete(&self.app_name, &self.stream_name, init_name)?;
+        }
+        for (_, name) in &self.expired_segments {
+            self.ts_handler.delete(name)?;
+        }
+        self.expired_segments.clear();
+        //clear keys
+        let mut key_indexes: BTreeSet<u64> = self
+            .archived_segments
+            .iter()
+            .chain(self.segments.iter())
+            .filter_map(|segment| segment.key_index)
+            .collect();
+        if let Some((key_index, _)) = self.current_key.take() {
+            key_indexes.insert(key_index);
+        }
+        for key_index in key_indexes {
+            self.ts_handler.delete(&key_name(key_index))?;
         }
         //clear m3u8
-        let m3u8_path = format!("{}/{}", self.m3u8_folder, self.m3u8_name);
-        fs::remove_file(m3u8_path)?;
+        self.store
+            .delete(&self.app_name, &self.stream_name, &self.m3u8_name)?;
 
         Ok(())
     }
@@ -128,11 +703,27 @@ impl M3u8 {
 
         self.m3u8_header = "#EXTM3U\n".to_string();
         self.m3u8_header += format!("#EXT-X-VERSION:{}\n", self.version).as_str();
-        self.m3u8_header +=
-            format!("#EXT-X-TARGETDURATION:{}\n", (self.duration + 999) / 1000).as_str();
+        //the segment durations rounded to the nearest integer must not exceed it
+        self.m3u8_header += format!(
+            "#EXT-X-TARGETDURATION:{}\n",
+            std::cmp::max((self.duration + 500) / 1000, 1)
+        )
+        .as_str();
+        if self.low_latency {
+            let part_target = self.part_duration as f64 / 1000.0;
+            self.m3u8_header += format!(
+                "#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK={:.3}\n",
+                part_target * 3.0
+            )
+            .as_str();
+            self.m3u8_header += format!("#EXT-X-PART-INF:PART-TARGET={part_target:.3}\n").as_str();
+        }
         self.m3u8_header += format!("#EXT-X-MEDIA-SEQUENCE:{}\n", self.sequence_no).as_str();
         self.m3u8_header += playlist_type;
         self.m3u8_header += allow_cache;
+        if let Some(init_name) = &self.init_name {
+            self.m3u8_header += format!("#EXT-X-MAP:URI=\"{init_name}\"\n").as_str();
+        }
 
         Ok(())
     }
@@ -140,11 +731,49 @@ impl M3u8 {
     pub fn refresh_playlist(&mut self) -> Result<String, MediaError> {
         self.generate_m3u8_header()?;
 
+        /*the partial segments are only listed for the segments
+        within three target durations from the end of the playlist*/
+        let part_window = self.duration * 3;
+        let mut listed_duration: i64 = self.parts.iter().map(|part| part.duration).sum();
+        let mut parts_start_index = self.segments.len();
+        for (index, segment) in self.segments.iter().enumerate().rev() {
+            listed_duration += segment.duration;
+            if listed_duration > part_window {
+                break;
+            }
+            parts_start_index = index;
+        }
+
         let mut m3u8_content = self.m3u8_header.clone();
-        for segment in &self.segments {
+        let mut is_eof = false;
+        //the EXT-X-KEY applies to the segments until the next one
+        let mut last_key_index: Option<u64> = None;
+        for (index, segment) in self.segments.iter().enumerate() {
             if segment.discontinuity {
                 m3u8_content += "#EXT-X-DISCONTINUITY\n";
             }
+            if let Some(key_index) = segment.key_index {
+                if last_key_index != Some(key_index) {
+                    m3u8_content += &self.generate_key_line(key_index);
+                    last_key_index = Some(key_index);
+                }
+            }
+            //EXT-X-DATERANGE requires EXT-X-PROGRAM-DATE-TIME
+            if self.dvr_window > 0 || self.ad_markers == HlsAdMarkers::DateRange {
+                m3u8_content += format!(
+                    "#EXT-X-PROGRAM-DATE-TIME:{}\n",
+                    segment
+                        .program_date_time
+                        .to_rfc3339_opts(SecondsFormat::Millis, true)
+                )
+                .as_str();
+            }
+            m3u8_content += &self.generate_cue_line(segment);
+            if self.low_latency && index >= parts_start_index {
+                for part in &segment.parts {
+                    m3u8_content += &generate_part_line(part);
+                }
+            }
             m3u8_content += format!(
                 "#EXTINF:{:.3}\n{}\n",
                 segment.duration as f64 / 1000.0,
@@ -154,15 +783,333 @@ impl M3u8 {
 
             if segment.is_eof {
                 m3u8_content += "#EXT-X-ENDLIST\n";
+                is_eof = true;
                 break;
             }
         }
 
-        let m3u8_path = format!("{}/{}", self.m3u8_folder, self.m3u8_name);
+        if !self.is_live && !is_eof {
+            m3u8_content += "#EXT-X-ENDLIST\n";
+        }
 
-        let mut file_handler = File::create(m3u8_path).unwrap();
-        file_handler.write_all(m3u8_content.as_bytes())?;
+        if self.low_latency && !is_eof {
+            if self.encryption != HlsEncryption::None {
+                let key_index = self.get_key_index(self.next_sequence_no());
+                if last_key_index != Some(key_index) {
+                    m3u8_content += &self.generate_key_line(key_index);
+                }
+            }
+            for part in &self.parts {
+                m3u8_content += &generate_part_line(part);
+            }
+            m3u8_content += format!(
+                "#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"{}\"\n",
+                self.ts_handler.get_part_name(self.parts.len())
+            )
+            .as_str();
+        }
+
+        self.store.write(
+            &self.app_name,
+            &self.stream_name,
+            &self.m3u8_name,
+            Bytes::from(m3u8_content.clone()),
+        )?;
 
         Ok(m3u8_content)
     }
 }
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02X}")).collect()
+}
+
+/*the name of a key file in the store*/
+fn key_name(key_index: u64) -> String {
+    format!("{key_index}.key")
+}
+
+/*Cut the segments which end before the start time from a DVR playlist, the segments
+are located by their EXT-X-PROGRAM-DATE-TIME, and the media sequence number is
+increased by the count of the cut segments.*/
+pub fn slice_playlist(content: &str, start_time: DateTime<Utc>) -> String {
+    let mut header: Vec<&str> = Vec::new();
+    let mut sequence_no: u64 = 0;
+    let mut cut_count: u64 = 0;
+    let mut body = String::new();
+
+    //the tags of the segment in progress
+    let mut segment_lines: Vec<&str> = Vec::new();
+    //the last EXT-X-KEY of the cut segments, it applies to the first kept segment
+    let mut key_line: Option<&str> = None;
+    let mut program_date_time: Option<DateTime<Utc>> = None;
+    let mut duration: f64 = 0.0;
+
+    for line in content.lines() {
+        if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            sequence_no = value.trim().parse().unwrap_or_default();
+            continue;
+        }
+        if body.is_empty() && segment_lines.is_empty() && is_header_line(line) {
+            header.push(line);
+            continue;
+        }
+
+        segment_lines.push(line);
+        if let Some(value) = line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:") {
+            program_date_time = DateTime::parse_from_rfc3339(value.trim())
+                .ok()
+                .map(|time| time.with_timezone(&Utc));
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            duration = value
+                .split(',')
+                .next()
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or_default();
+        } else if !line.starts_with('#') {
+            //the uri line ends a segment
+            let is_ended = program_date_time.is_some_and(|time| {
+                time + ChronoDuration::milliseconds((duration * 1000.0) as i64) <= start_time
+            });
+            if is_ended {
+                cut_count += 1;
+                if let Some(line) = segment_lines
+                    .iter()
+                    .rev()
+                    .find(|line| line.starts_with("#EXT-X-KEY:"))
+                {
+                    key_line = Some(line);
+                }
+            } else {
+                let has_key = segment_lines
+                    .iter()
+                    .any(|line| line.starts_with("#EXT-X-KEY:"));
+                if let Some(line) = key_line.take() {
+                    if !has_key && body.is_empty() {
+                        body += line;
+                        body += "\n";
+                    }
+                }
+                for segment_line in &segment_lines {
+                    body += segment_line;
+                    body += "\n";
+                }
+            }
+            segment_lines.clear();
+            program_date_time = None;
+            duration = 0.0;
+        }
+    }
+
+    let mut playlist = String::new();
+    for line in header {
+        playlist += line;
+        playlist += "\n";
+        if line.starts_with("#EXT-X-TARGETDURATION:") {
+            playlist += format!("#EXT-X-MEDIA-SEQUENCE:{}\n", sequence_no + cut_count).as_str();
+        }
+    }
+    playlist += &body;
+    //the partial segments, preload hint and endlist after the last segment
+    for line in segment_lines {
+        playlist += line;
+        playlist += "\n";
+    }
+
+    playlist
+}
+
+/*the playlist tags which apply to the whole playlist*/
+fn is_header_line(line: &str) -> bool {
+    [
+        "#EXTM3U",
+        "#EXT-X-VERSION:",
+        "#EXT-X-TARGETDURATION:",
+        "#EXT-X-SERVER-CONTROL:",
+        "#EXT-X-PART-INF:",
+        "#EXT-X-PLAYLIST-TYPE:",
+        "#EXT-X-ALLOW-CACHE:",
+        "#EXT-X-MAP:",
+    ]
+    .iter()
+    .any(|tag| line.starts_with(tag))
+}
+
+fn generate_part_line(part: &Part) -> String {
+    let independent = if part.independent {
+        ",INDEPENDENT=YES"
+    } else {
+        ""
+    };
+    format!(
+        "#EXT-X-PART:DURATION={:.3},URI=\"{}\"{}\n",
+        part.duration as f64 / 1000.0,
+        part.name,
+        independent
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{slice_playlist, M3u8, PlaylistPosition},
+        crate::{
+            define::HlsEncryption,
+            store::{memory::MemoryHlsStore, HlsStore},
+        },
+        bytes::BytesMut,
+        chrono::{DateTime, Utc},
+        std::sync::Arc,
+    };
+
+    #[test]
+    fn test_playlist_position() {
+        let content = "#EXTM3U\n\
+                       #EXT-X-VERSION:6\n\
+                       #EXT-X-TARGETDURATION:2\n\
+                       #EXT-X-MEDIA-SEQUENCE:10\n\
+                       #EXT-X-PART:DURATION=0.200,URI=\"10.0.ts\",INDEPENDENT=YES\n\
+                       #EXTINF:2.000\n\
+                       10.ts\n\
+                       #EXTINF:2.000\n\
+                       11.ts\n\
+                       #EXT-X-PART:DURATION=0.200,URI=\"12.0.ts\",INDEPENDENT=YES\n\
+                       #EXT-X-PART:DURATION=0.200,URI=\"12.1.ts\"\n\
+                       #EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"12.2.ts\"\n";
+
+        let position = PlaylistPosition::parse(content);
+        assert_eq!(
+            position,
+            PlaylistPosition {
+                target_duration: 2,
+                msn: 12,
+                part_count: 2,
+                is_end: false,
+            }
+        );
+
+        assert!(position.contains(11, None));
+        assert!(!position.contains(12, None));
+        assert!(position.contains(12, Some(1)));
+        assert!(!position.contains(12, Some(2)));
+        assert!(!position.contains(13, Some(0)));
+    }
+
+    #[test]
+    fn test_slice_playlist() {
+        let content = "#EXTM3U\n\
+                       #EXT-X-VERSION:3\n\
+                       #EXT-X-TARGETDURATION:5\n\
+                       #EXT-X-MEDIA-SEQUENCE:20\n\
+                       #EXT-X-PROGRAM-DATE-TIME:2023-05-01T10:00:00.000Z\n\
+                       #EXTINF:5.000\n\
+                       20.ts\n\
+                       #EXT-X-PROGRAM-DATE-TIME:2023-05-01T10:00:05.000Z\n\
+                       #EXTINF:5.000\n\
+                       21.ts\n\
+                       #EXT-X-DISCONTINUITY\n\
+                       #EXT-X-PROGRAM-DATE-TIME:2023-05-01T10:01:00.000Z\n\
+                       #EXTINF:4.000\n\
+                       22.ts\n";
+
+        let start_time = DateTime::parse_from_rfc3339("2023-05-01T10:00:07.000Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            slice_playlist(content, start_time),
+            "#EXTM3U\n\
+             #EXT-X-VERSION:3\n\
+             #EXT-X-TARGETDURATION:5\n\
+             #EXT-X-MEDIA-SEQUENCE:21\n\
+             #EXT-X-PROGRAM-DATE-TIME:2023-05-01T10:00:05.000Z\n\
+             #EXTINF:5.000\n\
+             21.ts\n\
+             #EXT-X-DISCONTINUITY\n\
+             #EXT-X-PROGRAM-DATE-TIME:2023-05-01T10:01:00.000Z\n\
+             #EXTINF:4.000\n\
+             22.ts\n"
+        );
+    }
+
+    #[test]
+    fn test_archive() {
+        let store = Arc::new(MemoryHlsStore::default());
+        let mut m3u8 = M3u8::new(
+            2000,
+            2,
+            String::from("test.m3u8"),
+            String::from("live"),
+            String::from("test"),
+            store.clone(),
+        );
+        m3u8.set_archive(true);
+
+        for _ in 0..3 {
+            m3u8.add_segment(2000, false, false, BytesMut::from(&b"ts"[..]))
+                .unwrap();
+            m3u8.refresh_playlist().unwrap();
+        }
+        //the live playlist slides but the first segment is kept
+        assert!(store.read("live", "test", "0.ts").unwrap().is_some());
+
+        let (path, duration) = m3u8.archive().unwrap();
+        assert_eq!(duration, 6000);
+        let archive_name = path.strip_prefix("live/").unwrap();
+        assert!(archive_name.starts_with("test_"));
+
+        let playlist = store
+            .read("live", archive_name, "test.m3u8")
+            .unwrap()
+            .unwrap();
+        let playlist = String::from_utf8_lossy(&playlist);
+        assert!(playlist.contains("#EXT-X-MEDIA-SEQUENCE:0\n"));
+        assert!(playlist.contains("#EXT-X-PLAYLIST-TYPE:VOD\n"));
+        assert_eq!(playlist.matches("#EXTINF:").count(), 3);
+        assert!(playlist.ends_with("#EXT-X-ENDLIST\n"));
+        assert!(store.read("live", "test", "test.m3u8").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let store = Arc::new(MemoryHlsStore::default());
+        let mut m3u8 = M3u8::new(
+            2000,
+            3,
+            String::from("test.m3u8"),
+            String::from("live"),
+            String::from("test"),
+            store.clone(),
+        );
+        m3u8.set_encryption(
+            HlsEncryption::Aes128,
+            2,
+            String::from("/keys/{app}/{stream}/{index}.key"),
+        );
+
+        for _ in 0..6 {
+            m3u8.add_segment(2000, false, false, BytesMut::from(&b"ts"[..]))
+                .unwrap();
+            m3u8.refresh_playlist().unwrap();
+        }
+
+        //the segment data is padded to the aes block size
+        assert_eq!(
+            store.read("live", "test", "5.ts").unwrap().unwrap().len(),
+            16
+        );
+        //the key 0 is deleted with the segments 0 and 1
+        assert!(store.read("live", "test", "0.key").unwrap().is_none());
+        assert!(store.read("live", "test", "1.key").unwrap().is_some());
+
+        let playlist = store.read("live", "test", "test.m3u8").unwrap().unwrap();
+        let playlist = String::from_utf8_lossy(&playlist);
+        assert!(playlist.contains(
+            "#EXT-X-MEDIA-SEQUENCE:3\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"/keys/live/test/1.key\"\n#EXTINF"
+        ));
+        assert_eq!(playlist.matches("#EXT-X-KEY:").count(), 2);
+
+        m3u8.clear().unwrap();
+        assert!(store.read("live", "test", "2.key").unwrap().is_none());
+    }
+}
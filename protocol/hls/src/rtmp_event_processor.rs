@@ -1,42 +1,298 @@
 use {
-    super::{errors::HlsError, flv_data_receiver::FlvDataReceiver},
-    rtmp::channels::define::{ChannelEventProducer, ClientEvent, ClientEventConsumer},
+    super::{
+        define::{
+            DemandActivity, HlsDemandEvent, HlsDemandEventReceiver, HlsDemandEventSender,
+            HlsOptions,
+        },
+        encryption::key::HlsKeyProviderRef,
+        errors::HlsError,
+        flv_data_receiver::FlvDataReceiver,
+        store::HlsStoreRef,
+        variant::VariantSets,
+    },
+    rtmp::{
+        channels::define::{ChannelEventProducer, ClientEvent, ClientEventConsumer},
+        notify::Notifier,
+    },
+    std::{collections::HashMap, sync::Arc},
+    tokio::sync::mpsc,
 };
 
 pub struct RtmpEventProcessor {
     client_event_consumer: ClientEventConsumer,
     event_producer: ChannelEventProducer,
+    store: HlsStoreRef,
+    options: HlsOptions,
+    //override the hls options of the apps
+    app_options: HashMap<String, HlsOptions>,
+    notifier: Option<Arc<Notifier>>,
+    //the encryption keys are generated randomly if it is not set
+    key_provider: Option<HlsKeyProviderRef>,
+    //the segments of the renditions in a variant set are aligned
+    variant_sets: Arc<VariantSets>,
+    /*the published on demand streams, the activity is set when it is being remuxed*/
+    on_demand_streams: HashMap<String, Option<DemandActivity>>,
+    demand_event_receiver: Option<HlsDemandEventReceiver>,
 }
 
 impl RtmpEventProcessor {
-    pub fn new(consumer: ClientEventConsumer, event_producer: ChannelEventProducer) -> Self {
+    pub fn new(
+        consumer: ClientEventConsumer,
+        event_producer: ChannelEventProducer,
+        store: HlsStoreRef,
+    ) -> Self {
         Self {
             client_event_consumer: consumer,
             event_producer,
+            store,
+            options: HlsOptions::default(),
+            app_options: HashMap::new(),
+            notifier: None,
+            key_provider: None,
+            variant_sets: Arc::new(VariantSets::default()),
+            on_demand_streams: HashMap::new(),
+            demand_event_receiver: None,
+        }
+    }
+
+    pub fn set_options(&mut self, options: HlsOptions) {
+        self.options = options;
+    }
+
+    pub fn set_notifier(&mut self, notifier: Notifier) {
+        self.notifier = Some(Arc::new(notifier));
+    }
+
+    pub fn set_key_provider(&mut self, key_provider: HlsKeyProviderRef) {
+        self.key_provider = Some(key_provider);
+    }
+
+    pub fn set_variant_sets(&mut self, variant_sets: Arc<VariantSets>) {
+        self.variant_sets = variant_sets;
+    }
+
+    pub fn set_app_options(&mut self, app_name: String, options: HlsOptions) {
+        self.app_options.insert(app_name, options);
+    }
+
+    /*the hls server sends the playlist requests to start the on demand streams*/
+    pub fn get_demand_event_sender(&mut self) -> HlsDemandEventSender {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.demand_event_receiver = Some(receiver);
+        sender
+    }
+
+    fn get_options(&self, app_name: &str, stream_name: &str) -> HlsOptions {
+        let mut options = self
+            .app_options
+            .get(app_name)
+            .unwrap_or(&self.options)
+            .clone();
+        if self
+            .variant_sets
+            .get_set_name(app_name, stream_name)
+            .is_some()
+        {
+            options.align_segments = true;
+        }
+        options
+    }
+
+    fn start_remuxing(
+        &self,
+        app_name: String,
+        stream_name: String,
+        options: &HlsOptions,
+        activity: Option<DemandActivity>,
+    ) {
+        let mut rtmp_subscriber = FlvDataReceiver::new(
+            app_name,
+            stream_name,
+            self.event_producer.clone(),
+            self.store.clone(),
+            options,
+        );
+        if let Some(notifier) = &self.notifier {
+            rtmp_subscriber.set_notifier(notifier.clone());
+        }
+        if let Some(key_provider) = &self.key_provider {
+            rtmp_subscriber.set_key_provider(key_provider.clone());
+        }
+        if let Some(activity) = &activity {
+            rtmp_subscriber.set_demand_activity(activity.clone());
+        }
+
+        tokio::spawn(async move {
+            if let Err(err) = rtmp_subscriber.run().await {
+                log::error!("hls handler run error {}", err);
+            }
+            if let Some(activity) = activity {
+                activity.stop();
+            }
+        });
+    }
+
+    async fn recv_demand_event(
+        receiver: &mut Option<HlsDemandEventReceiver>,
+    ) -> Option<HlsDemandEvent> {
+        match receiver {
+            Some(receiver) => receiver.recv().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn process_demand_event(&mut self, event: HlsDemandEvent) {
+        let key = format!("{}/{}", event.app_name, event.stream_name);
+        //the stream is not published or it is remuxed when it is published
+        let is_on_demand = self.on_demand_streams.contains_key(&key);
+        if event.responder.send(is_on_demand).is_err() {
+            log::warn!("hls demand event of {} is not responded", key);
+        }
+        let activity = match self.on_demand_streams.get_mut(&key) {
+            Some(activity) => activity,
+            None => return,
+        };
+
+        match activity {
+            Some(activity) if !activity.is_stopped() => activity.touch(),
+            _ => {
+                let new_activity = DemandActivity::default();
+                *activity = Some(new_activity.clone());
+
+                log::info!("hls stream {} is requested, start remuxing", key);
+                let options = self.get_options(&event.app_name, &event.stream_name);
+                self.start_remuxing(
+                    event.app_name,
+                    event.stream_name,
+                    &options,
+                    Some(new_activity),
+                );
+            }
+        }
+    }
+
+    fn process_client_event(&mut self, event: ClientEvent) {
+        match event {
+            ClientEvent::Publish {
+                app_name,
+                stream_name,
+            } => {
+                let options = self.get_options(&app_name, &stream_name);
+                if options.on_demand {
+                    self.on_demand_streams
+                        .insert(format!("{app_name}/{stream_name}"), None);
+                } else {
+                    self.start_remuxing(app_name, stream_name, &options, None);
+                }
+            }
+            //the remuxing of the stream ends when the stream data stops
+            ClientEvent::UnPublish {
+                app_name,
+                stream_name,
+            } => {
+                self.on_demand_streams
+                    .remove(&format!("{app_name}/{stream_name}"));
+            }
+            _ => {
+                log::trace!("other infos...");
+            }
         }
     }
 
     pub async fn run(&mut self) -> Result<(), HlsError> {
         loop {
-            let val = self.client_event_consumer.recv().await?;
-            match val {
-                ClientEvent::Publish {
-                    app_name,
-                    stream_name,
-                } => {
-                    let mut rtmp_subscriber =
-                        FlvDataReceiver::new(app_name, stream_name, self.event_producer.clone(), 5);
-
-                    tokio::spawn(async move {
-                        if let Err(err) = rtmp_subscriber.run().await {
-                            println!("hls handler run error {err}");
-                        }
-                    });
+            tokio::select! {
+                val = self.client_event_consumer.recv() => {
+                    self.process_client_event(val?);
                 }
-                _ => {
-                    log::trace!("other infos...");
+                Some(event) = Self::recv_demand_event(&mut self.demand_event_receiver) => {
+                    self.process_demand_event(event);
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::RtmpEventProcessor,
+        crate::{
+            define::{HlsDemandEvent, HlsOptions},
+            store::memory::MemoryHlsStore,
+        },
+        rtmp::channels::define::{ChannelEvent, ChannelEventConsumer, ClientEvent},
+        std::{sync::Arc, time::Duration},
+        tokio::{
+            sync::{broadcast, mpsc, oneshot},
+            time::timeout,
+        },
+    };
+
+    async fn next_event(consumer: &mut ChannelEventConsumer, secs: u64) -> Option<ChannelEvent> {
+        timeout(Duration::from_secs(secs), consumer.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[tokio::test]
+    async fn test_on_demand_remuxing() {
+        let (client_event_producer, client_event_consumer) = broadcast::channel(16);
+        let (event_producer, mut event_consumer) = mpsc::unbounded_channel();
+        let mut processor = RtmpEventProcessor::new(
+            client_event_consumer,
+            event_producer,
+            Arc::new(MemoryHlsStore::default()),
+        );
+        processor.set_options(HlsOptions {
+            on_demand: true,
+            idle_timeout: 1,
+            ..Default::default()
+        });
+        let demand_event_sender = processor.get_demand_event_sender();
+        tokio::spawn(async move { processor.run().await });
+
+        let request_playlist = || {
+            let (responder, receiver) = oneshot::channel();
+            demand_event_sender
+                .send(HlsDemandEvent {
+                    app_name: String::from("live"),
+                    stream_name: String::from("test"),
+                    responder,
+                })
+                .unwrap();
+            receiver
+        };
+
+        //the published stream is not remuxed until its playlist is requested
+        client_event_producer
+            .send(ClientEvent::Publish {
+                app_name: String::from("live"),
+                stream_name: String::from("test"),
+            })
+            .unwrap();
+        assert!(next_event(&mut event_consumer, 1).await.is_none());
+
+        for _ in 0..2 {
+            assert!(request_playlist().await.unwrap());
+            //the publisher stalls: it is subscribed but sends no data
+            let _data_producer = match next_event(&mut event_consumer, 1).await {
+                Some(ChannelEvent::Subscribe { responder, .. }) => {
+                    let (data_producer, data_consumer) = mpsc::unbounded_channel();
+                    responder.send(data_consumer).unwrap();
+                    data_producer
+                }
+                _ => panic!("the stream is expected to be subscribed"),
+            };
+
+            //the remuxing stops after the idle window without requests
+            match next_event(&mut event_consumer, 3).await {
+                Some(ChannelEvent::UnSubscribe { .. }) => {}
+                _ => panic!("the stream is expected to be unsubscribed"),
+            }
+            //let the remuxing task mark the activity as stopped
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
@@ -1,80 +1,628 @@
 use {
+    super::{
+        define::{HlsDemandEvent, HlsDemandEventSender},
+        m3u8::{self, PlaylistPosition},
+        session::HlsSessionManager,
+        store::HlsStoreRef,
+        variant::MasterPlaylistGenerator,
+    },
+    bytes::Bytes,
+    chrono::{DateTime, TimeZone, Utc},
     hyper::{
+        header::{self, HeaderValue},
+        http::response::Builder,
+        server::conn::AddrStream,
         service::{make_service_fn, service_fn},
-        Body, Request, Response, Server, StatusCode,
+        Body, Method, Request, Response, Server, StatusCode,
+    },
+    rtmp::{
+        notify::{Notifier, PlayRequestInfo},
+        session::common::NotifyInfo,
+        utils::is_valid_path_name,
+    },
+    std::{net::SocketAddr, sync::Arc, time::Duration},
+    tokio::{
+        sync::oneshot,
+        time::{self, Instant},
     },
-    tokio::fs::File,
-    tokio_util::codec::{BytesCodec, FramedRead},
 };
 
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
 type Result<T> = std::result::Result<T, GenericError>;
 static NOTFOUND: &[u8] = b"Not Found";
+//how often a blocked playlist request checks the playlist
+const PLAYLIST_POLL_INTERVAL: Duration = Duration::from_millis(20);
+//how long the first request of an on demand stream waits for the playlist
+const DEMAND_PLAYLIST_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_PLAYLIST_MAX_AGE: u64 = 1;
+pub const DEFAULT_SEGMENT_MAX_AGE: u64 = 3600;
 
-async fn handle_connection(req: Request<Body>) -> Result<Response<Body>> {
-    let path = req.uri().path();
+/*the HTTP options of the hls server, they are used when a CDN is in front of it*/
+#[derive(Debug, Clone)]
+pub struct HlsServerOptions {
+    //the Cache-Control max-age of the live playlists in seconds
+    pub playlist_max_age: u64,
+    //the Cache-Control max-age of the segments and the VOD playlists in seconds
+    pub segment_max_age: u64,
+    //the origins allowed by CORS, * allows any origin, empty disables CORS
+    pub cors_origins: Vec<String>,
+}
 
-    let mut file_path: String = String::from("");
+impl Default for HlsServerOptions {
+    fn default() -> Self {
+        Self {
+            playlist_max_age: DEFAULT_PLAYLIST_MAX_AGE,
+            segment_max_age: DEFAULT_SEGMENT_MAX_AGE,
+            cors_origins: Vec::new(),
+        }
+    }
+}
 
-    if path.ends_with(".m3u8") {
-        //http://127.0.0.1/app_name/stream_name/stream_name.m3u8
-        let m3u8_index = path.find(".m3u8").unwrap();
+/*the states shared by the requests*/
+#[derive(Clone)]
+pub struct ServerContext {
+    options: Arc<HlsServerOptions>,
+    store: HlsStoreRef,
+    //authorizes the key requests by the on_play hook
+    notifier: Option<Arc<Notifier>>,
+    master_playlist: Option<Arc<MasterPlaylistGenerator>>,
+    //the playlist requests start the on demand streams
+    demand_event_sender: Option<HlsDemandEventSender>,
+    //tracks the viewers by their requests
+    session_manager: Option<Arc<HlsSessionManager>>,
+}
 
-        if m3u8_index > 0 {
-            let (left, _) = path.split_at(m3u8_index);
-            let rv: Vec<_> = left.split('/').collect();
+impl ServerContext {
+    /*the options are the HTTP options of the server, the notifier authorizes the key
+    requests by the on_play hook, the master playlists of the variant sets are generated
+    by master_playlist, and the playlist requests are sent to demand_event_sender to
+    start the on demand streams, and the viewers are tracked by session_manager*/
+    pub fn new(
+        options: HlsServerOptions,
+        store: HlsStoreRef,
+        notifier: Option<Notifier>,
+        master_playlist: Option<MasterPlaylistGenerator>,
+        demand_event_sender: Option<HlsDemandEventSender>,
+        session_manager: Option<Arc<HlsSessionManager>>,
+    ) -> Self {
+        Self {
+            options: Arc::new(options),
+            store,
+            notifier: notifier.map(Arc::new),
+            master_playlist: master_playlist.map(Arc::new),
+            demand_event_sender,
+            session_manager,
+        }
+    }
+}
 
-            let app_name = String::from(rv[1]);
-            let stream_name = String::from(rv[2]);
+/*the session id of the viewer is the session query parameter or the hls_session
+cookie, the viewer without it is identified by the remote ip*/
+fn get_session_id(req: &Request<Body>) -> Option<String> {
+    for param in req.uri().query().unwrap_or_default().split('&') {
+        if let Some(("session", value)) = param.split_once('=') {
+            if !value.is_empty() {
+                return Some(value.to_string());
+            }
+        }
+    }
 
-            file_path = format!("./{app_name}/{stream_name}/{stream_name}.m3u8");
+    for cookie in req.headers().get_all(header::COOKIE) {
+        for item in cookie.to_str().unwrap_or_default().split(';') {
+            if let Some(("hls_session", value)) = item.trim().split_once('=') {
+                if !value.is_empty() {
+                    return Some(value.to_string());
+                }
+            }
         }
-    } else if path.ends_with(".ts") {
-        //http://127.0.0.1/app_name/stream_name/ts_name.m3u8
-        let ts_index = path.find(".ts").unwrap();
+    }
 
-        if ts_index > 0 {
-            let (left, _) = path.split_at(ts_index);
+    None
+}
 
-            let rv: Vec<_> = left.split('/').collect();
+fn track_session(
+    context: &ServerContext,
+    req: &Request<Body>,
+    remote_addr: &SocketAddr,
+    app_name: &str,
+    stream_name: &str,
+) {
+    if let Some(session_manager) = &context.session_manager {
+        let notify_info = NotifyInfo {
+            request_url: req.uri().to_string(),
+            remote_addr: remote_addr.to_string(),
+        };
+        session_manager.on_request(
+            app_name,
+            stream_name,
+            remote_addr.ip(),
+            get_session_id(req).as_deref(),
+            notify_info,
+        );
+    }
+}
 
-            let app_name = String::from(rv[1]);
-            let stream_name = String::from(rv[2]);
-            let ts_name = String::from(rv[3]);
+/*the _HLS_msn and _HLS_part query parameters of a LL-HLS blocking playlist request*/
+fn parse_blocking_request(query: Option<&str>) -> Option<(Option<u64>, Option<u64>)> {
+    let mut msn = None;
+    let mut part = None;
 
-            file_path = format!("./{app_name}/{stream_name}/{ts_name}.ts");
+    for param in query.unwrap_or_default().split('&') {
+        match param.split_once('=') {
+            Some(("_HLS_msn", value)) => msn = Some(value.parse().ok()?),
+            Some(("_HLS_part", value)) => part = Some(value.parse().ok()?),
+            _ => {}
         }
     }
 
-    simple_file_send(file_path.as_str()).await
+    Some((msn, part))
 }
 
-/// HTTP status code 404
-fn not_found() -> Response<Body> {
+/*the start query parameter of a DVR playlist request, it is the epoch time in seconds*/
+fn parse_start_time(query: Option<&str>) -> Option<Option<DateTime<Utc>>> {
+    for param in query.unwrap_or_default().split('&') {
+        if let Some(("start", value)) = param.split_once('=') {
+            let seconds: f64 = value.parse().ok()?;
+            let start_time = Utc
+                .timestamp_millis_opt((seconds * 1000.0) as i64)
+                .single()?;
+            return Some(Some(start_time));
+        }
+    }
+
+    Some(None)
+}
+
+/*split the request path into its items, the items which may escape the folder of
+the stream in the store(e.g. .. and the encoded or windows separators) are rejected*/
+fn split_path(path: &str) -> Option<Vec<&str>> {
+    let items: Vec<&str> = path.strip_prefix('/')?.split('/').collect();
+
+    if items.iter().all(|item| is_valid_path_name(item)) {
+        Some(items)
+    } else {
+        None
+    }
+}
+
+fn content_type(file_name: &str) -> &'static str {
+    match file_name.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("m3u8") => "application/vnd.apple.mpegurl",
+        Some("ts") => "video/mp2t",
+        Some("m4s") => "video/iso.segment",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+fn response_builder(file_name: &str, max_age: u64) -> Builder {
     Response::builder()
-        .status(StatusCode::NOT_FOUND)
-        .body(NOTFOUND.into())
+        .header(header::CONTENT_TYPE, content_type(file_name))
+        .header(header::CACHE_CONTROL, format!("max-age={max_age}"))
+}
+
+/*the playlist begins at the start time if it is requested, the VOD playlists
+are cached as long as the segments*/
+fn playlist_response(
+    options: &HlsServerOptions,
+    file_name: &str,
+    data: Bytes,
+    start_time: Option<DateTime<Utc>>,
+) -> Response<Body> {
+    let data = match start_time {
+        Some(start_time) => Bytes::from(m3u8::slice_playlist(
+            &String::from_utf8_lossy(&data),
+            start_time,
+        )),
+        None => data,
+    };
+    let max_age = if String::from_utf8_lossy(&data).contains("#EXT-X-PLAYLIST-TYPE:VOD") {
+        options.segment_max_age
+    } else {
+        options.playlist_max_age
+    };
+
+    response_builder(file_name, max_age)
+        .body(Body::from(data))
         .unwrap()
 }
 
-async fn simple_file_send(filename: &str) -> Result<Response<Body>> {
-    // Serve a file by asynchronously reading it by chunks using tokio-util crate.
+/*a single byte range of the Range header*/
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    //the header is absent or not supported(e.g. multiple ranges), the whole data is sent
+    Full,
+    //the first and the last byte positions
+    Partial(usize, usize),
+    Unsatisfiable,
+}
+
+fn parse_range(value: Option<&str>, len: usize) -> ByteRange {
+    let range = match value.and_then(|value| value.trim().strip_prefix("bytes=")) {
+        Some(range) if !range.contains(',') => range,
+        _ => return ByteRange::Full,
+    };
+    let (first, last) = match range.split_once('-') {
+        Some((first, last)) => (first.trim(), last.trim()),
+        None => return ByteRange::Full,
+    };
+
+    //bytes=-500 is the last 500 bytes
+    if first.is_empty() {
+        return match last.parse::<usize>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial(len.saturating_sub(suffix), len - 1),
+            Err(_) => ByteRange::Full,
+        };
+    }
+
+    let first: usize = match first.parse() {
+        Ok(first) => first,
+        Err(_) => return ByteRange::Full,
+    };
+    let last: usize = if last.is_empty() {
+        usize::MAX
+    } else {
+        match last.parse() {
+            Ok(last) if last >= first => last,
+            _ => return ByteRange::Full,
+        }
+    };
+    if first >= len {
+        return ByteRange::Unsatisfiable;
+    }
+
+    ByteRange::Partial(first, last.min(len - 1))
+}
+
+/*the segments and the init segments support the byte range requests*/
+fn segment_response(
+    options: &HlsServerOptions,
+    req: &Request<Body>,
+    file_name: &str,
+    data: Bytes,
+) -> Response<Body> {
+    let builder =
+        response_builder(file_name, options.segment_max_age).header(header::ACCEPT_RANGES, "bytes");
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+
+    match parse_range(range, data.len()) {
+        ByteRange::Full => builder.body(Body::from(data)).unwrap(),
+        ByteRange::Partial(first, last) => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", first, last, data.len()),
+            )
+            .body(Body::from(data.slice(first..=last)))
+            .unwrap(),
+        ByteRange::Unsatisfiable => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", data.len()))
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
+
+/*the value of Access-Control-Allow-Origin, it is * or the origin of the request
+if the origin is allowed*/
+fn get_allowed_origin(options: &HlsServerOptions, req: &Request<Body>) -> Option<HeaderValue> {
+    if options.cors_origins.iter().any(|origin| origin == "*") {
+        return Some(HeaderValue::from_static("*"));
+    }
+
+    let origin = req.headers().get(header::ORIGIN)?;
+    let is_allowed = options
+        .cors_origins
+        .iter()
+        .any(|allowed_origin| origin.as_bytes() == allowed_origin.as_bytes());
+    if is_allowed {
+        Some(origin.clone())
+    } else {
+        None
+    }
+}
+
+fn add_cors_headers(
+    options: &HlsServerOptions,
+    allowed_origin: Option<HeaderValue>,
+    is_preflight: bool,
+    response: &mut Response<Body>,
+) {
+    let headers = response.headers_mut();
+    //the response depends on the origin of the request unless any origin is allowed
+    if !options.cors_origins.is_empty() && !options.cors_origins.iter().any(|o| o == "*") {
+        headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    }
+
+    let allowed_origin = match allowed_origin {
+        Some(allowed_origin) => allowed_origin,
+        None => return,
+    };
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allowed_origin);
+    headers.insert(
+        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static("Content-Length, Content-Range"),
+    );
+    if is_preflight {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, HEAD, OPTIONS"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("Range"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from_static("86400"),
+        );
+    }
+}
+
+/*handle a request of the hls server, it is also called by the other http servers
+which serve the hls streams*/
+pub async fn handle_connection(
+    req: Request<Body>,
+    context: ServerContext,
+    remote_addr: SocketAddr,
+) -> Result<Response<Body>> {
+    let options = context.options.clone();
+    let allowed_origin = get_allowed_origin(&options, &req);
+    let is_preflight = req.method() == Method::OPTIONS;
+
+    let mut response = match *req.method() {
+        Method::GET | Method::HEAD => route(req, context, remote_addr).await?,
+        Method::OPTIONS => Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(header::ALLOW, "GET, HEAD, OPTIONS")
+            .body(Body::empty())
+            .unwrap(),
+        _ => Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(header::ALLOW, "GET, HEAD, OPTIONS")
+            .body(Body::empty())
+            .unwrap(),
+    };
+    add_cors_headers(&options, allowed_origin, is_preflight, &mut response);
+
+    Ok(response)
+}
+
+async fn route(
+    req: Request<Body>,
+    context: ServerContext,
+    remote_addr: SocketAddr,
+) -> Result<Response<Body>> {
+    let path = req.uri().path();
+    let items = match split_path(path) {
+        Some(items) => items,
+        None => return Ok(status_response(StatusCode::BAD_REQUEST)),
+    };
+    let store = &context.store;
+
+    match items[..] {
+        //http://127.0.0.1/app_name/variant_set_name.m3u8
+        [app_name, file_name] if file_name.ends_with(".m3u8") => {
+            let name = file_name.trim_end_matches(".m3u8");
+            if let Some(master_playlist) = &context.master_playlist {
+                if let Some(playlist) = master_playlist.generate(app_name, name).await {
+                    return Ok(
+                        response_builder(file_name, context.options.playlist_max_age)
+                            .body(Body::from(playlist))
+                            .unwrap(),
+                    );
+                }
+            }
+            Ok(not_found())
+        }
+        //http://127.0.0.1/app_name/stream_name/key_index.key
+        [app_name, stream_name, file_name] if file_name.ends_with(".key") => {
+            read_key(
+                store,
+                &req,
+                context.notifier.clone(),
+                remote_addr,
+                app_name,
+                stream_name,
+                file_name,
+            )
+            .await
+        }
+        //http://127.0.0.1/app_name/stream_name/stream_name.m3u8
+        [app_name, stream_name, file_name] if file_name.ends_with(".m3u8") => {
+            let mut is_on_demand = false;
+            if let Some(sender) = &context.demand_event_sender {
+                let (responder, receiver) = oneshot::channel();
+                let demand_event = HlsDemandEvent {
+                    app_name: app_name.to_string(),
+                    stream_name: stream_name.to_string(),
+                    responder,
+                };
+                if sender.send(demand_event).is_ok() {
+                    is_on_demand = receiver.await.unwrap_or(false);
+                }
+            }
+            let response = read_playlist(
+                &context,
+                &req,
+                app_name,
+                stream_name,
+                file_name,
+                is_on_demand,
+            )
+            .await?;
+            if response.status().is_success() {
+                track_session(&context, &req, &remote_addr, app_name, stream_name);
+            }
+            Ok(response)
+        }
+        //http://127.0.0.1/app_name/stream_name/ts_name.ts
+        //http://127.0.0.1/app_name/stream_name/init.mp4 and segment_name.m4s for fmp4
+        [app_name, stream_name, file_name]
+            if [".ts", ".m4s", ".mp4"]
+                .iter()
+                .any(|extension| file_name.ends_with(extension)) =>
+        {
+            match store.read(app_name, stream_name, file_name) {
+                Ok(Some(data)) => {
+                    track_session(&context, &req, &remote_addr, app_name, stream_name);
+                    Ok(segment_response(&context.options, &req, file_name, data))
+                }
+                Ok(None) => Ok(not_found()),
+                Err(err) => {
+                    log::error!("hls server read {} error: {}", path, err);
+                    Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR))
+                }
+            }
+        }
+        _ => Ok(not_found()),
+    }
+}
+
+/*Read the playlist, the first request of an on demand stream waits until the playlist is
+generated, and a LL-HLS blocking request with _HLS_msn(and _HLS_part) is held until the
+playlist contains the requested segment(or partial segment), or three target durations pass.*/
+async fn read_playlist(
+    context: &ServerContext,
+    req: &Request<Body>,
+    app_name: &str,
+    stream_name: &str,
+    file_name: &str,
+    is_on_demand: bool,
+) -> Result<Response<Body>> {
+    let (msn, part) = match parse_blocking_request(req.uri().query()) {
+        Some((None, Some(_))) | None => return Ok(status_response(StatusCode::BAD_REQUEST)),
+        Some(request) => request,
+    };
+    let start_time = match parse_start_time(req.uri().query()) {
+        Some(start_time) => start_time,
+        None => return Ok(status_response(StatusCode::BAD_REQUEST)),
+    };
+
+    let demand_deadline = Instant::now() + DEMAND_PLAYLIST_TIMEOUT;
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let data = match context.store.read(app_name, stream_name, file_name) {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                if is_on_demand && Instant::now() < demand_deadline {
+                    time::sleep(PLAYLIST_POLL_INTERVAL).await;
+                    continue;
+                }
+                return Ok(not_found());
+            }
+            Err(err) => {
+                log::error!("hls server read {} error: {}", file_name, err);
+                return Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR));
+            }
+        };
+
+        let msn = if let Some(msn) = msn {
+            msn
+        } else {
+            return Ok(playlist_response(
+                &context.options,
+                file_name,
+                data,
+                start_time,
+            ));
+        };
+
+        let position = PlaylistPosition::parse(&String::from_utf8_lossy(&data));
+        if position.is_end || position.contains(msn, part) {
+            return Ok(playlist_response(
+                &context.options,
+                file_name,
+                data,
+                start_time,
+            ));
+        }
+        //the requested segment is more than two segments after the last one
+        if msn > position.msn + 1 {
+            return Ok(status_response(StatusCode::BAD_REQUEST));
+        }
 
-    if let Ok(file) = File::open(filename).await {
-        let stream = FramedRead::new(file, BytesCodec::new());
-        let body = Body::wrap_stream(stream);
-        return Ok(Response::new(body));
+        let deadline = *deadline.get_or_insert_with(|| {
+            Instant::now() + Duration::from_secs(position.target_duration.max(1) * 3)
+        });
+        if Instant::now() >= deadline {
+            return Ok(status_response(StatusCode::SERVICE_UNAVAILABLE));
+        }
+        time::sleep(PLAYLIST_POLL_INTERVAL).await;
     }
+}
+
+/*the keys are only served to the players authorized by the on_play hook*/
+async fn read_key(
+    store: &HlsStoreRef,
+    req: &Request<Body>,
+    notifier: Option<Arc<Notifier>>,
+    remote_addr: SocketAddr,
+    app_name: &str,
+    stream_name: &str,
+    file_name: &str,
+) -> Result<Response<Body>> {
+    if let Some(notifier) = notifier {
+        let info = PlayRequestInfo {
+            app_name: app_name.to_string(),
+            stream_name: stream_name.to_string(),
+            request_url: req.uri().to_string(),
+            remote_addr: remote_addr.to_string(),
+        };
+        if !notifier.on_play_authorize(&info).await {
+            return Ok(status_response(StatusCode::FORBIDDEN));
+        }
+    }
+
+    match store.read(app_name, stream_name, file_name) {
+        Ok(Some(data)) => Ok(Response::builder()
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(Body::from(data))
+            .unwrap()),
+        Ok(None) => Ok(not_found()),
+        Err(err) => {
+            log::error!("hls server read {} error: {}", file_name, err);
+            Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
 
-    Ok(not_found())
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::empty())
+        .unwrap()
 }
 
-pub async fn run(port: usize) -> Result<()> {
+/// HTTP status code 404
+fn not_found() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(NOTFOUND.into())
+        .unwrap()
+}
+
+pub async fn run(port: usize, context: ServerContext) -> Result<()> {
     let listen_address = format!("0.0.0.0:{port}");
     let sock_addr = listen_address.parse().unwrap();
 
-    let new_service =
-        make_service_fn(move |_| async { Ok::<_, GenericError>(service_fn(handle_connection)) });
+    let new_service = make_service_fn(move |conn: &AddrStream| {
+        let context = context.clone();
+        let remote_addr = conn.remote_addr();
+        async move {
+            Ok::<_, GenericError>(service_fn(move |req| {
+                handle_connection(req, context.clone(), remote_addr)
+            }))
+        }
+    });
 
     let server = Server::bind(&sock_addr).serve(new_service);
     log::info!("Hls server listening on http://{}", sock_addr);
@@ -82,3 +630,151 @@ pub async fn run(port: usize) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{content_type, parse_range, read_key, split_path, ByteRange},
+        crate::store::{memory::MemoryHlsStore, HlsStoreRef},
+        bytes::Bytes,
+        hyper::{
+            service::{make_service_fn, service_fn},
+            Body, Request, Response, Server, StatusCode,
+        },
+        rtmp::notify::Notifier,
+        std::{convert::Infallible, net::SocketAddr, sync::Arc},
+    };
+
+    //an on_play hook which allows the requests to /allow and denies the others
+    fn start_on_play_hook() -> SocketAddr {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+                let status = if req.uri().path() == "/allow" {
+                    StatusCode::OK
+                } else {
+                    StatusCode::FORBIDDEN
+                };
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .status(status)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+            }))
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+        address
+    }
+
+    fn on_play_notifier(url: String) -> Option<Arc<Notifier>> {
+        Some(Arc::new(Notifier::new(
+            None,
+            None,
+            Some(url),
+            None,
+            None,
+            None,
+        )))
+    }
+
+    async fn request_key(
+        store: &HlsStoreRef,
+        notifier: Option<Arc<Notifier>>,
+        file_name: &str,
+    ) -> StatusCode {
+        let req = Request::builder()
+            .uri(format!("/live/test/{file_name}"))
+            .body(Body::empty())
+            .unwrap();
+        read_key(
+            store,
+            &req,
+            notifier,
+            "127.0.0.1:50000".parse().unwrap(),
+            "live",
+            "test",
+            file_name,
+        )
+        .await
+        .unwrap()
+        .status()
+    }
+
+    #[tokio::test]
+    async fn test_read_key_authorization() {
+        let store: HlsStoreRef = Arc::new(MemoryHlsStore::default());
+        store
+            .write("live", "test", "0.key", Bytes::from_static(&[0; 16]))
+            .unwrap();
+        let hook = start_on_play_hook();
+
+        //the keys are public without the on_play hook
+        assert_eq!(request_key(&store, None, "0.key").await, StatusCode::OK);
+
+        let allow = on_play_notifier(format!("http://{hook}/allow"));
+        assert_eq!(
+            request_key(&store, allow.clone(), "0.key").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            request_key(&store, allow, "1.key").await,
+            StatusCode::NOT_FOUND
+        );
+
+        let deny = on_play_notifier(format!("http://{hook}/deny"));
+        assert_eq!(
+            request_key(&store, deny, "0.key").await,
+            StatusCode::FORBIDDEN
+        );
+
+        //an unreachable hook denies the request
+        let unreachable = on_play_notifier(String::from("http://127.0.0.1:1/allow"));
+        assert_eq!(
+            request_key(&store, unreachable, "0.key").await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(
+            split_path("/live/test/0.ts"),
+            Some(vec!["live", "test", "0.ts"])
+        );
+        assert_eq!(split_path("/live/../../etc/passwd"), None);
+        assert_eq!(split_path("/live/test/..%2f0.ts"), None);
+        assert_eq!(split_path("/live//0.ts"), None);
+        assert_eq!(split_path("/"), None);
+        assert_eq!(content_type("test.m3u8"), "application/vnd.apple.mpegurl");
+        assert_eq!(content_type("0.ts"), "video/mp2t");
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range(None, 100), ByteRange::Full);
+        assert_eq!(
+            parse_range(Some("bytes=0-9"), 100),
+            ByteRange::Partial(0, 9)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=90-"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=50-200"), 100),
+            ByteRange::Partial(50, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-10"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=100-"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), 100), ByteRange::Full);
+        assert_eq!(parse_range(Some("items=0-1"), 100), ByteRange::Full);
+    }
+}
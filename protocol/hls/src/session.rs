@@ -0,0 +1,436 @@
+use {
+    rtmp::{
+        channels::define::{ChannelEvent, ChannelEventProducer},
+        session::{
+            common::{NotifyInfo, SubscribeOptions, SubscriberInfo},
+            define::SubscribeType,
+        },
+    },
+    std::{
+        collections::HashMap,
+        net::IpAddr,
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+    tokio::{
+        sync::oneshot,
+        time::{self, Instant},
+    },
+    uuid::Uuid,
+};
+
+pub const DEFAULT_SESSION_TIMEOUT: u64 = 30;
+pub const DEFAULT_MAX_SESSIONS_PER_IP: usize = 16;
+//the longer session tokens are ignored
+const MAX_SESSION_ID_LENGTH: usize = 64;
+
+struct HlsViewerSession {
+    app_name: String,
+    stream_name: String,
+    remote_ip: IpAddr,
+    info: SubscriberInfo,
+    last_request_time: Instant,
+}
+
+/*HLS playback is stateless, a viewer is identified by the session id the client sends
+(the session query parameter or the hls_session cookie) or by the remote ip, its first
+request subscribes the stream as a PlayerHls subscriber(on_play), and it unsubscribes
+(on_stop) after it has no request for the session timeout.
+
+The session ids are chosen by the clients, so a remote ip has at most
+max_sessions_per_ip sessions with an id per stream, the requests with the other ids are
+counted as the one viewer of the ip. The viewers behind a NAT or a CDN share the remote
+ip, they are counted as one viewer unless they send session ids.*/
+pub struct HlsSessionManager {
+    //{app_name}/{stream_name}/ip/{remote ip} or {app_name}/{stream_name}/id/{session id}
+    //to session
+    sessions: Mutex<HashMap<String, HlsViewerSession>>,
+    event_producer: ChannelEventProducer,
+    timeout: Duration,
+    max_sessions_per_ip: usize,
+}
+
+impl HlsSessionManager {
+    pub fn new(event_producer: ChannelEventProducer, timeout: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            event_producer,
+            timeout,
+            max_sessions_per_ip: DEFAULT_MAX_SESSIONS_PER_IP,
+        }
+    }
+
+    /*0 ignores the session ids and identifies the viewers by the remote ip*/
+    pub fn set_max_sessions_per_ip(&mut self, max_sessions_per_ip: usize) {
+        self.max_sessions_per_ip = max_sessions_per_ip;
+    }
+
+    /*a playlist or segment request of the viewer*/
+    pub fn on_request(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        remote_ip: IpAddr,
+        session_id: Option<&str>,
+        notify_info: NotifyInfo,
+    ) {
+        let mut sessions = match self.sessions.lock() {
+            Ok(sessions) => sessions,
+            Err(err) => {
+                log::error!("hls sessions lock error: {}", err);
+                return;
+            }
+        };
+
+        let ip_key = format!("{app_name}/{stream_name}/ip/{remote_ip}");
+        let key = match session_id {
+            Some(session_id)
+                if self.max_sessions_per_ip > 0 && session_id.len() <= MAX_SESSION_ID_LENGTH =>
+            {
+                format!("{app_name}/{stream_name}/id/{session_id}")
+            }
+            _ => ip_key.clone(),
+        };
+
+        if let Some(session) = sessions.get_mut(&key) {
+            session.last_request_time = Instant::now();
+            return;
+        }
+
+        //the ip has used up its sessions of the stream
+        let key = if key != ip_key
+            && Self::count_sessions(&sessions, app_name, stream_name, remote_ip)
+                >= self.max_sessions_per_ip
+        {
+            log::debug!(
+                "hls sessions of {} exceed the limit of {}/{}",
+                remote_ip,
+                app_name,
+                stream_name
+            );
+            if let Some(session) = sessions.get_mut(&ip_key) {
+                session.last_request_time = Instant::now();
+                return;
+            }
+            ip_key
+        } else {
+            key
+        };
+
+        let info = SubscriberInfo {
+            id: Uuid::new_v4(),
+            sub_type: SubscribeType::PlayerHls,
+            notify_info,
+            options: SubscribeOptions::default(),
+        };
+        let (responder, receiver) = oneshot::channel();
+        let subscribe_event = ChannelEvent::Subscribe {
+            app_name: app_name.to_string(),
+            stream_name: stream_name.to_string(),
+            info: info.clone(),
+            responder,
+        };
+        if let Err(err) = self.event_producer.send(subscribe_event) {
+            log::error!("hls session subscribe error: {}", err);
+            return;
+        }
+        //the viewer does not consume the stream data
+        tokio::spawn(async move {
+            if receiver.await.is_err() {
+                log::trace!("hls session subscribe is rejected");
+            }
+        });
+
+        log::info!(
+            "hls viewer {} of {}/{} starts",
+            info.id,
+            app_name,
+            stream_name
+        );
+        sessions.insert(
+            key,
+            HlsViewerSession {
+                app_name: app_name.to_string(),
+                stream_name: stream_name.to_string(),
+                remote_ip,
+                info,
+                last_request_time: Instant::now(),
+            },
+        );
+    }
+
+    fn count_sessions(
+        sessions: &HashMap<String, HlsViewerSession>,
+        app_name: &str,
+        stream_name: &str,
+        remote_ip: IpAddr,
+    ) -> usize {
+        sessions
+            .values()
+            .filter(|session| {
+                session.remote_ip == remote_ip
+                    && session.app_name == app_name
+                    && session.stream_name == stream_name
+            })
+            .count()
+    }
+
+    fn expire_sessions(&self) {
+        let mut sessions = match self.sessions.lock() {
+            Ok(sessions) => sessions,
+            Err(err) => {
+                log::error!("hls sessions lock error: {}", err);
+                return;
+            }
+        };
+
+        let expired_keys: Vec<String> = sessions
+            .iter()
+            .filter(|(_, session)| session.last_request_time.elapsed() > self.timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in expired_keys {
+            if let Some(session) = sessions.remove(&key) {
+                log::info!(
+                    "hls viewer {} of {}/{} stops",
+                    session.info.id,
+                    session.app_name,
+                    session.stream_name
+                );
+                let unsubscribe_event = ChannelEvent::UnSubscribe {
+                    app_name: session.app_name,
+                    stream_name: session.stream_name,
+                    info: session.info,
+                };
+                if let Err(err) = self.event_producer.send(unsubscribe_event) {
+                    log::error!("hls session unsubscribe error: {}", err);
+                }
+            }
+        }
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            self.expire_sessions();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::HlsSessionManager,
+        rtmp::{
+            channels::{
+                define::{ChannelEvent, ChannelEventProducer},
+                ChannelsManager,
+            },
+            session::{
+                common::{NotifyInfo, PublisherInfo},
+                define::{PublishType, SubscribeType},
+            },
+        },
+        std::{net::IpAddr, time::Duration},
+        tokio::{
+            sync::{mpsc, oneshot},
+            time::sleep,
+        },
+        uuid::Uuid,
+    };
+
+    fn ip(n: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, n])
+    }
+
+    fn notify_info() -> NotifyInfo {
+        NotifyInfo {
+            request_url: String::from("/live/test/test.m3u8"),
+            remote_addr: String::from("127.0.0.1:50000"),
+        }
+    }
+
+    async fn get_hls_viewer_count(event_producer: &ChannelEventProducer) -> usize {
+        let (data_sender, mut data_receiver) = mpsc::unbounded_channel();
+        let (size_sender, size_receiver) = oneshot::channel();
+        assert!(event_producer
+            .send(ChannelEvent::ApiStatistic {
+                data_sender,
+                size_sender,
+            })
+            .is_ok());
+        assert_eq!(size_receiver.await.unwrap(), 1);
+        data_receiver.recv().await.unwrap().hls_viewer_count
+    }
+
+    #[tokio::test]
+    async fn test_session_reuse() {
+        let (event_producer, mut event_consumer) = mpsc::unbounded_channel();
+        let session_manager = HlsSessionManager::new(event_producer, Duration::from_secs(30));
+
+        //the first request subscribes the stream as a PlayerHls viewer
+        session_manager.on_request("live", "test", ip(1), Some("viewer1"), notify_info());
+        let viewer_id = match event_consumer.try_recv() {
+            Ok(ChannelEvent::Subscribe {
+                app_name,
+                stream_name,
+                info,
+                ..
+            }) => {
+                assert_eq!((app_name.as_str(), stream_name.as_str()), ("live", "test"));
+                assert!(info.sub_type == SubscribeType::PlayerHls);
+                info.id
+            }
+            _ => panic!("the first request does not subscribe"),
+        };
+
+        //the later requests of the viewer reuse its session
+        session_manager.on_request("live", "test", ip(1), Some("viewer1"), notify_info());
+        session_manager.on_request("live", "test", ip(1), Some("viewer1"), notify_info());
+        assert!(event_consumer.try_recv().is_err());
+
+        //another viewer and the same viewer of another stream are new sessions
+        session_manager.on_request("live", "test", ip(1), Some("viewer2"), notify_info());
+        session_manager.on_request("live", "other", ip(1), Some("viewer1"), notify_info());
+        for _ in 0..2 {
+            match event_consumer.try_recv() {
+                Ok(ChannelEvent::Subscribe { info, .. }) => assert_ne!(info.id, viewer_id),
+                _ => panic!("a new viewer does not subscribe"),
+            }
+        }
+        assert!(event_consumer.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_expiry() {
+        let (event_producer, mut event_consumer) = mpsc::unbounded_channel();
+        let session_manager = HlsSessionManager::new(event_producer, Duration::from_millis(300));
+
+        session_manager.on_request("live", "test", ip(1), Some("viewer1"), notify_info());
+        session_manager.on_request("live", "test", ip(1), Some("viewer2"), notify_info());
+        let mut viewer_ids = Vec::new();
+        while let Ok(ChannelEvent::Subscribe { info, .. }) = event_consumer.try_recv() {
+            viewer_ids.push(info.id);
+        }
+        assert_eq!(viewer_ids.len(), 2);
+
+        //viewer2 keeps requesting within the timeout
+        sleep(Duration::from_millis(200)).await;
+        session_manager.on_request("live", "test", ip(1), Some("viewer2"), notify_info());
+        session_manager.expire_sessions();
+        assert!(event_consumer.try_recv().is_err());
+
+        //viewer1 has no request for the timeout and unsubscribes(on_stop)
+        sleep(Duration::from_millis(200)).await;
+        session_manager.expire_sessions();
+        match event_consumer.try_recv() {
+            Ok(ChannelEvent::UnSubscribe {
+                app_name,
+                stream_name,
+                info,
+            }) => {
+                assert_eq!((app_name.as_str(), stream_name.as_str()), ("live", "test"));
+                assert_eq!(info.id, viewer_ids[0]);
+            }
+            _ => panic!("the expired viewer does not unsubscribe"),
+        }
+        assert!(event_consumer.try_recv().is_err());
+
+        //a request after the expiry starts a new session
+        session_manager.on_request("live", "test", ip(1), Some("viewer1"), notify_info());
+        match event_consumer.try_recv() {
+            Ok(ChannelEvent::Subscribe { info, .. }) => assert_ne!(info.id, viewer_ids[0]),
+            _ => panic!("the returning viewer does not subscribe"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hls_viewer_count() {
+        let mut channels = ChannelsManager::new(None);
+        let event_producer = channels.get_channel_event_producer();
+        tokio::spawn(async move { channels.run().await });
+
+        let (responder, receiver) = oneshot::channel();
+        assert!(event_producer
+            .send(ChannelEvent::Publish {
+                app_name: String::from("live"),
+                stream_name: String::from("test"),
+                info: PublisherInfo {
+                    id: Uuid::new_v4(),
+                    sub_type: PublishType::PushRtmp,
+                    notify_info: notify_info(),
+                },
+                responder,
+            })
+            .is_ok());
+        let _data_producer = receiver.await.unwrap();
+        assert_eq!(get_hls_viewer_count(&event_producer).await, 0);
+
+        let session_manager =
+            HlsSessionManager::new(event_producer.clone(), Duration::from_millis(300));
+        session_manager.on_request("live", "test", ip(1), Some("viewer1"), notify_info());
+        session_manager.on_request("live", "test", ip(1), Some("viewer2"), notify_info());
+        session_manager.on_request("live", "test", ip(1), Some("viewer1"), notify_info());
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(get_hls_viewer_count(&event_producer).await, 2);
+
+        sleep(Duration::from_millis(100)).await;
+        session_manager.on_request("live", "test", ip(1), Some("viewer2"), notify_info());
+        sleep(Duration::from_millis(200)).await;
+        session_manager.expire_sessions();
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(get_hls_viewer_count(&event_producer).await, 1);
+
+        sleep(Duration::from_millis(300)).await;
+        session_manager.expire_sessions();
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(get_hls_viewer_count(&event_producer).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_max_sessions_per_ip() {
+        let (event_producer, mut event_consumer) = mpsc::unbounded_channel();
+        let mut session_manager = HlsSessionManager::new(event_producer, Duration::from_secs(30));
+        session_manager.set_max_sessions_per_ip(2);
+        let mut subscribe_count = || {
+            let mut count = 0;
+            while let Ok(event) = event_consumer.try_recv() {
+                assert!(matches!(event, ChannelEvent::Subscribe { .. }));
+                count += 1;
+            }
+            count
+        };
+
+        //the ids beyond the limit of the ip are counted as the viewer of the ip
+        for i in 0..10 {
+            let session_id = format!("viewer{}", i);
+            session_manager.on_request("live", "test", ip(1), Some(&session_id), notify_info());
+        }
+        session_manager.on_request("live", "test", ip(1), None, notify_info());
+        assert_eq!(subscribe_count(), 3);
+
+        //the limit is per ip and per stream, and a session id is kept on another ip
+        session_manager.on_request("live", "test", ip(2), Some("viewer10"), notify_info());
+        session_manager.on_request("live", "other", ip(1), Some("viewer11"), notify_info());
+        session_manager.on_request("live", "test", ip(2), Some("viewer0"), notify_info());
+        assert_eq!(subscribe_count(), 2);
+
+        //the viewers without a session id share the ip
+        session_manager.on_request("live", "test", ip(3), None, notify_info());
+        session_manager.on_request("live", "test", ip(3), None, notify_info());
+        assert_eq!(subscribe_count(), 1);
+
+        //the too long ids are ignored
+        let session_id = "x".repeat(100);
+        session_manager.on_request("live", "test", ip(3), Some(&session_id), notify_info());
+        assert_eq!(subscribe_count(), 0);
+
+        //0 ignores the session ids
+        session_manager.set_max_sessions_per_ip(0);
+        session_manager.on_request("live", "test", ip(4), Some("viewer12"), notify_info());
+        session_manager.on_request("live", "test", ip(4), Some("viewer13"), notify_info());
+        assert_eq!(subscribe_count(), 1);
+    }
+}
@@ -0,0 +1,134 @@
+use {
+    super::HlsStore,
+    crate::errors::{MediaError, MediaErrorValue},
+    bytes::Bytes,
+    rtmp::utils::is_valid_path_name,
+    std::{collections::HashMap, fs, io::ErrorKind, path::PathBuf},
+};
+
+/*Write the hls files to {root}/{app_name}/{stream_name}/{name}*/
+pub struct FileHlsStore {
+    root: PathBuf,
+    //override the root of the apps
+    app_roots: HashMap<String, PathBuf>,
+}
+
+impl FileHlsStore {
+    pub fn new(root: String) -> Self {
+        Self {
+            root: PathBuf::from(root),
+            app_roots: HashMap::new(),
+        }
+    }
+
+    pub fn set_app_root(&mut self, app_name: String, root: String) {
+        self.app_roots.insert(app_name, PathBuf::from(root));
+    }
+
+    /*the names come from the publishers, they must not escape the root*/
+    fn get_folder(&self, app_name: &str, stream_name: &str) -> Result<PathBuf, MediaError> {
+        for name in [app_name, stream_name] {
+            if !is_valid_path_name(name) {
+                return Err(MediaError {
+                    value: MediaErrorValue::InvalidPathName(name.to_string()),
+                });
+            }
+        }
+
+        Ok(self
+            .app_roots
+            .get(app_name)
+            .unwrap_or(&self.root)
+            .join(app_name)
+            .join(stream_name))
+    }
+}
+
+impl HlsStore for FileHlsStore {
+    fn write(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        name: &str,
+        data: Bytes,
+    ) -> Result<(), MediaError> {
+        let folder = self.get_folder(app_name, stream_name)?;
+        fs::create_dir_all(&folder)?;
+        fs::write(folder.join(name), &data[..])?;
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        name: &str,
+    ) -> Result<Option<Bytes>, MediaError> {
+        match fs::read(self.get_folder(app_name, stream_name)?.join(name)) {
+            Ok(data) => Ok(Some(Bytes::from(data))),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn delete(&self, app_name: &str, stream_name: &str, name: &str) -> Result<(), MediaError> {
+        match fs::remove_file(self.get_folder(app_name, stream_name)?.join(name)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn clear(&self, app_name: &str, stream_name: &str) -> Result<(), MediaError> {
+        match fs::remove_dir_all(self.get_folder(app_name, stream_name)?) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn archive(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        archive_name: &str,
+    ) -> Result<String, MediaError> {
+        let archive_folder = self.get_folder(app_name, archive_name)?;
+        fs::rename(self.get_folder(app_name, stream_name)?, &archive_folder)?;
+        Ok(archive_folder.to_string_lossy().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{FileHlsStore, HlsStore},
+        bytes::Bytes,
+        std::fs,
+    };
+
+    #[test]
+    fn test_invalid_names() {
+        let folder = std::env::temp_dir().join(format!("hls_store_{}", std::process::id()));
+        let root = folder.join("root");
+        let store = FileHlsStore::new(root.to_string_lossy().to_string());
+
+        store
+            .write("live", "test", "0.ts", Bytes::from_static(b"ts"))
+            .unwrap();
+        //the names may not escape the root
+        assert!(store
+            .write("live", "../../x", "0.ts", Bytes::from_static(b"ts"))
+            .is_err());
+        assert!(store.read("..", "root", "0.ts").is_err());
+        assert!(store.clear("live", "..").is_err());
+        assert!(store.archive("live", "test", "../../archive").is_err());
+        assert!(!folder.join("x").exists() && !folder.join("archive").exists());
+
+        let archive_path = store.archive("live", "test", "test_archive").unwrap();
+        assert!(archive_path.starts_with(root.to_string_lossy().as_ref()));
+        assert_eq!(
+            store.read("live", "test_archive", "0.ts").unwrap(),
+            Some(Bytes::from_static(b"ts"))
+        );
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
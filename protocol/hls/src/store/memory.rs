@@ -0,0 +1,156 @@
+use {
+    super::HlsStore,
+    crate::errors::MediaError,
+    bytes::Bytes,
+    std::{
+        collections::{HashMap, VecDeque},
+        sync::RwLock,
+    },
+};
+
+/*the files of one stream, the segments are kept in writing order*/
+#[derive(Default)]
+struct StreamFiles {
+    files: HashMap<String, Bytes>,
+    segments: VecDeque<String>,
+}
+
+/*Keep the hls files in memory, the segments of each stream are kept in a ring,
+the oldest segment is dropped when the ring is full.*/
+pub struct MemoryHlsStore {
+    //app_name/stream_name -> files
+    streams: RwLock<HashMap<String, StreamFiles>>,
+    max_segments: usize,
+}
+
+impl MemoryHlsStore {
+    pub fn new(max_segments: usize) -> Self {
+        Self {
+            streams: RwLock::new(HashMap::new()),
+            max_segments,
+        }
+    }
+}
+
+impl Default for MemoryHlsStore {
+    fn default() -> Self {
+        //enough for the partial segments of LL-HLS
+        Self::new(1024)
+    }
+}
+
+/*the playlists and the keys are not counted in the ring*/
+fn is_segment(name: &str) -> bool {
+    !name.ends_with(".m3u8") && !name.ends_with(".key")
+}
+
+impl HlsStore for MemoryHlsStore {
+    fn write(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        name: &str,
+        data: Bytes,
+    ) -> Result<(), MediaError> {
+        let key = format!("{}/{}", app_name, stream_name);
+        let mut streams = self.streams.write().unwrap();
+        let stream_files = streams.entry(key).or_default();
+
+        if stream_files.files.insert(name.to_string(), data).is_none() && is_segment(name) {
+            stream_files.segments.push_back(name.to_string());
+            while stream_files.segments.len() > self.max_segments {
+                if let Some(oldest) = stream_files.segments.pop_front() {
+                    stream_files.files.remove(&oldest);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        name: &str,
+    ) -> Result<Option<Bytes>, MediaError> {
+        let key = format!("{}/{}", app_name, stream_name);
+        let streams = self.streams.read().unwrap();
+
+        Ok(streams
+            .get(&key)
+            .and_then(|stream_files| stream_files.files.get(name))
+            .cloned())
+    }
+
+    fn delete(&self, app_name: &str, stream_name: &str, name: &str) -> Result<(), MediaError> {
+        let key = format!("{}/{}", app_name, stream_name);
+        let mut streams = self.streams.write().unwrap();
+
+        if let Some(stream_files) = streams.get_mut(&key) {
+            if stream_files.files.remove(name).is_some() {
+                stream_files.segments.retain(|segment| segment != name);
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&self, app_name: &str, stream_name: &str) -> Result<(), MediaError> {
+        let key = format!("{}/{}", app_name, stream_name);
+        self.streams.write().unwrap().remove(&key);
+        Ok(())
+    }
+
+    fn archive(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        archive_name: &str,
+    ) -> Result<String, MediaError> {
+        let key = format!("{}/{}", app_name, stream_name);
+        let archive_key = format!("{}/{}", app_name, archive_name);
+        let mut streams = self.streams.write().unwrap();
+
+        if let Some(stream_files) = streams.remove(&key) {
+            streams.insert(archive_key.clone(), stream_files);
+        }
+        Ok(archive_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{HlsStore, MemoryHlsStore},
+        bytes::Bytes,
+    };
+
+    #[test]
+    fn test_memory_store_ring() {
+        let store = MemoryHlsStore::new(2);
+
+        for i in 0..3 {
+            let name = format!("{}.ts", i);
+            store
+                .write("live", "test", &name, Bytes::from(name.clone()))
+                .unwrap();
+        }
+        store
+            .write("live", "test", "test.m3u8", Bytes::from("playlist"))
+            .unwrap();
+
+        //the oldest segment is dropped, the playlist is not counted in the ring
+        assert!(store.read("live", "test", "0.ts").unwrap().is_none());
+        assert_eq!(
+            store.read("live", "test", "2.ts").unwrap(),
+            Some(Bytes::from("2.ts"))
+        );
+        assert!(store.read("live", "test", "test.m3u8").unwrap().is_some());
+
+        store.delete("live", "test", "1.ts").unwrap();
+        assert!(store.read("live", "test", "1.ts").unwrap().is_none());
+
+        store.clear("live", "test").unwrap();
+        assert!(store.read("live", "test", "test.m3u8").unwrap().is_none());
+    }
+}
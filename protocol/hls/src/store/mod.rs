@@ -0,0 +1,38 @@
+pub mod filesystem;
+pub mod memory;
+
+use {super::errors::MediaError, bytes::Bytes, std::sync::Arc};
+
+/*
+ The storage of the hls playlists and segments, it is written by the remuxer and read
+ by the hls http server. The files of a stream are addressed by app_name/stream_name/name,
+ e.g. live/test/test.m3u8 or live/test/0.ts.
+*/
+pub trait HlsStore: Send + Sync {
+    fn write(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        name: &str,
+        data: Bytes,
+    ) -> Result<(), MediaError>;
+    fn read(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        name: &str,
+    ) -> Result<Option<Bytes>, MediaError>;
+    fn delete(&self, app_name: &str, stream_name: &str, name: &str) -> Result<(), MediaError>;
+    /*delete all the files of a stream*/
+    fn clear(&self, app_name: &str, stream_name: &str) -> Result<(), MediaError>;
+    /*move all the files of a stream to app_name/archive_name, they are kept
+    as a VOD, returns the path of the archive*/
+    fn archive(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        archive_name: &str,
+    ) -> Result<String, MediaError>;
+}
+
+pub type HlsStoreRef = Arc<dyn HlsStore>;
@@ -1,14 +1,20 @@
 #[cfg(test)]
 mod tests {
+    use crate::define::{FlvDemuxerData, HlsOptions};
     use crate::errors::MediaError;
     use crate::flv2hls::Flv2HlsRemuxer;
+    use crate::store::{memory::MemoryHlsStore, HlsStore};
+    use crate::timed_metadata::data_message;
     use bytes::BytesMut;
-    use xflv::define::FlvData;
+    use rtmp::amf0::Amf0ValueType;
+    use xflv::define::{frame_type, FlvData};
+    use xmpegts::id3;
 
-    use xflv::demuxer::FlvDemuxer;
+    use xflv::demuxer::{FlvDemuxer, FlvDemuxerAudioData, FlvDemuxerVideoData};
 
     use std::fs::File;
     use std::io::prelude::*;
+    use std::sync::Arc;
     use std::time::Instant;
 
     #[allow(dead_code)]
@@ -64,7 +70,12 @@ mod tests {
         demuxer.read_flv_header()?;
 
         let start = Instant::now();
-        let mut media_demuxer = Flv2HlsRemuxer::new(5, String::from("live"), String::from("test"));
+        let mut media_demuxer = Flv2HlsRemuxer::new(
+            String::from("live"),
+            String::from("test"),
+            Arc::new(MemoryHlsStore::default()),
+            &HlsOptions::default(),
+        );
 
         loop {
             let data_ = demuxer.read_flv_tag();
@@ -89,4 +100,305 @@ mod tests {
 
         Ok(())
     }
+
+    fn audio_frame(dts: i64) -> FlvDemuxerData {
+        FlvDemuxerData::Audio {
+            data: FlvDemuxerAudioData {
+                has_data: true,
+                dts,
+                pts: dts,
+                data: BytesMut::from(&[0_u8; 64][..]),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn video_frame(dts: i64, is_key_frame: bool) -> FlvDemuxerData {
+        FlvDemuxerData::Video {
+            data: FlvDemuxerVideoData {
+                has_data: true,
+                dts,
+                pts: dts,
+                frame_type: if is_key_frame {
+                    frame_type::KEY_FRAME
+                } else {
+                    frame_type::INTER_FRAME
+                },
+                data: BytesMut::from(&[0_u8, 0, 0, 1, 0x65, 0, 0, 0][..]),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn read_playlist(store: &MemoryHlsStore) -> String {
+        let playlist = store.read("live", "test", "test.m3u8").unwrap().unwrap();
+        String::from_utf8_lossy(&playlist).to_string()
+    }
+
+    #[test]
+    fn test_audio_only_segments() -> Result<(), MediaError> {
+        let store = Arc::new(MemoryHlsStore::default());
+        let options = HlsOptions {
+            duration: 2,
+            ..Default::default()
+        };
+        let mut remuxer = Flv2HlsRemuxer::new(
+            String::from("live"),
+            String::from("test"),
+            store.clone(),
+            &options,
+        );
+
+        //the stream begins at 100s and the timestamp jumps 20s at 105s
+        for i in 0..250 {
+            remuxer.process_demux_data(&audio_frame(100_000 + i * 20))?;
+        }
+        for i in 0..150 {
+            remuxer.process_demux_data(&audio_frame(125_000 + i * 20))?;
+        }
+
+        let playlist = read_playlist(&store);
+        assert!(playlist.contains("#EXT-X-TARGETDURATION:2\n"));
+        assert!(playlist.contains("#EXTINF:2.000\n0.ts\n#EXTINF:2.000\n1.ts\n"));
+        //the segment before the jump ends with its last frame
+        assert!(
+            playlist.contains("#EXTINF:0.980\n2.ts\n#EXT-X-DISCONTINUITY\n#EXTINF:2.000\n3.ts\n")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_duration() -> Result<(), MediaError> {
+        let store = Arc::new(MemoryHlsStore::default());
+        let options = HlsOptions {
+            duration: 2,
+            max_duration: 4,
+            ..Default::default()
+        };
+        let mut remuxer = Flv2HlsRemuxer::new(
+            String::from("live"),
+            String::from("test"),
+            store.clone(),
+            &options,
+        );
+
+        //a 30 seconds GOP
+        for i in 0..300 {
+            remuxer.process_demux_data(&video_frame(i * 40, i % 750 == 0))?;
+        }
+
+        let playlist = read_playlist(&store);
+        assert!(playlist.contains("#EXT-X-TARGETDURATION:4\n"));
+        assert_eq!(playlist.matches("#EXTINF:4.000\n").count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_target_is_fixed() -> Result<(), MediaError> {
+        let store = Arc::new(MemoryHlsStore::default());
+        let options = HlsOptions {
+            duration: 2,
+            low_latency: true,
+            part_duration: 200,
+            ..Default::default()
+        };
+        let mut remuxer = Flv2HlsRemuxer::new(
+            String::from("live"),
+            String::from("test"),
+            store.clone(),
+            &options,
+        );
+
+        //24 fps video with a 2 seconds GOP and 23ms audio frames, the frame
+        //intervals do not divide the part target
+        let mut audio_dts = 0;
+        for i in 0..300 {
+            let video_dts = i * 1000 / 24;
+            while audio_dts < video_dts {
+                remuxer.process_demux_data(&audio_frame(audio_dts))?;
+                audio_dts += 23;
+            }
+            remuxer.process_demux_data(&video_frame(video_dts, i % 48 == 0))?;
+
+            let playlist = match store.read("live", "test", "test.m3u8").unwrap() {
+                Some(playlist) => String::from_utf8_lossy(&playlist).to_string(),
+                None => continue,
+            };
+            assert!(playlist.contains("#EXT-X-PART-INF:PART-TARGET=0.200\n"));
+            for part in playlist.split("#EXT-X-PART:DURATION=").skip(1) {
+                let duration: f64 = part.split(',').next().unwrap().parse().unwrap();
+                assert!(duration > 0.0 && duration <= 0.2, "part of {}s", duration);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_discontinuity_threshold() -> Result<(), MediaError> {
+        let store = Arc::new(MemoryHlsStore::default());
+        let options = HlsOptions {
+            duration: 10,
+            discontinuity_threshold: 2,
+            ..Default::default()
+        };
+        let mut remuxer = Flv2HlsRemuxer::new(
+            String::from("live"),
+            String::from("test"),
+            store.clone(),
+            &options,
+        );
+
+        //a jump of exactly the threshold between two frames is continuous
+        for i in 0..50 {
+            remuxer.process_demux_data(&audio_frame(i * 20))?;
+        }
+        for i in 0..50 {
+            remuxer.process_demux_data(&audio_frame(2_980 + i * 20))?;
+        }
+        //no segment is cut
+        assert!(store.read("live", "test", "test.m3u8")?.is_none());
+
+        //a jump 1ms longer than the threshold begins a discontinuity
+        for i in 0..50 {
+            remuxer.process_demux_data(&audio_frame(5_961 + i * 20))?;
+        }
+        remuxer.process_demux_data(&audio_frame(20_000))?;
+        let playlist = read_playlist(&store);
+        assert!(playlist.contains("#EXTINF:3.960\n0.ts\n#EXT-X-DISCONTINUITY\n"));
+        assert_eq!(playlist.matches("#EXT-X-DISCONTINUITY\n").count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_gop_is_continuous() -> Result<(), MediaError> {
+        let store = Arc::new(MemoryHlsStore::default());
+        let options = HlsOptions {
+            duration: 2,
+            ..Default::default()
+        };
+        let mut remuxer = Flv2HlsRemuxer::new(
+            String::from("live"),
+            String::from("test"),
+            store.clone(),
+            &options,
+        );
+
+        //the 20 seconds segments of a long GOP are longer than the default threshold(15s),
+        //but there is no timestamp jump between their frames
+        for i in 0..1500 {
+            remuxer.process_demux_data(&video_frame(i * 40, i % 500 == 0))?;
+        }
+
+        let playlist = read_playlist(&store);
+        assert_eq!(playlist.matches("#EXTINF:20.000\n").count(), 2);
+        assert!(!playlist.contains("#EXT-X-DISCONTINUITY"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timed_metadata() -> Result<(), MediaError> {
+        let store = Arc::new(MemoryHlsStore::default());
+        let options = HlsOptions {
+            duration: 2,
+            timed_metadata: true,
+            ..Default::default()
+        };
+        let mut remuxer = Flv2HlsRemuxer::new(
+            String::from("live"),
+            String::from("test"),
+            store.clone(),
+            &options,
+        );
+
+        for i in 0..500 {
+            let dts = i * 20;
+            //a 4 seconds ad break at 3s, and a text at 8s
+            if dts == 3000 {
+                let data = data_message(
+                    "onCuePoint",
+                    vec![
+                        ("name", Amf0ValueType::UTF8String(String::from("spliceOut"))),
+                        ("duration", Amf0ValueType::Number(4.0)),
+                    ],
+                );
+                remuxer.process_flv_data(FlvData::MetaData {
+                    timestamp: 3000,
+                    data,
+                })?;
+            }
+            if dts == 8000 {
+                let data = data_message(
+                    "onTextData",
+                    vec![("text", Amf0ValueType::UTF8String(String::from("goal")))],
+                );
+                remuxer.process_flv_data(FlvData::MetaData {
+                    timestamp: 8000,
+                    data,
+                })?;
+            }
+            remuxer.process_demux_data(&audio_frame(dts))?;
+        }
+
+        //the ad break begins at the cue point and returns to the program after 4s
+        let playlist = read_playlist(&store);
+        assert!(playlist.contains(
+            "#EXTINF:1.000\n1.ts\n\
+             #EXT-X-CUE-OUT:DURATION=4.000\n#EXTINF:2.000\n2.ts\n\
+             #EXT-X-CUE-OUT-CONT:ElapsedTime=2.000,Duration=4.000\n#EXTINF:2.000\n3.ts\n\
+             #EXT-X-CUE-IN\n#EXTINF:2.000\n4.ts\n"
+        ));
+
+        //the ID3 tag is muxed into the segment
+        let segment = store.read("live", "test", "4.ts").unwrap().unwrap();
+        let tag = id3::txxx_tag("onTextData", "{\"text\":\"goal\"}");
+        assert!(segment.windows(tag.len()).any(|window| window == &tag[..]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timed_metadata_disabled() -> Result<(), MediaError> {
+        let store = Arc::new(MemoryHlsStore::default());
+        let options = HlsOptions {
+            duration: 2,
+            ..Default::default()
+        };
+        let mut remuxer = Flv2HlsRemuxer::new(
+            String::from("live"),
+            String::from("test"),
+            store.clone(),
+            &options,
+        );
+
+        for i in 0..200 {
+            let dts = i * 20;
+            if dts == 1000 {
+                let data = data_message(
+                    "onTextData",
+                    vec![("text", Amf0ValueType::UTF8String(String::from("goal")))],
+                );
+                remuxer.process_flv_data(FlvData::MetaData {
+                    timestamp: 1000,
+                    data,
+                })?;
+            }
+            remuxer.process_demux_data(&audio_frame(dts))?;
+        }
+
+        //neither the ID3 tag nor the metadata stream is in the segment
+        let segment = store.read("live", "test", "0.ts").unwrap().unwrap();
+        let tag = id3::txxx_tag("onTextData", "{\"text\":\"goal\"}");
+        assert!(!segment.windows(tag.len()).any(|window| window == &tag[..]));
+        let descriptor = id3::metadata_descriptor();
+        assert!(!segment
+            .windows(descriptor.len())
+            .any(|window| window == &descriptor[..]));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,225 @@
+use {
+    bytes::BytesMut,
+    bytesio::bytes_reader::BytesReader,
+    rtmp::amf0::{amf0_reader::Amf0Reader, Amf0ValueType},
+    xmpegts::id3,
+};
+
+/*the splice points of an ad break*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpliceCue {
+    //the ad break begins, the duration is in milliseconds
+    Out {
+        id: Option<u32>,
+        duration: Option<i64>,
+    },
+    //back to the program
+    In,
+}
+
+/*the data messages(onCuePoint and onTextData) carried into the hls streams*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimedMetadata {
+    Splice(SpliceCue),
+    //the ID3 tag muxed into the metadata stream of the ts
+    Id3(BytesMut),
+}
+
+/*Parse an AMF0 data message of the publisher. The cue points named spliceOut,
+cue-out or adStart begin an ad break, the duration(seconds) and the id are read
+from the cue point or its parameters, and the ones named spliceIn, cue-in or adEnd
+end it. The other cue points and onTextData become an ID3 TXXX frame whose
+description is the handler name and the value is the data in JSON.*/
+pub fn parse_data_message(data: BytesMut) -> Option<TimedMetadata> {
+    let mut values = Amf0Reader::new(BytesReader::new(data)).read_all().ok()?;
+    if matches!(values.first(), Some(Amf0ValueType::UTF8String(name)) if name == "@setDataFrame") {
+        values.remove(0);
+    }
+    if values.is_empty() {
+        return None;
+    }
+
+    let handler = match values.remove(0) {
+        Amf0ValueType::UTF8String(handler) => handler,
+        _ => return None,
+    };
+    if handler != "onCuePoint" && handler != "onTextData" {
+        return None;
+    }
+
+    if handler == "onCuePoint" {
+        if let Some(cue) = values.first().and_then(parse_splice_cue) {
+            return Some(TimedMetadata::Splice(cue));
+        }
+    }
+
+    let value = match values.len() {
+        1 => to_json(&values[0]),
+        _ => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(to_json)
+                .collect::<Vec<String>>()
+                .join(",")
+        ),
+    };
+    Some(TimedMetadata::Id3(id3::txxx_tag(&handler, &value)))
+}
+
+fn parse_splice_cue(cue_point: &Amf0ValueType) -> Option<SpliceCue> {
+    let properties = match cue_point {
+        Amf0ValueType::Object(properties) | Amf0ValueType::EcmaArray(properties) => properties,
+        _ => return None,
+    };
+    let name = match properties.get("name") {
+        Some(Amf0ValueType::UTF8String(name)) => name.to_lowercase(),
+        _ => return None,
+    };
+
+    let get_number = |key: &str| {
+        let parameters = match properties.get("parameters") {
+            Some(Amf0ValueType::Object(parameters))
+            | Some(Amf0ValueType::EcmaArray(parameters)) => Some(parameters),
+            _ => None,
+        };
+        match parameters.and_then(|parameters| parameters.get(key)) {
+            Some(value) => as_number(value),
+            None => properties.get(key).and_then(as_number),
+        }
+    };
+
+    match name.as_str() {
+        "spliceout" | "cue-out" | "adstart" => Some(SpliceCue::Out {
+            id: get_number("id").map(|id| id as u32),
+            duration: get_number("duration")
+                .filter(|duration| *duration > 0.0)
+                .map(|duration| (duration * 1000.0) as i64),
+        }),
+        "splicein" | "cue-in" | "adend" => Some(SpliceCue::In),
+        _ => None,
+    }
+}
+
+fn as_number(value: &Amf0ValueType) -> Option<f64> {
+    match value {
+        Amf0ValueType::Number(number) => Some(*number),
+        Amf0ValueType::UTF8String(text) | Amf0ValueType::LongUTF8String(text) => {
+            text.trim().parse().ok()
+        }
+        _ => None,
+    }
+}
+
+fn to_json(value: &Amf0ValueType) -> String {
+    match value {
+        Amf0ValueType::Number(number) => {
+            if number.fract() == 0.0 && number.abs() < 1e15 {
+                format!("{}", *number as i64)
+            } else {
+                format!("{number}")
+            }
+        }
+        Amf0ValueType::Boolean(boolean) => boolean.to_string(),
+        Amf0ValueType::UTF8String(text) | Amf0ValueType::LongUTF8String(text) => json_string(text),
+        Amf0ValueType::Object(properties) | Amf0ValueType::EcmaArray(properties) => format!(
+            "{{{}}}",
+            properties
+                .iter()
+                .map(|(key, value)| format!("{}:{}", json_string(key), to_json(value)))
+                .collect::<Vec<String>>()
+                .join(",")
+        ),
+        Amf0ValueType::StrictArray(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(to_json)
+                .collect::<Vec<String>>()
+                .join(",")
+        ),
+        Amf0ValueType::Null | Amf0ValueType::END => String::from("null"),
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            '\n' => result += "\\n",
+            '\r' => result += "\\r",
+            '\t' => result += "\\t",
+            c if (c as u32) < 0x20 => result += &format!("\\u{:04x}", c as u32),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/*the AMF0 data message of a handler with the properties object, it is used by the tests*/
+#[cfg(test)]
+pub fn data_message(handler: &str, properties: Vec<(&str, rtmp::amf0::Amf0ValueType)>) -> BytesMut {
+    use {
+        bytesio::bytes_writer::BytesWriter, indexmap::IndexMap, rtmp::amf0::amf0_writer::Amf0Writer,
+    };
+
+    let mut writer = Amf0Writer::new(BytesWriter::new());
+    writer.write_string(&String::from(handler)).unwrap();
+    let properties: IndexMap<String, rtmp::amf0::Amf0ValueType> = properties
+        .into_iter()
+        .map(|(key, value)| (String::from(key), value))
+        .collect();
+    writer.write_object(&properties).unwrap();
+    writer.extract_current_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{data_message, parse_data_message, SpliceCue, TimedMetadata},
+        indexmap::IndexMap,
+        rtmp::amf0::Amf0ValueType,
+        xmpegts::id3,
+    };
+
+    #[test]
+    fn test_parse_data_message() {
+        let mut parameters = IndexMap::new();
+        parameters.insert(String::from("duration"), Amf0ValueType::Number(30.0));
+        let splice_out = data_message(
+            "onCuePoint",
+            vec![
+                ("name", Amf0ValueType::UTF8String(String::from("spliceOut"))),
+                ("parameters", Amf0ValueType::Object(parameters)),
+            ],
+        );
+        assert_eq!(
+            parse_data_message(splice_out),
+            Some(TimedMetadata::Splice(SpliceCue::Out {
+                id: None,
+                duration: Some(30000)
+            }))
+        );
+
+        let text_data = data_message(
+            "onTextData",
+            vec![(
+                "text",
+                Amf0ValueType::UTF8String(String::from("goal \"9\"")),
+            )],
+        );
+        assert_eq!(
+            parse_data_message(text_data),
+            Some(TimedMetadata::Id3(id3::txxx_tag(
+                "onTextData",
+                "{\"text\":\"goal \\\"9\\\"\"}"
+            )))
+        );
+
+        let metadata = data_message("onMetaData", vec![("width", Amf0ValueType::Number(1280.0))]);
+        assert_eq!(parse_data_message(metadata), None);
+    }
+}
@@ -1,35 +1,93 @@
 use {
-    super::errors::MediaError,
+    super::{define::DEFAULT_SEGMENT_NAME, errors::MediaError, store::HlsStoreRef},
     bytes::BytesMut,
-    std::{fs, fs::File, io::Write},
 };
 
 pub struct Ts {
     ts_number: u32,
-    folder_name: String,
+    app_name: String,
+    stream_name: String,
+    store: HlsStoreRef,
+    name_template: String,
+    extension: &'static str,
 }
 
 impl Ts {
-    pub fn new(app_name: String, stream_name: String) -> Self {
-        let folder_name = format!("./{app_name}/{stream_name}");
-        fs::create_dir_all(folder_name.clone()).unwrap();
-
+    pub fn new(app_name: String, stream_name: String, store: HlsStoreRef) -> Self {
         Self {
             ts_number: 0,
-            folder_name,
+            app_name,
+            stream_name,
+            store,
+            name_template: String::from(DEFAULT_SEGMENT_NAME),
+            extension: "ts",
         }
     }
-    pub fn write(&mut self, data: BytesMut) -> Result<(String, String), MediaError> {
-        let ts_file_name = format!("{}.ts", self.ts_number);
-        let ts_file_path = format!("{}/{}", self.folder_name, ts_file_name);
+
+    /*the extension of the segments, e.g. ts or m4s*/
+    pub fn set_extension(&mut self, extension: &'static str) {
+        self.extension = extension;
+    }
+
+    pub fn set_name_template(&mut self, name_template: String) {
+        if !name_template.contains("{seq}") || !name_template.ends_with(".ts") {
+            log::warn!(
+                "invalid segment name template: {}, use the default one",
+                name_template
+            );
+            return;
+        }
+        self.name_template = name_template;
+    }
+
+    fn render_name(&self) -> String {
+        let name = self
+            .name_template
+            .replace("{app}", &self.app_name)
+            .replace("{stream}", &self.stream_name)
+            .replace("{seq}", &self.ts_number.to_string());
+        format!("{}.{}", name.trim_end_matches(".ts"), self.extension)
+    }
+
+    pub fn write(&mut self, data: BytesMut) -> Result<String, MediaError> {
+        let ts_file_name = self.render_name();
         self.ts_number += 1;
 
-        let mut ts_file_handler = File::create(ts_file_path.clone())?;
-        ts_file_handler.write_all(&data[..])?;
+        self.store.write(
+            &self.app_name,
+            &self.stream_name,
+            &ts_file_name,
+            data.freeze(),
+        )?;
+
+        Ok(ts_file_name)
+    }
+    /*the partial segments of the segment in progress are named as {segment_name}.{part_index}.ts*/
+    pub fn get_part_name(&self, part_index: usize) -> String {
+        let segment_name = self.render_name();
+        let extension = format!(".{}", self.extension);
+        format!(
+            "{}.{}{}",
+            segment_name.trim_end_matches(&extension),
+            part_index,
+            extension
+        )
+    }
+
+    pub fn write_part(&mut self, part_index: usize, data: BytesMut) -> Result<String, MediaError> {
+        let part_file_name = self.get_part_name(part_index);
+
+        self.store.write(
+            &self.app_name,
+            &self.stream_name,
+            &part_file_name,
+            data.freeze(),
+        )?;
 
-        Ok((ts_file_name, ts_file_path))
+        Ok(part_file_name)
     }
-    pub fn delete(&mut self, ts_file_name: String) {
-        fs::remove_file(ts_file_name).unwrap();
+    pub fn delete(&mut self, ts_file_name: &str) -> Result<(), MediaError> {
+        self.store
+            .delete(&self.app_name, &self.stream_name, ts_file_name)
     }
 }
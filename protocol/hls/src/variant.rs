@@ -0,0 +1,223 @@
+use {
+    rtmp::{
+        channels::define::{ChannelEvent, ChannelEventProducer},
+        statistics::StreamStatistics,
+    },
+    std::sync::Arc,
+    tokio::sync::{mpsc, oneshot},
+};
+
+/*The renditions of a stream which are published as separate streams, e.g.
+live/show_1080, live/show_720 and live/show_360, the master playlist of the
+set is served as /live/show.m3u8.*/
+#[derive(Debug, Clone)]
+pub struct VariantSet {
+    pub app_name: String,
+    pub name: String,
+    pub stream_names: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VariantSets {
+    sets: Vec<VariantSet>,
+    /*group the streams named {name}{separator}{suffix} into the set {name}
+    if they are not in a configured set*/
+    separator: Option<String>,
+}
+
+impl VariantSets {
+    pub fn add_set(&mut self, set: VariantSet) {
+        self.sets.push(set);
+    }
+
+    pub fn set_separator(&mut self, separator: String) {
+        if !separator.is_empty() {
+            self.separator = Some(separator);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty() && self.separator.is_none()
+    }
+
+    /*the name of the variant set which the stream belongs to*/
+    pub fn get_set_name(&self, app_name: &str, stream_name: &str) -> Option<String> {
+        for set in &self.sets {
+            if set.app_name == app_name && set.stream_names.iter().any(|name| name == stream_name) {
+                return Some(set.name.clone());
+            }
+        }
+
+        let separator = self.separator.as_ref()?;
+        match stream_name.rsplit_once(separator.as_str()) {
+            Some((name, suffix)) if !name.is_empty() && !suffix.is_empty() => {
+                Some(name.to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+/*a rendition listed in the master playlist*/
+#[derive(Debug, Clone)]
+pub struct VariantInfo {
+    pub stream_name: String,
+    //bits per second
+    pub bandwidth: u64,
+    pub resolution: Option<(u32, u32)>,
+    pub codecs: Vec<String>,
+}
+
+impl From<&StreamStatistics> for VariantInfo {
+    fn from(statistics: &StreamStatistics) -> Self {
+        Self {
+            stream_name: statistics.stream_name.clone(),
+            bandwidth: statistics.bandwidth(),
+            resolution: statistics.resolution(),
+            codecs: statistics.codecs(),
+        }
+    }
+}
+
+/*the variant playlists are relative to the master playlist, the renditions
+are sorted by bandwidth from high to low*/
+pub fn generate_master_playlist(mut variants: Vec<VariantInfo>) -> String {
+    variants.sort_by_key(|variant| std::cmp::Reverse(variant.bandwidth));
+
+    let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-INDEPENDENT-SEGMENTS\n");
+    for variant in variants {
+        //BANDWIDTH is required, it is 0 before the bitrate is calculated
+        let mut stream_inf = format!("#EXT-X-STREAM-INF:BANDWIDTH={}", variant.bandwidth.max(1));
+        if let Some((width, height)) = variant.resolution {
+            stream_inf += &format!(",RESOLUTION={width}x{height}");
+        }
+        if !variant.codecs.is_empty() {
+            stream_inf += &format!(",CODECS=\"{}\"", variant.codecs.join(","));
+        }
+        playlist += &stream_inf;
+        playlist += "\n";
+        playlist += &format!("{0}/{0}.m3u8\n", variant.stream_name);
+    }
+
+    playlist
+}
+
+/*Generate the master playlists from the statistics of the published streams.*/
+pub struct MasterPlaylistGenerator {
+    variant_sets: Arc<VariantSets>,
+    event_producer: ChannelEventProducer,
+}
+
+impl MasterPlaylistGenerator {
+    pub fn new(variant_sets: Arc<VariantSets>, event_producer: ChannelEventProducer) -> Self {
+        Self {
+            variant_sets,
+            event_producer,
+        }
+    }
+
+    async fn get_stream_statistics(&self) -> Vec<StreamStatistics> {
+        let (data_sender, mut data_receiver) = mpsc::unbounded_channel();
+        let (size_sender, size_receiver) = oneshot::channel();
+        let channel_event = ChannelEvent::ApiStatistic {
+            data_sender,
+            size_sender,
+        };
+        if let Err(err) = self.event_producer.send(channel_event) {
+            log::error!("master playlist send statistic event error: {}", err);
+            return Vec::new();
+        }
+
+        let mut data = Vec::new();
+        match size_receiver.await {
+            Ok(size) => {
+                while data.len() < size {
+                    match data_receiver.recv().await {
+                        Some(stream_statistics) => data.push(stream_statistics),
+                        None => break,
+                    }
+                }
+            }
+            Err(err) => {
+                log::error!("master playlist recv statistic size error: {}", err);
+            }
+        }
+
+        data
+    }
+
+    /*the master playlist of the variant set, it is None if no rendition of it is
+    published, the on demand renditions are remuxed when they are requested*/
+    pub async fn generate(&self, app_name: &str, name: &str) -> Option<String> {
+        if self.variant_sets.is_empty() {
+            return None;
+        }
+
+        let variants: Vec<VariantInfo> = self
+            .get_stream_statistics()
+            .await
+            .iter()
+            .filter(|statistics| statistics.app_name == app_name)
+            .filter(|statistics| {
+                self.variant_sets
+                    .get_set_name(app_name, &statistics.stream_name)
+                    .as_deref()
+                    == Some(name)
+            })
+            .map(VariantInfo::from)
+            .collect();
+
+        if variants.is_empty() {
+            return None;
+        }
+
+        Some(generate_master_playlist(variants))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_master_playlist, VariantInfo, VariantSet, VariantSets};
+
+    #[test]
+    fn test_master_playlist() {
+        let mut variant_sets = VariantSets::default();
+        variant_sets.add_set(VariantSet {
+            app_name: String::from("live"),
+            name: String::from("concert"),
+            stream_names: vec![String::from("main"), String::from("backup_low")],
+        });
+        variant_sets.set_separator(String::from("_"));
+
+        assert_eq!(
+            variant_sets.get_set_name("live", "backup_low").as_deref(),
+            Some("concert")
+        );
+        assert_eq!(
+            variant_sets.get_set_name("live", "show_720").as_deref(),
+            Some("show")
+        );
+        assert_eq!(variant_sets.get_set_name("live", "show"), None);
+
+        let variant = |stream_name: &str, bandwidth: u64, height: u32| VariantInfo {
+            stream_name: String::from(stream_name),
+            bandwidth,
+            resolution: Some((height * 16 / 9, height)),
+            codecs: vec![String::from("avc1.64001f"), String::from("mp4a.40.2")],
+        };
+        let playlist = generate_master_playlist(vec![
+            variant("show_360", 800_000, 360),
+            variant("show_1080", 5_000_000, 1080),
+        ]);
+        assert_eq!(
+            playlist,
+            "#EXTM3U\n\
+             #EXT-X-VERSION:3\n\
+             #EXT-X-INDEPENDENT-SEGMENTS\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,CODECS=\"avc1.64001f,mp4a.40.2\"\n\
+             show_1080/show_1080.m3u8\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.64001f,mp4a.40.2\"\n\
+             show_360/show_360.m3u8\n"
+        );
+    }
+}
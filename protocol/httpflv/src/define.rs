@@ -1,5 +1,5 @@
 use {
-    futures::channel::mpsc::{UnboundedReceiver, UnboundedSender},
+    tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender},
     {bytes::BytesMut, std::io},
 };
 pub mod tag_type {
@@ -1,11 +1,12 @@
 use {
     failure::Fail,
     futures::channel::mpsc::SendError,
-    xflv::errors::MuxerError,
+    xflv::errors::{FlvDemuxerError, MuxerError},
     rtmp::{
         amf0::errors::Amf0WriteError, cache::errors::MetadataError, session::errors::SessionError,
     },
     std::fmt,
+    tokio_tungstenite::tungstenite::Error as WebSocketError,
 };
 
 #[derive(Debug)]
@@ -37,6 +38,18 @@ pub enum HttpFLvErrorValue {
     MetadataError(MetadataError),
     #[fail(display = "tokio mpsc error")]
     MpscSendError(SendError),
+    #[fail(display = "websocket error: {}", _0)]
+    WebSocketError(Box<WebSocketError>),
+    #[fail(display = "flv demuxer error: {}", _0)]
+    FlvDemuxerError(FlvDemuxerError),
+    #[fail(display = "not a flv stream")]
+    NotFlvStream,
+    #[fail(display = "the publish is rejected")]
+    PublishRejected,
+    #[fail(display = "the stream is not found")]
+    StreamNotFound,
+    #[fail(display = "the player is disconnected")]
+    PlayerDisconnected,
 }
 
 impl From<SessionError> for HttpFLvError {
@@ -63,6 +76,22 @@ impl From<SendError> for HttpFLvError {
     }
 }
 
+impl From<FlvDemuxerError> for HttpFLvError {
+    fn from(error: FlvDemuxerError) -> Self {
+        HttpFLvError {
+            value: HttpFLvErrorValue::FlvDemuxerError(error),
+        }
+    }
+}
+
+impl From<WebSocketError> for HttpFLvError {
+    fn from(error: WebSocketError) -> Self {
+        HttpFLvError {
+            value: HttpFLvErrorValue::WebSocketError(Box::new(error)),
+        }
+    }
+}
+
 impl From<Amf0WriteError> for HttpFLvError {
     fn from(error: Amf0WriteError) -> Self {
         HttpFLvError {
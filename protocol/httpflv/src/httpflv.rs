@@ -7,7 +7,7 @@ use {
         cache::metadata::MetaData,
         channels::define::{ChannelData, ChannelDataConsumer, ChannelEvent, ChannelEventProducer},
         session::{
-            common::{NotifyInfo, SubscriberInfo},
+            common::{NotifyInfo, SubscribeOptions, SubscriberInfo},
             define::SubscribeType,
             errors::{SessionError, SessionErrorValue},
         },
@@ -16,12 +16,18 @@ use {
     std::{net::SocketAddr, time::Duration},
     tokio::{
         sync::{mpsc, oneshot},
-        time::sleep,
+        time::{sleep, Instant},
     },
     uuid::Uuid,
     xflv::muxer::{FlvMuxer, HEADER_LENGTH},
 };
 
+//how often a stream which is not found is subscribed again
+const SUBSCRIBE_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+/*how long a player waits for a stream which is not found, it covers the first
+request of a stream which is pulled by the relay*/
+pub const DEFAULT_SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(3);
+
 pub struct HttpFlv {
     app_name: String,
     stream_name: String,
@@ -34,6 +40,10 @@ pub struct HttpFlv {
     subscriber_id: Uuid,
     request_url: String,
     remote_addr: SocketAddr,
+    //the playback options of the request
+    subscribe_options: SubscribeOptions,
+    //how long to wait for a stream which is not published(or pulled) yet
+    subscribe_timeout: Duration,
 }
 
 impl HttpFlv {
@@ -44,6 +54,7 @@ impl HttpFlv {
         http_response_data_producer: HttpResponseDataProducer,
         request_url: String,
         remote_addr: SocketAddr,
+        subscribe_options: SubscribeOptions,
     ) -> Self {
         let (_, data_consumer) = mpsc::unbounded_channel();
         let subscriber_id = Uuid::new_v4();
@@ -58,9 +69,15 @@ impl HttpFlv {
             subscriber_id,
             request_url,
             remote_addr,
+            subscribe_options,
+            subscribe_timeout: DEFAULT_SUBSCRIBE_TIMEOUT,
         }
     }
 
+    pub fn set_subscribe_timeout(&mut self, subscribe_timeout: Duration) {
+        self.subscribe_timeout = subscribe_timeout;
+    }
+
     pub async fn run(&mut self) -> Result<(), HttpFLvError> {
         self.subscribe_from_rtmp_channels().await?;
         self.send_media_stream().await?;
@@ -69,28 +86,51 @@ impl HttpFlv {
     }
 
     pub async fn send_media_stream(&mut self) -> Result<(), HttpFLvError> {
-        self.muxer.write_flv_header()?;
+        self.muxer.write_flv_header_with_flags(
+            self.subscribe_options.has_audio,
+            self.subscribe_options.has_video,
+        )?;
         self.muxer.write_previous_tag_size(0)?;
 
+        let result = self.send_flv_tags().await;
+        self.unsubscribe_from_rtmp_channels().await?;
+
+        match result {
+            //the player is gone, it is not an error
+            Err(HttpFLvError {
+                value: HttpFLvErrorValue::PlayerDisconnected,
+            }) => {
+                log::info!("http flv player disconnected: {}", self.remote_addr);
+                Ok(())
+            }
+            _ => result,
+        }
+    }
+
+    /*Write the flv body until the stream ends or the player is disconnected, the http
+    response body is dropped by hyper once the connection is closed, so the player is
+    detected at once even if the stream has no data.*/
+    async fn send_flv_tags(&mut self) -> Result<(), HttpFLvError> {
         self.flush_response_data()?;
-        let mut retry_count = 0;
-        //write flv body
+
         loop {
-            if let Some(data) = self.data_consumer.recv().await {
-                if let Err(err) = self.write_flv_tag(data) {
-                    log::error!("write_flv_tag err: {}", err);
-                    retry_count += 1;
-                } else {
-                    retry_count = 0;
+            tokio::select! {
+                data = self.data_consumer.recv() => {
+                    match data {
+                        Some(data) => self.write_flv_tag(data)?,
+                        None => {
+                            log::info!("http flv stream ended: {}/{}", self.app_name, self.stream_name);
+                            return Ok(());
+                        }
+                    }
+                }
+                _ = self.http_response_data_producer.closed() => {
+                    return Err(HttpFLvError {
+                        value: HttpFLvErrorValue::PlayerDisconnected,
+                    });
                 }
-            } else {
-                retry_count += 1;
-            }
-            if retry_count > 10 {
-                break;
             }
         }
-        self.unsubscribe_from_rtmp_channels().await
     }
 
     pub fn write_flv_tag(&mut self, channel_data: ChannelData) -> Result<(), HttpFLvError> {
@@ -113,8 +153,12 @@ impl HttpFlv {
 
             ChannelData::MetaData { timestamp, data } => {
                 let mut metadata = MetaData::new();
-                metadata.save(data);
-                let data = metadata.remove_set_data_frame()?;
+                //the other data messages(e.g. onCuePoint) are written as they are
+                let data = if metadata.save(data.clone()) {
+                    metadata.remove_set_data_frame()?
+                } else {
+                    data
+                };
 
                 common_data = data;
                 common_timestamp = timestamp;
@@ -137,7 +181,11 @@ impl HttpFlv {
 
     pub fn flush_response_data(&mut self) -> Result<(), HttpFLvError> {
         let data = self.muxer.writer.extract_current_bytes();
-        self.http_response_data_producer.start_send(Ok(data))?;
+        if self.http_response_data_producer.send(Ok(data)).is_err() {
+            return Err(HttpFLvError {
+                value: HttpFLvErrorValue::PlayerDisconnected,
+            });
+        }
 
         Ok(())
     }
@@ -150,6 +198,7 @@ impl HttpFlv {
                 request_url: self.request_url.clone(),
                 remote_addr: self.remote_addr.to_string(),
             },
+            options: self.subscribe_options.clone(),
         };
 
         let subscribe_event = ChannelEvent::UnSubscribe {
@@ -164,8 +213,10 @@ impl HttpFlv {
         Ok(())
     }
 
+    /*Subscribe the stream, it is subscribed again until the subscribe timeout if it is
+    not found, StreamNotFound is returned at last.*/
     pub async fn subscribe_from_rtmp_channels(&mut self) -> Result<(), HttpFLvError> {
-        let mut retry_count: u8 = 0;
+        let deadline = Instant::now() + self.subscribe_timeout;
 
         loop {
             let (sender, receiver) = oneshot::channel();
@@ -177,6 +228,7 @@ impl HttpFlv {
                     request_url: self.request_url.clone(),
                     remote_addr: self.remote_addr.to_string(),
                 },
+                options: self.subscribe_options.clone(),
             };
 
             let subscribe_event = ChannelEvent::Subscribe {
@@ -203,21 +255,105 @@ impl HttpFlv {
                     break;
                 }
                 Err(_) => {
-                    if retry_count > 10 {
-                        let session_error = SessionError {
-                            value: SessionErrorValue::SubscribeCountLimitReach,
-                        };
+                    let now = Instant::now();
+                    if now >= deadline {
                         return Err(HttpFLvError {
-                            value: HttpFLvErrorValue::SessionError(session_error),
+                            value: HttpFLvErrorValue::StreamNotFound,
                         });
                     }
+                    sleep(SUBSCRIBE_RETRY_INTERVAL.min(deadline - now)).await;
                 }
             }
-
-            sleep(Duration::from_millis(800)).await;
-            retry_count += 1;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::HttpFlv,
+        crate::{
+            errors::HttpFLvErrorValue,
+            rtmp::{
+                channels::{
+                    define::{ChannelEvent, ChannelEventProducer},
+                    ChannelsManager,
+                },
+                session::{
+                    common::{NotifyInfo, PublisherInfo, SubscribeOptions},
+                    define::PublishType,
+                },
+            },
+        },
+        std::time::Duration,
+        tokio::{
+            sync::{mpsc, oneshot},
+            time::sleep,
+        },
+        uuid::Uuid,
+    };
+
+    fn start_channels() -> ChannelEventProducer {
+        let mut channels = ChannelsManager::new(None);
+        let event_producer = channels.get_channel_event_producer();
+        tokio::spawn(async move { channels.run().await });
+        event_producer
+    }
+
+    fn new_player(event_producer: ChannelEventProducer, subscribe_timeout: Duration) -> HttpFlv {
+        let (http_response_data_producer, _) = mpsc::unbounded_channel();
+        let mut player = HttpFlv::new(
+            String::from("live"),
+            String::from("test"),
+            event_producer,
+            http_response_data_producer,
+            String::from("/live/test.flv"),
+            "127.0.0.1:50000".parse().unwrap(),
+            SubscribeOptions::default(),
+        );
+        player.set_subscribe_timeout(subscribe_timeout);
+        player
+    }
+
+    #[tokio::test]
+    async fn test_publish_within_subscribe_timeout() {
+        let event_producer = start_channels();
+        let mut player = new_player(event_producer.clone(), Duration::from_secs(3));
+        let player = tokio::spawn(async move { player.subscribe_from_rtmp_channels().await });
+
+        //the stream is published(e.g. pulled by the relay) after the first request
+        sleep(Duration::from_millis(700)).await;
+        let (responder, receiver) = oneshot::channel();
+        assert!(event_producer
+            .send(ChannelEvent::Publish {
+                app_name: String::from("live"),
+                stream_name: String::from("test"),
+                info: PublisherInfo {
+                    id: Uuid::new_v4(),
+                    sub_type: PublishType::SubscriberRtmp,
+                    notify_info: NotifyInfo {
+                        request_url: String::from(""),
+                        remote_addr: String::from(""),
+                    },
+                },
+                responder,
+            })
+            .is_ok());
+        let _data_producer = receiver.await.unwrap();
+
+        assert!(player.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_timeout() {
+        let event_producer = start_channels();
+        let mut player = new_player(event_producer, Duration::from_millis(300));
+
+        match player.subscribe_from_rtmp_channels().await {
+            Err(err) => assert!(matches!(err.value, HttpFLvErrorValue::StreamNotFound)),
+            Ok(()) => panic!("the stream is expected to be not found"),
+        }
+    }
+}
@@ -5,4 +5,6 @@ pub mod server;
 pub mod server_test;
 pub mod errors;
 pub mod httpflv;
-pub mod define;
\ No newline at end of file
+pub mod define;
+pub mod publisher;
+pub mod websocket;
\ No newline at end of file
@@ -0,0 +1,251 @@
+use {
+    super::errors::{HttpFLvError, HttpFLvErrorValue},
+    crate::rtmp::{
+        channels::define::{ChannelData, ChannelDataProducer, ChannelEvent, ChannelEventProducer},
+        session::{
+            common::{NotifyInfo, PublisherInfo},
+            define::PublishType,
+            errors::{SessionError, SessionErrorValue},
+        },
+    },
+    bytes::BytesMut,
+    std::net::SocketAddr,
+    tokio::sync::{mpsc, oneshot},
+    uuid::Uuid,
+    xflv::{define::FlvData, demuxer::FlvDemuxer},
+};
+
+const FLV_HEADER_LENGTH: usize = 9;
+//previous tag size(4 bytes) + tag header(11 bytes)
+const TAG_PREFIX_LENGTH: usize = 15;
+//the AMF0 strings "onMetaData" and "@setDataFrame"
+const ON_METADATA: &[u8] = b"\x02\x00\x0aonMetaData";
+const SET_DATA_FRAME: &[u8] = b"\x02\x00\x0d@setDataFrame";
+
+/*A publisher which pushes a FLV stream over HTTP(a POST body) or WebSocket(binary
+frames), the received data is demuxed into tags by FlvDemuxer and published to the
+channels like the data of a RTMP publisher.*/
+pub struct FlvPublisher {
+    app_name: String,
+    stream_name: String,
+
+    event_producer: ChannelEventProducer,
+    data_producer: ChannelDataProducer,
+    publisher_id: Uuid,
+    publish_type: PublishType,
+    request_url: String,
+    remote_addr: SocketAddr,
+
+    //the received data which is not a complete tag yet
+    buffer: BytesMut,
+    has_read_header: bool,
+}
+
+impl FlvPublisher {
+    pub fn new(
+        app_name: String,
+        stream_name: String,
+        event_producer: ChannelEventProducer,
+        publish_type: PublishType,
+        request_url: String,
+        remote_addr: SocketAddr,
+    ) -> Self {
+        let (data_producer, _) = mpsc::unbounded_channel();
+
+        Self {
+            app_name,
+            stream_name,
+            event_producer,
+            data_producer,
+            publisher_id: Uuid::new_v4(),
+            publish_type,
+            request_url,
+            remote_addr,
+            buffer: BytesMut::new(),
+            has_read_header: false,
+        }
+    }
+
+    fn get_publisher_info(&self) -> PublisherInfo {
+        PublisherInfo {
+            id: self.publisher_id,
+            sub_type: self.publish_type.clone(),
+            notify_info: NotifyInfo {
+                request_url: self.request_url.clone(),
+                remote_addr: self.remote_addr.to_string(),
+            },
+        }
+    }
+
+    pub async fn publish_to_channels(&mut self) -> Result<(), HttpFLvError> {
+        let (sender, receiver) = oneshot::channel();
+        let publish_event = ChannelEvent::Publish {
+            app_name: self.app_name.clone(),
+            stream_name: self.stream_name.clone(),
+            info: self.get_publisher_info(),
+            responder: sender,
+        };
+
+        if self.event_producer.send(publish_event).is_err() {
+            return Err(HttpFLvError {
+                value: HttpFLvErrorValue::SessionError(SessionError {
+                    value: SessionErrorValue::ChannelEventSendErr,
+                }),
+            });
+        }
+
+        //the responder is dropped if the stream can not be published
+        match receiver.await {
+            Ok(producer) => {
+                self.data_producer = producer;
+                Ok(())
+            }
+            Err(_) => Err(HttpFLvError {
+                value: HttpFLvErrorValue::PublishRejected,
+            }),
+        }
+    }
+
+    pub fn unpublish_from_channels(&mut self) {
+        let unpublish_event = ChannelEvent::UnPublish {
+            app_name: self.app_name.clone(),
+            stream_name: self.stream_name.clone(),
+            info: self.get_publisher_info(),
+        };
+        if let Err(err) = self.event_producer.send(unpublish_event) {
+            log::error!("unpublish_from_channels err {}\n", err);
+        }
+    }
+
+    /*the data is a part of the FLV stream, the complete tags are published and the
+    rest is kept until more data is received*/
+    pub fn on_data(&mut self, data: &[u8]) -> Result<(), HttpFLvError> {
+        self.buffer.extend_from_slice(data);
+
+        if !self.has_read_header {
+            if self.buffer.len() < FLV_HEADER_LENGTH {
+                return Ok(());
+            }
+            if &self.buffer[..3] != b"FLV" {
+                return Err(HttpFLvError {
+                    value: HttpFLvErrorValue::NotFlvStream,
+                });
+            }
+            let mut demuxer = FlvDemuxer::new(self.buffer.split_to(FLV_HEADER_LENGTH));
+            demuxer.read_flv_header()?;
+            self.has_read_header = true;
+        }
+
+        while self.buffer.len() >= TAG_PREFIX_LENGTH {
+            let data_size = ((self.buffer[5] as usize) << 16)
+                | ((self.buffer[6] as usize) << 8)
+                | self.buffer[7] as usize;
+            if self.buffer.len() < TAG_PREFIX_LENGTH + data_size {
+                break;
+            }
+
+            let tag = self.buffer.split_to(TAG_PREFIX_LENGTH + data_size);
+            if let Some(flv_data) = FlvDemuxer::new(tag).read_flv_tag()? {
+                self.publish_flv_data(flv_data)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn publish_flv_data(&mut self, flv_data: FlvData) -> Result<(), HttpFLvError> {
+        let channel_data = match flv_data {
+            FlvData::Audio { timestamp, data } => ChannelData::Audio { timestamp, data },
+            FlvData::Video { timestamp, data } => ChannelData::Video { timestamp, data },
+            FlvData::MetaData { timestamp, data } => {
+                //the RTMP publishers send the metadata with @setDataFrame
+                let data = if data.starts_with(ON_METADATA) {
+                    let mut metadata = BytesMut::from(SET_DATA_FRAME);
+                    metadata.extend_from_slice(&data);
+                    metadata
+                } else {
+                    data
+                };
+                ChannelData::MetaData { timestamp, data }
+            }
+        };
+
+        if self.data_producer.send(channel_data).is_err() {
+            return Err(HttpFLvError {
+                value: HttpFLvErrorValue::SessionError(SessionError {
+                    value: SessionErrorValue::SendChannelDataErr,
+                }),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::FlvPublisher,
+        crate::rtmp::{channels::define::ChannelData, session::define::PublishType},
+        tokio::sync::mpsc,
+    };
+
+    fn flv_tag(tag_type: u8, timestamp: u32, body: &[u8]) -> Vec<u8> {
+        let mut tag = vec![tag_type];
+        tag.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        tag.extend_from_slice(&timestamp.to_be_bytes()[1..]);
+        tag.push((timestamp >> 24) as u8);
+        tag.extend_from_slice(&[0, 0, 0]);
+        tag.extend_from_slice(body);
+        tag.extend_from_slice(&(body.len() as u32 + 11).to_be_bytes());
+        tag
+    }
+
+    #[test]
+    fn test_on_data() {
+        let mut stream = b"FLV\x01\x05\x00\x00\x00\x09\x00\x00\x00\x00".to_vec();
+        stream.extend(flv_tag(18, 0, b"\x02\x00\x0aonMetaData\x05"));
+        stream.extend(flv_tag(9, 40, b"\x17\x01\x00\x00\x00"));
+        stream.extend(flv_tag(8, 0x01000000, b"\xaf\x01"));
+
+        let (event_producer, _) = mpsc::unbounded_channel();
+        let mut publisher = FlvPublisher::new(
+            String::from("live"),
+            String::from("test"),
+            event_producer,
+            PublishType::PushHttpFlv,
+            String::from("/live/test.flv"),
+            "127.0.0.1:1000".parse().unwrap(),
+        );
+        let (data_producer, mut data_consumer) = mpsc::unbounded_channel();
+        publisher.data_producer = data_producer;
+
+        //the tags are split across the received data
+        for data in stream.chunks(7) {
+            assert!(publisher.on_data(data).is_ok());
+        }
+
+        match data_consumer.try_recv() {
+            Ok(ChannelData::MetaData { timestamp, data }) => {
+                assert_eq!(timestamp, 0);
+                assert_eq!(
+                    &data[..],
+                    b"\x02\x00\x0d@setDataFrame\x02\x00\x0aonMetaData\x05"
+                );
+            }
+            _ => panic!("metadata is expected"),
+        }
+        match data_consumer.try_recv() {
+            Ok(ChannelData::Video { timestamp, data }) => {
+                assert_eq!(timestamp, 40);
+                assert_eq!(&data[..], b"\x17\x01\x00\x00\x00");
+            }
+            _ => panic!("video is expected"),
+        }
+        match data_consumer.try_recv() {
+            Ok(ChannelData::Audio { timestamp, .. }) => assert_eq!(timestamp, 0x01000000),
+            _ => panic!("audio is expected"),
+        }
+        assert!(data_consumer.try_recv().is_err());
+    }
+}
@@ -1,52 +1,299 @@
 use {
-    super::httpflv::HttpFlv,
-    futures::channel::mpsc::unbounded,
+    super::{
+        errors::HttpFLvErrorValue,
+        httpflv::{HttpFlv, DEFAULT_SUBSCRIBE_TIMEOUT},
+        publisher::FlvPublisher,
+        websocket,
+    },
+    futures::stream,
     hyper::{
+        body::HttpBody,
         server::conn::AddrStream,
         service::{make_service_fn, service_fn},
-        Body, Request, Response, Server, StatusCode,
+        Body, Method, Request, Response, Server, StatusCode,
+    },
+    rtmp::{
+        channels::define::ChannelEventProducer,
+        session::{common::SubscribeOptions, define::PublishType},
     },
-    rtmp::channels::define::ChannelEventProducer,
-    std::net::SocketAddr,
+    std::{net::SocketAddr, sync::Arc, time::Duration},
+    tokio::sync::mpsc,
 };
 
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
 type Result<T> = std::result::Result<T, GenericError>;
 static NOTFOUND: &[u8] = b"Not Found";
 
-async fn handle_connection(
+/*the HTTP options of the httpflv server*/
+#[derive(Debug, Clone)]
+pub struct HttpFlvServerOptions {
+    //accept the flv streams published by POST or websocket(?publish)
+    pub ingest: bool,
+    //how long a player waits for a stream which is not found before the 404 response
+    pub subscribe_timeout: Duration,
+}
+
+impl Default for HttpFlvServerOptions {
+    fn default() -> Self {
+        Self {
+            ingest: false,
+            subscribe_timeout: DEFAULT_SUBSCRIBE_TIMEOUT,
+        }
+    }
+}
+
+/*the states shared by the requests*/
+#[derive(Clone)]
+pub struct ServerContext {
+    event_producer: ChannelEventProducer,
+    options: Arc<HttpFlvServerOptions>,
+}
+
+impl ServerContext {
+    pub fn new(event_producer: ChannelEventProducer, options: HttpFlvServerOptions) -> Self {
+        Self {
+            event_producer,
+            options: Arc::new(options),
+        }
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/*a websocket request publishes the stream if it has the publish query parameter*/
+fn is_publish_request(req: &Request<Body>) -> bool {
+    req.uri().query().is_some_and(|query| {
+        query
+            .split('&')
+            .any(|item| item == "publish" || item.starts_with("publish="))
+    })
+}
+
+/*the app and stream names of /{app}/{stream}.flv, the .flv is at index*/
+fn split_stream_path(path: &str, index: usize) -> Option<(String, String)> {
+    let (left, right) = path.split_at(index);
+    if right != ".flv" {
+        return None;
+    }
+
+    match left.split('/').collect::<Vec<_>>()[..] {
+        ["", app_name, stream_name] if !app_name.is_empty() && !stream_name.is_empty() => {
+            Some((String::from(app_name), String::from(stream_name)))
+        }
+        _ => None,
+    }
+}
+
+/*The playback options in the query parameters: only-audio=1 or only-video=1 plays
+one of the streams, no-cache=1 starts from the live data without the cached gops and
+gop=N only sends the latest N cached gops.*/
+fn parse_subscribe_options(query: Option<&str>) -> SubscribeOptions {
+    let mut options = SubscribeOptions::default();
+
+    if let Some(query) = query {
+        for item in query.split('&') {
+            let (key, value) = item.split_once('=').unwrap_or((item, "1"));
+            let enabled = value == "1" || value.eq_ignore_ascii_case("true");
+            match key {
+                "only-audio" if enabled => options.has_video = false,
+                "only-video" if enabled => options.has_audio = false,
+                "no-cache" if enabled => options.gop_num = Some(0),
+                "gop" => {
+                    if let Ok(gop_num) = value.parse() {
+                        options.gop_num = Some(gop_num);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    //the request can not turn off both of the streams
+    if !options.has_audio && !options.has_video {
+        options.has_audio = true;
+        options.has_video = true;
+    }
+    options
+}
+
+/*Publish the flv stream of the POST body or the websocket binary frames, the POST
+request is responded when its body ends.*/
+async fn publish(
     req: Request<Body>,
-    event_producer: ChannelEventProducer, // event_producer: ChannelEventProducer
+    context: ServerContext,
+    app_name: String,
+    stream_name: String,
+    remote_addr: SocketAddr,
+) -> Result<Response<Body>> {
+    let websocket_response = if websocket::is_websocket_request(&req) {
+        let response = websocket::handshake_response(&req);
+        if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+            return Ok(response);
+        }
+        Some(response)
+    } else {
+        None
+    };
+
+    let publish_type = if websocket_response.is_some() {
+        PublishType::PushWebSocketFlv
+    } else {
+        PublishType::PushHttpFlv
+    };
+    let mut publisher = FlvPublisher::new(
+        app_name,
+        stream_name,
+        context.event_producer,
+        publish_type,
+        req.uri().to_string(),
+        remote_addr,
+    );
+
+    if let Err(err) = publisher.publish_to_channels().await {
+        log::error!("flv publisher publish error {}\n", err);
+        return Ok(status_response(match err.value {
+            HttpFLvErrorValue::PublishRejected => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }));
+    }
+
+    if let Some(response) = websocket_response {
+        tokio::spawn(async move {
+            match hyper::upgrade::on(req).await {
+                Ok(upgraded) => {
+                    if let Err(err) = websocket::receive_media_stream(upgraded, publisher).await {
+                        log::error!("websocket flv receive error {}\n", err);
+                    }
+                }
+                Err(err) => {
+                    log::error!("websocket flv upgrade error {}\n", err);
+                    publisher.unpublish_from_channels();
+                }
+            }
+        });
+        return Ok(response);
+    }
+
+    let mut body = req.into_body();
+    let result = loop {
+        match body.data().await {
+            Some(Ok(data)) => {
+                if let Err(err) = publisher.on_data(&data) {
+                    break Err(err);
+                }
+            }
+            Some(Err(err)) => {
+                log::info!("http flv publisher disconnected: {}", err);
+                break Ok(());
+            }
+            None => break Ok(()),
+        }
+    };
+    publisher.unpublish_from_channels();
+
+    match result {
+        Ok(()) => Ok(status_response(StatusCode::OK)),
+        Err(err) => {
+            log::error!("http flv publisher error {}\n", err);
+            Ok(status_response(StatusCode::BAD_REQUEST))
+        }
+    }
+}
+
+/*handle a request of the httpflv server, it is also called by the other http servers
+which serve the httpflv streams, the websocket requests are played by websocket-flv,
+and the POST and websocket publish requests publish the streams if ingest is enabled*/
+pub async fn handle_connection(
+    req: Request<Body>,
+    context: ServerContext,
     remote_addr: SocketAddr,
 ) -> Result<Response<Body>> {
     let path = req.uri().path();
 
     match path.find(".flv") {
         Some(index) if index > 0 => {
-            let (left, _) = path.split_at(index);
-            let rv: Vec<_> = left.split('/').collect();
+            let (app_name, stream_name) = match split_stream_path(path, index) {
+                Some(names) => names,
+                None => return Ok(status_response(StatusCode::BAD_REQUEST)),
+            };
 
-            let app_name = String::from(rv[1]);
-            let stream_name = String::from(rv[2]);
+            if req.method() == Method::POST
+                || (websocket::is_websocket_request(&req) && is_publish_request(&req))
+            {
+                if !context.options.ingest {
+                    return Ok(status_response(StatusCode::FORBIDDEN));
+                }
+                return publish(req, context, app_name, stream_name, remote_addr).await;
+            }
+
+            let websocket_response = if websocket::is_websocket_request(&req) {
+                let response = websocket::handshake_response(&req);
+                if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+                    return Ok(response);
+                }
+                Some(response)
+            } else {
+                None
+            };
 
-            let (http_response_data_producer, http_response_data_consumer) = unbounded();
+            let (http_response_data_producer, mut http_response_data_consumer) =
+                mpsc::unbounded_channel();
 
             let mut flv_hanlder = HttpFlv::new(
                 app_name,
                 stream_name,
-                event_producer,
+                context.event_producer,
                 http_response_data_producer,
                 req.uri().to_string(),
                 remote_addr,
+                parse_subscribe_options(req.uri().query()),
             );
+            flv_hanlder.set_subscribe_timeout(context.options.subscribe_timeout);
+
+            //the stream is subscribed before the response, so a missing stream is 404
+            if let Err(err) = flv_hanlder.subscribe_from_rtmp_channels().await {
+                log::error!("flv handler subscribe error {}\n", err);
+                return Ok(match err.value {
+                    HttpFLvErrorValue::StreamNotFound => Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(NOTFOUND.into())
+                        .unwrap(),
+                    _ => status_response(StatusCode::INTERNAL_SERVER_ERROR),
+                });
+            }
 
             tokio::spawn(async move {
-                if let Err(err) = flv_hanlder.run().await {
-                    log::error!("flv handler run error {}\n", err);
+                if let Err(err) = flv_hanlder.send_media_stream().await {
+                    log::error!("flv handler send error {}\n", err);
                 }
             });
 
-            let mut resp = Response::new(Body::wrap_stream(http_response_data_consumer));
+            if let Some(response) = websocket_response {
+                tokio::spawn(async move {
+                    match hyper::upgrade::on(req).await {
+                        Ok(upgraded) => {
+                            if let Err(err) =
+                                websocket::send_media_stream(upgraded, http_response_data_consumer)
+                                    .await
+                            {
+                                log::error!("websocket flv send error {}\n", err);
+                            }
+                        }
+                        Err(err) => log::error!("websocket flv upgrade error {}\n", err),
+                    }
+                });
+                return Ok(response);
+            }
+
+            /*hyper drops the body when the connection is closed, then the HttpFlv
+            is notified by the closed data channel*/
+            let body = stream::poll_fn(move |cx| http_response_data_consumer.poll_recv(cx));
+            let mut resp = Response::new(Body::wrap_stream(body));
             resp.headers_mut()
                 .insert("Access-Control-Allow-Origin", "*".parse().unwrap());
 
@@ -60,16 +307,16 @@ async fn handle_connection(
     }
 }
 
-pub async fn run(event_producer: ChannelEventProducer, port: usize) -> Result<()> {
+pub async fn run(port: usize, context: ServerContext) -> Result<()> {
     let listen_address = format!("0.0.0.0:{port}");
     let sock_addr = listen_address.parse().unwrap();
 
     let new_service = make_service_fn(move |socket: &AddrStream| {
         let remote_addr = socket.remote_addr();
-        let flv_copy = event_producer.clone();
+        let context = context.clone();
         async move {
             Ok::<_, GenericError>(service_fn(move |req| {
-                handle_connection(req, flv_copy.clone(), remote_addr)
+                handle_connection(req, context.clone(), remote_addr)
             }))
         }
     });
@@ -82,3 +329,43 @@ pub async fn run(event_producer: ChannelEventProducer, port: usize) -> Result<()
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_subscribe_options, split_stream_path};
+
+    fn split(path: &str) -> Option<(String, String)> {
+        split_stream_path(path, path.find(".flv").unwrap())
+    }
+
+    #[test]
+    fn test_split_stream_path() {
+        assert_eq!(
+            split("/live/test.flv"),
+            Some((String::from("live"), String::from("test")))
+        );
+        assert_eq!(split("/x.flv"), None);
+        assert_eq!(split("/live/.flv"), None);
+        assert_eq!(split("//test.flv"), None);
+        assert_eq!(split("/live/test/extra.flv"), None);
+        assert_eq!(split("/live/test.flv/extra"), None);
+    }
+
+    #[test]
+    fn test_parse_subscribe_options() {
+        let default = parse_subscribe_options(None);
+        assert!(default.has_audio && default.has_video);
+        assert_eq!(default.gop_num, None);
+
+        let audio_only = parse_subscribe_options(Some("only-audio=1&no-cache=1"));
+        assert!(audio_only.has_audio && !audio_only.has_video);
+        assert_eq!(audio_only.gop_num, Some(0));
+
+        let video_only = parse_subscribe_options(Some("only-video&gop=2"));
+        assert!(!video_only.has_audio && video_only.has_video);
+        assert_eq!(video_only.gop_num, Some(2));
+
+        let both_off = parse_subscribe_options(Some("only-audio=1&only-video=1"));
+        assert!(both_off.has_audio && both_off.has_video);
+    }
+}
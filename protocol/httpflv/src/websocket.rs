@@ -0,0 +1,265 @@
+use {
+    super::{define::HttpResponseDataConsumer, errors::HttpFLvError, publisher::FlvPublisher},
+    futures::{SinkExt, StreamExt},
+    hyper::{
+        header::{self, HeaderName},
+        upgrade::Upgraded,
+        Body, Request, Response, StatusCode,
+    },
+    std::time::Duration,
+    tokio::{
+        io::{AsyncRead, AsyncWrite},
+        time::{self, Instant},
+    },
+    tokio_tungstenite::{
+        tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+        WebSocketStream,
+    },
+};
+
+//how often a ping is sent to the player
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+//the player is gone if nothing(e.g. the pong) is received from it in the time
+const PING_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn header_contains(req: &Request<Body>, name: HeaderName, value: &str) -> bool {
+    req.headers().get_all(name).iter().any(|header_value| {
+        header_value.to_str().is_ok_and(|header_value| {
+            header_value
+                .split(',')
+                .any(|item| item.trim().eq_ignore_ascii_case(value))
+        })
+    })
+}
+
+/*the request asks to upgrade the connection to a websocket*/
+pub fn is_websocket_request(req: &Request<Body>) -> bool {
+    header_contains(req, header::CONNECTION, "upgrade")
+        && header_contains(req, header::UPGRADE, "websocket")
+}
+
+/*the 101 response which accepts the websocket handshake, or 400 if the request is
+not a valid version 13 handshake*/
+pub fn handshake_response(req: &Request<Body>) -> Response<Body> {
+    let key = req.headers().get(header::SEC_WEBSOCKET_KEY);
+    let version = req.headers().get(header::SEC_WEBSOCKET_VERSION);
+
+    match (key, version) {
+        (Some(key), Some(version)) if version.as_bytes() == b"13" => Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::CONNECTION, "Upgrade")
+            .header(header::UPGRADE, "websocket")
+            .header(
+                header::SEC_WEBSOCKET_ACCEPT,
+                derive_accept_key(key.as_bytes()),
+            )
+            .body(Body::empty())
+            .unwrap(),
+        _ => Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(header::SEC_WEBSOCKET_VERSION, "13")
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
+
+/*Send the flv data written by HttpFlv to the player, every flushed tag is a binary
+frame, and the player is pinged to keep the connection alive. The data consumer is
+dropped when the player is gone, so the HttpFlv stops and unsubscribes like an http
+player.*/
+pub async fn send_media_stream(
+    upgraded: Upgraded,
+    data_consumer: HttpResponseDataConsumer,
+) -> Result<(), HttpFLvError> {
+    let websocket = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+    send_websocket_stream(websocket, data_consumer, PING_INTERVAL, PING_TIMEOUT).await
+}
+
+async fn send_websocket_stream<S>(
+    websocket: WebSocketStream<S>,
+    mut data_consumer: HttpResponseDataConsumer,
+    ping_period: Duration,
+    ping_timeout: Duration,
+) -> Result<(), HttpFLvError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut sink, mut stream) = websocket.split();
+
+    let mut ping_interval = time::interval_at(Instant::now() + ping_period, ping_period);
+    let mut last_receive_time = Instant::now();
+
+    loop {
+        tokio::select! {
+            data = data_consumer.recv() => {
+                let data = match data {
+                    Some(Ok(data)) => data,
+                    _ => break,
+                };
+                sink.feed(Message::Binary(data.to_vec())).await?;
+                //the tags which are ready are sent together
+                while let Ok(Ok(data)) = data_consumer.try_recv() {
+                    sink.feed(Message::Binary(data.to_vec())).await?;
+                }
+                sink.flush().await?;
+            }
+            message = stream.next() => {
+                match message {
+                    //the pings are answered by tungstenite
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => last_receive_time = Instant::now(),
+                    Some(Err(err)) => return Err(err.into()),
+                }
+            }
+            _ = ping_interval.tick() => {
+                if last_receive_time.elapsed() > ping_timeout {
+                    log::info!("websocket flv player does not respond to the pings");
+                    break;
+                }
+                sink.send(Message::Ping(Vec::new())).await?;
+            }
+        }
+    }
+
+    if let Err(err) = sink.close().await {
+        log::trace!("websocket flv close error: {}", err);
+    }
+
+    Ok(())
+}
+
+/*Receive the flv stream of a websocket publisher, the binary frames are the parts of
+the stream, the publisher has been published and it is unpublished when the websocket
+is closed.*/
+pub async fn receive_media_stream(
+    upgraded: Upgraded,
+    mut publisher: FlvPublisher,
+) -> Result<(), HttpFLvError> {
+    let mut websocket = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+
+    let result = loop {
+        match websocket.next().await {
+            Some(Ok(Message::Binary(data))) => {
+                if let Err(err) = publisher.on_data(&data) {
+                    break Err(err);
+                }
+            }
+            //the pings are answered by tungstenite
+            Some(Ok(Message::Close(_))) | None => break Ok(()),
+            Some(Ok(_)) => {}
+            Some(Err(err)) => break Err(err.into()),
+        }
+    };
+    publisher.unpublish_from_channels();
+
+    if let Err(err) = websocket.close(None).await {
+        log::trace!("websocket flv close error: {}", err);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::send_websocket_stream,
+        bytes::BytesMut,
+        futures::{SinkExt, StreamExt},
+        std::time::Duration,
+        tokio::{io::duplex, io::AsyncReadExt, sync::mpsc, time::timeout},
+        tokio_tungstenite::{
+            tungstenite::{protocol::Role, Message},
+            WebSocketStream,
+        },
+    };
+
+    const PING_PERIOD: Duration = Duration::from_millis(50);
+    const PING_TIMEOUT: Duration = Duration::from_millis(150);
+
+    #[tokio::test]
+    async fn test_missing_pong_closes_session() {
+        let (server_io, mut client_io) = duplex(64 * 1024);
+        let (_data_producer, data_consumer) = mpsc::unbounded_channel();
+        let server = WebSocketStream::from_raw_socket(server_io, Role::Server, None).await;
+        let session = tokio::spawn(send_websocket_stream(
+            server,
+            data_consumer,
+            PING_PERIOD,
+            PING_TIMEOUT,
+        ));
+
+        //the client does not read, so the pings are never answered
+        let result = timeout(Duration::from_secs(2), session)
+            .await
+            .expect("the session is expected to be closed");
+        assert!(result.unwrap().is_ok());
+
+        //the unmasked frames of the server: the empty pings and then the close
+        let mut frames = Vec::new();
+        client_io.read_to_end(&mut frames).await.unwrap();
+        assert!(frames.starts_with(&[0x89, 0x00]));
+        assert!(frames.ends_with(&[0x88, 0x00]));
+        assert!(frames.len() >= 4);
+    }
+
+    #[tokio::test]
+    async fn test_pong_keeps_session_alive() {
+        let (server_io, client_io) = duplex(64 * 1024);
+        let (data_producer, data_consumer) = mpsc::unbounded_channel();
+        let server = WebSocketStream::from_raw_socket(server_io, Role::Server, None).await;
+        let mut session = tokio::spawn(send_websocket_stream(
+            server,
+            data_consumer,
+            PING_PERIOD,
+            PING_TIMEOUT,
+        ));
+
+        //the pings are answered while the client reads
+        let mut client = WebSocketStream::from_raw_socket(client_io, Role::Client, None).await;
+        let (binary_producer, mut binary_consumer) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(Ok(message)) = client.next().await {
+                if let Message::Binary(data) = message {
+                    let _ = binary_producer.send(data);
+                }
+            }
+        });
+
+        assert!(timeout(PING_TIMEOUT * 4, &mut session).await.is_err());
+
+        assert!(data_producer.send(Ok(BytesMut::from(&b"FLV"[..]))).is_ok());
+        let data = timeout(Duration::from_secs(2), binary_consumer.recv())
+            .await
+            .unwrap();
+        assert_eq!(data, Some(b"FLV".to_vec()));
+
+        //the session is closed when the stream ends
+        drop(data_producer);
+        assert!(timeout(Duration::from_secs(2), session)
+            .await
+            .unwrap()
+            .unwrap()
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_client_close_ends_session() {
+        let (server_io, client_io) = duplex(64 * 1024);
+        let (_data_producer, data_consumer) = mpsc::unbounded_channel();
+        let server = WebSocketStream::from_raw_socket(server_io, Role::Server, None).await;
+        let session = tokio::spawn(send_websocket_stream(
+            server,
+            data_consumer,
+            Duration::from_secs(10),
+            Duration::from_secs(30),
+        ));
+
+        let mut client = WebSocketStream::from_raw_socket(client_io, Role::Client, None).await;
+        client.send(Message::Close(None)).await.unwrap();
+        assert!(timeout(Duration::from_secs(2), session)
+            .await
+            .unwrap()
+            .unwrap()
+            .is_ok());
+    }
+}
@@ -0,0 +1,67 @@
+use {
+    super::errors::RecordError,
+    serde::Serialize,
+    tokio::sync::{mpsc, oneshot},
+};
+
+pub const DEFAULT_FILE_NAME: &str = "{app}/{stream}_{time}.flv";
+
+/*the options used to record the streams of an app*/
+#[derive(Debug, Clone)]
+pub struct RecordOptions {
+    //record the streams when they are published, otherwise they are started by the http api
+    pub auto_start: bool,
+    //the root folder of the files
+    pub root: String,
+    /*the file name template, {app}, {stream} and {time}(the start time of the file,
+    e.g. 20240101120000) are replaced*/
+    pub file_name: String,
+    //roll to a new file at the next key frame after the seconds, 0 means no limit
+    pub max_duration: u64,
+    //roll to a new file at the next key frame after the bytes, 0 means no limit
+    pub max_size: u64,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        Self {
+            auto_start: true,
+            root: String::from("."),
+            file_name: String::from(DEFAULT_FILE_NAME),
+            max_duration: 0,
+            max_size: 0,
+        }
+    }
+}
+
+pub type RecordEventProducer = mpsc::UnboundedSender<RecordEvent>;
+pub type RecordEventConsumer = mpsc::UnboundedReceiver<RecordEvent>;
+
+type RecordResponder<T> = oneshot::Sender<T>;
+
+#[derive(Debug)]
+pub enum RecordEvent {
+    /*Start to record a published stream*/
+    Start {
+        app_name: String,
+        stream_name: String,
+        responder: RecordResponder<Result<(), RecordError>>,
+    },
+    /*Stop the recording of a stream, the current file is finished*/
+    Stop {
+        app_name: String,
+        stream_name: String,
+        responder: RecordResponder<bool>,
+    },
+    /*Query the streams which are being recorded*/
+    Status {
+        responder: RecordResponder<Vec<RecordStatus>>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordStatus {
+    pub app_name: String,
+    pub stream_name: String,
+    pub start_time: String,
+}
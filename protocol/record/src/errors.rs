@@ -0,0 +1,100 @@
+use {
+    failure::{Backtrace, Fail},
+    rtmp::{amf0::Amf0WriteError, session::errors::SessionError},
+    std::{fmt, io::Error as IoError},
+    tokio::sync::broadcast::error::RecvError,
+    xflv::errors::{FlvDemuxerError, MuxerError},
+};
+
+#[derive(Debug)]
+pub struct RecordError {
+    pub value: RecordErrorValue,
+}
+
+#[derive(Debug, Fail)]
+pub enum RecordErrorValue {
+    #[fail(display = "record error")]
+    Error,
+    #[fail(display = "session error:{}\n", _0)]
+    SessionError(#[cause] SessionError),
+    #[fail(display = "flv muxer error:{}\n", _0)]
+    MuxerError(MuxerError),
+    #[fail(display = "flv demuxer error:{}\n", _0)]
+    FlvDemuxerError(#[cause] FlvDemuxerError),
+    #[fail(display = "amf write error:{}\n", _0)]
+    Amf0WriteError(#[cause] Amf0WriteError),
+    #[fail(display = "io error:{}\n", _0)]
+    IoError(#[cause] IoError),
+    #[fail(display = "receive error:{}\n", _0)]
+    RecvError(#[cause] RecvError),
+    #[fail(display = "the stream is not found")]
+    StreamNotFound,
+    #[fail(display = "the stream is being recorded")]
+    AlreadyRecording,
+    #[fail(display = "invalid record path: {}", _0)]
+    InvalidPath(String),
+}
+
+impl From<SessionError> for RecordError {
+    fn from(error: SessionError) -> Self {
+        RecordError {
+            value: RecordErrorValue::SessionError(error),
+        }
+    }
+}
+
+impl From<MuxerError> for RecordError {
+    fn from(error: MuxerError) -> Self {
+        RecordError {
+            value: RecordErrorValue::MuxerError(error),
+        }
+    }
+}
+
+impl From<FlvDemuxerError> for RecordError {
+    fn from(error: FlvDemuxerError) -> Self {
+        RecordError {
+            value: RecordErrorValue::FlvDemuxerError(error),
+        }
+    }
+}
+
+impl From<Amf0WriteError> for RecordError {
+    fn from(error: Amf0WriteError) -> Self {
+        RecordError {
+            value: RecordErrorValue::Amf0WriteError(error),
+        }
+    }
+}
+
+impl From<IoError> for RecordError {
+    fn from(error: IoError) -> Self {
+        RecordError {
+            value: RecordErrorValue::IoError(error),
+        }
+    }
+}
+
+impl From<RecvError> for RecordError {
+    fn from(error: RecvError) -> Self {
+        RecordError {
+            value: RecordErrorValue::RecvError(error),
+        }
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl Fail for RecordError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.value.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.value.backtrace()
+    }
+}
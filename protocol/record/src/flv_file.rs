@@ -0,0 +1,282 @@
+use {
+    super::errors::RecordError,
+    bytes::BytesMut,
+    bytesio::bytes_writer::BytesWriter,
+    indexmap::IndexMap,
+    rtmp::amf0::{amf0_writer::Amf0Writer, Amf0ValueType},
+    std::{
+        fs::{self, File, OpenOptions},
+        io::{self, BufWriter, Write},
+        path::Path,
+    },
+    xflv::{
+        define::tag_type,
+        muxer::{FlvMuxer, HEADER_LENGTH},
+    },
+};
+
+//the flv header and the first previous tag size
+const FLV_HEADER_SIZE: u64 = 13;
+//the properties of the live stream which are replaced by the ones of the file
+const FILE_PROPERTIES: [&str; 4] = ["duration", "filesize", "hasKeyframes", "keyframes"];
+
+/*A flv file being recorded. The tags are written to a temporary file first, when it
+is finished the onMetaData with the duration and the key frame index is written in
+front of them, so the file can be seeked by the players.*/
+pub struct FlvFile {
+    path: String,
+    temp_path: String,
+    writer: BufWriter<File>,
+    muxer: FlvMuxer,
+    //the timestamps in the file start from the base timestamp
+    base_timestamp: u32,
+    //the last timestamp in milliseconds
+    duration: u32,
+    //the size of the tags
+    size: u64,
+    has_audio: bool,
+    has_video: bool,
+    //the time(seconds) and the position in the tags of the key frames
+    keyframes: Vec<(f64, u64)>,
+}
+
+impl FlvFile {
+    pub fn create(path: String, base_timestamp: u32) -> Result<Self, RecordError> {
+        if let Some(folder) = Path::new(&path).parent() {
+            fs::create_dir_all(folder)?;
+        }
+        //an existing recording is never overwritten
+        if Path::new(&path).exists() {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists).into());
+        }
+        let temp_path = format!("{path}.tmp");
+        let writer = BufWriter::new(
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp_path)?,
+        );
+
+        Ok(Self {
+            path,
+            temp_path,
+            writer,
+            muxer: FlvMuxer::new(),
+            base_timestamp,
+            duration: 0,
+            size: 0,
+            has_audio: false,
+            has_video: false,
+            keyframes: Vec::new(),
+        })
+    }
+
+    //the duration in milliseconds if a frame of the timestamp is written
+    pub fn duration_at(&self, timestamp: u32) -> u32 {
+        self.duration
+            .max(timestamp.saturating_sub(self.base_timestamp))
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn write_tag(
+        &mut self,
+        tag_type: u8,
+        timestamp: u32,
+        data: BytesMut,
+        is_key_frame: bool,
+    ) -> Result<(), RecordError> {
+        //the frames a little earlier than the first key frame are written at 0
+        let timestamp = timestamp.saturating_sub(self.base_timestamp);
+        match tag_type {
+            tag_type::AUDIO => self.has_audio = true,
+            tag_type::VIDEO => self.has_video = true,
+            _ => {}
+        }
+        if is_key_frame {
+            self.keyframes.push((timestamp as f64 / 1000.0, self.size));
+        }
+        self.duration = self.duration.max(timestamp);
+
+        let data_len = data.len() as u32;
+        self.muxer
+            .write_flv_tag_header(tag_type, data_len, timestamp)?;
+        self.muxer.write_flv_tag_body(data)?;
+        self.muxer
+            .write_previous_tag_size(data_len + HEADER_LENGTH)?;
+
+        let data = self.muxer.writer.extract_current_bytes();
+        self.writer.write_all(&data)?;
+        self.size += data.len() as u64;
+
+        Ok(())
+    }
+
+    /*Write the file with the header, the onMetaData and the recorded tags, the
+    properties of the stream metadata are kept. Returns the path and the duration
+    in seconds.*/
+    pub fn finish(
+        mut self,
+        properties: Option<&IndexMap<String, Amf0ValueType>>,
+    ) -> Result<(String, f64), RecordError> {
+        self.writer.flush()?;
+
+        //the size of the metadata does not change with the positions
+        let metadata_size = self.build_metadata(properties, 0, 0)?.len() as u64;
+        let tags_offset = FLV_HEADER_SIZE + HEADER_LENGTH as u64 + metadata_size + 4;
+        let metadata = self.build_metadata(properties, tags_offset, tags_offset + self.size)?;
+
+        self.muxer
+            .write_flv_header_with_flags(self.has_audio, self.has_video)?;
+        self.muxer.write_previous_tag_size(0)?;
+        let metadata_len = metadata.len() as u32;
+        self.muxer
+            .write_flv_tag_header(tag_type::SCRIPT_DATA_AMF, metadata_len, 0)?;
+        self.muxer.write_flv_tag_body(metadata)?;
+        self.muxer
+            .write_previous_tag_size(metadata_len + HEADER_LENGTH)?;
+
+        let mut file = BufWriter::new(File::create(&self.path)?);
+        file.write_all(&self.muxer.writer.extract_current_bytes())?;
+        io::copy(&mut File::open(&self.temp_path)?, &mut file)?;
+        file.flush()?;
+        fs::remove_file(&self.temp_path)?;
+
+        Ok((self.path, self.duration as f64 / 1000.0))
+    }
+
+    fn build_metadata(
+        &self,
+        properties: Option<&IndexMap<String, Amf0ValueType>>,
+        tags_offset: u64,
+        file_size: u64,
+    ) -> Result<BytesMut, RecordError> {
+        let mut metadata = properties.cloned().unwrap_or_default();
+        for key in FILE_PROPERTIES {
+            metadata.shift_remove(key);
+        }
+
+        let mut keyframes = IndexMap::new();
+        keyframes.insert(
+            String::from("times"),
+            Amf0ValueType::StrictArray(
+                self.keyframes
+                    .iter()
+                    .map(|(time, _)| Amf0ValueType::Number(*time))
+                    .collect(),
+            ),
+        );
+        keyframes.insert(
+            String::from("filepositions"),
+            Amf0ValueType::StrictArray(
+                self.keyframes
+                    .iter()
+                    .map(|(_, position)| Amf0ValueType::Number((tags_offset + position) as f64))
+                    .collect(),
+            ),
+        );
+
+        metadata.insert(
+            String::from("duration"),
+            Amf0ValueType::Number(self.duration as f64 / 1000.0),
+        );
+        metadata.insert(
+            String::from("filesize"),
+            Amf0ValueType::Number(file_size as f64),
+        );
+        metadata.insert(
+            String::from("hasKeyframes"),
+            Amf0ValueType::Boolean(!self.keyframes.is_empty()),
+        );
+        metadata.insert(String::from("keyframes"), Amf0ValueType::Object(keyframes));
+
+        let mut writer = Amf0Writer::new(BytesWriter::new());
+        writer.write_string(&String::from("onMetaData"))?;
+        writer.write_ecma_array(&metadata)?;
+
+        Ok(writer.extract_current_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::FlvFile,
+        bytes::BytesMut,
+        bytesio::bytes_reader::BytesReader,
+        rtmp::amf0::{amf0_reader::Amf0Reader, Amf0ValueType},
+        std::fs,
+        xflv::define::tag_type,
+    };
+
+    #[test]
+    fn test_finish() {
+        let folder = std::env::temp_dir().join(format!("flv_file_{}", std::process::id()));
+        let path = folder.join("test.flv").to_string_lossy().to_string();
+
+        let mut file = FlvFile::create(path.clone(), 1000).unwrap();
+        let frame = BytesMut::from(&b"\x17\x01\x00\x00\x00"[..]);
+        file.write_tag(tag_type::VIDEO, 1000, frame.clone(), true)
+            .unwrap();
+        file.write_tag(
+            tag_type::AUDIO,
+            1020,
+            BytesMut::from(&b"\xaf\x01"[..]),
+            false,
+        )
+        .unwrap();
+        file.write_tag(tag_type::VIDEO, 3000, frame, true).unwrap();
+        let (finished_path, duration) = file.finish(None).unwrap();
+        assert_eq!(finished_path, path);
+        assert_eq!(duration, 2.0);
+
+        let data = fs::read(&path).unwrap();
+        fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(&data[..5], b"FLV\x01\x05");
+
+        let metadata_len =
+            ((data[14] as usize) << 16) | ((data[15] as usize) << 8) | data[16] as usize;
+        let metadata = BytesMut::from(&data[24..24 + metadata_len]);
+        let values = Amf0Reader::new(BytesReader::new(metadata))
+            .read_all()
+            .unwrap();
+        let properties = match &values[1] {
+            Amf0ValueType::Object(properties) => properties,
+            _ => panic!("the metadata properties are expected"),
+        };
+        assert_eq!(properties["duration"], Amf0ValueType::Number(2.0));
+        assert_eq!(
+            properties["filesize"],
+            Amf0ValueType::Number(data.len() as f64)
+        );
+
+        let keyframes = match &properties["keyframes"] {
+            Amf0ValueType::Object(keyframes) => keyframes,
+            _ => panic!("the keyframes are expected"),
+        };
+        let positions = match &keyframes["filepositions"] {
+            Amf0ValueType::StrictArray(positions) => positions,
+            _ => panic!("the filepositions are expected"),
+        };
+        assert_eq!(positions.len(), 2);
+        //the key frames are video tags at the positions
+        for position in positions {
+            if let Amf0ValueType::Number(position) = position {
+                assert_eq!(data[*position as usize], tag_type::VIDEO);
+            }
+        }
+        assert_eq!(
+            keyframes["times"],
+            Amf0ValueType::StrictArray(vec![
+                Amf0ValueType::Number(0.0),
+                Amf0ValueType::Number(2.0)
+            ])
+        );
+    }
+}
@@ -0,0 +1,5 @@
+pub mod define;
+pub mod errors;
+pub mod flv_file;
+pub mod recorder;
+pub mod rtmp_event_processor;
@@ -0,0 +1,425 @@
+use {
+    super::{
+        define::RecordOptions,
+        errors::{RecordError, RecordErrorValue},
+        flv_file::FlvFile,
+    },
+    bytes::BytesMut,
+    bytesio::bytes_reader::BytesReader,
+    chrono::{DateTime, Utc},
+    indexmap::IndexMap,
+    rtmp::{
+        amf0::{amf0_reader::Amf0Reader, Amf0ValueType},
+        channels::define::{ChannelData, ChannelDataConsumer, ChannelEvent, ChannelEventProducer},
+        notify::{Notifier, RecordInfo},
+        session::{
+            common::{NotifyInfo, SubscribeOptions, SubscriberInfo},
+            define::SubscribeType,
+            errors::{SessionError, SessionErrorValue},
+        },
+        utils::is_valid_path_name,
+    },
+    std::{
+        io,
+        path::{Component, Path, PathBuf},
+        sync::Arc,
+    },
+    tokio::sync::{mpsc, oneshot},
+    uuid::Uuid,
+    xflv::{
+        define::{aac_packet_type, avc_packet_type, frame_type, tag_type, SoundFormat},
+        demuxer_tag::{AudioTagHeaderDemuxer, VideoTagHeaderDemuxer},
+    },
+};
+
+//how many files of the same name can be rolled in a second
+const MAX_SEQUENCE: u32 = 1000;
+
+/*the properties of the onMetaData, the data may begin with @setDataFrame*/
+fn parse_metadata(data: BytesMut) -> Option<IndexMap<String, Amf0ValueType>> {
+    let values = Amf0Reader::new(BytesReader::new(data)).read_all().ok()?;
+    let mut values = values.into_iter().skip_while(
+        |value| matches!(value, Amf0ValueType::UTF8String(name) if name == "@setDataFrame"),
+    );
+
+    match values.next()? {
+        Amf0ValueType::UTF8String(name) if name == "onMetaData" => {}
+        _ => return None,
+    }
+    match values.next()? {
+        Amf0ValueType::Object(properties) | Amf0ValueType::EcmaArray(properties) => {
+            Some(properties)
+        }
+        _ => None,
+    }
+}
+
+/*Record a stream to flv files. Every file begins with the sequence headers and a key
+frame, a new file is rolled at the key frame after the max duration or size, and the
+on_record_done notification is sent when a file is finished.*/
+pub struct FlvRecorder {
+    app_name: String,
+    stream_name: String,
+
+    event_producer: ChannelEventProducer,
+    data_consumer: ChannelDataConsumer,
+    subscriber_id: Uuid,
+    options: RecordOptions,
+    notifier: Option<Arc<Notifier>>,
+
+    //the latest metadata properties and sequence headers, they begin every file
+    metadata: Option<IndexMap<String, Amf0ValueType>>,
+    audio_seq: Option<BytesMut>,
+    video_seq: Option<BytesMut>,
+    file: Option<FlvFile>,
+}
+
+impl FlvRecorder {
+    pub fn new(
+        app_name: String,
+        stream_name: String,
+        event_producer: ChannelEventProducer,
+        options: RecordOptions,
+    ) -> Self {
+        let (_, data_consumer) = mpsc::unbounded_channel();
+
+        Self {
+            app_name,
+            stream_name,
+            event_producer,
+            data_consumer,
+            subscriber_id: Uuid::new_v4(),
+            options,
+            notifier: None,
+            metadata: None,
+            audio_seq: None,
+            video_seq: None,
+            file: None,
+        }
+    }
+
+    pub fn set_notifier(&mut self, notifier: Arc<Notifier>) {
+        self.notifier = Some(notifier);
+    }
+
+    pub async fn run(&mut self, stop_receiver: oneshot::Receiver<()>) -> Result<(), RecordError> {
+        self.subscribe_from_rtmp_channels().await?;
+        self.record(stop_receiver).await
+    }
+
+    /*Record until the stream ends or the recording is stopped, the current file is
+    finished and the stream is unsubscribed.*/
+    pub async fn record(
+        &mut self,
+        mut stop_receiver: oneshot::Receiver<()>,
+    ) -> Result<(), RecordError> {
+        let result = loop {
+            tokio::select! {
+                data = self.data_consumer.recv() => {
+                    match data {
+                        Some(data) => {
+                            if let Err(err) = self.on_channel_data(data).await {
+                                break Err(err);
+                            }
+                        }
+                        None => break Ok(()),
+                    }
+                }
+                _ = &mut stop_receiver => break Ok(()),
+            }
+        };
+
+        let finish_result = self.finish_file().await;
+        self.unsubscribe_from_rtmp_channels();
+
+        result.and(finish_result)
+    }
+
+    async fn on_channel_data(&mut self, channel_data: ChannelData) -> Result<(), RecordError> {
+        match channel_data {
+            ChannelData::MetaData { timestamp, data } => {
+                if let Some(properties) = parse_metadata(data.clone()) {
+                    self.metadata = Some(properties);
+                } else if let Some(file) = &mut self.file {
+                    //the other data messages(e.g. onCuePoint) are recorded as they are
+                    file.write_tag(tag_type::SCRIPT_DATA_AMF, timestamp, data, false)?;
+                }
+            }
+            ChannelData::Audio { timestamp, data } => {
+                let tag = AudioTagHeaderDemuxer::new(data.clone()).parse_tag_header()?;
+                if tag.sound_format == SoundFormat::AAC as u8
+                    && tag.aac_packet_type == aac_packet_type::AAC_SEQHDR
+                {
+                    self.audio_seq = Some(data.clone());
+                } else if self.video_seq.is_none() {
+                    //the audio only streams are rolled at any frame
+                    self.roll_file(timestamp).await?;
+                }
+
+                if let Some(file) = &mut self.file {
+                    file.write_tag(tag_type::AUDIO, timestamp, data, false)?;
+                }
+            }
+            ChannelData::Video { timestamp, data } => {
+                let tag = VideoTagHeaderDemuxer::new(data.clone()).parse_tag_header()?;
+                let is_key_frame = tag.frame_type == frame_type::KEY_FRAME;
+
+                if is_key_frame && tag.avc_packet_type == avc_packet_type::AVC_SEQHDR {
+                    self.video_seq = Some(data.clone());
+                } else if is_key_frame {
+                    self.roll_file(timestamp).await?;
+                }
+
+                if let Some(file) = &mut self.file {
+                    let is_key_frame =
+                        is_key_frame && tag.avc_packet_type != avc_packet_type::AVC_SEQHDR;
+                    file.write_tag(tag_type::VIDEO, timestamp, data, is_key_frame)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_file_full(&self, file: &FlvFile, timestamp: u32) -> bool {
+        (self.options.max_duration > 0
+            && file.duration_at(timestamp) as u64 >= self.options.max_duration * 1000)
+            || (self.options.max_size > 0 && file.size() >= self.options.max_size)
+    }
+
+    /*Begin a new file at the frame if no file is being recorded or the current one is
+    full, the new file begins with the sequence headers.*/
+    async fn roll_file(&mut self, timestamp: u32) -> Result<(), RecordError> {
+        match &self.file {
+            Some(file) if !self.is_file_full(file, timestamp) => return Ok(()),
+            Some(_) => self.finish_file().await?,
+            None => {}
+        }
+
+        let now = Utc::now();
+        let mut sequence = 0;
+        let mut file = loop {
+            let path = self.file_path(now, sequence)?;
+            match FlvFile::create(path.to_string_lossy().to_string(), timestamp) {
+                Ok(file) => {
+                    log::info!(
+                        "record {}/{} to {:?}",
+                        self.app_name,
+                        self.stream_name,
+                        path
+                    );
+                    break file;
+                }
+                //the file of the same name is rolled in the same second
+                Err(RecordError {
+                    value: RecordErrorValue::IoError(err),
+                }) if err.kind() == io::ErrorKind::AlreadyExists && sequence < MAX_SEQUENCE => {
+                    sequence += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        if let Some(audio_seq) = &self.audio_seq {
+            file.write_tag(tag_type::AUDIO, timestamp, audio_seq.clone(), false)?;
+        }
+        if let Some(video_seq) = &self.video_seq {
+            file.write_tag(tag_type::VIDEO, timestamp, video_seq.clone(), false)?;
+        }
+        if let Some(notifier) = &self.notifier {
+            let record_info = RecordInfo {
+                app_name: self.app_name.clone(),
+                stream_name: self.stream_name.clone(),
+                path: file.path().to_string(),
+                duration: 0.0,
+            };
+            notifier.on_record_notify(&record_info).await;
+        }
+        self.file = Some(file);
+
+        Ok(())
+    }
+
+    /*The path of a new file in the root folder, the sequence(if it is not 0) is added
+    to the file name if the file exists. The app and stream names must be file names,
+    and the path can not escape the root folder.*/
+    fn file_path(&self, time: DateTime<Utc>, sequence: u32) -> Result<PathBuf, RecordError> {
+        for name in [&self.app_name, &self.stream_name] {
+            if !is_valid_path_name(name) {
+                return Err(RecordError {
+                    value: RecordErrorValue::InvalidPath(name.clone()),
+                });
+            }
+        }
+
+        let mut file_name = self
+            .options
+            .file_name
+            .replace("{app}", &self.app_name)
+            .replace("{stream}", &self.stream_name)
+            .replace("{time}", &time.format("%Y%m%d%H%M%S").to_string());
+        if sequence > 0 {
+            let index = file_name
+                .rfind('.')
+                .filter(|index| !file_name[*index..].contains('/'))
+                .unwrap_or(file_name.len());
+            file_name.insert_str(index, &format!("_{sequence}"));
+        }
+
+        let root = Path::new(&self.options.root);
+        let path = root.join(&file_name);
+        if !path.starts_with(root)
+            || path
+                .components()
+                .any(|component| component == Component::ParentDir)
+        {
+            return Err(RecordError {
+                value: RecordErrorValue::InvalidPath(file_name),
+            });
+        }
+
+        Ok(path)
+    }
+
+    async fn finish_file(&mut self) -> Result<(), RecordError> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let (path, duration) = file.finish(self.metadata.as_ref())?;
+        log::info!(
+            "record {}/{} is finished: {}, duration: {}s",
+            self.app_name,
+            self.stream_name,
+            path,
+            duration
+        );
+
+        if let Some(notifier) = &self.notifier {
+            let record_info = RecordInfo {
+                app_name: self.app_name.clone(),
+                stream_name: self.stream_name.clone(),
+                path,
+                duration,
+            };
+            notifier.on_record_done_notify(&record_info).await;
+        }
+
+        Ok(())
+    }
+
+    fn get_subscriber_info(&self) -> SubscriberInfo {
+        SubscriberInfo {
+            id: self.subscriber_id,
+            sub_type: SubscribeType::RecordFlv,
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: String::from(""),
+            },
+            options: SubscribeOptions::default(),
+        }
+    }
+
+    pub async fn subscribe_from_rtmp_channels(&mut self) -> Result<(), RecordError> {
+        let (sender, receiver) = oneshot::channel();
+        let subscribe_event = ChannelEvent::Subscribe {
+            app_name: self.app_name.clone(),
+            stream_name: self.stream_name.clone(),
+            info: self.get_subscriber_info(),
+            responder: sender,
+        };
+
+        if self.event_producer.send(subscribe_event).is_err() {
+            return Err(RecordError {
+                value: RecordErrorValue::SessionError(SessionError {
+                    value: SessionErrorValue::SendChannelDataErr,
+                }),
+            });
+        }
+
+        //the responder is dropped if the stream is not published
+        match receiver.await {
+            Ok(consumer) => {
+                self.data_consumer = consumer;
+                Ok(())
+            }
+            Err(_) => Err(RecordError {
+                value: RecordErrorValue::StreamNotFound,
+            }),
+        }
+    }
+
+    pub fn unsubscribe_from_rtmp_channels(&mut self) {
+        let subscribe_event = ChannelEvent::UnSubscribe {
+            app_name: self.app_name.clone(),
+            stream_name: self.stream_name.clone(),
+            info: self.get_subscriber_info(),
+        };
+        if let Err(err) = self.event_producer.send(subscribe_event) {
+            log::error!("unsubscribe_from_channels err {}\n", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::FlvRecorder,
+        crate::{define::RecordOptions, errors::RecordErrorValue, flv_file::FlvFile},
+        chrono::{TimeZone, Utc},
+        std::{fs, path::Path},
+        tokio::sync::mpsc,
+    };
+
+    fn new_recorder(root: &str, app_name: &str, stream_name: &str) -> FlvRecorder {
+        let (event_producer, _) = mpsc::unbounded_channel();
+        FlvRecorder::new(
+            String::from(app_name),
+            String::from(stream_name),
+            event_producer,
+            RecordOptions {
+                root: String::from(root),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_file_path() {
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let recorder = new_recorder("/record", "live", "test");
+        assert_eq!(
+            recorder.file_path(time, 0).unwrap(),
+            Path::new("/record/live/test_20240101120000.flv")
+        );
+        assert_eq!(
+            recorder.file_path(time, 2).unwrap(),
+            Path::new("/record/live/test_20240101120000_2.flv")
+        );
+
+        for (app_name, stream_name) in [
+            ("..", "test"),
+            ("live", "../../etc/passwd"),
+            ("/etc", "test"),
+        ] {
+            match new_recorder("/record", app_name, stream_name).file_path(time, 0) {
+                Err(err) => assert!(matches!(err.value, RecordErrorValue::InvalidPath(_))),
+                Ok(path) => panic!("{:?} is expected to be rejected", path),
+            }
+        }
+    }
+
+    #[test]
+    fn test_file_is_not_overwritten() {
+        let folder = std::env::temp_dir().join(format!("flv_recorder_{}", std::process::id()));
+        let path = folder.join("test.flv").to_string_lossy().to_string();
+
+        let file = FlvFile::create(path.clone(), 0).unwrap();
+        //the temporary file of a recording in progress
+        assert!(FlvFile::create(path.clone(), 0).is_err());
+        file.finish(None).unwrap();
+        //the finished file
+        assert!(FlvFile::create(path, 0).is_err());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
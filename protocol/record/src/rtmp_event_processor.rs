@@ -0,0 +1,232 @@
+use {
+    super::{
+        define::{
+            RecordEvent, RecordEventConsumer, RecordEventProducer, RecordOptions, RecordStatus,
+        },
+        errors::{RecordError, RecordErrorValue},
+        recorder::FlvRecorder,
+    },
+    chrono::{DateTime, SecondsFormat, Utc},
+    rtmp::{
+        channels::define::{ChannelEventProducer, ClientEvent, ClientEventConsumer},
+        notify::Notifier,
+        utils::is_valid_path_name,
+    },
+    std::{collections::HashMap, sync::Arc},
+    tokio::sync::{mpsc, oneshot},
+};
+
+/*a stream being recorded, the recorder is stopped by the stop sender*/
+struct Recording {
+    app_name: String,
+    stream_name: String,
+    stop_sender: oneshot::Sender<()>,
+    start_time: DateTime<Utc>,
+}
+
+impl Recording {
+    //the recorder ends by itself when the stream is unpublished
+    fn is_running(&self) -> bool {
+        !self.stop_sender.is_closed()
+    }
+}
+
+/*Record the published streams of the apps whose recording is started automatically,
+and start or stop the recording of a stream by the events of the http api.*/
+pub struct RtmpEventProcessor {
+    client_event_consumer: ClientEventConsumer,
+    event_producer: ChannelEventProducer,
+    options: RecordOptions,
+    //override the record options of the apps
+    app_options: HashMap<String, RecordOptions>,
+    notifier: Option<Arc<Notifier>>,
+    record_event_producer: RecordEventProducer,
+    record_event_consumer: RecordEventConsumer,
+    //app_name/stream_name -> the recording
+    recordings: HashMap<String, Recording>,
+}
+
+impl RtmpEventProcessor {
+    pub fn new(consumer: ClientEventConsumer, event_producer: ChannelEventProducer) -> Self {
+        let (record_event_producer, record_event_consumer) = mpsc::unbounded_channel();
+
+        Self {
+            client_event_consumer: consumer,
+            event_producer,
+            options: RecordOptions::default(),
+            app_options: HashMap::new(),
+            notifier: None,
+            record_event_producer,
+            record_event_consumer,
+            recordings: HashMap::new(),
+        }
+    }
+
+    pub fn set_options(&mut self, options: RecordOptions) {
+        self.options = options;
+    }
+
+    pub fn set_app_options(&mut self, app_name: String, options: RecordOptions) {
+        self.app_options.insert(app_name, options);
+    }
+
+    pub fn set_notifier(&mut self, notifier: Notifier) {
+        self.notifier = Some(Arc::new(notifier));
+    }
+
+    pub fn get_record_event_producer(&self) -> RecordEventProducer {
+        self.record_event_producer.clone()
+    }
+
+    fn get_options(&self, app_name: &str) -> RecordOptions {
+        self.app_options
+            .get(app_name)
+            .unwrap_or(&self.options)
+            .clone()
+    }
+
+    fn start_recording(
+        &mut self,
+        app_name: String,
+        stream_name: String,
+        responder: Option<oneshot::Sender<Result<(), RecordError>>>,
+    ) {
+        //the names are parts of the file paths
+        if let Some(name) = [&app_name, &stream_name]
+            .iter()
+            .find(|name| !is_valid_path_name(name))
+        {
+            log::error!("record {}/{} error: invalid name", app_name, stream_name);
+            if let Some(responder) = responder {
+                let _ = responder.send(Err(RecordError {
+                    value: RecordErrorValue::InvalidPath(name.to_string()),
+                }));
+            }
+            return;
+        }
+
+        let key = format!("{app_name}/{stream_name}");
+        if self
+            .recordings
+            .get(&key)
+            .is_some_and(|recording| recording.is_running())
+        {
+            if let Some(responder) = responder {
+                let _ = responder.send(Err(RecordError {
+                    value: RecordErrorValue::AlreadyRecording,
+                }));
+            }
+            return;
+        }
+
+        let (stop_sender, stop_receiver) = oneshot::channel();
+        self.recordings.insert(
+            key,
+            Recording {
+                app_name: app_name.clone(),
+                stream_name: stream_name.clone(),
+                stop_sender,
+                start_time: Utc::now(),
+            },
+        );
+
+        let options = self.get_options(&app_name);
+        let mut recorder =
+            FlvRecorder::new(app_name, stream_name, self.event_producer.clone(), options);
+        if let Some(notifier) = &self.notifier {
+            recorder.set_notifier(notifier.clone());
+        }
+
+        tokio::spawn(async move {
+            let result = recorder.subscribe_from_rtmp_channels().await;
+            let is_subscribed = result.is_ok();
+            match responder {
+                Some(responder) => {
+                    let _ = responder.send(result);
+                }
+                None => {
+                    if let Err(err) = result {
+                        log::error!("record subscribe error {}", err);
+                    }
+                }
+            }
+
+            if is_subscribed {
+                if let Err(err) = recorder.record(stop_receiver).await {
+                    log::error!("record error {}", err);
+                }
+            }
+        });
+    }
+
+    fn process_record_event(&mut self, event: RecordEvent) {
+        match event {
+            RecordEvent::Start {
+                app_name,
+                stream_name,
+                responder,
+            } => {
+                self.start_recording(app_name, stream_name, Some(responder));
+            }
+            RecordEvent::Stop {
+                app_name,
+                stream_name,
+                responder,
+            } => {
+                let is_stopped = match self.recordings.remove(&format!("{app_name}/{stream_name}"))
+                {
+                    Some(recording) if recording.is_running() => {
+                        recording.stop_sender.send(()).is_ok()
+                    }
+                    _ => false,
+                };
+                let _ = responder.send(is_stopped);
+            }
+            RecordEvent::Status { responder } => {
+                self.recordings
+                    .retain(|_, recording| recording.is_running());
+                let status = self
+                    .recordings
+                    .values()
+                    .map(|recording| RecordStatus {
+                        app_name: recording.app_name.clone(),
+                        stream_name: recording.stream_name.clone(),
+                        start_time: recording
+                            .start_time
+                            .to_rfc3339_opts(SecondsFormat::Secs, true),
+                    })
+                    .collect();
+                let _ = responder.send(status);
+            }
+        }
+    }
+
+    fn process_client_event(&mut self, event: ClientEvent) {
+        match event {
+            ClientEvent::Publish {
+                app_name,
+                stream_name,
+            } => {
+                if self.get_options(&app_name).auto_start {
+                    self.start_recording(app_name, stream_name, None);
+                }
+            }
+            _ => {
+                log::trace!("other infos...");
+            }
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<(), RecordError> {
+        loop {
+            tokio::select! {
+                val = self.client_event_consumer.recv() => {
+                    self.process_client_event(val?);
+                }
+                Some(event) = self.record_event_consumer.recv() => {
+                    self.process_record_event(event);
+                }
+            }
+        }
+    }
+}
@@ -50,6 +50,7 @@ impl Amf0Reader {
             amf0_markers::NULL => self.read_null(),
             amf0_markers::ECMA_ARRAY => self.read_ecma_array(),
             amf0_markers::LONG_STRING => self.read_long_string(),
+            amf0_markers::STRICT_ARRAY => self.read_strict_array(),
             _ => Err(Amf0ReadError {
                 value: Amf0ReadErrorValue::UnknownMarker { marker: markers },
             }),
@@ -157,6 +158,17 @@ impl Amf0Reader {
         Ok(Amf0ValueType::LongUTF8String(val))
     }
 
+    pub fn read_strict_array(&mut self) -> Result<Amf0ValueType, Amf0ReadError> {
+        let len = self.reader.read_u32::<BigEndian>()?;
+
+        let mut values = Vec::new();
+        for _ in 0..len {
+            values.push(self.read_any()?);
+        }
+
+        Ok(Amf0ValueType::StrictArray(values))
+    }
+
     // pub fn get_remaining_bytes(&mut self) -> BytesMut {
     //     return self.reader.get_remaining_bytes();
     // }
@@ -28,6 +28,8 @@ impl Amf0Writer {
             Amf0ValueType::Number(ref val) => self.write_number(val),
             Amf0ValueType::UTF8String(ref val) => self.write_string(val),
             Amf0ValueType::Object(ref val) => self.write_object(val),
+            Amf0ValueType::EcmaArray(ref val) => self.write_ecma_array(val),
+            Amf0ValueType::StrictArray(ref val) => self.write_strict_array(val),
             _ => Ok(()),
         }
     }
@@ -85,6 +87,33 @@ impl Amf0Writer {
         Ok(())
     }
 
+    pub fn write_ecma_array(
+        &mut self,
+        properties: &IndexMap<String, Amf0ValueType>,
+    ) -> Result<(), Amf0WriteError> {
+        self.writer.write_u8(amf0_markers::ECMA_ARRAY)?;
+        self.writer.write_u32::<BigEndian>(properties.len() as u32)?;
+
+        for (key, value) in properties {
+            self.writer.write_u16::<BigEndian>(key.len() as u16)?;
+            self.writer.write(key.as_bytes())?;
+            self.write_any(value)?;
+        }
+
+        self.write_object_eof()?;
+        Ok(())
+    }
+
+    pub fn write_strict_array(&mut self, values: &[Amf0ValueType]) -> Result<(), Amf0WriteError> {
+        self.writer.write_u8(amf0_markers::STRICT_ARRAY)?;
+        self.writer.write_u32::<BigEndian>(values.len() as u32)?;
+
+        for value in values {
+            self.write_any(value)?;
+        }
+        Ok(())
+    }
+
     // pub async fn flush(&mut self) -> Result<(), Amf0WriteError> {
     //     self.writer.flush()?;
     // }
@@ -9,6 +9,7 @@ pub enum Amf0ValueType {
     Null,
     EcmaArray(IndexMap<String, Amf0ValueType>),
     LongUTF8String(String),
+    StrictArray(Vec<Amf0ValueType>),
     END,
 }
 
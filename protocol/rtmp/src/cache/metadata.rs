@@ -24,10 +24,13 @@ impl MetaData {
         }
     }
     //, values: Vec<Amf0ValueType>
-    pub fn save(&mut self, body: BytesMut) {
+    //returns false if the body is not the stream metadata, e.g. a cue point
+    pub fn save(&mut self, body: BytesMut) -> bool {
         if self.is_metadata(body.clone()) {
             self.chunk_body = body;
+            return true;
         }
+        false
     }
 
     //used for the http-flv protocol
@@ -49,9 +49,13 @@ impl Cache {
     }
 
     //, values: Vec<Amf0ValueType>
-    pub fn save_metadata(&mut self, chunk_body: BytesMut, timestamp: u32) {
-        self.metadata.save(chunk_body);
+    //returns false if it is another data message which is not cached
+    pub fn save_metadata(&mut self, chunk_body: BytesMut, timestamp: u32) -> bool {
+        if !self.metadata.save(chunk_body) {
+            return false;
+        }
         self.metadata_timestamp = timestamp;
+        true
     }
 
     pub fn get_metadata(&self) -> Option<ChannelData> {
@@ -121,12 +125,12 @@ impl Cache {
         }
         None
     }
-    //save video gops and sequence header information
+    //save video gops and sequence header information, return if it is a key frame
     pub async fn save_video_data(
         &mut self,
         chunk_body: BytesMut,
         timestamp: u32,
-    ) -> Result<(), CacheError> {
+    ) -> Result<bool, CacheError> {
         let mut parser = demuxer_tag::VideoTagHeaderDemuxer::new(chunk_body.clone());
         let tag = parser.parse_tag_header()?;
 
@@ -155,7 +159,7 @@ impl Cache {
             .notify_video_statistics_info(chunk_body.len(), is_key_frame)
             .await;
 
-        Ok(())
+        Ok(is_key_frame)
     }
 
     pub fn get_gops_data(&self) -> Option<VecDeque<Gop>> {
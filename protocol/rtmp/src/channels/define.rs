@@ -2,7 +2,7 @@ use {
     crate::session::common::{PublisherInfo, SubscriberInfo},
     crate::statistics::StreamStatistics,
     bytes::BytesMut,
-    serde::Serialize,
+    serde::{Deserialize, Serialize},
     std::fmt,
     tokio::sync::{broadcast, mpsc, oneshot},
     uuid::Uuid,
@@ -78,12 +78,35 @@ pub enum TransmitterEvent {
         info: SubscriberInfo,
     },
     UnPublish {},
+    /*the new publisher takes over the stream and the old one is kicked off*/
+    ReplacePublisher {
+        data_consumer: ChannelDataConsumer,
+    },
+    /*a hot-standby publisher waits to take over the stream*/
+    AddStandbyPublisher {
+        data_consumer: ChannelDataConsumer,
+    },
+    RemoveStandbyPublisher {},
+    /*the active publisher leaves and the hot-standby one takes over the stream*/
+    PromoteStandbyPublisher {},
 
     Api {
         sender: AvStatisticSender,
     },
 }
 
+/*What to do when a stream is published while it already has a publisher*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PublishConflictPolicy {
+    //reject the new publisher
+    Reject,
+    //kick off the old publisher, the new one takes over and the subscribers stay attached
+    Replace,
+    //keep the new publisher as a hot-standby backup, it takes over when the old one leaves
+    Queue,
+}
+
 impl fmt::Display for TransmitterEvent {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", *self)
@@ -124,5 +147,6 @@ pub enum PubSubInfo {
     Publish {
         app_name: String,
         stream_name: String,
+        pub_info: PublisherInfo,
     },
 }
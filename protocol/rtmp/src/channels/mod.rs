@@ -4,18 +4,27 @@ pub mod errors;
 
 use {
     crate::cache::Cache,
+    crate::cluster::{StreamLocation, StreamRegistry},
     crate::notify::Notifier,
-    crate::session::{common::SubscriberInfo, define::SubscribeType},
+    crate::session::{
+        common::{PublisherInfo, SubscribeOptions, SubscriberInfo},
+        define::{PublishType, SubscribeType},
+    },
     define::{
         AvStatisticSender, ChannelData, ChannelDataConsumer, ChannelDataProducer, ChannelEvent,
         ChannelEventConsumer, ChannelEventProducer, ClientEvent, ClientEventConsumer,
-        ClientEventProducer, PubSubInfo, StreamStatisticSizeSender, TransmitterEvent,
-        TransmitterEventConsumer, TransmitterEventProducer,
+        ClientEventProducer, PubSubInfo, PublishConflictPolicy, StreamStatisticSizeSender,
+        TransmitterEvent, TransmitterEventConsumer, TransmitterEventProducer,
     },
     errors::{ChannelError, ChannelErrorValue},
-    std::collections::HashMap,
+    std::{
+        collections::{HashMap, HashSet},
+        future,
+        sync::Arc,
+    },
     tokio::sync::{broadcast, mpsc, mpsc::UnboundedReceiver},
     uuid::Uuid,
+    xflv::{define as flv_define, demuxer_tag},
 };
 
 /************************************************************************************
@@ -40,6 +49,71 @@ use {
 *
 *************************************************************************************/
 
+/*The latest metadata and sequence headers of the hot-standby publisher, its other
+data is dropped. They are sent out first when it takes over the stream.*/
+#[derive(Default)]
+struct SequenceHeaders {
+    metadata: Option<ChannelData>,
+    audio_seq: Option<ChannelData>,
+    video_seq: Option<ChannelData>,
+}
+
+impl SequenceHeaders {
+    fn save(&mut self, channel_data: ChannelData) {
+        match &channel_data {
+            ChannelData::MetaData { .. } => {
+                self.metadata = Some(channel_data.clone());
+            }
+            ChannelData::Audio { data, .. } => {
+                let mut parser = demuxer_tag::AudioTagHeaderDemuxer::new(data.clone());
+                if let Ok(tag) = parser.parse_tag_header() {
+                    if tag.sound_format == flv_define::SoundFormat::AAC as u8
+                        && tag.aac_packet_type == flv_define::aac_packet_type::AAC_SEQHDR
+                    {
+                        self.audio_seq = Some(channel_data.clone());
+                    }
+                }
+            }
+            ChannelData::Video { data, .. } => {
+                let mut parser = demuxer_tag::VideoTagHeaderDemuxer::new(data.clone());
+                if let Ok(tag) = parser.parse_tag_header() {
+                    if tag.frame_type == flv_define::frame_type::KEY_FRAME
+                        && tag.avc_packet_type == flv_define::avc_packet_type::AVC_SEQHDR
+                    {
+                        self.video_seq = Some(channel_data.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    fn take_all(&mut self) -> Vec<ChannelData> {
+        let headers = std::mem::take(self);
+        vec![headers.metadata, headers.audio_seq, headers.video_seq]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+async fn recv_standby_data(
+    standby_data_consumer: &mut Option<ChannelDataConsumer>,
+) -> Option<ChannelData> {
+    match standby_data_consumer {
+        Some(data_consumer) => data_consumer.recv().await,
+        None => future::pending().await,
+    }
+}
+
+//the audio-only or video-only subscribers do not receive the other data
+fn is_subscribed(options: &SubscribeOptions, channel_data: &ChannelData) -> bool {
+    match channel_data {
+        ChannelData::Audio { .. } => options.has_audio,
+        ChannelData::Video { .. } => options.has_video,
+        ChannelData::MetaData { .. } => true,
+    }
+}
+
 //receive data from ChannelsManager and send to players/subscribers
 pub struct Transmitter {
     //used for receiving Audio/Video data
@@ -48,6 +122,15 @@ pub struct Transmitter {
     event_consumer: TransmitterEventConsumer,
     //used for sending audio/video data to players/subscribers
     subscriberid_to_producer: HashMap<Uuid, ChannelDataProducer>,
+    //the subscribers which only receive the audio or the video
+    subscriberid_to_options: HashMap<Uuid, SubscribeOptions>,
+    //the relay push subscribers which wait for the next key frame
+    key_frame_waiters: HashSet<Uuid>,
+    //the hls viewers are served by the hls server, they are only counted
+    hls_viewers: HashSet<Uuid>,
+    //used for receiving the data of the hot-standby publisher
+    standby_data_consumer: Option<ChannelDataConsumer>,
+    standby_headers: SequenceHeaders,
     //used for cache metadata and GOP
     cache: Cache,
 }
@@ -64,6 +147,11 @@ impl Transmitter {
             data_consumer,
             event_consumer,
             subscriberid_to_producer: HashMap::new(),
+            subscriberid_to_options: HashMap::new(),
+            key_frame_waiters: HashSet::new(),
+            hls_viewers: HashSet::new(),
+            standby_data_consumer: None,
+            standby_headers: SequenceHeaders::default(),
             cache: Cache::new(app_name, stream_name, gop_num),
         }
     }
@@ -74,35 +162,56 @@ impl Transmitter {
                 data = self.event_consumer.recv() =>{
                     if let Some(val) = data {
                         match val {
+                            TransmitterEvent::Subscribe { info, .. }
+                                if info.sub_type == SubscribeType::PlayerHls =>
+                            {
+                                self.hls_viewers.insert(info.id);
+                            }
                             TransmitterEvent::Subscribe {
                                 producer,
                                 info,
                             } => {
 
+                                let options = &info.options;
+
                                 if let Some(meta_body_data) = self.cache.get_metadata() {
                                     producer.send(meta_body_data).map_err(|_| ChannelError {
                                         value: ChannelErrorValue::SendError,
                                     })?;
                                 }
                                 if let Some(audio_seq_data) = self.cache.get_audio_seq() {
-                                    producer.send(audio_seq_data).map_err(|_| ChannelError {
-                                        value: ChannelErrorValue::SendError,
-                                    })?;
+                                    if options.has_audio {
+                                        producer.send(audio_seq_data).map_err(|_| ChannelError {
+                                            value: ChannelErrorValue::SendError,
+                                        })?;
+                                    }
                                 }
                                 if let Some(video_seq_data) = self.cache.get_video_seq() {
-                                    producer.send(video_seq_data).map_err(|_| ChannelError {
-                                        value: ChannelErrorValue::SendError,
-                                    })?;
+                                    if options.has_video {
+                                        producer.send(video_seq_data).map_err(|_| ChannelError {
+                                            value: ChannelErrorValue::SendError,
+                                        })?;
+                                    }
                                 }
 
                                 match info.sub_type {
                                     SubscribeType::PlayerRtmp
                                     | SubscribeType::PlayerHttpFlv
                                     | SubscribeType::PlayerHls
-                                    | SubscribeType::GenerateHls => {
-                                        if let Some(gops_data) = self.cache.get_gops_data() {
+                                    | SubscribeType::GenerateHls
+                                    | SubscribeType::GenerateDash
+                                    | SubscribeType::RecordFlv => {
+                                        if let Some(mut gops_data) = self.cache.get_gops_data() {
+                                            //only the latest gops are sent if the number is limited
+                                            if let Some(gop_num) = options.gop_num {
+                                                let skip_num = gops_data.len().saturating_sub(gop_num);
+                                                gops_data.drain(..skip_num);
+                                            }
                                             for gop in gops_data {
                                                 for channel_data in gop.get_frame_data() {
+                                                    if !is_subscribed(options, &channel_data) {
+                                                        continue;
+                                                    }
                                                     producer.send(channel_data).map_err(|_| ChannelError {
                                                         value: ChannelErrorValue::SendError,
                                                     })?;
@@ -110,7 +219,14 @@ impl Transmitter {
                                             }
                                         }
                                     }
-                                    SubscribeType::PublisherRtmp => {}
+                                    /*The relay push session may be a reconnection, it starts
+                                    with the sequence headers and resumes from the next key frame.*/
+                                    SubscribeType::PublisherRtmp => {
+                                        self.key_frame_waiters.insert(info.id);
+                                    }
+                                }
+                                if !options.has_audio || !options.has_video {
+                                    self.subscriberid_to_options.insert(info.id, info.options);
                                 }
                                 self.subscriberid_to_producer
                                     .insert(info.id, producer);
@@ -118,12 +234,36 @@ impl Transmitter {
                             TransmitterEvent::UnSubscribe { info } => {
                                 self.subscriberid_to_producer
                                     .remove(&info.id);
+                                self.subscriberid_to_options.remove(&info.id);
+                                self.key_frame_waiters.remove(&info.id);
+                                self.hls_viewers.remove(&info.id);
                             }
                             TransmitterEvent::UnPublish {} => {
                                 return Ok(());
                             }
+                            TransmitterEvent::ReplacePublisher { data_consumer } => {
+                                //the old publisher fails to send data and ends
+                                self.data_consumer = data_consumer;
+                            }
+                            TransmitterEvent::AddStandbyPublisher { data_consumer } => {
+                                self.standby_data_consumer = Some(data_consumer);
+                                self.standby_headers = SequenceHeaders::default();
+                            }
+                            TransmitterEvent::RemoveStandbyPublisher {} => {
+                                self.standby_data_consumer = None;
+                                self.standby_headers = SequenceHeaders::default();
+                            }
+                            TransmitterEvent::PromoteStandbyPublisher {} => {
+                                if let Some(data_consumer) = self.standby_data_consumer.take() {
+                                    self.data_consumer = data_consumer;
+                                    for channel_data in self.standby_headers.take_all() {
+                                        self.on_channel_data(channel_data).await?;
+                                    }
+                                }
+                            }
                             TransmitterEvent::Api { sender } => {
-                                let avstatistic_data = self.cache.av_statistics.get_avstatistic_data().await;
+                                let mut avstatistic_data = self.cache.av_statistics.get_avstatistic_data().await;
+                                avstatistic_data.hls_viewer_count = self.hls_viewers.len();
                                 if let Err(err) = sender.send(avstatistic_data){
                                     log::info!("Transmitter send avstatistic data err: {}",err);
                                 }
@@ -134,50 +274,105 @@ impl Transmitter {
 
                 data = self.data_consumer.recv() =>{
                     if let Some(val) = data {
-                        match val {
-                            ChannelData::MetaData { timestamp, data } => {
-                                self.cache.save_metadata(data, timestamp);
-                            }
-                            ChannelData::Audio { timestamp, data } => {
-                                self.cache.save_audio_data(data.clone(), timestamp).await?;
-
-                                let data = ChannelData::Audio {
-                                    timestamp,
-                                    data: data.clone(),
-                                };
-
-                                for (_, v) in self.subscriberid_to_producer.iter() {
-                                    if let Err(audio_err) = v.send(data.clone()).map_err(|_| ChannelError {
-                                        value: ChannelErrorValue::SendAudioError,
-                                    }) {
-                                        log::error!("Transmiter send error: {}", audio_err);
-                                    }
-                                }
-                            }
-                            ChannelData::Video { timestamp, data } => {
-                                self.cache.save_video_data(data.clone(), timestamp).await?;
-
-                                let data = ChannelData::Video {
-                                    timestamp,
-                                    data: data.clone(),
-                                };
-                                for (_, v) in self.subscriberid_to_producer.iter() {
-                                    if let Err(video_err) = v.send(data.clone()).map_err(|_| ChannelError {
-                                        value: ChannelErrorValue::SendVideoError,
-                                    }) {
-                                        log::error!("Transmiter send error: {}", video_err);
-                                    }
-                                }
-                            }
-                        }
+                        self.on_channel_data(val).await?;
                     }
                 }
 
+                data = recv_standby_data(&mut self.standby_data_consumer) =>{
+                    match data {
+                        Some(val) => self.standby_headers.save(val),
+                        None => {
+                            self.standby_data_consumer = None;
+                            self.standby_headers = SequenceHeaders::default();
+                        }
+                    }
+                }
             }
         }
 
         //Ok(())
     }
+
+    fn is_subscribed(&self, subscriber_id: &Uuid, channel_data: &ChannelData) -> bool {
+        match self.subscriberid_to_options.get(subscriber_id) {
+            Some(options) => is_subscribed(options, channel_data),
+            None => true,
+        }
+    }
+
+    async fn on_channel_data(&mut self, val: ChannelData) -> Result<(), ChannelError> {
+        match val {
+            ChannelData::MetaData { timestamp, data } => {
+                if self.cache.save_metadata(data.clone(), timestamp) {
+                    return Ok(());
+                }
+
+                //the other data messages(e.g. onCuePoint and onTextData) are sent as they are
+                let data = ChannelData::MetaData { timestamp, data };
+                for (k, v) in self.subscriberid_to_producer.iter() {
+                    if self.key_frame_waiters.contains(k) {
+                        continue;
+                    }
+                    if let Err(err) = v.send(data.clone()) {
+                        log::error!("Transmiter send metadata error: {}", err);
+                    }
+                }
+            }
+            ChannelData::Audio { timestamp, data } => {
+                self.cache.save_audio_data(data.clone(), timestamp).await?;
+
+                let data = ChannelData::Audio {
+                    timestamp,
+                    data: data.clone(),
+                };
+
+                for (k, v) in self.subscriberid_to_producer.iter() {
+                    if self.key_frame_waiters.contains(k) || !self.is_subscribed(k, &data) {
+                        continue;
+                    }
+                    if let Err(audio_err) = v.send(data.clone()).map_err(|_| ChannelError {
+                        value: ChannelErrorValue::SendAudioError,
+                    }) {
+                        log::error!("Transmiter send error: {}", audio_err);
+                    }
+                }
+            }
+            ChannelData::Video { timestamp, data } => {
+                let is_key_frame = self.cache.save_video_data(data.clone(), timestamp).await?;
+                if is_key_frame {
+                    self.key_frame_waiters.clear();
+                }
+
+                let data = ChannelData::Video {
+                    timestamp,
+                    data: data.clone(),
+                };
+                for (k, v) in self.subscriberid_to_producer.iter() {
+                    if self.key_frame_waiters.contains(k) || !self.is_subscribed(k, &data) {
+                        continue;
+                    }
+                    if let Err(video_err) = v.send(data.clone()).map_err(|_| ChannelError {
+                        value: ChannelErrorValue::SendVideoError,
+                    }) {
+                        log::error!("Transmiter send error: {}", video_err);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+//the active and the hot-standby publishers of a stream
+struct StreamPublishers {
+    active: Uuid,
+    standby: Option<Uuid>,
+}
+
+struct StreamRegistryUpdate {
+    app_name: String,
+    stream_name: String,
+    is_register: bool,
 }
 
 pub struct ChannelsManager {
@@ -200,8 +395,18 @@ pub struct ChannelsManager {
     rtmp_pull_enabled: bool,
     //enable hls
     hls_enabled: bool,
+    dash_enabled: bool,
+    //record the streams to local flv files
+    record_enabled: bool,
     //http notifier on sub/pub event
     notifier: Option<Notifier>,
+    //used to register the streams published to this node in cluster mode
+    stream_registry_sender: Option<mpsc::UnboundedSender<StreamRegistryUpdate>>,
+    //what to do when an existing stream is published again
+    publish_conflict_policy: PublishConflictPolicy,
+    app_publish_conflict_policies: HashMap<String, PublishConflictPolicy>,
+    //app_name/stream_name -> publishers
+    stream_publishers: HashMap<String, StreamPublishers>,
 }
 
 impl ChannelsManager {
@@ -219,7 +424,13 @@ impl ChannelsManager {
             rtmp_pull_enabled: false,
             rtmp_gop_num: 1,
             hls_enabled: false,
+            dash_enabled: false,
+            record_enabled: false,
             notifier,
+            stream_registry_sender: None,
+            publish_conflict_policy: PublishConflictPolicy::Reject,
+            app_publish_conflict_policies: HashMap::new(),
+            stream_publishers: HashMap::new(),
         }
     }
     pub async fn run(&mut self) {
@@ -242,6 +453,53 @@ impl ChannelsManager {
         self.hls_enabled = enabled;
     }
 
+    pub fn set_dash_enabled(&mut self, enabled: bool) {
+        self.dash_enabled = enabled;
+    }
+
+    pub fn set_record_enabled(&mut self, enabled: bool) {
+        self.record_enabled = enabled;
+    }
+
+    pub fn set_publish_conflict_policy(&mut self, policy: PublishConflictPolicy) {
+        self.publish_conflict_policy = policy;
+    }
+
+    //override the publish conflict policy of an app
+    pub fn set_app_publish_conflict_policy(
+        &mut self,
+        app_name: String,
+        policy: PublishConflictPolicy,
+    ) {
+        self.app_publish_conflict_policies.insert(app_name, policy);
+    }
+
+    /*Enable the cluster mode, node_address is the rtmp address other nodes pull
+    the streams of this node from. The registry is updated in order by a background task.*/
+    pub fn set_stream_registry(&mut self, registry: Arc<dyn StreamRegistry>, node_address: String) {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<StreamRegistryUpdate>();
+
+        tokio::spawn(async move {
+            while let Some(update) = receiver.recv().await {
+                let location = StreamLocation {
+                    app_name: update.app_name,
+                    stream_name: update.stream_name,
+                    address: node_address.clone(),
+                };
+                let rv = if update.is_register {
+                    registry.register(location).await
+                } else {
+                    registry.unregister(location).await
+                };
+                if let Err(err) = rv {
+                    log::error!("update stream registry err: {}", err);
+                }
+            }
+        });
+
+        self.stream_registry_sender = Some(sender);
+    }
+
     pub fn get_channel_event_producer(&mut self) -> ChannelEventProducer {
         self.channel_event_producer.clone()
     }
@@ -266,7 +524,7 @@ impl ChannelsManager {
                     responder,
                     info,
                 } => {
-                    let rv = self.publish(&app_name, &stream_name);
+                    let rv = self.publish(&app_name, &stream_name, &info);
                     match rv {
                         Ok(producer) => {
                             if responder.send(producer).is_err() {
@@ -280,6 +538,7 @@ impl ChannelsManager {
                                 PubSubInfo::Publish {
                                     app_name,
                                     stream_name,
+                                    pub_info: info,
                                 },
                             );
                         }
@@ -293,15 +552,28 @@ impl ChannelsManager {
                 ChannelEvent::UnPublish {
                     app_name,
                     stream_name,
-                    info: _,
+                    info,
                 } => {
-                    if let Err(err) = self.unpublish(&app_name, &stream_name) {
-                        log::error!(
-                            "event_loop Unpublish err: {} with app name: {} stream name :{}\n",
-                            err,
-                            app_name,
-                            stream_name
-                        );
+                    //ignore the publishers which are replaced or never published
+                    let pub_info = match self.channels_info.get(&info.id) {
+                        Some(PubSubInfo::Publish { pub_info, .. }) => pub_info.clone(),
+                        _ => continue,
+                    };
+                    self.channels_info.remove(&info.id);
+
+                    match self.remove_publisher(&app_name, &stream_name, &pub_info) {
+                        Ok(true) => {
+                            self.update_stream_registry(&app_name, &stream_name, &pub_info, false)
+                        }
+                        Ok(false) => {}
+                        Err(err) => {
+                            log::error!(
+                                "event_loop Unpublish err: {} with app name: {} stream name :{}\n",
+                                err,
+                                app_name,
+                                stream_name
+                            );
+                        }
                     }
 
                     if let Some(notifier) = &self.notifier {
@@ -329,11 +601,12 @@ impl ChannelsManager {
                             self.channels_info.insert(
                                 sub_id,
                                 PubSubInfo::Subscribe {
-                                    app_name,
-                                    stream_name,
+                                    app_name: app_name.clone(),
+                                    stream_name: stream_name.clone(),
                                     sub_info: info,
                                 },
                             );
+                            self.notify_pull_client(&app_name, &stream_name, true);
                         }
                         Err(err) => {
                             log::error!("event_loop Subscribe error: {}", err);
@@ -346,11 +619,13 @@ impl ChannelsManager {
                     stream_name,
                     info,
                 } => {
+                    self.channels_info.remove(&info.id);
                     if self.unsubscribe(&app_name, &stream_name, info).is_ok() {
                         if let Some(notifier) = &self.notifier {
                             notifier.on_stop_notify(event_serialize_str).await;
                         }
                     }
+                    self.notify_pull_client(&app_name, &stream_name, false);
                 }
 
                 ChannelEvent::ApiStatistic {
@@ -403,8 +678,8 @@ impl ChannelsManager {
     }
 
     fn api_kick_off_client(&mut self, uid: Uuid) {
-        let info = if let Some(info) = self.channels_info.get(&uid) {
-            info.clone()
+        let info = if let Some(info) = self.channels_info.remove(&uid) {
+            info
         } else {
             return;
         };
@@ -413,8 +688,11 @@ impl ChannelsManager {
             PubSubInfo::Publish {
                 app_name,
                 stream_name,
-            } => {
-                if let Err(err) = self.unpublish(&app_name, &stream_name) {
+                pub_info,
+            } => match self.remove_publisher(&app_name, &stream_name, &pub_info) {
+                Ok(true) => self.update_stream_registry(&app_name, &stream_name, &pub_info, false),
+                Ok(false) => {}
+                Err(err) => {
                     log::error!(
                         "event_loop ApiKickClient pub err: {} with app name: {} stream name :{}\n",
                         err,
@@ -422,7 +700,7 @@ impl ChannelsManager {
                         stream_name
                     );
                 }
-            }
+            },
             PubSubInfo::Subscribe {
                 app_name,
                 stream_name,
@@ -436,10 +714,81 @@ impl ChannelsManager {
                         stream_name
                     );
                 }
+                self.notify_pull_client(&app_name, &stream_name, false);
             }
         }
     }
 
+    //only the streams pushed to this node(by rtmp or http) are registered, not the pulled ones
+    fn update_stream_registry(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+        pub_info: &PublisherInfo,
+        is_register: bool,
+    ) {
+        if pub_info.sub_type == PublishType::SubscriberRtmp {
+            return;
+        }
+
+        if let Some(sender) = &self.stream_registry_sender {
+            let update = StreamRegistryUpdate {
+                app_name: app_name.to_string(),
+                stream_name: stream_name.to_string(),
+                is_register,
+            };
+            if sender.send(update).is_err() {
+                log::error!("send stream registry update err");
+            }
+        }
+    }
+
+    fn get_subscriber_count(&self, app_name: &String, stream_name: &String) -> usize {
+        self.channels_info
+            .values()
+            .filter(|info| {
+                matches!(info, PubSubInfo::Subscribe { app_name: app, stream_name: stream, .. }
+                    if app == app_name && stream == stream_name)
+            })
+            .count()
+    }
+
+    //the stream is pulled from other rtmp server by the local pull client
+    fn is_pulled_stream(&self, app_name: &String, stream_name: &String) -> bool {
+        self.channels_info.values().any(|info| {
+            matches!(info, PubSubInfo::Publish { app_name: app, stream_name: stream, pub_info }
+                if app == app_name && stream == stream_name
+                    && pub_info.sub_type == PublishType::SubscriberRtmp)
+        })
+    }
+
+    /*Tell the pull clients that a pulled stream gets its first subscriber or loses
+    its last one, then the idle pulled streams can be torn down.*/
+    fn notify_pull_client(&mut self, app_name: &String, stream_name: &String, is_subscribe: bool) {
+        if !self.rtmp_pull_enabled || !self.is_pulled_stream(app_name, stream_name) {
+            return;
+        }
+
+        let subscriber_count = self.get_subscriber_count(app_name, stream_name);
+        let client_event = if is_subscribe && subscriber_count == 1 {
+            ClientEvent::Subscribe {
+                app_name: app_name.clone(),
+                stream_name: stream_name.clone(),
+            }
+        } else if !is_subscribe && subscriber_count == 0 {
+            ClientEvent::UnSubscribe {
+                app_name: app_name.clone(),
+                stream_name: stream_name.clone(),
+            }
+        } else {
+            return;
+        };
+
+        if let Err(err) = self.client_event_producer.send(client_event) {
+            log::error!("notify pull client err: {}", err);
+        }
+    }
+
     //player subscribe a stream
     pub async fn subscribe(
         &mut self,
@@ -518,24 +867,138 @@ impl ChannelsManager {
         Ok(())
     }
 
-    //publish a stream
-    pub fn publish(
+    fn get_publish_conflict_policy(&self, app_name: &String) -> PublishConflictPolicy {
+        if let Some(policy) = self.app_publish_conflict_policies.get(app_name) {
+            *policy
+        } else {
+            self.publish_conflict_policy
+        }
+    }
+
+    /*Publish to a stream which already has a publisher according to the conflict policy*/
+    fn publish_to_existing_stream(
         &mut self,
         app_name: &String,
         stream_name: &String,
+        pub_id: Uuid,
     ) -> Result<ChannelDataProducer, ChannelError> {
-        match self.channels.get_mut(app_name) {
-            Some(val) => {
-                if val.get(stream_name).is_some() {
+        let policy = self.get_publish_conflict_policy(app_name);
+        let key = format!("{}/{}", app_name, stream_name);
+
+        let (publishers, event_producer) = match (
+            self.stream_publishers.get_mut(&key),
+            self.channels
+                .get(app_name)
+                .and_then(|val| val.get(stream_name)),
+        ) {
+            (Some(publishers), Some(event_producer)) if policy != PublishConflictPolicy::Reject => {
+                (publishers, event_producer)
+            }
+            _ => {
+                return Err(ChannelError {
+                    value: ChannelErrorValue::Exists,
+                })
+            }
+        };
+
+        let (data_publisher, data_consumer) = mpsc::unbounded_channel();
+        match policy {
+            PublishConflictPolicy::Replace => {
+                log::info!(
+                    "replace the publisher, app_name: {}, stream_name: {}",
+                    app_name,
+                    stream_name
+                );
+                event_producer
+                    .send(TransmitterEvent::ReplacePublisher { data_consumer })
+                    .map_err(|_| ChannelError {
+                        value: ChannelErrorValue::SendError,
+                    })?;
+                let old_id = std::mem::replace(&mut publishers.active, pub_id);
+                self.channels_info.remove(&old_id);
+            }
+            PublishConflictPolicy::Queue => {
+                if publishers.standby.is_some() {
                     return Err(ChannelError {
                         value: ChannelErrorValue::Exists,
                     });
                 }
+                log::info!(
+                    "add a standby publisher, app_name: {}, stream_name: {}",
+                    app_name,
+                    stream_name
+                );
+                event_producer
+                    .send(TransmitterEvent::AddStandbyPublisher { data_consumer })
+                    .map_err(|_| ChannelError {
+                        value: ChannelErrorValue::SendError,
+                    })?;
+                publishers.standby = Some(pub_id);
             }
-            None => {
-                let stream_map = HashMap::new();
-                self.channels.insert(app_name.clone(), stream_map);
+            PublishConflictPolicy::Reject => {}
+        }
+
+        Ok(data_publisher)
+    }
+
+    /*Remove a publisher of a stream, the standby publisher takes over the stream if the
+    active one leaves. Return true if the stream is unpublished.*/
+    fn remove_publisher(
+        &mut self,
+        app_name: &String,
+        stream_name: &String,
+        pub_info: &PublisherInfo,
+    ) -> Result<bool, ChannelError> {
+        let key = format!("{}/{}", app_name, stream_name);
+
+        if let Some(publishers) = self.stream_publishers.get_mut(&key) {
+            let event = if publishers.standby == Some(pub_info.id) {
+                publishers.standby = None;
+                TransmitterEvent::RemoveStandbyPublisher {}
+            } else if let Some(standby) = publishers.standby.take() {
+                log::info!(
+                    "the standby publisher takes over, app_name: {}, stream_name: {}",
+                    app_name,
+                    stream_name
+                );
+                publishers.active = standby;
+                TransmitterEvent::PromoteStandbyPublisher {}
+            } else {
+                self.stream_publishers.remove(&key);
+                self.unpublish(app_name, stream_name)?;
+                return Ok(true);
+            };
+
+            if let Some(event_producer) = self
+                .channels
+                .get(app_name)
+                .and_then(|val| val.get(stream_name))
+            {
+                event_producer.send(event).map_err(|_| ChannelError {
+                    value: ChannelErrorValue::SendError,
+                })?;
+            }
+            return Ok(false);
+        }
+
+        self.unpublish(app_name, stream_name)?;
+        Ok(true)
+    }
+
+    //publish a stream
+    pub fn publish(
+        &mut self,
+        app_name: &String,
+        stream_name: &String,
+        pub_info: &PublisherInfo,
+    ) -> Result<ChannelDataProducer, ChannelError> {
+        if let Some(val) = self.channels.get(app_name) {
+            if val.contains_key(stream_name) {
+                return self.publish_to_existing_stream(app_name, stream_name, pub_info.id);
             }
+        } else {
+            let stream_map = HashMap::new();
+            self.channels.insert(app_name.clone(), stream_map);
         }
 
         if let Some(stream_map) = self.channels.get_mut(app_name) {
@@ -571,8 +1034,20 @@ impl ChannelsManager {
             });
 
             stream_map.insert(stream_name.clone(), event_publisher);
+            self.stream_publishers.insert(
+                format!("{}/{}", app_name, stream_name),
+                StreamPublishers {
+                    active: pub_info.id,
+                    standby: None,
+                },
+            );
+            self.update_stream_registry(app_name, stream_name, pub_info, true);
 
-            if self.rtmp_push_enabled || self.hls_enabled {
+            if self.rtmp_push_enabled
+                || self.hls_enabled
+                || self.dash_enabled
+                || self.record_enabled
+            {
                 let client_event = ClientEvent::Publish {
                     app_name: app_name.clone(),
                     stream_name: stream_name.clone(),
@@ -608,6 +1083,19 @@ impl ChannelsManager {
                         app_name,
                         stream_name
                     );
+
+                    if self.rtmp_push_enabled || self.hls_enabled {
+                        let client_event = ClientEvent::UnPublish {
+                            app_name: app_name.clone(),
+                            stream_name: stream_name.clone(),
+                        };
+
+                        //send unpublish info to push clients to stop the relay pushes,
+                        //and to hls to forget the on demand streams
+                        if let Err(err) = self.client_event_producer.send(client_event) {
+                            log::error!("unpublish send client event err: {}", err);
+                        }
+                    }
                 }
                 None => {
                     return Err(ChannelError {
@@ -629,9 +1117,284 @@ impl ChannelsManager {
 #[cfg(test)]
 mod tests {
 
-    use std::cell::RefCell;
+    use {
+        super::{
+            define::{ChannelData, ChannelDataConsumer, PublishConflictPolicy},
+            errors::ChannelErrorValue,
+            ChannelsManager,
+        },
+        crate::session::{
+            common::{NotifyInfo, PublisherInfo, SubscribeOptions, SubscriberInfo},
+            define::{PublishType, SubscribeType},
+        },
+        bytes::BytesMut,
+        std::{cell::RefCell, sync::Arc, time::Duration},
+        uuid::Uuid,
+    };
+
+    fn publisher_info() -> PublisherInfo {
+        PublisherInfo {
+            id: Uuid::new_v4(),
+            sub_type: PublishType::PushRtmp,
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: String::from(""),
+            },
+        }
+    }
+
+    fn subscriber_info() -> SubscriberInfo {
+        SubscriberInfo {
+            id: Uuid::new_v4(),
+            sub_type: SubscribeType::PlayerRtmp,
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: String::from(""),
+            },
+            options: SubscribeOptions::default(),
+        }
+    }
+
+    //the AAC sequence header(AAC LC, 44100Hz, stereo)
+    fn audio_seq() -> ChannelData {
+        ChannelData::Audio {
+            timestamp: 0,
+            data: BytesMut::from(&b"\xaf\x00\x12\x10"[..]),
+        }
+    }
+
+    fn video_frame(timestamp: u32) -> ChannelData {
+        ChannelData::Video {
+            timestamp,
+            data: BytesMut::from(&b"\x17\x01\x00\x00\x00\x00\x00\x00\x01\x65"[..]),
+        }
+    }
+
+    //let the transmitters handle the events and data which are sent
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    //the timestamps of the received data, the sequence headers are at 0
+    fn received(consumer: &mut ChannelDataConsumer) -> Vec<(&'static str, u32)> {
+        let mut data = Vec::new();
+        while let Ok(channel_data) = consumer.try_recv() {
+            data.push(match channel_data {
+                ChannelData::Audio { timestamp, .. } => ("audio", timestamp),
+                ChannelData::Video { timestamp, .. } => ("video", timestamp),
+                ChannelData::MetaData { timestamp, .. } => ("metadata", timestamp),
+            });
+        }
+        data
+    }
+
+    fn is_exists_error<T>(rv: Result<T, super::errors::ChannelError>) -> bool {
+        matches!(rv, Err(err) if matches!(err.value, ChannelErrorValue::Exists))
+    }
+
+    #[tokio::test]
+    async fn test_publish_conflict_reject() {
+        let mut manager = ChannelsManager::new(None);
+        let (app_name, stream_name) = (String::from("live"), String::from("test"));
+
+        let producer = manager
+            .publish(&app_name, &stream_name, &publisher_info())
+            .unwrap();
+        assert!(is_exists_error(manager.publish(
+            &app_name,
+            &stream_name,
+            &publisher_info()
+        )));
+
+        //the first publisher is not affected
+        let mut consumer = manager
+            .subscribe(&app_name, &stream_name, subscriber_info())
+            .await
+            .unwrap();
+        settle().await;
+        assert!(producer.send(video_frame(40)).is_ok());
+        settle().await;
+        assert_eq!(received(&mut consumer), vec![("video", 40)]);
+    }
+
+    #[tokio::test]
+    async fn test_publish_conflict_replace() {
+        let mut manager = ChannelsManager::new(None);
+        manager.set_publish_conflict_policy(PublishConflictPolicy::Replace);
+        let (app_name, stream_name) = (String::from("live"), String::from("test"));
+
+        let old_producer = manager
+            .publish(&app_name, &stream_name, &publisher_info())
+            .unwrap();
+        assert!(old_producer.send(audio_seq()).is_ok());
+        settle().await;
+        let mut consumer = manager
+            .subscribe(&app_name, &stream_name, subscriber_info())
+            .await
+            .unwrap();
+        settle().await;
+        //the cached sequence header, it is also replayed in the gop cache
+        assert_eq!(received(&mut consumer).first(), Some(&("audio", 0)));
+
+        let new_info = publisher_info();
+        let new_producer = manager.publish(&app_name, &stream_name, &new_info).unwrap();
+        settle().await;
+        assert_eq!(manager.stream_publishers["live/test"].active, new_info.id);
+
+        //the subscriber stays attached and receives the data of the new publisher
+        assert!(new_producer.send(video_frame(40)).is_ok());
+        settle().await;
+        assert_eq!(received(&mut consumer), vec![("video", 40)]);
+        //the old publisher is kicked off
+        assert!(old_producer.send(video_frame(80)).is_err());
+
+        //a new subscriber still gets the cached sequence headers
+        let mut new_consumer = manager
+            .subscribe(&app_name, &stream_name, subscriber_info())
+            .await
+            .unwrap();
+        settle().await;
+        let data = received(&mut new_consumer);
+        assert_eq!(data.first(), Some(&("audio", 0)));
+        assert_eq!(data.last(), Some(&("video", 40)));
+    }
+
+    #[tokio::test]
+    async fn test_publish_conflict_queue() {
+        let mut manager = ChannelsManager::new(None);
+        manager.set_publish_conflict_policy(PublishConflictPolicy::Queue);
+        let (app_name, stream_name) = (String::from("live"), String::from("test"));
+
+        let active_info = publisher_info();
+        let active_producer = manager
+            .publish(&app_name, &stream_name, &active_info)
+            .unwrap();
+        let mut consumer = manager
+            .subscribe(&app_name, &stream_name, subscriber_info())
+            .await
+            .unwrap();
+
+        let standby_info = publisher_info();
+        let standby_producer = manager
+            .publish(&app_name, &stream_name, &standby_info)
+            .unwrap();
+        //only one standby publisher is kept
+        assert!(is_exists_error(manager.publish(
+            &app_name,
+            &stream_name,
+            &publisher_info()
+        )));
+        settle().await;
+
+        //the data of the standby publisher is not sent until it takes over
+        assert!(standby_producer.send(audio_seq()).is_ok());
+        assert!(standby_producer.send(video_frame(1000)).is_ok());
+        assert!(active_producer.send(video_frame(40)).is_ok());
+        settle().await;
+        assert_eq!(received(&mut consumer), vec![("video", 40)]);
+
+        //the active publisher leaves, the standby one is promoted without unpublishing
+        assert!(!manager
+            .remove_publisher(&app_name, &stream_name, &active_info)
+            .unwrap());
+        settle().await;
+        assert_eq!(
+            manager.stream_publishers["live/test"].active,
+            standby_info.id
+        );
+        //its sequence headers are replayed first
+        assert_eq!(received(&mut consumer), vec![("audio", 0)]);
+        assert!(standby_producer.send(video_frame(1040)).is_ok());
+        settle().await;
+        assert_eq!(received(&mut consumer), vec![("video", 1040)]);
+
+        //the stream is unpublished when the last publisher leaves
+        assert!(manager
+            .remove_publisher(&app_name, &stream_name, &standby_info)
+            .unwrap());
+        assert!(!manager.channels["live"].contains_key("test"));
+    }
+
+    #[tokio::test]
+    async fn test_remove_standby_publisher() {
+        let mut manager = ChannelsManager::new(None);
+        manager.set_publish_conflict_policy(PublishConflictPolicy::Queue);
+        let (app_name, stream_name) = (String::from("live"), String::from("test"));
+
+        let active_info = publisher_info();
+        let active_producer = manager
+            .publish(&app_name, &stream_name, &active_info)
+            .unwrap();
+        let mut consumer = manager
+            .subscribe(&app_name, &stream_name, subscriber_info())
+            .await
+            .unwrap();
+        let standby_info = publisher_info();
+        let _standby_producer = manager
+            .publish(&app_name, &stream_name, &standby_info)
+            .unwrap();
+        settle().await;
+
+        //the standby publisher leaves before it takes over, the stream is kept
+        assert!(!manager
+            .remove_publisher(&app_name, &stream_name, &standby_info)
+            .unwrap());
+        assert!(manager.channels["live"].contains_key("test"));
+        assert_eq!(
+            manager.stream_publishers["live/test"].active,
+            active_info.id
+        );
+        assert_eq!(manager.stream_publishers["live/test"].standby, None);
+
+        assert!(active_producer.send(video_frame(40)).is_ok());
+        settle().await;
+        assert_eq!(received(&mut consumer), vec![("video", 40)]);
+
+        //another standby publisher can be queued again
+        assert!(manager
+            .publish(&app_name, &stream_name, &publisher_info())
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_app_publish_conflict_policy() {
+        let mut manager = ChannelsManager::new(None);
+        manager.set_publish_conflict_policy(PublishConflictPolicy::Replace);
+        manager
+            .set_app_publish_conflict_policy(String::from("strict"), PublishConflictPolicy::Reject);
+        manager
+            .set_app_publish_conflict_policy(String::from("backup"), PublishConflictPolicy::Queue);
+        let stream_name = String::from("test");
+
+        for app_name in ["live", "strict", "backup"] {
+            let app_name = String::from(app_name);
+            manager
+                .publish(&app_name, &stream_name, &publisher_info())
+                .unwrap();
+        }
+
+        //the global policy
+        let live = String::from("live");
+        let info = publisher_info();
+        manager.publish(&live, &stream_name, &info).unwrap();
+        assert_eq!(manager.stream_publishers["live/test"].active, info.id);
+
+        //the app policies win over the global one
+        let strict = String::from("strict");
+        assert!(is_exists_error(manager.publish(
+            &strict,
+            &stream_name,
+            &publisher_info()
+        )));
+        let backup = String::from("backup");
+        let info = publisher_info();
+        manager.publish(&backup, &stream_name, &info).unwrap();
+        assert_eq!(
+            manager.stream_publishers["backup/test"].standby,
+            Some(info.id)
+        );
+    }
 
-    use std::sync::Arc;
     pub struct TestFunc {}
 
     impl TestFunc {
@@ -0,0 +1,38 @@
+use {failure::Fail, std::fmt};
+
+#[derive(Debug, Fail)]
+pub enum RegistryErrorValue {
+    #[fail(display = "registry request error: {}\n", _0)]
+    RequestError(reqwest::Error),
+    #[fail(display = "registry response status error: {}\n", _0)]
+    StatusError(u16),
+    #[fail(display = "registry serialize error: {}\n", _0)]
+    SerializeError(serde_json::Error),
+}
+
+#[derive(Debug)]
+pub struct RegistryError {
+    pub value: RegistryErrorValue,
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl From<reqwest::Error> for RegistryError {
+    fn from(error: reqwest::Error) -> Self {
+        RegistryError {
+            value: RegistryErrorValue::RequestError(error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for RegistryError {
+    fn from(error: serde_json::Error) -> Self {
+        RegistryError {
+            value: RegistryErrorValue::SerializeError(error),
+        }
+    }
+}
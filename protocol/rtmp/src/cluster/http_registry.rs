@@ -0,0 +1,96 @@
+use {
+    super::{
+        errors::{RegistryError, RegistryErrorValue},
+        StreamLocation, StreamRegistry,
+    },
+    async_trait::async_trait,
+    reqwest::{Client, RequestBuilder},
+};
+
+/*
+ A registry client for the http registry server, the server provides:
+ POST {url}/register   body: {"app_name":"live","stream_name":"test","address":"ip:port"}
+ POST {url}/unregister body: the same as register
+ GET  {url}/lookup?app_name=live&stream_name=test, returns the address or an empty body
+ The shared secret is sent in the X-Registry-Secret header if it is set.
+*/
+pub const REGISTRY_SECRET_HEADER: &str = "X-Registry-Secret";
+
+pub struct HttpRegistry {
+    request_client: Client,
+    url: String,
+    secret: Option<String>,
+}
+
+impl HttpRegistry {
+    pub fn new(url: String) -> Self {
+        Self {
+            request_client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            secret: None,
+        }
+    }
+
+    pub fn set_secret(&mut self, secret: String) {
+        self.secret = Some(secret);
+    }
+
+    fn with_secret(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.secret {
+            Some(secret) => request.header(REGISTRY_SECRET_HEADER, secret),
+            None => request,
+        }
+    }
+
+    async fn post(&self, path: &str, location: StreamLocation) -> Result<(), RegistryError> {
+        let response = self
+            .with_secret(self.request_client.post(format!("{}/{}", self.url, path)))
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&location)?)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(RegistryError {
+                value: RegistryErrorValue::StatusError(response.status().as_u16()),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StreamRegistry for HttpRegistry {
+    async fn register(&self, location: StreamLocation) -> Result<(), RegistryError> {
+        self.post("register", location).await
+    }
+
+    async fn unregister(&self, location: StreamLocation) -> Result<(), RegistryError> {
+        self.post("unregister", location).await
+    }
+
+    async fn lookup(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+    ) -> Result<Option<String>, RegistryError> {
+        let response = self
+            .with_secret(self.request_client.get(format!("{}/lookup", self.url)))
+            .query(&[("app_name", app_name), ("stream_name", stream_name)])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(RegistryError {
+                value: RegistryErrorValue::StatusError(response.status().as_u16()),
+            });
+        }
+
+        let address = response.text().await?;
+        if address.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(address))
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use {
+    super::{errors::RegistryError, StreamLocation, StreamRegistry},
+    async_trait::async_trait,
+    std::{
+        collections::HashMap,
+        sync::{Arc, RwLock},
+    },
+};
+
+/*An in-process registry, it can be shared by the nodes running in one process
+and it also backs the http registry server.*/
+#[derive(Clone, Default)]
+pub struct MemoryRegistry {
+    //app_name/stream_name -> node address
+    streams: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_stream(&self, location: StreamLocation) {
+        let key = format!("{}/{}", location.app_name, location.stream_name);
+        self.streams.write().unwrap().insert(key, location.address);
+    }
+
+    pub fn unregister_stream(&self, location: StreamLocation) {
+        let key = format!("{}/{}", location.app_name, location.stream_name);
+        let mut streams = self.streams.write().unwrap();
+        if streams.get(&key) == Some(&location.address) {
+            streams.remove(&key);
+        }
+    }
+
+    pub fn lookup_stream(&self, app_name: &str, stream_name: &str) -> Option<String> {
+        let key = format!("{}/{}", app_name, stream_name);
+        self.streams.read().unwrap().get(&key).cloned()
+    }
+}
+
+#[async_trait]
+impl StreamRegistry for MemoryRegistry {
+    async fn register(&self, location: StreamLocation) -> Result<(), RegistryError> {
+        self.register_stream(location);
+        Ok(())
+    }
+
+    async fn unregister(&self, location: StreamLocation) -> Result<(), RegistryError> {
+        self.unregister_stream(location);
+        Ok(())
+    }
+
+    async fn lookup(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+    ) -> Result<Option<String>, RegistryError> {
+        Ok(self.lookup_stream(app_name, stream_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryRegistry, StreamLocation, StreamRegistry};
+
+    fn location(stream_name: &str, address: &str) -> StreamLocation {
+        StreamLocation {
+            app_name: String::from("live"),
+            stream_name: String::from(stream_name),
+            address: String::from(address),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_registry() {
+        let registry = MemoryRegistry::new();
+        //two local nodes share the same registry
+        let origin = registry.clone();
+        let edge = registry.clone();
+
+        origin
+            .register(location("test", "127.0.0.1:1935"))
+            .await
+            .unwrap();
+        assert_eq!(
+            edge.lookup("live", "test").await.unwrap(),
+            Some(String::from("127.0.0.1:1935"))
+        );
+        assert_eq!(edge.lookup("live", "other").await.unwrap(), None);
+
+        //the stream is republished to another node before the old one unregisters
+        edge.register(location("test", "127.0.0.1:1936"))
+            .await
+            .unwrap();
+        origin
+            .unregister(location("test", "127.0.0.1:1935"))
+            .await
+            .unwrap();
+        assert_eq!(
+            registry.lookup("live", "test").await.unwrap(),
+            Some(String::from("127.0.0.1:1936"))
+        );
+
+        edge.unregister(location("test", "127.0.0.1:1936"))
+            .await
+            .unwrap();
+        assert_eq!(registry.lookup("live", "test").await.unwrap(), None);
+    }
+}
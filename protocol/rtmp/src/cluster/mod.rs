@@ -0,0 +1,36 @@
+pub mod errors;
+pub mod http_registry;
+pub mod memory_registry;
+
+use {
+    async_trait::async_trait,
+    errors::RegistryError,
+    serde::{Deserialize, Serialize},
+};
+
+/*The request body of the registry, address is the rtmp address(ip:port) of the
+node which the stream is published to.*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamLocation {
+    pub app_name: String,
+    pub stream_name: String,
+    pub address: String,
+}
+
+/*
+ In cluster mode every node registers the streams published to it, and the
+ pull client looks up the origin node of a stream before pulling it, so all
+ the nodes form one logical live service.
+*/
+#[async_trait]
+pub trait StreamRegistry: Send + Sync {
+    async fn register(&self, location: StreamLocation) -> Result<(), RegistryError>;
+    /*only removes the stream if it is still registered by the same node*/
+    async fn unregister(&self, location: StreamLocation) -> Result<(), RegistryError>;
+    /*return the rtmp address of the node which holds the stream*/
+    async fn lookup(
+        &self,
+        app_name: &str,
+        stream_name: &str,
+    ) -> Result<Option<String>, RegistryError>;
+}
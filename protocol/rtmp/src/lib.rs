@@ -12,6 +12,7 @@ pub mod amf0;
 pub mod cache;
 pub mod channels;
 pub mod chunk;
+pub mod cluster;
 pub mod config;
 pub mod handshake;
 pub mod messages;
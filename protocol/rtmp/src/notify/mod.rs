@@ -1,4 +1,24 @@
-use reqwest::Client;
+use {reqwest::Client, serde::Serialize};
+
+/*the recording of a stream is finished, path is where it is archived
+and duration is in seconds, the duration is 0 when a recording file begins*/
+#[derive(Debug, Serialize, Clone)]
+pub struct RecordInfo {
+    pub app_name: String,
+    pub stream_name: String,
+    pub path: String,
+    pub duration: f64,
+}
+
+/*the request of a protected resource(e.g. a hls key), it is posted to the
+on_play url which authorizes it by the response status*/
+#[derive(Debug, Serialize, Clone)]
+pub struct PlayRequestInfo {
+    pub app_name: String,
+    pub stream_name: String,
+    pub request_url: String,
+    pub remote_addr: String,
+}
 
 pub struct Notifier {
     request_client: Client,
@@ -6,6 +26,8 @@ pub struct Notifier {
     on_unpublish_url: Option<String>,
     on_play_url: Option<String>,
     on_stop_url: Option<String>,
+    on_record_url: Option<String>,
+    on_record_done_url: Option<String>,
 }
 
 impl Notifier {
@@ -14,6 +36,8 @@ impl Notifier {
         on_unpublish_url: Option<String>,
         on_play_url: Option<String>,
         on_stop_url: Option<String>,
+        on_record_url: Option<String>,
+        on_record_done_url: Option<String>,
     ) -> Self {
         Self {
             request_client: reqwest::Client::new(),
@@ -21,6 +45,8 @@ impl Notifier {
             on_unpublish_url,
             on_play_url,
             on_stop_url,
+            on_record_url,
+            on_record_done_url,
         }
     }
     pub async fn on_publish_notify(&self, body: String) {
@@ -98,4 +124,85 @@ impl Notifier {
             }
         }
     }
+
+    pub async fn on_record_notify(&self, info: &RecordInfo) {
+        if let Some(on_record_url) = &self.on_record_url {
+            let body = match serde_json::to_string(info) {
+                Ok(body) => body,
+                Err(err) => {
+                    log::error!("on_record serialize error: {}", err);
+                    return;
+                }
+            };
+            match self
+                .request_client
+                .post(on_record_url)
+                .body(body)
+                .send()
+                .await
+            {
+                Err(err) => {
+                    log::error!("on_record error: {}", err);
+                }
+                Ok(response) => {
+                    log::info!("on_record success: {:?}", response);
+                }
+            }
+        }
+    }
+
+    pub async fn on_record_done_notify(&self, info: &RecordInfo) {
+        if let Some(on_record_done_url) = &self.on_record_done_url {
+            let body = match serde_json::to_string(info) {
+                Ok(body) => body,
+                Err(err) => {
+                    log::error!("on_record_done serialize error: {}", err);
+                    return;
+                }
+            };
+            match self
+                .request_client
+                .post(on_record_done_url)
+                .body(body)
+                .send()
+                .await
+            {
+                Err(err) => {
+                    log::error!("on_record_done error: {}", err);
+                }
+                Ok(response) => {
+                    log::info!("on_record_done success: {:?}", response);
+                }
+            }
+        }
+    }
+
+    /*the play request is allowed if the on_play url is not configured, or it
+    responds with a 2xx status*/
+    pub async fn on_play_authorize(&self, info: &PlayRequestInfo) -> bool {
+        let on_play_url = match &self.on_play_url {
+            Some(on_play_url) => on_play_url,
+            None => return true,
+        };
+        let body = match serde_json::to_string(info) {
+            Ok(body) => body,
+            Err(err) => {
+                log::error!("on_play authorize serialize error: {}", err);
+                return false;
+            }
+        };
+        match self
+            .request_client
+            .post(on_play_url)
+            .body(body)
+            .send()
+            .await
+        {
+            Err(err) => {
+                log::error!("on_play authorize error: {}", err);
+                false
+            }
+            Ok(response) => response.status().is_success(),
+        }
+    }
 }
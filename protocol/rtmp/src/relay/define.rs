@@ -0,0 +1,63 @@
+use {
+    super::errors::ClientError,
+    serde::Serialize,
+    tokio::sync::{mpsc, oneshot},
+    uuid::Uuid,
+};
+
+pub type RelayEventProducer = mpsc::UnboundedSender<RelayEvent>;
+pub type RelayEventConsumer = mpsc::UnboundedReceiver<RelayEvent>;
+
+type RelayResponder<T> = oneshot::Sender<T>;
+
+#[derive(Debug)]
+pub enum RelayEvent {
+    /*Start to push a local stream to a remote rtmp url*/
+    StartPush {
+        app_name: String,
+        stream_name: String,
+        url: String,
+        responder: RelayResponder<Result<Uuid, ClientError>>,
+    },
+    /*Stop a running push by its id*/
+    StopPush {
+        id: Uuid,
+        responder: RelayResponder<bool>,
+    },
+    /*Query the status of all the dynamic pushes*/
+    PushStatus {
+        responder: RelayResponder<Vec<RelayPushStatus>>,
+    },
+    /*Sent by the push session task itself when its state changes*/
+    PushStateChanged {
+        id: Uuid,
+        state: RelayPushState,
+        error: Option<String>,
+        retry_count: u32,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub enum RelayPushState {
+    /*connecting to the remote rtmp server*/
+    Connecting,
+    /*the session is connected and forwarding the local stream*/
+    Running,
+    /*the session ends with an error and waits to reconnect*/
+    Reconnecting,
+    /*the session ends normally, e.g. the local stream is unpublished*/
+    Finished,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RelayPushStatus {
+    pub id: String,
+    pub app_name: String,
+    pub stream_name: String,
+    pub url: String,
+    pub state: RelayPushState,
+    pub start_time: String,
+    /*the last error which causes a reconnection*/
+    pub error: Option<String>,
+    pub retry_count: u32,
+}
@@ -1,4 +1,5 @@
 use {
+    crate::utils::errors::RtmpUrlParseError,
     failure::Fail,
     std::{fmt, io::Error},
     tokio::sync::broadcast::error::RecvError,
@@ -24,6 +25,8 @@ pub enum PushClientErrorValue {
     SendError,
     #[fail(display = "io error\n")]
     IOError(Error),
+    #[fail(display = "rtmp url parse error\n")]
+    RtmpUrlParseError(RtmpUrlParseError),
 }
 
 impl From<Error> for ClientError {
@@ -41,3 +44,11 @@ impl From<RecvError> for ClientError {
         }
     }
 }
+
+impl From<RtmpUrlParseError> for ClientError {
+    fn from(error: RtmpUrlParseError) -> Self {
+        ClientError {
+            value: PushClientErrorValue::RtmpUrlParseError(error),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod define;
+pub mod errors;
 pub mod pull_client;
 pub mod push_client;
-pub mod errors;
\ No newline at end of file
+pub mod push_manager;
@@ -2,13 +2,77 @@ use {
     super::errors::ClientError,
     crate::{
         channels::define::{ChannelEventProducer, ClientEvent, ClientEventConsumer},
+        cluster::StreamRegistry,
         session::client_session::{ClientSession, ClientType},
+        utils::RtmpUrlParser,
+    },
+    std::{
+        collections::HashMap,
+        future,
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+    tokio::{
+        net::TcpStream,
+        sync::mpsc,
+        time::{self, Instant},
     },
-    tokio::net::TcpStream,
 };
 
+const ORIGIN_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+//a failed origin is tried after the healthy ones until the cooldown passes
+const ORIGIN_FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+//how long to wait before trying all the origins again
+const PULL_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/*
+ replace the {app} and {stream} placeholders in an origin url template, e.g.
+ rtmp://origin.cn/{app}/{stream}?token=abc -> rtmp://origin.cn/live/test?token=abc
+*/
+pub fn render_origin_url(template: &str, app_name: &str, stream_name: &str) -> String {
+    template
+        .replace("{app}", app_name)
+        .replace("{stream}", stream_name)
+}
+
+#[derive(Debug, Default, Clone)]
+struct OriginHealth {
+    failure_count: u32,
+    last_failure: Option<Instant>,
+}
+
+impl OriginHealth {
+    fn is_healthy(&self) -> bool {
+        match self.last_failure {
+            Some(last_failure) => last_failure.elapsed() >= ORIGIN_FAILURE_COOLDOWN,
+            None => true,
+        }
+    }
+}
+
+type OriginsHealth = Arc<Mutex<Vec<OriginHealth>>>;
+
+/*sent from the pull client to a running pull task*/
+#[derive(Debug)]
+enum PullControl {
+    //the pulled stream has subscribers
+    Active,
+    //the last subscriber of the pulled stream leaves
+    Idle,
+}
+
 pub struct PullClient {
-    address: String,
+    /*the ordered origin url templates*/
+    origins: Vec<String>,
+    origins_health: OriginsHealth,
+    /*used to locate the origin node of a stream in cluster mode*/
+    stream_registry: Option<Arc<dyn StreamRegistry>>,
+    node_address: String,
+    /*how long a pulled stream is kept after its last subscriber leaves*/
+    idle_timeout: Duration,
+    /*app_name/stream_name -> the control sender of the pull task*/
+    pulling_streams: HashMap<String, mpsc::UnboundedSender<PullControl>>,
     client_event_consumer: ClientEventConsumer,
     channel_event_producer: ChannelEventProducer,
 }
@@ -19,45 +83,589 @@ impl PullClient {
         consumer: ClientEventConsumer,
         producer: ChannelEventProducer,
     ) -> Self {
+        let origins = vec![format!("rtmp://{}/{{app}}/{{stream}}", address)];
+        let origins_health = Arc::new(Mutex::new(vec![OriginHealth::default(); origins.len()]));
+
         Self {
-            address,
+            origins,
+            origins_health,
+            stream_registry: None,
+            node_address: String::new(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            pulling_streams: HashMap::new(),
 
             client_event_consumer: consumer,
             channel_event_producer: producer,
         }
     }
 
+    pub fn set_origins(&mut self, origins: Vec<String>) {
+        self.origins_health = Arc::new(Mutex::new(vec![OriginHealth::default(); origins.len()]));
+        self.origins = origins;
+    }
+
+    /*node_address is the rtmp address of this node, used to avoid pulling from itself*/
+    pub fn set_stream_registry(&mut self, registry: Arc<dyn StreamRegistry>, node_address: String) {
+        self.stream_registry = Some(registry);
+        self.node_address = node_address;
+    }
+
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = idle_timeout;
+    }
+
     pub async fn run(&mut self) -> Result<(), ClientError> {
         loop {
             let val = self.client_event_consumer.recv().await?;
+            //remove the finished pull tasks
+            self.pulling_streams.retain(|_, sender| !sender.is_closed());
 
-            if let ClientEvent::Subscribe {
-                app_name,
-                stream_name,
-            } = val
-            {
-                log::info!(
-                    "receive pull event, app_name :{}, stream_name: {}",
+            match val {
+                ClientEvent::Subscribe {
                     app_name,
-                    stream_name
-                );
-                let stream = TcpStream::connect(self.address.clone()).await?;
-
-                let mut client_session = ClientSession::new(
-                    stream,
-                    ClientType::Play,
-                    self.address.clone(),
-                    app_name.clone(),
-                    stream_name.clone(),
-                    self.channel_event_producer.clone(),
+                    stream_name,
+                } => {
+                    log::info!(
+                        "receive pull event, app_name :{}, stream_name: {}",
+                        app_name,
+                        stream_name
+                    );
+
+                    let key = format!("{}/{}", app_name, stream_name);
+                    if let Some(sender) = self.pulling_streams.get(&key) {
+                        if sender.send(PullControl::Active).is_ok() {
+                            continue;
+                        }
+                    }
+
+                    let (control_sender, control_receiver) = mpsc::unbounded_channel();
+                    let task = PullTask {
+                        app_name,
+                        stream_name,
+                        origins: self.origins.clone(),
+                        origins_health: self.origins_health.clone(),
+                        stream_registry: self.stream_registry.clone(),
+                        node_address: self.node_address.clone(),
+                        idle_timeout: self.idle_timeout,
+                        control_receiver,
+                        channel_event_producer: self.channel_event_producer.clone(),
+                    };
+                    tokio::spawn(task.run());
+                    self.pulling_streams.insert(key, control_sender);
+                }
+                ClientEvent::UnSubscribe {
+                    app_name,
+                    stream_name,
+                } => {
+                    let key = format!("{}/{}", app_name, stream_name);
+                    if let Some(sender) = self.pulling_streams.get(&key) {
+                        log::info!(
+                            "pulled stream becomes idle, app_name :{}, stream_name: {}",
+                            app_name,
+                            stream_name
+                        );
+                        let _ = sender.send(PullControl::Idle);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+async fn wait_idle_deadline(idle_deadline: Option<Instant>) {
+    match idle_deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => future::pending().await,
+    }
+}
+
+/*Pull one stream from the origins, fail over to the next origin when the current
+one can not be connected or the session breaks, try all the origins again after
+the retry interval, and stop the pulling when the stream stays idle longer than
+the idle timeout.*/
+struct PullTask {
+    app_name: String,
+    stream_name: String,
+    origins: Vec<String>,
+    origins_health: OriginsHealth,
+    stream_registry: Option<Arc<dyn StreamRegistry>>,
+    node_address: String,
+    idle_timeout: Duration,
+    control_receiver: mpsc::UnboundedReceiver<PullControl>,
+    channel_event_producer: ChannelEventProducer,
+}
+
+impl PullTask {
+    async fn run(mut self) {
+        /*The subscriber who triggers the pulling fails because the stream does not exist
+        yet, so the stream is idle until a subscriber comes again.*/
+        let mut idle_deadline = Some(Instant::now() + self.idle_timeout);
+
+        loop {
+            //in cluster mode, pull from the node which holds the stream first
+            if let Some(address) = self.lookup_origin().await {
+                let url = format!("rtmp://{}/{}/{}", address, self.app_name, self.stream_name);
+                if self.pull_from_origin(url, None, &mut idle_deadline).await {
+                    return;
+                }
+            }
+
+            for index in self.get_origin_order() {
+                let url =
+                    render_origin_url(&self.origins[index], &self.app_name, &self.stream_name);
+                if self
+                    .pull_from_origin(url, Some(index), &mut idle_deadline)
+                    .await
+                {
+                    return;
+                }
+            }
+
+            log::error!(
+                "pull failed from all the origins, retry in {:?}, app_name: {}, stream_name: {}",
+                PULL_RETRY_INTERVAL,
+                self.app_name,
+                self.stream_name
+            );
+            if !self.wait_retry(&mut idle_deadline).await {
+                log::info!(
+                    "stop idle pulled stream, app_name: {}, stream_name: {}",
+                    self.app_name,
+                    self.stream_name
                 );
+                return;
+            }
+        }
+    }
+
+    /*wait for the retry interval, return false if the stream becomes idle*/
+    async fn wait_retry(&mut self, idle_deadline: &mut Option<Instant>) -> bool {
+        let retry_time = Instant::now() + PULL_RETRY_INTERVAL;
 
-                tokio::spawn(async move {
-                    if let Err(err) = client_session.run().await {
-                        log::error!("client_session as pull client run error: {}", err);
+        loop {
+            tokio::select! {
+                _ = time::sleep_until(retry_time) => return true,
+                control = self.control_receiver.recv() => match control {
+                    Some(PullControl::Active) => *idle_deadline = None,
+                    Some(PullControl::Idle) => {
+                        *idle_deadline = Some(Instant::now() + self.idle_timeout)
                     }
-                });
+                    None => return false,
+                },
+                _ = wait_idle_deadline(*idle_deadline) => return false,
+            }
+        }
+    }
+
+    async fn lookup_origin(&self) -> Option<String> {
+        let stream_registry = self.stream_registry.as_ref()?;
+
+        match stream_registry
+            .lookup(&self.app_name, &self.stream_name)
+            .await
+        {
+            //never pull a stream from the node itself
+            Ok(Some(address)) if address != self.node_address => Some(address),
+            Ok(_) => None,
+            Err(err) => {
+                log::error!("pull client lookup stream registry error: {}", err);
+                None
+            }
+        }
+    }
+
+    /*the healthy origins first and then the failed ones, both in the configured order*/
+    fn get_origin_order(&self) -> Vec<usize> {
+        let origins_health = self.origins_health.lock().unwrap();
+        let (mut healthy, unhealthy): (Vec<usize>, Vec<usize>) =
+            (0..self.origins.len()).partition(|index| origins_health[*index].is_healthy());
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    //the origins located from the stream registry have no health records
+    fn mark_origin(&self, index: Option<usize>, succeeded: bool) {
+        let index = if let Some(index) = index {
+            index
+        } else {
+            return;
+        };
+
+        let mut origins_health = self.origins_health.lock().unwrap();
+        let health = &mut origins_health[index];
+        if succeeded {
+            *health = OriginHealth::default();
+        } else {
+            health.failure_count += 1;
+            health.last_failure = Some(Instant::now());
+            log::warn!(
+                "pull origin: {} is unhealthy, failure count: {}",
+                self.origins[index],
+                health.failure_count
+            );
+        }
+    }
+
+    /*return true if the pulling is finished and no more origins need to be tried*/
+    async fn pull_from_origin(
+        &mut self,
+        url: String,
+        index: Option<usize>,
+        idle_deadline: &mut Option<Instant>,
+    ) -> bool {
+        let mut url_parser = RtmpUrlParser::new(url.clone());
+        if let Err(err) = url_parser.parse_url() {
+            log::error!("pull origin url: {} parse error: {}", url, err);
+            self.mark_origin(index, false);
+            return false;
+        }
+        url_parser.append_port(String::from("1935"));
+
+        log::info!(
+            "pull stream from origin: {}, app_name: {}, stream_name: {}",
+            url,
+            self.app_name,
+            self.stream_name
+        );
+
+        let stream = match time::timeout(
+            ORIGIN_CONNECT_TIMEOUT,
+            TcpStream::connect(url_parser.raw_domain_name.clone()),
+        )
+        .await
+        {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(err)) => {
+                log::error!("pull origin: {} connect error: {}", url, err);
+                self.mark_origin(index, false);
+                return false;
             }
+            Err(_) => {
+                log::error!("pull origin: {} connect timeout", url);
+                self.mark_origin(index, false);
+                return false;
+            }
+        };
+
+        let mut client_session = ClientSession::new(
+            stream,
+            ClientType::Play,
+            url_parser.raw_domain_name,
+            url_parser.app_name,
+            url_parser.raw_stream_name,
+            self.channel_event_producer.clone(),
+        );
+        client_session.publish(self.app_name.clone(), self.stream_name.clone());
+
+        let run_result = {
+            let session_run = client_session.run();
+            tokio::pin!(session_run);
+
+            loop {
+                tokio::select! {
+                    rv = &mut session_run => break Some(rv),
+                    control = self.control_receiver.recv() => match control {
+                        Some(PullControl::Active) => *idle_deadline = None,
+                        Some(PullControl::Idle) => {
+                            *idle_deadline = Some(Instant::now() + self.idle_timeout)
+                        }
+                        None => break None,
+                    },
+                    _ = wait_idle_deadline(*idle_deadline) => break None,
+                }
+            }
+        };
+
+        let is_published = client_session.is_published();
+        if let Err(err) = client_session.unpublish_to_channels().await {
+            log::error!("pull origin: {} unpublish error: {}", url, err);
         }
+
+        match run_result {
+            Some(Ok(_)) => {
+                log::info!("pull origin: {} session finished", url);
+                true
+            }
+            Some(Err(err)) => {
+                log::error!("pull origin: {} session run error: {}", url, err);
+                //the origin has served the stream, so it is a broken session not a bad origin
+                self.mark_origin(index, is_published);
+                false
+            }
+            None => {
+                log::info!(
+                    "stop idle pulled stream, app_name: {}, stream_name: {}",
+                    self.app_name,
+                    self.stream_name
+                );
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{render_origin_url, OriginHealth, PullControl, PullTask, ORIGIN_FAILURE_COOLDOWN},
+        crate::{
+            channels::{
+                define::{ChannelDataProducer, ChannelEvent, ChannelEventProducer},
+                ChannelsManager,
+            },
+            rtmp::RtmpServer,
+            session::{
+                common::{NotifyInfo, PublisherInfo, SubscribeOptions, SubscriberInfo},
+                define::{PublishType, SubscribeType},
+            },
+        },
+        std::{
+            sync::{Arc, Mutex},
+            time::Duration,
+        },
+        tokio::{
+            net::TcpListener,
+            sync::{mpsc, oneshot},
+            task::JoinHandle,
+            time::{sleep, timeout, Instant},
+        },
+        uuid::Uuid,
+    };
+
+    fn start_channels() -> ChannelEventProducer {
+        let mut channels = ChannelsManager::new(None);
+        let event_producer = channels.get_channel_event_producer();
+        tokio::spawn(async move { channels.run().await });
+        event_producer
+    }
+
+    async fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    //an origin server which has published live/test
+    async fn start_origin(port: u16) -> ChannelDataProducer {
+        let event_producer = start_channels();
+        let mut server = RtmpServer::new(format!("127.0.0.1:{port}"), event_producer.clone());
+        tokio::spawn(async move { server.run().await });
+
+        let (responder, receiver) = oneshot::channel();
+        event_producer
+            .send(ChannelEvent::Publish {
+                app_name: String::from("live"),
+                stream_name: String::from("test"),
+                info: PublisherInfo {
+                    id: Uuid::new_v4(),
+                    sub_type: PublishType::PushRtmp,
+                    notify_info: NotifyInfo {
+                        request_url: String::from(""),
+                        remote_addr: String::from(""),
+                    },
+                },
+                responder,
+            })
+            .unwrap();
+        let data_producer = receiver.await.unwrap();
+        sleep(Duration::from_millis(100)).await;
+        data_producer
+    }
+
+    fn new_task(
+        origins: Vec<String>,
+        idle_timeout: Duration,
+        channel_event_producer: ChannelEventProducer,
+    ) -> (PullTask, mpsc::UnboundedSender<PullControl>) {
+        let (control_sender, control_receiver) = mpsc::unbounded_channel();
+        let task = PullTask {
+            app_name: String::from("live"),
+            stream_name: String::from("test"),
+            origins_health: Arc::new(Mutex::new(vec![OriginHealth::default(); origins.len()])),
+            origins,
+            stream_registry: None,
+            node_address: String::new(),
+            idle_timeout,
+            control_receiver,
+            channel_event_producer,
+        };
+        (task, control_sender)
+    }
+
+    //whether live/test is published to the local channels
+    async fn is_pulled(event_producer: &ChannelEventProducer) -> bool {
+        let (responder, receiver) = oneshot::channel();
+        let info = SubscriberInfo {
+            id: Uuid::new_v4(),
+            sub_type: SubscribeType::PlayerRtmp,
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: String::from(""),
+            },
+            options: SubscribeOptions::default(),
+        };
+        event_producer
+            .send(ChannelEvent::Subscribe {
+                app_name: String::from("live"),
+                stream_name: String::from("test"),
+                info: info.clone(),
+                responder,
+            })
+            .unwrap();
+        let is_pulled = receiver.await.is_ok();
+        if is_pulled {
+            event_producer
+                .send(ChannelEvent::UnSubscribe {
+                    app_name: String::from("live"),
+                    stream_name: String::from("test"),
+                    info,
+                })
+                .unwrap();
+        }
+        is_pulled
+    }
+
+    async fn wait_pulled(event_producer: &ChannelEventProducer) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if is_pulled(event_producer).await {
+                return true;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn test_origin_failover() {
+        let dead_port = free_port().await;
+        let origin_port = free_port().await;
+        let _origin = start_origin(origin_port).await;
+
+        let local_channels = start_channels();
+        let (task, control_sender) = new_task(
+            vec![
+                format!("rtmp://127.0.0.1:{dead_port}/{{app}}/{{stream}}"),
+                format!("rtmp://127.0.0.1:{origin_port}/{{app}}/{{stream}}"),
+            ],
+            Duration::from_secs(30),
+            local_channels.clone(),
+        );
+        let origins_health = task.origins_health.clone();
+        control_sender.send(PullControl::Active).unwrap();
+        tokio::spawn(task.run());
+
+        //the dead origin is skipped and the stream is pulled from the next one
+        assert!(wait_pulled(&local_channels).await);
+        let origins_health = origins_health.lock().unwrap();
+        assert_eq!(origins_health[0].failure_count, 1);
+        assert_eq!(origins_health[1].failure_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_retry_all_origins() {
+        let origin_port = free_port().await;
+        let local_channels = start_channels();
+        let (task, control_sender) = new_task(
+            vec![format!("rtmp://127.0.0.1:{origin_port}/{{app}}/{{stream}}")],
+            Duration::from_secs(30),
+            local_channels.clone(),
+        );
+        control_sender.send(PullControl::Active).unwrap();
+        tokio::spawn(task.run());
+
+        //the origin comes up after the first pass fails
+        sleep(Duration::from_millis(300)).await;
+        assert!(!is_pulled(&local_channels).await);
+        let _origin = start_origin(origin_port).await;
+        assert!(wait_pulled(&local_channels).await);
+    }
+
+    #[test]
+    fn test_origin_order() {
+        let (channel_event_producer, _) = mpsc::unbounded_channel();
+        let (task, _) = new_task(
+            vec![
+                String::from("rtmp://origin0/{app}/{stream}"),
+                String::from("rtmp://origin1/{app}/{stream}"),
+                String::from("rtmp://origin2/{app}/{stream}"),
+            ],
+            Duration::from_secs(30),
+            channel_event_producer,
+        );
+        assert_eq!(task.get_origin_order(), vec![0, 1, 2]);
+
+        //the failed origins are tried after the healthy ones
+        task.mark_origin(Some(0), false);
+        task.mark_origin(Some(1), false);
+        assert_eq!(task.get_origin_order(), vec![2, 0, 1]);
+
+        //a failed origin recovers after the cooldown
+        task.origins_health.lock().unwrap()[0].last_failure =
+            Instant::now().checked_sub(ORIGIN_FAILURE_COOLDOWN);
+        assert_eq!(task.get_origin_order(), vec![0, 2, 1]);
+
+        //or when it serves a stream
+        task.mark_origin(Some(1), true);
+        assert_eq!(task.get_origin_order(), vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_idle_teardown() {
+        let origin_port = free_port().await;
+        let _origin = start_origin(origin_port).await;
+
+        let local_channels = start_channels();
+        let (task, control_sender) = new_task(
+            vec![format!("rtmp://127.0.0.1:{origin_port}/{{app}}/{{stream}}")],
+            Duration::from_millis(500),
+            local_channels.clone(),
+        );
+        let handle: JoinHandle<()> = tokio::spawn(task.run());
+        assert!(wait_pulled(&local_channels).await);
+
+        //the stream is kept while it has subscribers
+        control_sender.send(PullControl::Active).unwrap();
+        sleep(Duration::from_millis(800)).await;
+        assert!(!handle.is_finished());
+
+        //the pulling stops after the idle timeout and the stream is removed
+        control_sender.send(PullControl::Idle).unwrap();
+        assert!(timeout(Duration::from_secs(2), handle).await.is_ok());
+        assert!(!is_pulled(&local_channels).await);
+    }
+
+    #[tokio::test]
+    async fn test_idle_teardown_without_origin() {
+        let dead_port = free_port().await;
+        let (task, _control_sender) = new_task(
+            vec![format!("rtmp://127.0.0.1:{dead_port}/{{app}}/{{stream}}")],
+            Duration::from_millis(500),
+            start_channels(),
+        );
+
+        //the origins are retried until the stream becomes idle
+        let handle = tokio::spawn(task.run());
+        assert!(timeout(Duration::from_secs(3), handle).await.is_ok());
+    }
+
+    #[test]
+    fn test_render_origin_url() {
+        assert_eq!(
+            render_origin_url("rtmp://origin.cn/{app}/{stream}?token=abc", "live", "test"),
+            "rtmp://origin.cn/live/test?token=abc"
+        );
+        assert_eq!(
+            render_origin_url(
+                "rtmp://origin.cn:1936/origin_{app}/{stream}_hd",
+                "live",
+                "test"
+            ),
+            "rtmp://origin.cn:1936/origin_live/test_hd"
+        );
     }
 }
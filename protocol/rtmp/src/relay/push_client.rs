@@ -1,29 +1,36 @@
 use {
-    super::errors::ClientError,
-    crate::{
-        channels::define::{ChannelEventProducer, ClientEvent, ClientEventConsumer},
-        session::client_session::{ClientSession, ClientType},
+    super::{
+        define::{RelayEvent, RelayEventProducer},
+        errors::{ClientError, PushClientErrorValue},
     },
-    tokio::net::TcpStream,
+    crate::channels::define::{ClientEvent, ClientEventConsumer},
+    std::collections::HashMap,
+    tokio::sync::oneshot,
+    uuid::Uuid,
 };
 
+/*The static push client, it pushes every published stream to the configured
+address, the push sessions are supervised by the relay push manager.*/
 pub struct PushClient {
     address: String,
     client_event_consumer: ClientEventConsumer,
-    channel_event_producer: ChannelEventProducer,
+    relay_event_producer: RelayEventProducer,
+    //app_name/stream_name -> relay push id
+    pushing_streams: HashMap<String, Uuid>,
 }
 
 impl PushClient {
     pub fn new(
         address: String,
         consumer: ClientEventConsumer,
-        producer: ChannelEventProducer,
+        relay_event_producer: RelayEventProducer,
     ) -> Self {
         Self {
             address,
 
             client_event_consumer: consumer,
-            channel_event_producer: producer,
+            relay_event_producer,
+            pushing_streams: HashMap::new(),
         }
     }
 
@@ -43,22 +50,52 @@ impl PushClient {
                         stream_name.clone(),
                         self.address.clone()
                     );
-                    let stream = TcpStream::connect(self.address.clone()).await?;
 
-                    let mut client_session = ClientSession::new(
-                        stream,
-                        ClientType::Publish,
-                        self.address.clone(),
-                        app_name.clone(),
-                        stream_name.clone(),
-                        self.channel_event_producer.clone(),
-                    );
+                    let url = format!("rtmp://{}/{}/{}", self.address, app_name, stream_name);
+                    let key = format!("{}/{}", app_name, stream_name);
 
-                    tokio::spawn(async move {
-                        if let Err(err) = client_session.run().await {
-                            log::error!("client_session as push client run error: {}", err);
+                    let (responder, receiver) = oneshot::channel();
+                    let relay_event = RelayEvent::StartPush {
+                        app_name,
+                        stream_name,
+                        url,
+                        responder,
+                    };
+                    self.relay_event_producer
+                        .send(relay_event)
+                        .map_err(|_| ClientError {
+                            value: PushClientErrorValue::SendError,
+                        })?;
+
+                    match receiver.await {
+                        Ok(Ok(id)) => {
+                            self.pushing_streams.insert(key, id);
+                        }
+                        Ok(Err(err)) => {
+                            log::error!("push client start push error: {}", err);
+                        }
+                        Err(err) => {
+                            log::error!("push client start push receive error: {}", err);
+                        }
+                    }
+                }
+                ClientEvent::UnPublish {
+                    app_name,
+                    stream_name,
+                } => {
+                    let key = format!("{}/{}", app_name, stream_name);
+                    if let Some(id) = self.pushing_streams.remove(&key) {
+                        let (responder, receiver) = oneshot::channel();
+                        let relay_event = RelayEvent::StopPush { id, responder };
+                        self.relay_event_producer
+                            .send(relay_event)
+                            .map_err(|_| ClientError {
+                                value: PushClientErrorValue::SendError,
+                            })?;
+                        if let Err(err) = receiver.await {
+                            log::error!("push client stop push receive error: {}", err);
                         }
-                    });
+                    }
                 }
 
                 _ => {
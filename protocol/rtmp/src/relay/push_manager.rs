@@ -0,0 +1,584 @@
+use {
+    super::{
+        define::{
+            RelayEvent, RelayEventConsumer, RelayEventProducer, RelayPushState, RelayPushStatus,
+        },
+        errors::ClientError,
+    },
+    crate::{
+        channels::define::ChannelEventProducer,
+        session::{
+            client_session::{ClientSession, ClientType},
+            errors::SessionErrorValue,
+        },
+        utils::RtmpUrlParser,
+    },
+    chrono::Local,
+    std::{collections::HashMap, time::Duration},
+    tokio::{
+        net::TcpStream,
+        sync::{mpsc, oneshot},
+        time::{self, Instant},
+    },
+    uuid::Uuid,
+};
+
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+//a session lasts longer than this is stable, then the backoff starts over
+const SESSION_STABLE_DURATION: Duration = Duration::from_secs(30);
+
+/*the reconnect delays of a push: 1s doubling up to 30s, a stable session resets it*/
+struct ReconnectBackoff {
+    next_delay: Duration,
+}
+
+impl ReconnectBackoff {
+    fn new() -> Self {
+        Self {
+            next_delay: RECONNECT_BACKOFF_MIN,
+        }
+    }
+
+    //the delay before reconnecting a session which is broken after the duration
+    fn on_failure(&mut self, session_duration: Duration) -> Duration {
+        if session_duration >= SESSION_STABLE_DURATION {
+            self.next_delay = RECONNECT_BACKOFF_MIN;
+        }
+        let delay = self.next_delay;
+        self.next_delay = std::cmp::min(delay * 2, RECONNECT_BACKOFF_MAX);
+        delay
+    }
+}
+
+struct PushRelay {
+    status: RelayPushStatus,
+    /*used to stop the push session, it is taken when the relay is stopped*/
+    stop_sender: Option<oneshot::Sender<()>>,
+}
+
+/*Manage the relay pushes which are started/stopped dynamically from the http api.
+Each push is a rtmp client session which subscribes a local stream and publishes
+it to a remote rtmp url.*/
+pub struct PushManager {
+    channel_event_producer: ChannelEventProducer,
+    relay_event_producer: RelayEventProducer,
+    relay_event_consumer: RelayEventConsumer,
+    relays: HashMap<Uuid, PushRelay>,
+}
+
+impl PushManager {
+    pub fn new(channel_event_producer: ChannelEventProducer) -> Self {
+        let (relay_event_producer, relay_event_consumer) = mpsc::unbounded_channel();
+
+        Self {
+            channel_event_producer,
+            relay_event_producer,
+            relay_event_consumer,
+            relays: HashMap::new(),
+        }
+    }
+
+    pub fn get_relay_event_producer(&self) -> RelayEventProducer {
+        self.relay_event_producer.clone()
+    }
+
+    pub async fn run(&mut self) {
+        while let Some(event) = self.relay_event_consumer.recv().await {
+            match event {
+                RelayEvent::StartPush {
+                    app_name,
+                    stream_name,
+                    url,
+                    responder,
+                } => {
+                    let rv = self.start_push(app_name, stream_name, url);
+                    if responder.send(rv).is_err() {
+                        log::error!("push manager start push responder send err");
+                    }
+                }
+                RelayEvent::StopPush { id, responder } => {
+                    let rv = self.stop_push(id);
+                    if responder.send(rv).is_err() {
+                        log::error!("push manager stop push responder send err");
+                    }
+                }
+                RelayEvent::PushStatus { responder } => {
+                    let status = self
+                        .relays
+                        .values()
+                        .map(|relay| relay.status.clone())
+                        .collect();
+                    if responder.send(status).is_err() {
+                        log::error!("push manager push status responder send err");
+                    }
+                }
+                RelayEvent::PushStateChanged {
+                    id,
+                    state,
+                    error,
+                    retry_count,
+                } => {
+                    //a finished push is removed, its session has ended
+                    if state == RelayPushState::Finished {
+                        if self.relays.remove(&id).is_some() {
+                            log::info!("relay push {} is finished", id);
+                        }
+                    } else if let Some(relay) = self.relays.get_mut(&id) {
+                        relay.status.state = state;
+                        if error.is_some() {
+                            relay.status.error = error;
+                        }
+                        relay.status.retry_count = retry_count;
+                    }
+                }
+            }
+        }
+    }
+
+    fn start_push(
+        &mut self,
+        app_name: String,
+        stream_name: String,
+        url: String,
+    ) -> Result<Uuid, ClientError> {
+        let mut url_parser = RtmpUrlParser::new(url.clone());
+        url_parser.parse_url()?;
+        url_parser.append_port(String::from("1935"));
+
+        let id = Uuid::new_v4();
+        let (stop_sender, stop_receiver) = oneshot::channel();
+
+        log::info!(
+            "start relay push, id: {}, app_name: {}, stream_name: {}, url: {}",
+            id,
+            app_name,
+            stream_name,
+            url
+        );
+
+        self.relays.insert(
+            id,
+            PushRelay {
+                status: RelayPushStatus {
+                    id: id.to_string(),
+                    app_name: app_name.clone(),
+                    stream_name: stream_name.clone(),
+                    url,
+                    state: RelayPushState::Connecting,
+                    start_time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                    error: None,
+                    retry_count: 0,
+                },
+                stop_sender: Some(stop_sender),
+            },
+        );
+
+        let push_session = PushSession {
+            id,
+            url_parser,
+            app_name,
+            stream_name,
+            channel_event_producer: self.channel_event_producer.clone(),
+            relay_event_producer: self.relay_event_producer.clone(),
+            retry_count: 0,
+        };
+        tokio::spawn(push_session.run(stop_receiver));
+
+        Ok(id)
+    }
+
+    fn stop_push(&mut self, id: Uuid) -> bool {
+        if let Some(mut relay) = self.relays.remove(&id) {
+            log::info!(
+                "stop relay push, id: {}, app_name: {}, stream_name: {}, url: {}",
+                id,
+                relay.status.app_name,
+                relay.status.stream_name,
+                relay.status.url
+            );
+            if let Some(stop_sender) = relay.stop_sender.take() {
+                //the receiver is dropped if the session has already ended.
+                let _ = stop_sender.send(());
+            }
+            return true;
+        }
+        false
+    }
+}
+
+/*the result of one push session*/
+enum PushSessionResult {
+    //stopped from the api
+    Stopped,
+    //the local stream is unpublished
+    Finished,
+    //the session is broken and needs to reconnect
+    Failed(String),
+}
+
+/*A supervised push: reconnect to the remote rtmp server with exponential backoff
+when the session is broken. The new session subscribes the local stream again and
+resumes from the next key frame after the cached sequence headers.*/
+struct PushSession {
+    id: Uuid,
+    url_parser: RtmpUrlParser,
+    app_name: String,
+    stream_name: String,
+    channel_event_producer: ChannelEventProducer,
+    relay_event_producer: RelayEventProducer,
+    retry_count: u32,
+}
+
+impl PushSession {
+    async fn run(mut self, mut stop_receiver: oneshot::Receiver<()>) {
+        let mut backoff = ReconnectBackoff::new();
+
+        loop {
+            let start_time = Instant::now();
+
+            let delay = match self.push(&mut stop_receiver).await {
+                PushSessionResult::Stopped => return,
+                PushSessionResult::Finished => {
+                    self.notify_state(RelayPushState::Finished, None);
+                    return;
+                }
+                PushSessionResult::Failed(err) => {
+                    let delay = backoff.on_failure(start_time.elapsed());
+                    self.retry_count += 1;
+                    log::error!(
+                        "relay push {} error: {}, reconnect in {:?}, retry count: {}",
+                        self.id,
+                        err,
+                        delay,
+                        self.retry_count
+                    );
+                    self.notify_state(RelayPushState::Reconnecting, Some(err));
+                    delay
+                }
+            };
+
+            tokio::select! {
+                _ = time::sleep(delay) => {}
+                _ = &mut stop_receiver => return,
+            }
+        }
+    }
+
+    async fn push(&mut self, stop_receiver: &mut oneshot::Receiver<()>) -> PushSessionResult {
+        self.notify_state(RelayPushState::Connecting, None);
+
+        let stream = tokio::select! {
+            rv = TcpStream::connect(self.url_parser.raw_domain_name.clone()) => rv,
+            _ = &mut *stop_receiver => return PushSessionResult::Stopped,
+        };
+
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => return PushSessionResult::Failed(err.to_string()),
+        };
+
+        let mut client_session = ClientSession::new(
+            stream,
+            ClientType::Publish,
+            self.url_parser.raw_domain_name.clone(),
+            self.url_parser.app_name.clone(),
+            self.url_parser.raw_stream_name.clone(),
+            self.channel_event_producer.clone(),
+        );
+        client_session.subscribe(self.app_name.clone(), self.stream_name.clone());
+        //the push is running once the remote server accepts the publish
+        let (publish_start_sender, mut publish_start_receiver) = oneshot::channel();
+        client_session.set_publish_start_sender(publish_start_sender);
+        let mut is_started = false;
+
+        let result = {
+            let run = client_session.run();
+            tokio::pin!(run);
+
+            loop {
+                tokio::select! {
+                    rv = &mut run => break match rv {
+                        Ok(_) => PushSessionResult::Finished,
+                        /*the local stream is unpublished*/
+                        Err(err) if matches!(err.value, SessionErrorValue::NoMediaDataReceived) => {
+                            PushSessionResult::Finished
+                        }
+                        Err(err) => PushSessionResult::Failed(err.to_string().trim_end().to_string()),
+                    },
+                    rv = &mut publish_start_receiver, if !is_started => {
+                        is_started = true;
+                        if rv.is_ok() {
+                            self.notify_state(RelayPushState::Running, None);
+                        }
+                    }
+                    _ = &mut *stop_receiver => break PushSessionResult::Stopped,
+                }
+            }
+        };
+
+        if let Err(err) = client_session.unsubscribe_from_channels().await {
+            log::error!("relay push {} unsubscribe error: {}", self.id, err);
+        }
+
+        result
+    }
+
+    fn notify_state(&self, state: RelayPushState, error: Option<String>) {
+        let event = RelayEvent::PushStateChanged {
+            id: self.id,
+            state,
+            error,
+            retry_count: self.retry_count,
+        };
+        if self.relay_event_producer.send(event).is_err() {
+            log::error!("relay push {} notify state err", self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{PushManager, ReconnectBackoff},
+        crate::{
+            channels::{
+                define::{ChannelDataProducer, ChannelEvent, ChannelEventProducer},
+                ChannelsManager,
+            },
+            relay::define::{RelayEvent, RelayEventProducer, RelayPushState, RelayPushStatus},
+            rtmp::RtmpServer,
+            session::{
+                common::{NotifyInfo, PublisherInfo},
+                define::PublishType,
+            },
+        },
+        std::time::Duration,
+        tokio::{
+            net::TcpListener,
+            sync::{mpsc, oneshot},
+            time::{sleep, Instant},
+        },
+        uuid::Uuid,
+    };
+
+    fn start_channels() -> ChannelEventProducer {
+        let mut channels = ChannelsManager::new(None);
+        let event_producer = channels.get_channel_event_producer();
+        tokio::spawn(async move { channels.run().await });
+        event_producer
+    }
+
+    fn start_push_manager(channel_event_producer: ChannelEventProducer) -> RelayEventProducer {
+        let mut push_manager = PushManager::new(channel_event_producer);
+        let producer = push_manager.get_relay_event_producer();
+        tokio::spawn(async move { push_manager.run().await });
+        producer
+    }
+
+    async fn start_push(producer: &RelayEventProducer, url: String) -> Uuid {
+        let (responder, receiver) = oneshot::channel();
+        producer
+            .send(RelayEvent::StartPush {
+                app_name: String::from("live"),
+                stream_name: String::from("test"),
+                url,
+                responder,
+            })
+            .unwrap();
+        receiver.await.unwrap().unwrap()
+    }
+
+    async fn push_status(producer: &RelayEventProducer) -> Vec<RelayPushStatus> {
+        let (responder, receiver) = oneshot::channel();
+        producer.send(RelayEvent::PushStatus { responder }).unwrap();
+        receiver.await.unwrap()
+    }
+
+    async fn stop_push(producer: &RelayEventProducer, id: Uuid) -> bool {
+        let (responder, receiver) = oneshot::channel();
+        producer
+            .send(RelayEvent::StopPush { id, responder })
+            .unwrap();
+        receiver.await.unwrap()
+    }
+
+    //wait until the status of the pushes matches
+    async fn wait_status<F>(producer: &RelayEventProducer, is_matched: F) -> Vec<RelayPushStatus>
+    where
+        F: Fn(&Vec<RelayPushStatus>) -> bool,
+    {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let status = push_status(producer).await;
+            if is_matched(&status) || Instant::now() >= deadline {
+                return status;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    fn local_publisher_info() -> PublisherInfo {
+        PublisherInfo {
+            id: Uuid::new_v4(),
+            sub_type: PublishType::PushRtmp,
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: String::from(""),
+            },
+        }
+    }
+
+    //publish live/test to the local channels
+    async fn publish_local_stream(
+        event_producer: &ChannelEventProducer,
+        info: PublisherInfo,
+    ) -> ChannelDataProducer {
+        let (responder, receiver) = oneshot::channel();
+        event_producer
+            .send(ChannelEvent::Publish {
+                app_name: String::from("live"),
+                stream_name: String::from("test"),
+                info,
+                responder,
+            })
+            .unwrap();
+        receiver.await.unwrap()
+    }
+
+    async fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[tokio::test]
+    async fn test_start_list_stop_push() {
+        let producer = start_push_manager(start_channels());
+
+        //a server which accepts the connection but never completes the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+
+        let id = start_push(&producer, format!("rtmp://127.0.0.1:{port}/live/remote")).await;
+        sleep(Duration::from_millis(300)).await;
+        let status = push_status(&producer).await;
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].id, id.to_string());
+        assert_eq!(
+            status[0].url,
+            format!("rtmp://127.0.0.1:{port}/live/remote")
+        );
+        //the push is not running before the publish is accepted
+        assert_eq!(status[0].state, RelayPushState::Connecting);
+
+        assert!(stop_push(&producer, id).await);
+        assert!(push_status(&producer).await.is_empty());
+        assert!(!stop_push(&producer, id).await);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_push_url() {
+        let producer = start_push_manager(start_channels());
+
+        let (responder, receiver) = oneshot::channel();
+        producer
+            .send(RelayEvent::StartPush {
+                app_name: String::from("live"),
+                stream_name: String::from("test"),
+                url: String::from("http://127.0.0.1/live/test"),
+                responder,
+            })
+            .unwrap();
+        assert!(receiver.await.unwrap().is_err());
+        assert!(push_status(&producer).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_push_running_and_finished() {
+        //the remote rtmp server
+        let port = free_port().await;
+        let mut server = RtmpServer::new(format!("127.0.0.1:{port}"), start_channels());
+        tokio::spawn(async move { server.run().await });
+        sleep(Duration::from_millis(100)).await;
+
+        let local_channels = start_channels();
+        let publisher_info = local_publisher_info();
+        let _data_producer = publish_local_stream(&local_channels, publisher_info.clone()).await;
+        let producer = start_push_manager(local_channels.clone());
+
+        let id = start_push(&producer, format!("rtmp://127.0.0.1:{port}/live/remote")).await;
+        let status = wait_status(&producer, |status| {
+            status
+                .first()
+                .is_some_and(|status| status.state == RelayPushState::Running)
+        })
+        .await;
+        assert_eq!(status[0].id, id.to_string());
+        assert_eq!(status[0].state, RelayPushState::Running);
+
+        //the push is finished and removed when the local stream is unpublished
+        local_channels
+            .send(ChannelEvent::UnPublish {
+                app_name: String::from("live"),
+                stream_name: String::from("test"),
+                info: publisher_info,
+            })
+            .unwrap();
+        assert!(wait_status(&producer, |status| status.is_empty())
+            .await
+            .is_empty());
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let mut backoff = ReconnectBackoff::new();
+        let broken = Duration::from_secs(1);
+
+        let delays: Vec<u64> = (0..7)
+            .map(|_| backoff.on_failure(broken).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30]);
+
+        //a session which lasted 30s is stable, the backoff starts over
+        assert_eq!(
+            backoff.on_failure(Duration::from_secs(30)),
+            Duration::from_secs(1)
+        );
+        assert_eq!(backoff.on_failure(broken), Duration::from_secs(2));
+        assert_eq!(
+            backoff.on_failure(Duration::from_secs(29)),
+            Duration::from_secs(4)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stop_unknown_push() {
+        let (channel_event_producer, _channel_event_consumer) = mpsc::unbounded_channel();
+        let mut push_manager = PushManager::new(channel_event_producer);
+        let producer = push_manager.get_relay_event_producer();
+        tokio::spawn(async move { push_manager.run().await });
+
+        let (responder, receiver) = oneshot::channel();
+        producer
+            .send(RelayEvent::StopPush {
+                id: Uuid::new_v4(),
+                responder,
+            })
+            .unwrap();
+        assert!(!receiver.await.unwrap());
+
+        let (responder, receiver) = oneshot::channel();
+        producer.send(RelayEvent::PushStatus { responder }).unwrap();
+        assert!(receiver.await.unwrap().is_empty());
+    }
+}
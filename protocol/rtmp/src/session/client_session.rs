@@ -25,7 +25,10 @@ use {
     bytesio::{bytes_writer::AsyncBytesWriter, bytesio::BytesIO},
     indexmap::IndexMap,
     std::sync::Arc,
-    tokio::{net::TcpStream, sync::Mutex},
+    tokio::{
+        net::TcpStream,
+        sync::{oneshot, Mutex},
+    },
     uuid::Uuid,
 };
 
@@ -79,6 +82,12 @@ pub struct ClientSession {
     client_type: ClientType,
     sub_app_name: Option<String>,
     sub_stream_name: Option<String>,
+    pub_app_name: Option<String>,
+    pub_stream_name: Option<String>,
+    /*the pulled stream has been published to the local channels*/
+    is_published: bool,
+    /*told when the remote server accepts the publish and the local stream is subscribed*/
+    publish_start_sender: Option<oneshot::Sender<()>>,
 }
 
 impl ClientSession {
@@ -125,6 +134,10 @@ impl ClientSession {
             client_type,
             sub_app_name: None,
             sub_stream_name: None,
+            pub_app_name: None,
+            pub_stream_name: None,
+            is_published: false,
+            publish_start_sender: None,
         }
     }
 
@@ -505,36 +518,22 @@ impl ClientSession {
                 "NetStream.Publish.Start" => {
                     self.state = ClientSessionState::StartPublish;
                     //subscribe from local session and publish to remote rtmp server
-                    if let (Some(app_name), Some(stream_name)) =
-                        (&self.sub_app_name, &self.sub_stream_name)
-                    {
-                        self.common
-                            .subscribe_from_channels(
-                                app_name.clone(),
-                                stream_name.clone(),
-                                self.session_id,
-                            )
-                            .await?;
-                    } else {
-                        self.common
-                            .subscribe_from_channels(
-                                self.app_name.clone(),
-                                self.stream_name.clone(),
-                                self.session_id,
-                            )
-                            .await?;
+                    let (app_name, stream_name) = self.get_subscribe_names();
+                    self.common
+                        .subscribe_from_channels(app_name, stream_name, self.session_id)
+                        .await?;
+                    if let Some(sender) = self.publish_start_sender.take() {
+                        let _ = sender.send(());
                     }
                 }
                 "NetStream.Publish.Reset" => {}
                 "NetStream.Play.Start" => {
                     //pull from remote rtmp server and publish to local session
+                    let (app_name, stream_name) = self.get_publish_names();
                     self.common
-                        .publish_to_channels(
-                            self.app_name.clone(),
-                            self.stream_name.clone(),
-                            self.session_id,
-                        )
-                        .await?
+                        .publish_to_channels(app_name, stream_name, self.session_id)
+                        .await?;
+                    self.is_published = true;
                 }
                 _ => {}
             }
@@ -543,8 +542,60 @@ impl ClientSession {
         Ok(())
     }
 
+    pub fn set_publish_start_sender(&mut self, sender: oneshot::Sender<()>) {
+        self.publish_start_sender = Some(sender);
+    }
+
     pub fn subscribe(&mut self, app_name: String, stream_name: String) {
         self.sub_app_name = Some(app_name);
         self.sub_stream_name = Some(stream_name);
     }
+
+    /*the local app/stream names a publish(push) client subscribes from*/
+    fn get_subscribe_names(&self) -> (String, String) {
+        if let (Some(app_name), Some(stream_name)) = (&self.sub_app_name, &self.sub_stream_name) {
+            (app_name.clone(), stream_name.clone())
+        } else {
+            (self.app_name.clone(), self.stream_name.clone())
+        }
+    }
+
+    /*used by the push client to release the local subscription when the session ends*/
+    pub async fn unsubscribe_from_channels(&mut self) -> Result<(), SessionError> {
+        let (app_name, stream_name) = self.get_subscribe_names();
+        self.common
+            .unsubscribe_from_channels(app_name, stream_name, self.session_id)
+            .await
+    }
+
+    pub fn publish(&mut self, app_name: String, stream_name: String) {
+        self.pub_app_name = Some(app_name);
+        self.pub_stream_name = Some(stream_name);
+    }
+
+    /*the local app/stream names a play(pull) client publishes to*/
+    fn get_publish_names(&self) -> (String, String) {
+        if let (Some(app_name), Some(stream_name)) = (&self.pub_app_name, &self.pub_stream_name) {
+            (app_name.clone(), stream_name.clone())
+        } else {
+            (self.app_name.clone(), self.stream_name.clone())
+        }
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.is_published
+    }
+
+    /*used by the pull client to remove the pulled stream from the local channels*/
+    pub async fn unpublish_to_channels(&mut self) -> Result<(), SessionError> {
+        if !self.is_published {
+            return Ok(());
+        }
+        self.is_published = false;
+
+        let (app_name, stream_name) = self.get_publish_names();
+        self.common
+            .unpublish_to_channels(app_name, stream_name, self.session_id)
+            .await
+    }
 }
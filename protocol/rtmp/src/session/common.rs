@@ -33,11 +33,31 @@ pub struct NotifyInfo {
     pub request_url: String,
     pub remote_addr: String,
 }
+/*what a subscriber receives from the stream*/
+#[derive(Debug, Clone)]
+pub struct SubscribeOptions {
+    pub has_audio: bool,
+    pub has_video: bool,
+    //how many of the latest cached gops are sent first, None sends all of them
+    pub gop_num: Option<usize>,
+}
+
+impl Default for SubscribeOptions {
+    fn default() -> Self {
+        Self {
+            has_audio: true,
+            has_video: true,
+            gop_num: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SubscriberInfo {
     pub id: Uuid,
     pub sub_type: SubscribeType,
     pub notify_info: NotifyInfo,
+    pub options: SubscribeOptions,
 }
 
 impl Serialize for SubscriberInfo {
@@ -280,6 +300,7 @@ impl Common {
                     request_url: self.request_url.clone(),
                     remote_addr,
                 },
+                options: SubscribeOptions::default(),
             },
             SessionType::Server => SubscriberInfo {
                 id: sub_id,
@@ -289,6 +310,7 @@ impl Common {
                     request_url: self.request_url.clone(),
                     remote_addr,
                 },
+                options: SubscribeOptions::default(),
             },
         }
     }
@@ -37,6 +37,9 @@ pub enum SubscribeType {
     /* Remote client request playing hls stream.*/
     PlayerHls,
     GenerateHls,
+    GenerateDash,
+    /* Record the stream to local flv files.*/
+    RecordFlv,
     /* Local client *subscribe* from local rtmp session
     and *publish* (relay push) the stream to remote server.*/
     PublisherRtmp,
@@ -50,6 +53,10 @@ pub enum PublishType {
     /* Local client *publish* the rtmp stream to local session,
     the rtmp stream is *subscribed* (pull) from remote server.*/
     SubscriberRtmp,
+    /* Receive flv stream from remote http client(POST) */
+    PushHttpFlv,
+    /* Receive flv stream from remote websocket client */
+    PushWebSocketFlv,
 }
 
 pub enum SessionType {
@@ -32,6 +32,8 @@ pub struct StreamStatistics {
     pub stream_name: String,
     pub video: VideoInfo,
     pub audio: AudioInfo,
+    //the viewers which play the stream by hls
+    pub hls_viewer_count: usize,
 }
 
 impl StreamStatistics {
@@ -42,4 +44,38 @@ impl StreamStatistics {
             ..Default::default()
         }
     }
+
+    /*the bitrate of the stream in bits per second*/
+    pub fn bandwidth(&self) -> u64 {
+        ((self.video.bitrate + self.audio.bitrate) * 1000.0) as u64
+    }
+
+    pub fn resolution(&self) -> Option<(u32, u32)> {
+        if self.video.width > 0 && self.video.height > 0 {
+            Some((self.video.width, self.video.height))
+        } else {
+            None
+        }
+    }
+
+    /*the RFC 6381 codecs, e.g. avc1.64001f,mp4a.40.2, the constraint flags
+    of H.264 are not kept so they are 00*/
+    pub fn codecs(&self) -> Vec<String> {
+        let mut codecs = Vec::new();
+        if let AvcCodecId::H264 = self.video.codec {
+            let profile = self.video.profile.clone() as i32;
+            let level = self.video.level.clone() as i32;
+            if profile > 0 && level > 0 {
+                codecs.push(format!("avc1.{profile:02x}00{level:02x}"));
+            }
+        }
+        //the AAC sequence header is received
+        if self.audio.samplerate > 0 {
+            let profile = self.audio.profile.clone() as i32;
+            if profile > 0 {
+                codecs.push(format!("mp4a.40.{profile}"));
+            }
+        }
+        codecs
+    }
 }
@@ -91,10 +91,33 @@ impl RtmpUrlParser {
     }
 }
 
+/*The app and stream names are used as the folder or file names of the hls and flv
+files, a name which may escape its folder(e.g. .., the separators, the drive prefixes
+and the encoded characters) is not valid.*/
+pub fn is_valid_path_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !name
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c == '%' || c == ':' || c.is_control())
+}
+
 #[cfg(test)]
 mod tests {
 
-    use super::RtmpUrlParser;
+    use super::{is_valid_path_name, RtmpUrlParser};
+
+    #[test]
+    fn test_is_valid_path_name() {
+        assert!(is_valid_path_name("test"));
+        assert!(is_valid_path_name("test_720p.v2"));
+        for name in [
+            "", "..", ".hidden", "../x", "a/b", "a\\b", "c:x", "..%2fx", "a\nb",
+        ] {
+            assert!(!is_valid_path_name(name), "{}", name);
+        }
+    }
+
     #[test]
     fn test_rtmp_url_parser() {
         let mut parser = RtmpUrlParser::new(String::from(